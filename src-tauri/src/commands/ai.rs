@@ -1,70 +1,240 @@
 use crate::services::db_service::DbState;
-use crate::services::cards;
-use crate::services::local_llm::{LocalLLMState, ModelStatus};
-use tauri::State;
+use crate::services::download_service::DownloadProgress;
+use crate::services::embedding_model::EmbeddingState;
+use crate::services::llm_provider::{self, ProviderKind, ProviderSettings};
+use crate::services::local_llm::{self, GenerationOptions, GenerationStats, LocalLLMState, ModelStatus};
+use crate::services::model_registry::{self, ModelEntry, ModelTask};
+use crate::services::organization_service::NoteScope;
+use crate::services::passphrase_service::PassphraseState;
+use crate::services::rag_service::{self, Source};
+use crate::services::search_service::{self, SemanticQuery};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager, State};
+
+/// `model_id`, or the chat model when it is not given
+fn model_or_chat(db_state: &State<'_, DbState>, model_id: Option<String>) -> Result<ModelEntry, String> {
+    let conn = db_state.0.lock().map_err(|e| e.to_string())?;
+    match model_id {
+        Some(id) => model_registry::get_model(&conn, &id)?.ok_or_else(|| format!("Model {} not found", id)),
+        None => model_registry::model_for_task(&conn, ModelTask::Chat),
+    }
+}
 
 #[tauri::command]
-pub fn get_model_status(llm_state: State<'_, LocalLLMState>) -> ModelStatus {
-    llm_state.get_status()
+pub fn get_model_status(
+    db_state: State<'_, DbState>,
+    llm_state: State<'_, LocalLLMState>,
+    model_id: Option<String>,
+) -> Result<ModelStatus, String> {
+    let model = model_or_chat(&db_state, model_id)?;
+    Ok(llm_state.get_status(&model))
 }
 
 #[tauri::command]
-pub fn delete_model(llm_state: State<'_, LocalLLMState>) -> Result<(), String> {
-    llm_state.delete_model().map_err(|e| e.to_string())
+pub fn delete_model(
+    db_state: State<'_, DbState>,
+    llm_state: State<'_, LocalLLMState>,
+    model_id: Option<String>,
+) -> Result<(), String> {
+    let model = model_or_chat(&db_state, model_id)?;
+    llm_state.delete_model(&model).map_err(|e| e.to_string())
+}
+
+/// Progress of a model file download, emitted as `model-download-progress`
+#[derive(Clone, Serialize)]
+pub struct DownloadEvent {
+    pub model_id: String,
+    /// "model" or "tokenizer"
+    pub file: String,
+    #[serde(flatten)]
+    pub progress: DownloadProgress,
+}
+
+/// Download whatever `model` is missing, reporting progress as events, and remember the
+/// hashes of the downloaded files. Returns the model as the registry now has it.
+pub(crate) async fn ensure_downloaded(
+    app: &AppHandle,
+    db_state: &State<'_, DbState>,
+    llm_state: &State<'_, LocalLLMState>,
+    model: &ModelEntry,
+) -> Result<ModelEntry, String> {
+    let files = llm_state
+        .check_and_download(model, |file, progress| {
+            let _ = app.emit_all(
+                "model-download-progress",
+                DownloadEvent { model_id: model.id.clone(), file: file.to_string(), progress: progress.clone() },
+            );
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // A freshly downloaded model describes itself; read the header before taking the lock
+    if files.model_sha256.is_some() || files.tokenizer_sha256.is_some() || model.architecture.is_none() {
+        let info = local_llm::read_gguf_info(Path::new(&model.model_path)).map_err(|e| e.to_string())?;
+        let conn = db_state.0.lock().map_err(|e| e.to_string())?;
+        model_registry::record_checksums(
+            &conn,
+            &model.id,
+            files.model_sha256.as_deref(),
+            files.tokenizer_sha256.as_deref(),
+        )?;
+        return model_registry::record_model_info(&conn, &model.id, &info);
+    }
+    Ok(model.clone())
 }
 
+/// Download a catalog model ahead of its first use
+#[tauri::command]
+pub async fn download_model(
+    app: AppHandle,
+    db_state: State<'_, DbState>,
+    llm_state: State<'_, LocalLLMState>,
+    model_id: Option<String>,
+) -> Result<ModelStatus, String> {
+    let model = model_or_chat(&db_state, model_id)?;
+    let model = ensure_downloaded(&app, &db_state, &llm_state, &model).await?;
+    Ok(llm_state.get_status(&model))
+}
+
+/// A piece of generated text for the request `request_id`, emitted as `llm-token`
+#[derive(Clone, Serialize)]
+pub struct TokenEvent {
+    pub request_id: String,
+    pub token: String,
+}
+
+/// A generated answer and the note passages it was given as numbered sources
+#[derive(Serialize)]
+pub struct Answer {
+    pub request_id: String,
+    /// Refers to sources as `[n]`, matching `Source::marker`
+    pub text: String,
+    pub sources: Vec<Source>,
+    /// `None` when the answer did not come from the model
+    pub stats: Option<GenerationStats>,
+}
+
+/// Passages retrieved for one question
+pub(crate) const MAX_PASSAGES: usize = 12;
+
+/// The question's embedding and the model that made it, when an embedding model is
+/// available; without one retrieval is keyword only
+pub(crate) fn embed_question(
+    embedding_state: &EmbeddingState,
+    question: &str,
+) -> Result<Option<(Vec<f32>, String)>, String> {
+    if !embedding_state.ensure_loaded().unwrap_or(false) {
+        return Ok(None);
+    }
+    let text = search_service::semantic_query_text(question);
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let vector = embedding_state.embed(&text).map_err(|e| e.to_string())?;
+    Ok(Some((vector, embedding_state.model_name().unwrap_or_default())))
+}
+
+/// Answer `query` from the user's notes, citing the passages it used. Text is streamed as
+/// `llm-token` events while it is generated; the full answer, its sources and its stats are
+/// returned once generation ends.
 #[tauri::command]
 pub async fn synthesize_query(
+    app: AppHandle,
     db_state: State<'_, DbState>,
     llm_state: State<'_, LocalLLMState>,
+    embedding_state: State<'_, EmbeddingState>,
+    passphrase_state: State<'_, Mutex<PassphraseState>>,
     query: String,
-) -> Result<String, String> {
-    // 1. Search for relevant cards using FTS5
-    let conn = db_state.0.lock().map_err(|e| e.to_string())?;
-    let cards = cards::search_cards(&conn, &query).map_err(|e| e.to_string())?;
+    request_id: String,
+    options: Option<GenerationOptions>,
+) -> Result<Answer, String> {
+    // Embed the question before locking the DB
+    let semantic = embed_question(&embedding_state, &query)?;
 
-    // 2. Bundle context from top 5 cards
-    let context = cards.iter()
-        .take(5)
-        .map(|c| format!("Source: Card {}\nContent: {}", c.id, c.content))
-        .collect::<Vec<_>>()
-        .join("\n\n");
+    let (passages, model, settings) = {
+        let conn = db_state.0.lock().map_err(|e| e.to_string())?;
+        let semantic_query = semantic.as_ref().map(|(vector, model)| SemanticQuery { vector, model });
+        (
+            rag_service::retrieve(&conn, &query, &NoteScope::All, semantic_query, MAX_PASSAGES)?,
+            model_registry::model_for_task(&conn, ModelTask::Chat)?,
+            llm_provider::get_settings(&conn, &passphrase_state)?,
+        )
+    };
 
-    if context.is_empty() {
-        return Ok("I couldn't find any relevant notes in your brain to answer that.".to_string());
+    if passages.is_empty() {
+        return Ok(Answer {
+            request_id,
+            text: rag_service::NOTHING_FOUND.to_string(),
+            sources: Vec::new(),
+            stats: None,
+        });
     }
 
-    // 3. Construct Prompt
-    let prompt = format!(
-        "<|im_start|>system\nYou are a helpful second brain assistant. Answer the query based ONLY on the provided context.\n<|im_end|>\n<|im_start|>user\nContext:\n{}\n\nQuery: {}\n<|im_end|>\n<|im_start|>assistant\n",
-        context, query
-    );
-
-    // 4. Generate response via Local Candle LLM
-    // We run this in a blocking task to avoid freezing the async runtime
-    let response = tauri::async_runtime::spawn_blocking(move || {
-        // Run the async check_and_download inside a blocking runtime block
-        // This is a bit tricky mixing sync/async, so we use a mini-runtime for the download part
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        let (model_path, tokenizer_path) = match rt.block_on(llm_state.check_and_download()) {
-             Ok(paths) => paths,
-             Err(e) => return format!("Error initializing brain: {}", e)
-        };
-
-        // Load model if not already loaded
-        if let Err(e) = llm_state.load_model(&model_path, &tokenizer_path) {
-             return format!("Error loading brain: {}", e);
-        }
+    let model = if settings.provider == ProviderKind::Embedded {
+        ensure_downloaded(&app, &db_state, &llm_state, &model)
+            .await
+            .map_err(|e| format!("Error initializing brain: {}", e))?
+    } else {
+        model
+    };
+
+    // Packing counts tokens with the model's tokenizer and generation blocks (CPU-bound
+    // inference or a streamed HTTP response), so both run on a blocking thread rather than
+    // the async runtime
+    let options = options.unwrap_or_default();
+    let cancel = llm_state.start_request(&request_id);
+    let id = request_id.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let llm_state = app.state::<LocalLLMState>();
+        let provider = llm_state.provider(&settings, model)?;
 
-        match llm_state.generate(&prompt) {
-            Ok(output) => output,
-            Err(e) => format!("Brain freeze: {}", e)
+        let budget = rag_service::context_budget(provider.context_length(), options.max_tokens);
+        let mut sources = rag_service::pack_sources(passages, budget, &|text| provider.count_tokens(text));
+        if sources.is_empty() {
+            return Err("The model's context is too small to hold any of the retrieved notes".to_string());
         }
+
+        let messages = rag_service::build_messages(&query, &sources);
+        let (text, stats) = provider.generate(&messages, &options, &cancel, &mut |token| {
+            let _ = app.emit_all("llm-token", TokenEvent { request_id: id.clone(), token: token.to_string() });
+        })?;
+        rag_service::mark_cited(&text, &mut sources);
+        Ok((text, sources, stats))
     })
-    .await
-    .map_err(|e| e.to_string())?;
+    .await;
+    llm_state.finish_request(&request_id);
 
-    Ok(response)
+    let (text, sources, stats) = result.map_err(|e| e.to_string())??;
+    Ok(Answer { request_id, text, sources, stats: Some(stats) })
+}
+
+#[tauri::command]
+pub async fn get_llm_settings(
+    db_state: State<'_, DbState>,
+    passphrase_state: State<'_, Mutex<PassphraseState>>,
+) -> Result<ProviderSettings, String> {
+    let conn = db_state.0.lock().unwrap();
+    llm_provider::get_settings(&conn, &passphrase_state)
+}
+
+/// Choose the provider (embedded, ollama or openai) and its server. An API key is
+/// encrypted with the vault key, so storing one needs the vault unlocked.
+#[tauri::command]
+pub async fn save_llm_settings(
+    db_state: State<'_, DbState>,
+    passphrase_state: State<'_, Mutex<PassphraseState>>,
+    settings: ProviderSettings,
+) -> Result<(), String> {
+    let conn = db_state.0.lock().unwrap();
+    llm_provider::save_settings(&conn, &passphrase_state, &settings)
+}
+
+/// Stop the generation started with `request_id`; it returns what it has produced so far
+#[tauri::command]
+pub fn cancel_generation(llm_state: State<'_, LocalLLMState>, request_id: String) -> bool {
+    llm_state.cancel(&request_id)
 }
 
 #[cfg(test)]
@@ -1,6 +1,7 @@
 use crate::services::db_service::DbState;
-use crate::services::cards;
-use tauri::State;
+use crate::services::{anki_service, cards};
+use std::path::PathBuf;
+use tauri::{AppHandle, State};
 
 #[tauri::command]
 pub fn create_card(
@@ -24,3 +25,31 @@ pub fn search_cards(
     cards::search_cards(&conn, &query)
         .map_err(|e| e.to_string())
 }
+
+/// Export cards (all, or the given ids) as an Anki `.apkg` deck
+#[tauri::command]
+pub fn export_anki_deck(
+    state: State<DbState>,
+    path: String,
+    deck_name: String,
+    card_ids: Option<Vec<i64>>,
+) -> Result<anki_service::AnkiExportReport, String> {
+    let conn = state.0.lock().unwrap();
+    anki_service::export_apkg(&conn, &PathBuf::from(path), &deck_name, card_ids.as_deref())
+}
+
+/// Import an Anki `.apkg` deck, including its review history; media goes to `media/` in the app data dir
+#[tauri::command]
+pub fn import_anki_deck(
+    app: AppHandle,
+    state: State<DbState>,
+    path: String,
+) -> Result<anki_service::AnkiImportReport, String> {
+    let media_dir = app
+        .path_resolver()
+        .app_data_dir()
+        .ok_or("Could not resolve the app data directory")?
+        .join("media");
+    let conn = state.0.lock().unwrap();
+    anki_service::import_apkg(&conn, &PathBuf::from(path), &media_dir)
+}
@@ -0,0 +1,202 @@
+use serde::Serialize;
+use tauri::{AppHandle, Manager, State};
+use crate::commands::ai::{embed_question, ensure_downloaded, TokenEvent, MAX_PASSAGES};
+use crate::services::chat_service::{self, ChatMessageRecord, ChatSession};
+use crate::services::db_service::DbState;
+use crate::services::embedding_model::EmbeddingState;
+use crate::services::llm_provider::{self, ProviderKind};
+use crate::services::local_llm::{GenerationOptions, GenerationStats, LocalLLMState};
+use crate::services::model_registry::{self, ModelTask};
+use crate::services::organization_service::NoteScope;
+use crate::services::passphrase_service::PassphraseState;
+use crate::services::rag_service;
+use crate::services::search_service::SemanticQuery;
+use std::sync::atomic::AtomicBool;
+use std::sync::Mutex;
+
+/// Length of the summary that replaces older history
+const SUMMARY_MAX_TOKENS: usize = 300;
+
+#[tauri::command]
+pub async fn create_chat_session(
+    db_state: State<'_, DbState>,
+    title: Option<String>,
+    scope: Option<NoteScope>,
+) -> Result<ChatSession, String> {
+    let conn = db_state.0.lock().unwrap();
+    chat_service::create_session(&conn, title.as_deref(), &scope.unwrap_or(NoteScope::All)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_chat_sessions(db_state: State<'_, DbState>) -> Result<Vec<ChatSession>, String> {
+    let conn = db_state.0.lock().unwrap();
+    chat_service::list_sessions(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_chat_messages(
+    db_state: State<'_, DbState>,
+    session_id: String,
+) -> Result<Vec<ChatMessageRecord>, String> {
+    let conn = db_state.0.lock().unwrap();
+    chat_service::list_messages(&conn, &session_id).map_err(|e| e.to_string())
+}
+
+/// Rename a session or change which notes it answers from
+#[tauri::command]
+pub async fn update_chat_session(
+    db_state: State<'_, DbState>,
+    id: String,
+    title: Option<String>,
+    scope: Option<NoteScope>,
+) -> Result<ChatSession, String> {
+    let conn = db_state.0.lock().unwrap();
+    chat_service::update_session(&conn, &id, title.as_deref(), scope.as_ref())
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Chat session {} not found", id))
+}
+
+#[tauri::command]
+pub async fn delete_chat_session(db_state: State<'_, DbState>, id: String) -> Result<bool, String> {
+    let conn = db_state.0.lock().unwrap();
+    chat_service::delete_session(&conn, &id).map_err(|e| e.to_string())
+}
+
+/// Save an answer as a new note linking to its sources; returns the note's id
+#[tauri::command]
+pub async fn save_chat_answer_as_note(db_state: State<'_, DbState>, message_id: i64) -> Result<String, String> {
+    let conn = db_state.0.lock().unwrap();
+    chat_service::save_answer_as_note(&conn, message_id)
+}
+
+#[derive(Serialize)]
+pub struct ChatReply {
+    pub question: ChatMessageRecord,
+    pub answer: ChatMessageRecord,
+    /// `None` when the answer did not come from the model
+    pub stats: Option<GenerationStats>,
+}
+
+/// Ask a question in a session. The answer draws on notes in the session's scope and on
+/// its history, and streams as `llm-token` events tagged with `request_id`; both messages
+/// are stored and returned once it is done. Nothing is stored when generation fails.
+#[tauri::command]
+pub async fn send_chat_message(
+    app: AppHandle,
+    db_state: State<'_, DbState>,
+    llm_state: State<'_, LocalLLMState>,
+    embedding_state: State<'_, EmbeddingState>,
+    passphrase_state: State<'_, Mutex<PassphraseState>>,
+    session_id: String,
+    content: String,
+    request_id: String,
+    options: Option<GenerationOptions>,
+) -> Result<ChatReply, String> {
+    let content = content.trim().to_string();
+    if content.is_empty() {
+        return Err("Message cannot be empty".to_string());
+    }
+
+    let (session, history) = {
+        let conn = db_state.0.lock().unwrap();
+        let session = chat_service::get_session(&conn, &session_id)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Chat session {} not found", session_id))?;
+        let messages = chat_service::list_messages(&conn, &session_id).map_err(|e| e.to_string())?;
+        let history = messages[session.summarized_count.min(messages.len())..].to_vec();
+        (session, history)
+    };
+
+    let search_text = chat_service::retrieval_query(&history, &content);
+    let semantic = embed_question(&embedding_state, &search_text)?;
+    let (passages, model, settings) = {
+        let conn = db_state.0.lock().unwrap();
+        let semantic_query = semantic.as_ref().map(|(vector, model)| SemanticQuery { vector, model });
+        (
+            rag_service::retrieve(&conn, &search_text, &session.scope, semantic_query, MAX_PASSAGES)?,
+            model_registry::model_for_task(&conn, ModelTask::Chat)?,
+            llm_provider::get_settings(&conn, &passphrase_state)?,
+        )
+    };
+
+    // A follow-up can still be answered from the conversation; a first question can't
+    let found = !passages.is_empty();
+    if !found && session.summary.is_none() && history.is_empty() {
+        let conn = db_state.0.lock().unwrap();
+        let (question, answer) =
+            chat_service::add_exchange(&conn, &session_id, &content, rag_service::NOTHING_FOUND, &[], None)
+                .map_err(|e| e.to_string())?;
+        return Ok(ChatReply { question, answer, stats: None });
+    }
+
+    let model = if settings.provider == ProviderKind::Embedded {
+        ensure_downloaded(&app, &db_state, &llm_state, &model)
+            .await
+            .map_err(|e| format!("Error initializing brain: {}", e))?
+    } else {
+        model
+    };
+
+    // Summarizing, counting tokens and generating all block, so they run on a blocking thread
+    let options = options.unwrap_or_default();
+    let cancel = llm_state.start_request(&request_id);
+    let id = request_id.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let llm_state = app.state::<LocalLLMState>();
+        let provider = llm_state.provider(&settings, model)?;
+        let count_tokens = |text: &str| provider.count_tokens(text);
+        let budget = rag_service::context_budget(provider.context_length(), options.max_tokens);
+
+        // Fold the oldest history into the summary until the rest fits its share of the budget
+        let mut summary = session.summary.clone();
+        let mut summarized_count = session.summarized_count;
+        let older = chat_service::messages_to_summarize(
+            summary.as_deref(),
+            &history,
+            chat_service::history_budget(budget),
+            &count_tokens,
+        );
+        if older > 0 {
+            let summary_options = GenerationOptions {
+                max_tokens: SUMMARY_MAX_TOKENS,
+                temperature: Some(0.2),
+                ..Default::default()
+            };
+            let (text, _) = provider.generate(
+                &chat_service::summary_prompt(summary.as_deref(), &history[..older]),
+                &summary_options,
+                &AtomicBool::new(false),
+                &mut |_| {},
+            )?;
+            summary = Some(text.trim().to_string());
+            summarized_count += older;
+        }
+        let history = &history[older..];
+
+        let history_tokens: usize = summary.as_deref().map(count_tokens).unwrap_or(0)
+            + history.iter().map(|m| count_tokens(&m.content)).sum::<usize>();
+        let mut sources =
+            rag_service::pack_sources(passages, budget.saturating_sub(history_tokens), &count_tokens);
+        if found && sources.is_empty() {
+            return Err("The model's context is too small to hold any of the retrieved notes".to_string());
+        }
+
+        let messages = chat_service::conversation(summary.as_deref(), history, &content, &sources);
+        let (text, stats) = provider.generate(&messages, &options, &cancel, &mut |token| {
+            let _ = app.emit_all("llm-token", TokenEvent { request_id: id.clone(), token: token.to_string() });
+        })?;
+        rag_service::mark_cited(&text, &mut sources);
+
+        let summary = summary.filter(|_| older > 0).map(|s| (s, summarized_count));
+        Ok((text, sources, stats, summary))
+    })
+    .await;
+    llm_state.finish_request(&request_id);
+
+    let (text, sources, stats, summary) = result.map_err(|e| e.to_string())??;
+    let conn = db_state.0.lock().unwrap();
+    let summary = summary.as_ref().map(|(summary, summarized_count)| (summary.as_str(), *summarized_count));
+    let (question, answer) = chat_service::add_exchange(&conn, &session_id, &content, &text, &sources, summary)
+        .map_err(|e| e.to_string())?;
+    Ok(ChatReply { question, answer, stats: Some(stats) })
+}
@@ -1,8 +1,11 @@
-use tauri::State;
+use tauri::{AppHandle, Manager, State};
 use std::path::PathBuf;
-use crate::services::{db_service, import_service, export_service, backup_service, search_service};
+use crate::services::background::AppScheduler;
+use crate::services::{db_service, import_service, export_service, backup_service, search_service, search_index, publish_service, vault_service};
+use crate::services::organization_service::NoteScope;
 use crate::services::passphrase_service::PassphraseState;
 use crate::services::encrypted_note_service;
+use crate::services::embedding_model::EmbeddingState;
 
 #[tauri::command]
 pub async fn get_notes(
@@ -66,47 +69,65 @@ pub async fn get_note(
     }
 }
 
+/// Have the embeddings job pick up a saved note now rather than at its next scheduled
+/// run, which is skipped on battery; the note counts as changed until it is embedded
+fn request_embedding(app: &AppHandle) {
+    if let Some(scheduler) = app.try_state::<AppScheduler>() {
+        let _ = scheduler.request("embeddings");
+    }
+}
+
 #[tauri::command]
 pub async fn create_note(
+    app: AppHandle,
     db_state: State<'_, db_service::DbState>,
     passphrase_state: State<'_, PassphraseState>,
     title: String,
     content: String,
 ) -> Result<String, String> {
-    let conn = db_state.0.lock().unwrap();
-    
-    // Check if encryption is enabled
-    let state_guard = passphrase_state.lock().map_err(|e| e.to_string())?;
-    
-    if state_guard.is_enabled() {
-        // Use encrypted note service
-        encrypted_note_service::create_encrypted_note(&conn, &passphrase_state, &title, &content)
-    } else {
-        // Use regular service
-        db_service::create_note(&conn, &title, &content).map_err(|e| e.to_string())
-    }
+    let id = {
+        let conn = db_state.0.lock().unwrap();
+
+        // Check if encryption is enabled
+        let state_guard = passphrase_state.lock().map_err(|e| e.to_string())?;
+
+        if state_guard.is_enabled() {
+            // Use encrypted note service
+            encrypted_note_service::create_encrypted_note(&conn, &passphrase_state, &title, &content)?
+        } else {
+            // Use regular service
+            db_service::create_note(&conn, &title, &content).map_err(|e| e.to_string())?
+        }
+    };
+    request_embedding(&app);
+    Ok(id)
 }
 
 #[tauri::command]
 pub async fn update_note(
+    app: AppHandle,
     db_state: State<'_, db_service::DbState>,
     passphrase_state: State<'_, PassphraseState>,
     id: String,
     title: String,
     content: String,
 ) -> Result<(), String> {
-    let conn = db_state.0.lock().unwrap();
-    
-    // Check if encryption is enabled
-    let state_guard = passphrase_state.lock().map_err(|e| e.to_string())?;
-    
-    if state_guard.is_enabled() {
-        // Use encrypted note service
-        encrypted_note_service::update_encrypted_note(&conn, &passphrase_state, &id, &title, &content)
-    } else {
-        // Use regular service
-        db_service::update_note(&conn, &id, &title, &content).map_err(|e| e.to_string())
+    {
+        let conn = db_state.0.lock().unwrap();
+
+        // Check if encryption is enabled
+        let state_guard = passphrase_state.lock().map_err(|e| e.to_string())?;
+
+        if state_guard.is_enabled() {
+            // Use encrypted note service
+            encrypted_note_service::update_encrypted_note(&conn, &passphrase_state, &id, &title, &content)?;
+        } else {
+            // Use regular service
+            db_service::update_note(&conn, &id, &title, &content).map_err(|e| e.to_string())?;
+        }
     }
+    request_embedding(&app);
+    Ok(())
 }
 
 #[tauri::command]
@@ -140,6 +161,34 @@ pub async fn export_notes(
     export_service::export_notes(&conn, &PathBuf::from(path)).map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn publish_site(
+    state: State<'_, db_service::DbState>,
+    scope: NoteScope,
+    out_dir: String,
+) -> Result<publish_service::PublishReport, String> {
+    let conn = state.0.lock().unwrap();
+    publish_service::publish_site(&conn, &scope, &PathBuf::from(out_dir))
+}
+
+#[tauri::command]
+pub async fn export_vault(
+    state: State<'_, db_service::DbState>,
+    path: String,
+) -> Result<(), String> {
+    let conn = state.0.lock().unwrap();
+    vault_service::export_vault_json(&conn, &PathBuf::from(path))
+}
+
+#[tauri::command]
+pub async fn import_vault(
+    state: State<'_, db_service::DbState>,
+    path: String,
+) -> Result<vault_service::VaultLoadReport, String> {
+    let conn = state.0.lock().unwrap();
+    vault_service::import_vault_json(&conn, &PathBuf::from(path))
+}
+
 #[tauri::command]
 pub async fn create_backup(
     db_state: State<'_, db_service::DbState>,
@@ -154,13 +203,45 @@ pub async fn create_backup(
 #[tauri::command]
 pub async fn search_notes(
     state: State<'_, db_service::DbState>,
+    embedding_state: State<'_, EmbeddingState>,
     query: String,
     role: Option<String>,
     global_search: Option<bool>,
+    options: Option<search_service::SearchOptions>,
 ) -> Result<search_service::SearchResultWithMetadata, String> {
-    let conn = state.0.lock().unwrap();
     let role_str = role.as_deref();
     let global = global_search.unwrap_or(false);
-    search_service::search_notes(&conn, &query, role_str, global).map_err(|e| e.to_string())
+    let options = options.unwrap_or_default();
+
+    // Embed the query before locking the DB; without a model this is a keyword search
+    let mut semantic = None;
+    if options.semantic_weight > 0.0 && embedding_state.ensure_loaded().unwrap_or(false) {
+        let text = search_service::semantic_query_text(&query);
+        if !text.is_empty() {
+            let vector = embedding_state.embed(&text).map_err(|e| e.to_string())?;
+            semantic = Some((vector, embedding_state.model_name().unwrap_or_default()));
+        }
+    }
+
+    let conn = state.0.lock().unwrap();
+    let semantic_query = semantic.as_ref().map(|(vector, model)| search_service::SemanticQuery { vector, model });
+    search_service::search_notes_hybrid(&conn, &query, role_str, global, &options, semantic_query)
+        .map_err(|e| e.to_string())
 }
 
+
+#[tauri::command]
+pub async fn rebuild_search_index(
+    state: State<'_, db_service::DbState>,
+) -> Result<search_index::SearchIndexStats, String> {
+    let conn = state.0.lock().unwrap();
+    search_index::rebuild(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn optimize_search_index(
+    state: State<'_, db_service::DbState>,
+) -> Result<search_index::SearchIndexStats, String> {
+    let conn = state.0.lock().unwrap();
+    search_index::optimize(&conn).map_err(|e| e.to_string())
+}
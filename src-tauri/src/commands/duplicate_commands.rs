@@ -0,0 +1,29 @@
+use std::sync::Mutex;
+use tauri::State;
+use crate::services::db_service::DbState;
+use crate::services::duplicate_service::{self, DuplicateCluster, MergeResult, MergeStrategy};
+use crate::services::passphrase_service::PassphraseState;
+
+/// Clusters of near-duplicate notes; `threshold` is the minimum similarity, 0.0 to 1.0
+#[tauri::command]
+pub async fn find_duplicates(
+    db_state: State<'_, DbState>,
+    threshold: Option<f64>,
+    use_embeddings: Option<bool>,
+    limit: Option<usize>,
+) -> Result<Vec<DuplicateCluster>, String> {
+    let conn = db_state.0.lock().unwrap();
+    duplicate_service::find_duplicates(&conn, threshold, use_embeddings.unwrap_or(false), limit.unwrap_or(100))
+}
+
+/// Merge notes into one; the others go to the trash
+#[tauri::command]
+pub async fn merge_notes(
+    db_state: State<'_, DbState>,
+    passphrase_state: State<'_, Mutex<PassphraseState>>,
+    ids: Vec<String>,
+    strategy: MergeStrategy,
+) -> Result<MergeResult, String> {
+    let conn = db_state.0.lock().unwrap();
+    duplicate_service::merge_notes(&conn, &passphrase_state, &ids, strategy)
+}
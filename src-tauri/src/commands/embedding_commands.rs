@@ -0,0 +1,43 @@
+use tauri::State;
+use serde::Serialize;
+use crate::services::db_service::DbState;
+use crate::services::embedding_model::{EmbeddingModelStatus, EmbeddingState};
+use crate::services::embedding_service::{self, EmbeddingStats, SemanticHit};
+
+#[derive(Serialize)]
+pub struct EmbeddingStatus {
+    pub model: EmbeddingModelStatus,
+    pub index: EmbeddingStats,
+}
+
+/// Notes closest in meaning to `query`, by cosine similarity of their chunk embeddings
+#[tauri::command]
+pub async fn semantic_search(
+    db_state: State<'_, DbState>,
+    embedding_state: State<'_, EmbeddingState>,
+    query: String,
+    k: Option<usize>,
+) -> Result<Vec<SemanticHit>, String> {
+    if !embedding_state.ensure_loaded().map_err(|e| e.to_string())? {
+        return Err("Embedding model not installed".to_string());
+    }
+    let model = embedding_state.model_name().unwrap_or_default();
+    // Embed before taking the DB lock so inference doesn't block other commands
+    let query_vector = embedding_state.embed(&query).map_err(|e| e.to_string())?;
+
+    let conn = db_state.0.lock().map_err(|e| e.to_string())?;
+    embedding_service::semantic_search(&conn, &query_vector, &model, k.unwrap_or(10))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_embedding_status(
+    db_state: State<'_, DbState>,
+    embedding_state: State<'_, EmbeddingState>,
+) -> Result<EmbeddingStatus, String> {
+    let conn = db_state.0.lock().map_err(|e| e.to_string())?;
+    Ok(EmbeddingStatus {
+        model: embedding_state.get_status(),
+        index: embedding_service::stats(&conn).map_err(|e| e.to_string())?,
+    })
+}
@@ -0,0 +1,53 @@
+use std::sync::Mutex;
+use tauri::State;
+use crate::services::{db_service, find_replace_service, revision_service};
+use crate::services::find_replace_service::{FindReplacePreview, FindReplaceResult};
+use crate::services::organization_service::NoteScope;
+use crate::services::passphrase_service::PassphraseState;
+use crate::services::revision_service::{RevisionBatch, UndoResult};
+
+/// Preview every match of a vault-wide find and replace without changing anything
+#[tauri::command]
+pub async fn find_replace(
+    db_state: State<'_, db_service::DbState>,
+    passphrase_state: State<'_, Mutex<PassphraseState>>,
+    pattern: String,
+    replacement: String,
+    scope: Option<NoteScope>,
+    regex: bool,
+) -> Result<FindReplacePreview, String> {
+    let conn = db_state.0.lock().unwrap();
+    find_replace_service::preview(&conn, &passphrase_state, &pattern, &replacement, &scope.unwrap_or(NoteScope::All), regex)
+}
+
+/// Apply a find and replace; the returned batch id can be passed to `undo_revision_batch`
+#[tauri::command]
+pub async fn apply_find_replace(
+    db_state: State<'_, db_service::DbState>,
+    passphrase_state: State<'_, Mutex<PassphraseState>>,
+    pattern: String,
+    replacement: String,
+    scope: Option<NoteScope>,
+    regex: bool,
+) -> Result<FindReplaceResult, String> {
+    let conn = db_state.0.lock().unwrap();
+    find_replace_service::apply(&conn, &passphrase_state, &pattern, &replacement, &scope.unwrap_or(NoteScope::All), regex)
+}
+
+#[tauri::command]
+pub async fn list_revision_batches(
+    db_state: State<'_, db_service::DbState>,
+    limit: Option<usize>,
+) -> Result<Vec<RevisionBatch>, String> {
+    let conn = db_state.0.lock().unwrap();
+    revision_service::list_batches(&conn, limit.unwrap_or(20)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn undo_revision_batch(
+    db_state: State<'_, db_service::DbState>,
+    batch_id: String,
+) -> Result<UndoResult, String> {
+    let conn = db_state.0.lock().unwrap();
+    revision_service::undo_batch(&conn, &batch_id)
+}
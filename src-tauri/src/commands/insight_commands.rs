@@ -0,0 +1,45 @@
+use std::sync::Mutex;
+use tauri::State;
+use crate::services::db_service::DbState;
+use crate::services::graph_analysis::{self, Insight};
+use crate::services::passphrase_service::PassphraseState;
+
+/// Open insights, best first; `include_answered` adds accepted and dismissed ones
+#[tauri::command]
+pub async fn list_insights(
+    db_state: State<'_, DbState>,
+    include_answered: Option<bool>,
+    limit: Option<usize>,
+) -> Result<Vec<Insight>, String> {
+    let conn = db_state.0.lock().unwrap();
+    graph_analysis::list_insights(&conn, include_answered.unwrap_or(false), limit.unwrap_or(50))
+        .map_err(|e| e.to_string())
+}
+
+/// Accept an insight, linking its two notes
+#[tauri::command]
+pub async fn accept_insight(
+    db_state: State<'_, DbState>,
+    passphrase_state: State<'_, Mutex<PassphraseState>>,
+    id: String,
+) -> Result<Insight, String> {
+    let conn = db_state.0.lock().unwrap();
+    graph_analysis::accept_insight(&conn, &passphrase_state, &id)
+}
+
+#[tauri::command]
+pub async fn dismiss_insight(db_state: State<'_, DbState>, id: String) -> Result<Insight, String> {
+    let conn = db_state.0.lock().unwrap();
+    graph_analysis::dismiss_insight(&conn, &id)
+}
+
+/// Hide an insight for `days` days (default 7)
+#[tauri::command]
+pub async fn snooze_insight(
+    db_state: State<'_, DbState>,
+    id: String,
+    days: Option<i64>,
+) -> Result<Insight, String> {
+    let conn = db_state.0.lock().unwrap();
+    graph_analysis::snooze_insight(&conn, &id, days.unwrap_or(7))
+}
@@ -0,0 +1,40 @@
+use tauri::State;
+use crate::services::background::AppScheduler;
+use crate::services::job_scheduler::{JobRun, JobSettingsInput, JobStatus};
+
+/// Background jobs with their schedules, run rules and last-run state
+#[tauri::command]
+pub async fn list_jobs(scheduler: State<'_, AppScheduler>) -> Result<Vec<JobStatus>, String> {
+    scheduler.list()
+}
+
+/// Run a job now, even if it is paused
+#[tauri::command]
+pub async fn trigger_job(scheduler: State<'_, AppScheduler>, name: String) -> Result<(), String> {
+    scheduler.trigger(&name)
+}
+
+/// Ask a running job to stop; returns false if it wasn't running
+#[tauri::command]
+pub async fn cancel_job(scheduler: State<'_, AppScheduler>, name: String) -> Result<bool, String> {
+    scheduler.cancel(&name)
+}
+
+/// Change a job's schedule, pause it, or change its battery/idle rules
+#[tauri::command]
+pub async fn update_job(
+    scheduler: State<'_, AppScheduler>,
+    name: String,
+    settings: JobSettingsInput,
+) -> Result<JobStatus, String> {
+    scheduler.update(&name, settings)
+}
+
+#[tauri::command]
+pub async fn list_job_runs(
+    scheduler: State<'_, AppScheduler>,
+    name: String,
+    limit: Option<usize>,
+) -> Result<Vec<JobRun>, String> {
+    scheduler.runs(&name, limit.unwrap_or(20))
+}
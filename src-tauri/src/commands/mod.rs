@@ -10,4 +10,15 @@ pub mod encryption_settings;
 pub mod dashboard_commands;
 pub mod search_commands;
 pub mod graph_commands;
-
+pub mod role_commands;
+pub mod saved_search_commands;
+pub mod find_replace_commands;
+pub mod embedding_commands;
+pub mod duplicate_commands;
+pub mod trash_commands;
+pub mod insight_commands;
+pub mod job_commands;
+pub mod model_commands;
+pub mod chat_commands;
+pub mod note_ai_commands;
+pub mod task_commands;
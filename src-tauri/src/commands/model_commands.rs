@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::path::Path;
+use tauri::State;
+use crate::services::db_service::DbState;
+use crate::services::local_llm::{self, LocalLLMState};
+use crate::services::model_registry::{self, ModelEntry, ModelTask};
+
+#[tauri::command]
+pub async fn list_models(db_state: State<'_, DbState>) -> Result<Vec<ModelEntry>, String> {
+    let conn = db_state.0.lock().unwrap();
+    model_registry::list_models(&conn)
+}
+
+/// Register a GGUF model and its `tokenizer.json` from disk
+#[tauri::command]
+pub async fn add_local_model(
+    db_state: State<'_, DbState>,
+    name: Option<String>,
+    model_path: String,
+    tokenizer_path: String,
+) -> Result<ModelEntry, String> {
+    // Reading the header can take a moment on a large file, so do it before taking the lock
+    let info = local_llm::read_gguf_info(Path::new(&model_path)).map_err(|e| e.to_string())?;
+    let conn = db_state.0.lock().unwrap();
+    model_registry::add_local_model(&conn, name.as_deref(), Path::new(&model_path), Path::new(&tokenizer_path), &info)
+}
+
+#[tauri::command]
+pub async fn remove_model(
+    db_state: State<'_, DbState>,
+    llm_state: State<'_, LocalLLMState>,
+    id: String,
+) -> Result<(), String> {
+    let conn = db_state.0.lock().unwrap();
+    model_registry::remove_model(&conn, &id)?;
+    llm_state.unload(&id);
+    Ok(())
+}
+
+/// The model id used for each task (chat, summarize, tag)
+#[tauri::command]
+pub async fn get_task_models(db_state: State<'_, DbState>) -> Result<HashMap<String, String>, String> {
+    let conn = db_state.0.lock().unwrap();
+    model_registry::task_models(&conn)
+}
+
+#[tauri::command]
+pub async fn set_task_model(db_state: State<'_, DbState>, task: ModelTask, model_id: String) -> Result<(), String> {
+    let conn = db_state.0.lock().unwrap();
+    model_registry::set_task_model(&conn, task, &model_id)
+}
@@ -0,0 +1,51 @@
+use tauri::{AppHandle, Manager, State};
+use crate::services::background::AppScheduler;
+use crate::services::db_service::DbState;
+use crate::services::note_ai_service::{self, NoteAction, NoteSuggestion};
+
+/// Queue AI actions on a note; each suggestion is emitted as `note-suggestion-ready`
+/// once the background job has generated it
+#[tauri::command]
+pub async fn request_note_actions(
+    app: AppHandle,
+    db_state: State<'_, DbState>,
+    note_id: String,
+    actions: Vec<NoteAction>,
+) -> Result<Vec<NoteSuggestion>, String> {
+    let queued = {
+        let conn = db_state.0.lock().unwrap();
+        note_ai_service::request(&conn, &note_id, &actions)?
+    };
+    // A run already in progress picks the new suggestions up before it finishes
+    if let Some(scheduler) = app.try_state::<AppScheduler>() {
+        let _ = scheduler.trigger("note_actions");
+    }
+    Ok(queued)
+}
+
+/// Suggestions for a note that are pending or waiting for review
+#[tauri::command]
+pub async fn list_note_suggestions(
+    db_state: State<'_, DbState>,
+    note_id: String,
+) -> Result<Vec<NoteSuggestion>, String> {
+    let conn = db_state.0.lock().unwrap();
+    note_ai_service::list_for_note(&conn, &note_id).map_err(|e| e.to_string())
+}
+
+/// Apply a reviewed suggestion; `values` replaces the generated output when the user edited it
+#[tauri::command]
+pub async fn apply_note_suggestion(
+    db_state: State<'_, DbState>,
+    id: String,
+    values: Option<Vec<String>>,
+) -> Result<NoteSuggestion, String> {
+    let conn = db_state.0.lock().unwrap();
+    note_ai_service::apply(&conn, &id, values)
+}
+
+#[tauri::command]
+pub async fn dismiss_note_suggestion(db_state: State<'_, DbState>, id: String) -> Result<bool, String> {
+    let conn = db_state.0.lock().unwrap();
+    note_ai_service::dismiss(&conn, &id).map_err(|e| e.to_string())
+}
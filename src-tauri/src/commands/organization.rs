@@ -76,3 +76,25 @@ pub async fn unlink_tag_from_note(
     organization_service::unlink_tag_from_note(&conn, &note_id, &tag_id).map_err(|e| e.to_string())
 }
 
+
+#[tauri::command]
+pub async fn rename_tag(
+    state: State<'_, db_service::DbState>,
+    tag_id: String,
+    new_name: String,
+) -> Result<Tag, String> {
+    let conn = state.0.lock().unwrap();
+    organization_service::rename_tag(&conn, &tag_id, new_name.trim())
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Tag {} not found", tag_id))
+}
+
+#[tauri::command]
+pub async fn merge_tags(
+    state: State<'_, db_service::DbState>,
+    source_ids: Vec<String>,
+    target_id: String,
+) -> Result<usize, String> {
+    let conn = state.0.lock().unwrap();
+    organization_service::merge_tags(&conn, &source_ids, &target_id).map_err(|e| e.to_string())
+}
@@ -1,19 +1,15 @@
-use crate::services::search_service::{get_related_notes, SearchResult};
-use rusqlite::Connection;
+use crate::services::db_service::DbState;
+use crate::services::search_service::{self, RelatedNote};
+use tauri::State;
 
+/// Notes related to the current one by embedding similarity, shared tags and links
 #[tauri::command]
 pub async fn get_related_notes(
-    state: tauri::State<'_, DbState>,
-    note_content: String,
+    state: State<'_, DbState>,
     current_note_id: String,
     limit: usize,
-) -> Result<Vec<SearchResult>, String> {
-    // Get database connection from state
-    let conn = state.db.lock().unwrap();
-
-    // Call service function with current note ID to exclude it from results
-    match get_related_notes(&conn, note_content, current_note_id, limit) {
-        Ok(notes) => Ok(notes),
-        Err(e) => Err(format!("Failed to get related notes: {}", e)),
-    }
+) -> Result<Vec<RelatedNote>, String> {
+    let conn = state.0.lock().unwrap();
+    search_service::get_related_notes(&conn, &current_note_id, limit)
+        .map_err(|e| format!("Failed to get related notes: {}", e))
 }
@@ -0,0 +1,39 @@
+use tauri::State;
+use crate::services::{db_service, role_service};
+use crate::services::role_service::Role;
+
+#[tauri::command]
+pub async fn list_roles(
+    state: State<'_, db_service::DbState>,
+) -> Result<Vec<Role>, String> {
+    let conn = state.0.lock().unwrap();
+    role_service::list_roles(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_role(
+    state: State<'_, db_service::DbState>,
+    name: String,
+) -> Result<Option<Role>, String> {
+    let conn = state.0.lock().unwrap();
+    role_service::get_role(&conn, &name).map_err(|e| e.to_string())
+}
+
+/// Create a role, or update it if one with the same name exists
+#[tauri::command]
+pub async fn save_role(
+    state: State<'_, db_service::DbState>,
+    role: Role,
+) -> Result<Role, String> {
+    let conn = state.0.lock().unwrap();
+    role_service::save_role(&conn, &role)
+}
+
+#[tauri::command]
+pub async fn delete_role(
+    state: State<'_, db_service::DbState>,
+    name: String,
+) -> Result<bool, String> {
+    let conn = state.0.lock().unwrap();
+    role_service::delete_role(&conn, &name).map_err(|e| e.to_string())
+}
@@ -0,0 +1,61 @@
+use tauri::State;
+use crate::services::{db_service, saved_search_service};
+use crate::services::saved_search_service::{SavedSearch, SavedSearchInput, SmartFolder};
+use crate::services::search_service::SearchResultWithMetadata;
+
+#[tauri::command]
+pub async fn list_saved_searches(
+    state: State<'_, db_service::DbState>,
+) -> Result<Vec<SavedSearch>, String> {
+    let conn = state.0.lock().unwrap();
+    saved_search_service::list_saved_searches(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn create_saved_search(
+    state: State<'_, db_service::DbState>,
+    search: SavedSearchInput,
+) -> Result<SavedSearch, String> {
+    let conn = state.0.lock().unwrap();
+    saved_search_service::create_saved_search(&conn, &search)
+}
+
+#[tauri::command]
+pub async fn update_saved_search(
+    state: State<'_, db_service::DbState>,
+    id: String,
+    search: SavedSearchInput,
+) -> Result<SavedSearch, String> {
+    let conn = state.0.lock().unwrap();
+    saved_search_service::update_saved_search(&conn, &id, &search)
+}
+
+#[tauri::command]
+pub async fn delete_saved_search(
+    state: State<'_, db_service::DbState>,
+    id: String,
+) -> Result<bool, String> {
+    let conn = state.0.lock().unwrap();
+    saved_search_service::delete_saved_search(&conn, &id).map_err(|e| e.to_string())
+}
+
+/// Evaluate a saved search now and return one page of matches
+#[tauri::command]
+pub async fn run_saved_search(
+    state: State<'_, db_service::DbState>,
+    id: String,
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> Result<SearchResultWithMetadata, String> {
+    let conn = state.0.lock().unwrap();
+    saved_search_service::run_saved_search(&conn, &id, limit, offset.unwrap_or(0))
+}
+
+/// Pinned saved searches for the folder tree, with live counts
+#[tauri::command]
+pub async fn get_smart_folders(
+    state: State<'_, db_service::DbState>,
+) -> Result<Vec<SmartFolder>, String> {
+    let conn = state.0.lock().unwrap();
+    saved_search_service::get_smart_folders(&conn)
+}
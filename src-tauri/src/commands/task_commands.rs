@@ -0,0 +1,24 @@
+use tauri::State;
+use crate::services::db_service::DbState;
+use crate::services::task_service::{self, Task};
+
+/// Tasks of one note, or every task when `note_id` is not given
+#[tauri::command]
+pub async fn list_tasks(db_state: State<'_, DbState>, note_id: Option<String>) -> Result<Vec<Task>, String> {
+    let conn = db_state.0.lock().unwrap();
+    task_service::list_tasks(&conn, note_id.as_deref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_task_done(db_state: State<'_, DbState>, id: String, done: bool) -> Result<Task, String> {
+    let conn = db_state.0.lock().unwrap();
+    task_service::set_done(&conn, &id, done)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Task {} not found", id))
+}
+
+#[tauri::command]
+pub async fn delete_task(db_state: State<'_, DbState>, id: String) -> Result<bool, String> {
+    let conn = db_state.0.lock().unwrap();
+    task_service::delete_task(&conn, &id).map_err(|e| e.to_string())
+}
@@ -0,0 +1,22 @@
+use tauri::State;
+use crate::services::db_service::DbState;
+use crate::services::trash_service::{self, TrashedNote};
+
+#[tauri::command]
+pub async fn list_trash(db_state: State<'_, DbState>) -> Result<Vec<TrashedNote>, String> {
+    let conn = db_state.0.lock().unwrap();
+    trash_service::list_trash(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn restore_note(db_state: State<'_, DbState>, id: String) -> Result<(), String> {
+    let conn = db_state.0.lock().unwrap();
+    trash_service::restore_note(&conn, &id)
+}
+
+/// Permanently delete trashed notes; returns how many were removed
+#[tauri::command]
+pub async fn empty_trash(db_state: State<'_, DbState>) -> Result<usize, String> {
+    let conn = db_state.0.lock().unwrap();
+    trash_service::empty_trash(&conn).map_err(|e| e.to_string())
+}
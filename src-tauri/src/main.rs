@@ -1,5 +1,6 @@
 use knowledge_base_pro::services::db_service;
 use knowledge_base_pro::services::local_llm::LocalLLMState;
+use knowledge_base_pro::services::embedding_model::EmbeddingState;
 use knowledge_base_pro::services::passphrase_service::PassphraseState;
 use knowledge_base_pro::commands::ai;
 use knowledge_base_pro::commands::data_settings;
@@ -15,6 +16,9 @@ fn main() {
     
     // Initialize Local LLM State (Lazy loading)
     let llm_state = LocalLLMState::new();
+
+    // Sentence-embedding model for semantic search (loaded on first use)
+    let embedding_state = EmbeddingState::new();
     
     // Initialize Passphrase State for encryption
     let passphrase_state = PassphraseState::new();
@@ -43,12 +47,19 @@ fn main() {
         .manage(db_service::DbState(Mutex::new(conn)))
         .manage(llm_state) // Manage the LLM State
         .manage(passphrase_state) // Manage encryption state
+        .manage(embedding_state)
          .invoke_handler(tauri::generate_handler![
             ai::synthesize_query,
+            ai::cancel_generation,
             ai::get_model_status,
             ai::delete_model,
+            ai::download_model,
+            ai::get_llm_settings,
+            ai::save_llm_settings,
             knowledge_base_pro::commands::cards::create_card,
             knowledge_base_pro::commands::cards::search_cards,
+            knowledge_base_pro::commands::cards::export_anki_deck,
+            knowledge_base_pro::commands::cards::import_anki_deck,
             knowledge_base_pro::commands::data::get_notes,
             knowledge_base_pro::commands::data::get_note,
             knowledge_base_pro::commands::data::create_note,
@@ -57,8 +68,13 @@ fn main() {
             knowledge_base_pro::commands::organization::get_folders,
             knowledge_base_pro::commands::data::import_files,
             knowledge_base_pro::commands::data::export_notes,
+            knowledge_base_pro::commands::data::publish_site,
+            knowledge_base_pro::commands::data::export_vault,
+            knowledge_base_pro::commands::data::import_vault,
             knowledge_base_pro::commands::data::create_backup,
             knowledge_base_pro::commands::data::search_notes,
+            knowledge_base_pro::commands::data::rebuild_search_index,
+            knowledge_base_pro::commands::data::optimize_search_index,
             knowledge_base_pro::commands::organization::create_folder,
              knowledge_base_pro::commands::organization::get_folders,
              knowledge_base_pro::commands::organization::get_tags,
@@ -67,6 +83,8 @@ fn main() {
             knowledge_base_pro::commands::organization::link_tag_to_note,
             knowledge_base_pro::commands::organization::get_note_tags,
             knowledge_base_pro::commands::organization::unlink_tag_from_note,
+            knowledge_base_pro::commands::organization::rename_tag,
+            knowledge_base_pro::commands::organization::merge_tags,
             knowledge_base_pro::commands::related_notes::get_related_notes,
             knowledge_base_pro::commands::data_settings::get_metadata,
             knowledge_base_pro::commands::data_settings::set_metadata,
@@ -93,6 +111,67 @@ fn main() {
             dashboard_commands::get_all_dashboard_layouts,
             // Search commands
             knowledge_base_pro::commands::search_commands::search_with_role,
+            // Role commands
+            knowledge_base_pro::commands::role_commands::list_roles,
+            knowledge_base_pro::commands::role_commands::get_role,
+            knowledge_base_pro::commands::role_commands::save_role,
+            knowledge_base_pro::commands::role_commands::delete_role,
+            // Saved search commands
+            knowledge_base_pro::commands::saved_search_commands::list_saved_searches,
+            knowledge_base_pro::commands::saved_search_commands::create_saved_search,
+            knowledge_base_pro::commands::saved_search_commands::update_saved_search,
+            knowledge_base_pro::commands::saved_search_commands::delete_saved_search,
+            knowledge_base_pro::commands::saved_search_commands::run_saved_search,
+            knowledge_base_pro::commands::saved_search_commands::get_smart_folders,
+            // Find and replace commands
+            knowledge_base_pro::commands::find_replace_commands::find_replace,
+            knowledge_base_pro::commands::find_replace_commands::apply_find_replace,
+            knowledge_base_pro::commands::find_replace_commands::list_revision_batches,
+            knowledge_base_pro::commands::find_replace_commands::undo_revision_batch,
+            // Embedding commands
+            knowledge_base_pro::commands::embedding_commands::semantic_search,
+            knowledge_base_pro::commands::embedding_commands::get_embedding_status,
+            // Duplicate commands
+            knowledge_base_pro::commands::duplicate_commands::find_duplicates,
+            knowledge_base_pro::commands::duplicate_commands::merge_notes,
+            // Trash commands
+            knowledge_base_pro::commands::trash_commands::list_trash,
+            knowledge_base_pro::commands::trash_commands::restore_note,
+            knowledge_base_pro::commands::trash_commands::empty_trash,
+            // Insight commands
+            knowledge_base_pro::commands::insight_commands::list_insights,
+            knowledge_base_pro::commands::insight_commands::accept_insight,
+            knowledge_base_pro::commands::insight_commands::dismiss_insight,
+            knowledge_base_pro::commands::insight_commands::snooze_insight,
+            // Background job commands
+            knowledge_base_pro::commands::job_commands::list_jobs,
+            knowledge_base_pro::commands::job_commands::trigger_job,
+            knowledge_base_pro::commands::job_commands::cancel_job,
+            knowledge_base_pro::commands::job_commands::update_job,
+            knowledge_base_pro::commands::job_commands::list_job_runs,
+            // Model registry commands
+            knowledge_base_pro::commands::model_commands::list_models,
+            knowledge_base_pro::commands::model_commands::add_local_model,
+            knowledge_base_pro::commands::model_commands::remove_model,
+            knowledge_base_pro::commands::model_commands::get_task_models,
+            knowledge_base_pro::commands::model_commands::set_task_model,
+            // Chat session commands
+            knowledge_base_pro::commands::chat_commands::create_chat_session,
+            knowledge_base_pro::commands::chat_commands::list_chat_sessions,
+            knowledge_base_pro::commands::chat_commands::get_chat_messages,
+            knowledge_base_pro::commands::chat_commands::update_chat_session,
+            knowledge_base_pro::commands::chat_commands::delete_chat_session,
+            knowledge_base_pro::commands::chat_commands::send_chat_message,
+            knowledge_base_pro::commands::chat_commands::save_chat_answer_as_note,
+            // Note AI action commands
+            knowledge_base_pro::commands::note_ai_commands::request_note_actions,
+            knowledge_base_pro::commands::note_ai_commands::list_note_suggestions,
+            knowledge_base_pro::commands::note_ai_commands::apply_note_suggestion,
+            knowledge_base_pro::commands::note_ai_commands::dismiss_note_suggestion,
+            // Task commands
+            knowledge_base_pro::commands::task_commands::list_tasks,
+            knowledge_base_pro::commands::task_commands::set_task_done,
+            knowledge_base_pro::commands::task_commands::delete_task,
             // Graph commands
             graph_commands::get_graph,
             graph_commands::get_node_neighbors,
@@ -120,7 +199,7 @@ fn main() {
                 })
                 .expect("Failed to register Alt+Space global shortcut");
                 
-            // Initialize Background Worker (The Subconscious)
+            // Start the background job scheduler (The Subconscious)
             knowledge_base_pro::services::background::init(app.handle());
 
             // Initialize Web Bridge (API Server)
@@ -0,0 +1,917 @@
+//! Anki `.apkg` deck export and import
+//!
+//! An `.apkg` is a zip archive holding a SQLite collection (`collection.anki2`),
+//! a `media` JSON map (`{"0": "image.png"}`) and the media files named by index.
+//! Cards map to Anki's "Basic" note type: card `content` is the Front field,
+//! `metadata.back` the Back field and `metadata.tags` the note tags. Scheduler
+//! state (`card_schedule`) and review history (`card_reviews`) travel both ways.
+
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use regex::Regex;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use zip::write::FileOptions;
+
+use crate::services::cards;
+
+const FIELD_SEPARATOR: char = '\u{1f}';
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+const SECONDS_PER_DAY: i64 = 86_400;
+
+#[derive(Debug, Serialize)]
+pub struct AnkiExportReport {
+    pub path: String,
+    pub cards: usize,
+    pub reviews: usize,
+    pub media_files: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnkiImportReport {
+    pub cards_imported: usize,
+    pub cards_skipped: usize,
+    pub reviews_imported: usize,
+    pub media_files: usize,
+}
+
+struct ExportCard {
+    id: i64,
+    content: String,
+    metadata: Value,
+}
+
+struct Schedule {
+    state: String,
+    due_at: Option<String>,
+    interval_days: i64,
+    ease_factor: f64,
+    reps: i64,
+    lapses: i64,
+}
+
+/// Export cards (all of them, or the given ids) to an Anki `.apkg` file
+pub fn export_apkg(
+    conn: &Connection,
+    path: &Path,
+    deck_name: &str,
+    card_ids: Option<&[i64]>,
+) -> Result<AnkiExportReport, String> {
+    let mut stmt = conn
+        .prepare("SELECT id, content, metadata FROM cards ORDER BY id")
+        .map_err(|e| e.to_string())?;
+    let wanted: Option<HashSet<i64>> = card_ids.map(|ids| ids.iter().copied().collect());
+    let cards: Vec<ExportCard> = stmt
+        .query_map([], |row| {
+            let metadata: Option<String> = row.get(2)?;
+            Ok(ExportCard {
+                id: row.get(0)?,
+                content: row.get(1)?,
+                metadata: metadata
+                    .and_then(|m| serde_json::from_str(&m).ok())
+                    .unwrap_or_else(|| json!({})),
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .filter_map(Result::ok)
+        .filter(|c| wanted.as_ref().is_none_or(|w| w.contains(&c.id)))
+        .collect();
+
+    let temp_dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+    let collection_path = temp_dir.path().join("collection.anki2");
+    let anki = Connection::open(&collection_path).map_err(|e| e.to_string())?;
+    anki.execute_batch(ANKI_SCHEMA).map_err(|e| e.to_string())?;
+
+    let now = Utc::now();
+    let now_secs = now.timestamp();
+    let base_id = now.timestamp_millis();
+    let model_id = base_id;
+    let deck_id = base_id + 1;
+
+    // Review due dates are stored as days since the collection was created
+    let schedules = load_schedules(conn)?;
+    let earliest_due = schedules
+        .values()
+        .filter(|s| s.state == "review")
+        .filter_map(|s| s.due_at.as_deref().and_then(parse_timestamp))
+        .min()
+        .unwrap_or(now_secs);
+    let crt = day_start(earliest_due.min(now_secs));
+
+    anki.execute(
+        "INSERT INTO col (id, crt, mod, scm, ver, dty, usn, ls, conf, models, decks, dconf, tags)
+         VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')",
+        params![
+            crt,
+            now.timestamp_millis(),
+            collection_conf(model_id, deck_id).to_string(),
+            basic_model(model_id, deck_id, now_secs).to_string(),
+            decks(deck_id, deck_name, now_secs).to_string(),
+            deck_conf().to_string(),
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+
+    let mut media_map = serde_json::Map::new();
+    let mut media_files: Vec<(String, PathBuf)> = Vec::new();
+    let mut card_ids_by_ours: HashMap<i64, i64> = HashMap::new();
+
+    for (i, card) in cards.iter().enumerate() {
+        let note_id = base_id + 10 + i as i64;
+        let anki_card_id = note_id + cards.len() as i64 + 10;
+        card_ids_by_ours.insert(card.id, anki_card_id);
+
+        let back = card.metadata.get("back").and_then(Value::as_str).unwrap_or("");
+        let tags: Vec<String> = card
+            .metadata
+            .get("tags")
+            .and_then(Value::as_array)
+            .map(|tags| {
+                tags.iter()
+                    .filter_map(Value::as_str)
+                    .map(|t| t.replace(char::is_whitespace, "_"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let guid = card
+            .metadata
+            .get("anki_guid")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("kbp{}", card.id));
+
+        for media_path in card.metadata.get("media").and_then(Value::as_array).into_iter().flatten() {
+            if let Some(p) = media_path.as_str().map(PathBuf::from).filter(|p| p.is_file()) {
+                let name = p.file_name().unwrap().to_string_lossy().to_string();
+                media_map.insert(media_files.len().to_string(), Value::String(name.clone()));
+                media_files.push((name, p));
+            }
+        }
+
+        let sort_field = strip_html(&card.content);
+        anki.execute(
+            "INSERT INTO notes (id, guid, mid, mod, usn, tags, flds, sfld, csum, flags, data)
+             VALUES (?1, ?2, ?3, ?4, -1, ?5, ?6, ?7, ?8, 0, '')",
+            params![
+                note_id,
+                guid,
+                model_id,
+                now_secs,
+                if tags.is_empty() { String::new() } else { format!(" {} ", tags.join(" ")) },
+                format!("{}{}{}", card.content, FIELD_SEPARATOR, back),
+                sort_field,
+                field_checksum(&sort_field),
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+
+        let (card_type, queue, due, ivl, factor, reps, lapses) = match schedules.get(&card.id) {
+            Some(s) => {
+                let due_secs = s.due_at.as_deref().and_then(parse_timestamp);
+                let (card_type, due) = match s.state.as_str() {
+                    "learning" => (1, due_secs.unwrap_or(now_secs)),
+                    "relearning" => (3, due_secs.unwrap_or(now_secs)),
+                    "review" => (2, (due_secs.unwrap_or(now_secs) - crt) / SECONDS_PER_DAY),
+                    _ => (0, i as i64),
+                };
+                let queue = if card_type == 3 { 1 } else { card_type };
+                (card_type, queue, due, s.interval_days, (s.ease_factor * 1000.0) as i64, s.reps, s.lapses)
+            }
+            None => (0, 0, i as i64, 0, 0, 0, 0),
+        };
+
+        anki.execute(
+            "INSERT INTO cards (id, nid, did, ord, mod, usn, type, queue, due, ivl, factor, reps, lapses, left, odue, odid, flags, data)
+             VALUES (?1, ?2, ?3, 0, ?4, -1, ?5, ?6, ?7, ?8, ?9, ?10, ?11, 0, 0, 0, 0, '')",
+            params![anki_card_id, note_id, deck_id, now_secs, card_type, queue, due, ivl, factor, reps, lapses],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    // Review history; revlog ids are millisecond timestamps and must be unique
+    let mut reviews = 0;
+    let mut used_revlog_ids = HashSet::new();
+    let mut stmt = conn
+        .prepare(
+            "SELECT card_id, reviewed_at, rating, interval_days, last_interval_days, ease_factor, time_ms, review_type
+             FROM card_reviews ORDER BY reviewed_at",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, f64>(5)?,
+                row.get::<_, i64>(6)?,
+                row.get::<_, String>(7)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    for row in rows {
+        let (card_id, reviewed_at, rating, ivl, last_ivl, ease, time_ms, review_type) = row.map_err(|e| e.to_string())?;
+        let Some(&anki_card_id) = card_ids_by_ours.get(&card_id) else { continue };
+        let mut revlog_id = parse_timestamp(&reviewed_at).unwrap_or(now_secs) * 1000;
+        while !used_revlog_ids.insert(revlog_id) {
+            revlog_id += 1;
+        }
+        anki.execute(
+            "INSERT INTO revlog (id, cid, usn, ease, ivl, lastIvl, factor, time, type) VALUES (?1, ?2, -1, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                revlog_id,
+                anki_card_id,
+                rating,
+                ivl,
+                last_ivl,
+                (ease * 1000.0) as i64,
+                time_ms,
+                review_type_to_anki(&review_type),
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        reviews += 1;
+    }
+    drop(anki);
+
+    // Package the archive
+    let file = fs::File::create(path).map_err(|e| format!("Failed to create {:?}: {}", path, e))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    zip.start_file("collection.anki2", options).map_err(|e| e.to_string())?;
+    zip.write_all(&fs::read(&collection_path).map_err(|e| e.to_string())?)
+        .map_err(|e| e.to_string())?;
+
+    zip.start_file("media", options).map_err(|e| e.to_string())?;
+    zip.write_all(Value::Object(media_map).to_string().as_bytes())
+        .map_err(|e| e.to_string())?;
+
+    for (index, (_, media_path)) in media_files.iter().enumerate() {
+        zip.start_file(index.to_string(), options).map_err(|e| e.to_string())?;
+        zip.write_all(&fs::read(media_path).map_err(|e| e.to_string())?)
+            .map_err(|e| e.to_string())?;
+    }
+    zip.finish().map_err(|e| e.to_string())?;
+
+    Ok(AnkiExportReport {
+        path: path.to_string_lossy().to_string(),
+        cards: cards.len(),
+        reviews,
+        media_files: media_files.len(),
+    })
+}
+
+/// Import an Anki `.apkg` file as flashcards, including scheduling state and review history
+///
+/// Media files are extracted into `media_dir` once the import has succeeded; a file
+/// whose name is taken by different content gets a numbered name instead of
+/// replacing it. Notes that were already imported (same Anki guid) are skipped, so
+/// re-importing a deck is safe.
+pub fn import_apkg(conn: &Connection, path: &Path, media_dir: &Path) -> Result<AnkiImportReport, String> {
+    let file = fs::File::open(path).map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| format!("Not a valid .apkg file: {}", e))?;
+
+    let collection_name = ["collection.anki21", "collection.anki2"]
+        .into_iter()
+        .find(|name| archive.by_name(name).is_ok())
+        .ok_or_else(|| {
+            if archive.by_name("collection.anki21b").is_ok() {
+                "This deck uses the compressed Anki 2.1.50+ format; export it with \"Support older Anki versions\" enabled".to_string()
+            } else {
+                "The archive does not contain an Anki collection".to_string()
+            }
+        })?;
+
+    let temp_dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+    let collection_path = temp_dir.path().join("collection.anki2");
+    {
+        let mut entry = archive.by_name(collection_name).map_err(|e| e.to_string())?;
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+        fs::write(&collection_path, bytes).map_err(|e| e.to_string())?;
+    }
+
+    // Media: index -> file name
+    let media_map: HashMap<String, String> = match archive.by_name("media") {
+        Ok(mut entry) => {
+            let mut text = String::new();
+            entry.read_to_string(&mut text).map_err(|e| e.to_string())?;
+            serde_json::from_str(&text).unwrap_or_default()
+        }
+        Err(_) => HashMap::new(),
+    };
+    let mut media_paths: HashMap<String, PathBuf> = HashMap::new();
+    let mut new_media: Vec<(PathBuf, Vec<u8>)> = Vec::new();
+    for (index, name) in &media_map {
+        let Ok(mut entry) = archive.by_name(index) else { continue };
+        // Only keep the file name so a crafted archive cannot write outside media_dir
+        let Some(file_name) = Path::new(name).file_name().and_then(|f| f.to_str()) else { continue };
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+        let target = media_target(media_dir, file_name, &bytes, &new_media);
+        if !target.exists() && !new_media.iter().any(|(path, _)| path == &target) {
+            new_media.push((target.clone(), bytes));
+        }
+        media_paths.insert(name.clone(), target);
+    }
+
+    let anki = Connection::open(&collection_path).map_err(|e| e.to_string())?;
+    let (crt, models_json): (i64, String) = anki
+        .query_row("SELECT crt, models FROM col", [], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| format!("Invalid Anki collection: {}", e))?;
+    let models: Value = serde_json::from_str(&models_json).unwrap_or_else(|_| json!({}));
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let mut report = AnkiImportReport {
+        cards_imported: 0,
+        cards_skipped: 0,
+        reviews_imported: 0,
+        media_files: media_paths.len(),
+    };
+
+    // Notes become cards
+    let mut our_card_by_note: HashMap<i64, i64> = HashMap::new();
+    {
+        let mut stmt = anki
+            .prepare("SELECT id, guid, mid, tags, flds FROM notes ORDER BY id")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+
+        for row in rows {
+            let (note_id, guid, model_id, tags, fields) = row.map_err(|e| e.to_string())?;
+
+            let existing: Option<i64> = tx
+                .query_row(
+                    "SELECT id FROM cards WHERE json_extract(metadata, '$.anki_guid') = ?1",
+                    params![guid],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(|e| e.to_string())?;
+            if existing.is_some() {
+                report.cards_skipped += 1;
+                continue;
+            }
+
+            // Media saved under a new name is referred to by that name
+            let mut media: Vec<String> = Vec::new();
+            let values: Vec<String> = fields
+                .split(FIELD_SEPARATOR)
+                .map(|v| rewrite_media_refs(v, &media_paths, &mut media))
+                .collect();
+            let names = field_names(&models, model_id);
+            let named_fields: serde_json::Map<String, Value> = values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let name = names.get(i).cloned().unwrap_or_else(|| format!("Field {}", i + 1));
+                    (name, Value::String(v.clone()))
+                })
+                .collect();
+            let tags: Vec<&str> = tags.split_whitespace().collect();
+
+            let metadata = json!({
+                "back": values.get(1).map(String::as_str).unwrap_or(""),
+                "tags": tags,
+                "fields": named_fields,
+                "media": media,
+                "anki_guid": guid,
+                "source": "anki",
+            });
+
+            let card_id = cards::create_card(
+                &tx,
+                "flashcard",
+                values.first().map(String::as_str).unwrap_or(""),
+                &metadata.to_string(),
+                "general",
+            )
+            .map_err(|e| e.to_string())?;
+            our_card_by_note.insert(note_id, card_id);
+            report.cards_imported += 1;
+        }
+    }
+
+    // Scheduler state: the first card (template) of each note
+    let mut our_card_by_anki_card: HashMap<i64, i64> = HashMap::new();
+    let mut scheduled: HashSet<i64> = HashSet::new();
+    {
+        let mut stmt = anki
+            .prepare("SELECT id, nid, type, due, ivl, factor, reps, lapses FROM cards ORDER BY nid, ord")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, i64>(4)?,
+                    row.get::<_, i64>(5)?,
+                    row.get::<_, i64>(6)?,
+                    row.get::<_, i64>(7)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+
+        for row in rows {
+            let (anki_card_id, note_id, card_type, due, ivl, factor, reps, lapses) = row.map_err(|e| e.to_string())?;
+            let Some(&card_id) = our_card_by_note.get(&note_id) else { continue };
+            our_card_by_anki_card.insert(anki_card_id, card_id);
+            if !scheduled.insert(card_id) {
+                continue;
+            }
+
+            let (state, due_at) = match card_type {
+                1 => ("learning", Some(format_timestamp(due))),
+                2 => ("review", Some(format_timestamp(crt + due * SECONDS_PER_DAY))),
+                3 => ("relearning", Some(format_timestamp(due))),
+                _ => ("new", None),
+            };
+            let ease = if factor > 0 { factor as f64 / 1000.0 } else { 2.5 };
+
+            tx.execute(
+                "INSERT OR REPLACE INTO card_schedule (card_id, state, due_at, interval_days, ease_factor, reps, lapses)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![card_id, state, due_at, ivl.max(0), ease, reps, lapses],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+
+    // Review history
+    {
+        let mut stmt = anki
+            .prepare("SELECT id, cid, ease, ivl, lastIvl, factor, time, type FROM revlog ORDER BY id")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, i64>(4)?,
+                    row.get::<_, i64>(5)?,
+                    row.get::<_, i64>(6)?,
+                    row.get::<_, i64>(7)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+
+        for row in rows {
+            let (revlog_id, anki_card_id, ease, ivl, last_ivl, factor, time_ms, review_type) = row.map_err(|e| e.to_string())?;
+            let Some(&card_id) = our_card_by_anki_card.get(&anki_card_id) else { continue };
+
+            // Negative intervals are learning steps in seconds
+            tx.execute(
+                "INSERT INTO card_reviews (card_id, reviewed_at, rating, interval_days, last_interval_days, ease_factor, time_ms, review_type)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    card_id,
+                    format_timestamp(revlog_id / 1000),
+                    ease,
+                    ivl.max(0),
+                    last_ivl.max(0),
+                    if factor > 0 { factor as f64 / 1000.0 } else { 2.5 },
+                    time_ms,
+                    review_type_from_anki(review_type),
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+            report.reviews_imported += 1;
+        }
+    }
+
+    write_media(media_dir, &new_media)?;
+    if let Err(e) = tx.commit() {
+        remove_media(&new_media);
+        return Err(e.to_string());
+    }
+    Ok(report)
+}
+
+/// Where to store `file_name` from a deck: its own name, unless a different file
+/// already has it, in which case `name-2.ext`, `name-3.ext`, ...
+fn media_target(media_dir: &Path, file_name: &str, bytes: &[u8], pending: &[(PathBuf, Vec<u8>)]) -> PathBuf {
+    let name = Path::new(file_name);
+    let stem = name.file_stem().and_then(|s| s.to_str()).unwrap_or(file_name);
+    let extension = name.extension().and_then(|e| e.to_str());
+    let mut n = 1;
+    loop {
+        let candidate = match (n, extension) {
+            (1, _) => media_dir.join(file_name),
+            (_, Some(ext)) => media_dir.join(format!("{}-{}.{}", stem, n, ext)),
+            (_, None) => media_dir.join(format!("{}-{}", stem, n)),
+        };
+        let same_content = match pending.iter().find(|(path, _)| path == &candidate) {
+            Some((_, pending_bytes)) => Some(pending_bytes.as_slice() == bytes),
+            None if candidate.exists() => Some(fs::read(&candidate).map(|b| b == bytes).unwrap_or(false)),
+            None => None,
+        };
+        match same_content {
+            None | Some(true) => return candidate,
+            Some(false) => n += 1,
+        }
+    }
+}
+
+/// Write the deck's new media files, removing them all again if one fails
+fn write_media(media_dir: &Path, files: &[(PathBuf, Vec<u8>)]) -> Result<(), String> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    fs::create_dir_all(media_dir).map_err(|e| e.to_string())?;
+    for (i, (path, bytes)) in files.iter().enumerate() {
+        if let Err(e) = fs::write(path, bytes) {
+            remove_media(&files[..=i]);
+            return Err(format!("Failed to write {:?}: {}", path, e));
+        }
+    }
+    Ok(())
+}
+
+fn remove_media(files: &[(PathBuf, Vec<u8>)]) {
+    for (path, _) in files {
+        let _ = fs::remove_file(path);
+    }
+}
+
+fn load_schedules(conn: &Connection) -> Result<HashMap<i64, Schedule>, String> {
+    let mut stmt = conn
+        .prepare("SELECT card_id, state, due_at, interval_days, ease_factor, reps, lapses FROM card_schedule")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                Schedule {
+                    state: row.get(1)?,
+                    due_at: row.get(2)?,
+                    interval_days: row.get(3)?,
+                    ease_factor: row.get(4)?,
+                    reps: row.get(5)?,
+                    lapses: row.get(6)?,
+                },
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<HashMap<_, _>, _>>().map_err(|e| e.to_string())
+}
+
+fn field_names(models: &Value, model_id: i64) -> Vec<String> {
+    models
+        .get(model_id.to_string())
+        .and_then(|m| m.get("flds"))
+        .and_then(Value::as_array)
+        .map(|fields| {
+            fields
+                .iter()
+                .filter_map(|f| f.get("name").and_then(Value::as_str).map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn strip_html(s: &str) -> String {
+    static TAG: OnceLock<Regex> = OnceLock::new();
+    let re = TAG.get_or_init(|| Regex::new(r"<[^>]*>").unwrap());
+    re.replace_all(s, "").trim().to_string()
+}
+
+/// `field` with each media reference (`src="name"` or `[sound:name]`) naming the file
+/// it was saved as; the saved paths it refers to are added to `used`
+fn rewrite_media_refs(field: &str, media_paths: &HashMap<String, PathBuf>, used: &mut Vec<String>) -> String {
+    static MEDIA_REF: OnceLock<Regex> = OnceLock::new();
+    let re = MEDIA_REF.get_or_init(|| {
+        Regex::new(r#"(?i)\bsrc\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))|\[sound:([^\]]+)\]"#).unwrap()
+    });
+    re.replace_all(field, |caps: &regex::Captures| {
+        let whole = caps.get(0).unwrap();
+        let name = (1..=4).find_map(|i| caps.get(i)).unwrap();
+        let Some(path) = media_paths.get(name.as_str()) else {
+            return whole.as_str().to_string();
+        };
+        let saved = path.to_string_lossy().to_string();
+        if !used.contains(&saved) {
+            used.push(saved);
+        }
+        let file_name = path.file_name().map(|f| f.to_string_lossy()).unwrap_or_default();
+        let mut reference = whole.as_str().to_string();
+        reference.replace_range(name.start() - whole.start()..name.end() - whole.start(), &file_name);
+        reference
+    })
+    .into_owned()
+}
+
+/// Anki's duplicate checksum: the first 32 bits of the SHA-1 of the sort field
+fn field_checksum(field: &str) -> i64 {
+    let digest = Sha1::digest(field.as_bytes());
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]) as i64
+}
+
+fn parse_timestamp(s: &str) -> Option<i64> {
+    NaiveDateTime::parse_from_str(s, DATE_FORMAT)
+        .ok()
+        .map(|dt| dt.and_utc().timestamp())
+}
+
+fn format_timestamp(secs: i64) -> String {
+    Utc.timestamp_opt(secs, 0)
+        .single()
+        .unwrap_or_else(Utc::now)
+        .format(DATE_FORMAT)
+        .to_string()
+}
+
+fn day_start(secs: i64) -> i64 {
+    secs - secs.rem_euclid(SECONDS_PER_DAY)
+}
+
+fn review_type_to_anki(review_type: &str) -> i64 {
+    match review_type {
+        "learn" => 0,
+        "relearn" => 2,
+        "cram" => 3,
+        "manual" => 4,
+        _ => 1,
+    }
+}
+
+fn review_type_from_anki(review_type: i64) -> &'static str {
+    match review_type {
+        0 => "learn",
+        2 => "relearn",
+        3 => "cram",
+        4 => "manual",
+        _ => "review",
+    }
+}
+
+fn collection_conf(model_id: i64, deck_id: i64) -> Value {
+    json!({
+        "nextPos": 1,
+        "estTimes": true,
+        "activeDecks": [deck_id],
+        "sortType": "noteFld",
+        "timeLim": 0,
+        "sortBackwards": false,
+        "addToCur": true,
+        "curDeck": deck_id,
+        "newSpread": 0,
+        "dueCounts": true,
+        "curModel": model_id.to_string(),
+        "collapseTime": 1200
+    })
+}
+
+fn basic_model(model_id: i64, deck_id: i64, now: i64) -> Value {
+    let field = |name: &str, ord: i64| {
+        json!({ "name": name, "ord": ord, "sticky": false, "rtl": false, "font": "Arial", "size": 20, "media": [] })
+    };
+    json!({
+        model_id.to_string(): {
+            "id": model_id,
+            "name": "Basic (Knowledge Base Pro)",
+            "type": 0,
+            "mod": now,
+            "usn": -1,
+            "sortf": 0,
+            "did": deck_id,
+            "tmpls": [{
+                "name": "Card 1",
+                "ord": 0,
+                "qfmt": "{{Front}}",
+                "afmt": "{{FrontSide}}<hr id=answer>{{Back}}",
+                "did": null,
+                "bqfmt": "",
+                "bafmt": ""
+            }],
+            "flds": [field("Front", 0), field("Back", 1)],
+            "css": ".card { font-family: arial; font-size: 20px; text-align: center; }",
+            "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\begin{document}\n",
+            "latexPost": "\\end{document}",
+            "tags": [],
+            "vers": [],
+            "req": [[0, "all", [0]]]
+        }
+    })
+}
+
+fn decks(deck_id: i64, deck_name: &str, now: i64) -> Value {
+    let deck = |id: i64, name: &str| {
+        json!({
+            "id": id,
+            "name": name,
+            "mod": now,
+            "usn": -1,
+            "desc": "",
+            "dyn": 0,
+            "conf": 1,
+            "collapsed": false,
+            "newToday": [0, 0],
+            "revToday": [0, 0],
+            "lrnToday": [0, 0],
+            "timeToday": [0, 0],
+            "extendNew": 10,
+            "extendRev": 50
+        })
+    };
+    json!({ "1": deck(1, "Default"), deck_id.to_string(): deck(deck_id, deck_name) })
+}
+
+fn deck_conf() -> Value {
+    json!({
+        "1": {
+            "id": 1,
+            "name": "Default",
+            "mod": 0,
+            "usn": 0,
+            "maxTaken": 60,
+            "autoplay": true,
+            "timer": 0,
+            "replayq": true,
+            "dyn": false,
+            "new": { "delays": [1, 10], "ints": [1, 4, 7], "initialFactor": 2500, "order": 1, "perDay": 20, "bury": true, "separate": true },
+            "rev": { "perDay": 200, "ease4": 1.3, "fuzz": 0.05, "maxIvl": 36500, "ivlFct": 1, "bury": true, "minSpace": 1 },
+            "lapse": { "delays": [10], "mult": 0, "minInt": 1, "leechFails": 8, "leechAction": 0 }
+        }
+    })
+}
+
+const ANKI_SCHEMA: &str = "
+CREATE TABLE col (
+    id integer primary key, crt integer not null, mod integer not null, scm integer not null,
+    ver integer not null, dty integer not null, usn integer not null, ls integer not null,
+    conf text not null, models text not null, decks text not null, dconf text not null, tags text not null
+);
+CREATE TABLE notes (
+    id integer primary key, guid text not null, mid integer not null, mod integer not null,
+    usn integer not null, tags text not null, flds text not null, sfld integer not null,
+    csum integer not null, flags integer not null, data text not null
+);
+CREATE TABLE cards (
+    id integer primary key, nid integer not null, did integer not null, ord integer not null,
+    mod integer not null, usn integer not null, type integer not null, queue integer not null,
+    due integer not null, ivl integer not null, factor integer not null, reps integer not null,
+    lapses integer not null, left integer not null, odue integer not null, odid integer not null,
+    flags integer not null, data text not null
+);
+CREATE TABLE revlog (
+    id integer primary key, cid integer not null, usn integer not null, ease integer not null,
+    ivl integer not null, lastIvl integer not null, factor integer not null, time integer not null,
+    type integer not null
+);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+CREATE INDEX ix_notes_usn on notes (usn);
+CREATE INDEX ix_cards_usn on cards (usn);
+CREATE INDEX ix_revlog_usn on revlog (usn);
+CREATE INDEX ix_cards_nid on cards (nid);
+CREATE INDEX ix_cards_sched on cards (did, queue, due);
+CREATE INDEX ix_revlog_cid on revlog (cid);
+CREATE INDEX ix_notes_csum on notes (csum);
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_apkg_round_trip_keeps_fields_schedule_and_history() {
+        let dir = tempdir().unwrap();
+        let source = Connection::open_in_memory().unwrap();
+        cards::create_tables(&source).unwrap();
+
+        let card_id = cards::create_card(
+            &source,
+            "flashcard",
+            "What is Rust?",
+            r#"{"back": "A systems language", "tags": ["lang", "rust"]}"#,
+            "learner",
+        )
+        .unwrap();
+        source
+            .execute(
+                "INSERT INTO card_schedule (card_id, state, due_at, interval_days, ease_factor, reps, lapses)
+                 VALUES (?1, 'review', '2030-01-10 00:00:00', 12, 2.6, 4, 1)",
+                params![card_id],
+            )
+            .unwrap();
+        source
+            .execute(
+                "INSERT INTO card_reviews (card_id, reviewed_at, rating, interval_days, ease_factor)
+                 VALUES (?1, '2029-12-29 10:00:00', 3, 12, 2.6)",
+                params![card_id],
+            )
+            .unwrap();
+
+        let apkg = dir.path().join("deck.apkg");
+        let export = export_apkg(&source, &apkg, "Rust", None).unwrap();
+        assert_eq!(export.cards, 1);
+        assert_eq!(export.reviews, 1);
+
+        let target = Connection::open_in_memory().unwrap();
+        cards::create_tables(&target).unwrap();
+        let report = import_apkg(&target, &apkg, &dir.path().join("media")).unwrap();
+        assert_eq!(report.cards_imported, 1);
+        assert_eq!(report.reviews_imported, 1);
+
+        let (content, metadata): (String, String) = target
+            .query_row("SELECT content, metadata FROM cards", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        let metadata: Value = serde_json::from_str(&metadata).unwrap();
+        assert_eq!(content, "What is Rust?");
+        assert_eq!(metadata["back"], "A systems language");
+        assert_eq!(metadata["tags"], json!(["lang", "rust"]));
+
+        let (state, due_at, interval): (String, String, i64) = target
+            .query_row("SELECT state, due_at, interval_days FROM card_schedule", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(state, "review");
+        assert_eq!(due_at, "2030-01-10 00:00:00");
+        assert_eq!(interval, 12);
+
+        // Importing the same deck again is a no-op
+        let again = import_apkg(&target, &apkg, &dir.path().join("media")).unwrap();
+        assert_eq!(again.cards_imported, 0);
+        assert_eq!(again.cards_skipped, 1);
+    }
+
+    #[test]
+    fn test_import_media_keeps_existing_files_and_cleans_up_on_failure() {
+        let dir = tempdir().unwrap();
+        let image = dir.path().join("diagram.png");
+        fs::write(&image, b"new deck").unwrap();
+
+        let source = Connection::open_in_memory().unwrap();
+        cards::create_tables(&source).unwrap();
+        let metadata = json!({
+            "back": "<img src=\"diagram.png\"> [sound:diagram.png] <img src=\"a.png\">",
+            "media": [image.to_string_lossy()],
+        });
+        cards::create_card(&source, "flashcard", "Diagram", &metadata.to_string(), "learner").unwrap();
+        let apkg = dir.path().join("deck.apkg");
+        export_apkg(&source, &apkg, "Media", None).unwrap();
+
+        // Another deck's file with the same name
+        let media_dir = dir.path().join("media");
+        fs::create_dir_all(&media_dir).unwrap();
+        fs::write(media_dir.join("diagram.png"), b"other deck").unwrap();
+
+        // A failed import leaves no files behind
+        let broken = Connection::open_in_memory().unwrap();
+        assert!(import_apkg(&broken, &apkg, &media_dir).is_err());
+        assert_eq!(fs::read_dir(&media_dir).unwrap().count(), 1);
+
+        let target = Connection::open_in_memory().unwrap();
+        cards::create_tables(&target).unwrap();
+        let report = import_apkg(&target, &apkg, &media_dir).unwrap();
+        assert_eq!(report.media_files, 1);
+        assert_eq!(fs::read(media_dir.join("diagram.png")).unwrap(), b"other deck");
+        assert_eq!(fs::read(media_dir.join("diagram-2.png")).unwrap(), b"new deck");
+
+        let metadata: String = target.query_row("SELECT metadata FROM cards", [], |row| row.get(0)).unwrap();
+        let metadata: Value = serde_json::from_str(&metadata).unwrap();
+        assert_eq!(metadata["media"], json!([media_dir.join("diagram-2.png").to_string_lossy()]));
+        // The card shows the file it brought, not the other deck's
+        assert_eq!(metadata["back"], "<img src=\"diagram-2.png\"> [sound:diagram-2.png] <img src=\"a.png\">");
+        assert_eq!(metadata["fields"]["Back"], metadata["back"]);
+    }
+
+    #[test]
+    fn test_media_refs_match_whole_names() {
+        let dir = tempdir().unwrap();
+        let media_paths: HashMap<String, PathBuf> =
+            [("a.png".to_string(), dir.path().join("a-2.png"))].into_iter().collect();
+        let mut used = Vec::new();
+        let field = rewrite_media_refs("<img src=\"data.png\"><img src='a.png'>", &media_paths, &mut used);
+        assert_eq!(field, "<img src=\"data.png\"><img src='a-2.png'>");
+        assert_eq!(used, vec![dir.path().join("a-2.png").to_string_lossy().to_string()]);
+
+        used.clear();
+        assert_eq!(rewrite_media_refs("[sound:data.png]", &media_paths, &mut used), "[sound:data.png]");
+        assert!(used.is_empty());
+    }
+}
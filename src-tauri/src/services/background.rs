@@ -1,26 +1,265 @@
-use std::thread;
-use std::time::Duration;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use chrono::Local;
 use tauri::{AppHandle, Manager};
 use crate::services::db_service::DbState;
+use crate::services::embedding_model::EmbeddingState;
+use crate::services::job_scheduler::{JobContext, JobDef, JobHost, Scheduler};
+use crate::services::llm_provider::ProviderKind;
+use crate::services::local_llm::LocalLLMState;
+use crate::services::passphrase_service::PassphraseState;
+use crate::services::{
+    backup_service, embedding_service, graph_analysis, link_service, note_ai_service, saved_search_service,
+    search_index, trash_service,
+};
 
+/// Notes embedded per run, so one run never holds up the others for long
+const EMBED_BATCH: usize = 32;
+const BACKUP_DIR: &str = "backups";
+/// Scheduled backups kept; older ones are deleted
+const BACKUPS_KEPT: usize = 7;
+/// Days a note stays in the trash before it is purged
+const TRASH_RETENTION_DAYS: i64 = 30;
+
+/// The app's scheduler, managed as Tauri state
+pub type AppScheduler = Arc<Scheduler<AppHandle>>;
+
+impl JobHost for AppHandle {
+    fn with_db<R>(&self, f: impl FnOnce(&rusqlite::Connection) -> R) -> Option<R> {
+        let state = self.state::<DbState>();
+        let conn = state.0.lock().ok()?;
+        Some(f(&conn))
+    }
+}
+
+fn jobs() -> Vec<JobDef<AppHandle>> {
+    vec![
+        JobDef {
+            name: "insights",
+            description: "Suggest links between related notes",
+            default_schedule: "every 1m",
+            skip_on_battery: false,
+            only_when_idle: false,
+            run: find_insights,
+        },
+        JobDef {
+            name: "saved_searches",
+            description: "Notify about new matches of saved searches",
+            default_schedule: "every 1m",
+            skip_on_battery: false,
+            only_when_idle: false,
+            run: check_saved_searches,
+        },
+        JobDef {
+            name: "embeddings",
+            description: "Embed new and changed notes for semantic search",
+            default_schedule: "every 30s",
+            skip_on_battery: true,
+            only_when_idle: false,
+            run: embed_changed_notes,
+        },
+        JobDef {
+            name: "backups",
+            description: "Back up the database",
+            default_schedule: "0 3 * * *",
+            skip_on_battery: true,
+            only_when_idle: false,
+            run: backup_database,
+        },
+        JobDef {
+            name: "index_optimize",
+            description: "Merge search index segments",
+            default_schedule: "0 4 * * 0",
+            skip_on_battery: true,
+            only_when_idle: true,
+            run: optimize_index,
+        },
+        JobDef {
+            name: "link_resolution",
+            description: "Resolve wiki-links to notes",
+            default_schedule: "every 5m",
+            skip_on_battery: false,
+            only_when_idle: false,
+            run: resolve_links,
+        },
+        JobDef {
+            name: "trash_purge",
+            description: "Delete notes trashed more than 30 days ago",
+            default_schedule: "0 5 * * *",
+            skip_on_battery: false,
+            only_when_idle: false,
+            run: purge_trash,
+        },
+        JobDef {
+            name: "note_actions",
+            description: "Generate requested note summaries, titles, tags and action items",
+            default_schedule: "every 30s",
+            skip_on_battery: false,
+            only_when_idle: false,
+            run: generate_note_suggestions,
+        },
+    ]
+}
+
+/// Register the background jobs ("the subconscious") and start the scheduler
 pub fn init(app: AppHandle) {
-    thread::spawn(move || {
-        loop {
-            // Sleep for 30 seconds (simulating "subconscious" processing cycle)
-            thread::sleep(Duration::from_secs(30));
-            
-            println!("[Subconscious] Waking up to analyze...");
-
-            // Access DB State safely
-            let state = app.state::<DbState>();
-            if let Ok(conn) = state.0.lock() {
-                use crate::services::graph_analysis;
-                
-                if let Some(insight) = graph_analysis::find_connections(&conn) {
-                    println!("[Subconscious] Insight found: {}", insight.description);
-                    app.emit_all("insight-found", insight.description).unwrap(); // Emitting simple string for now
+    match Scheduler::new(app.clone(), jobs()) {
+        Ok(scheduler) => {
+            scheduler.start();
+            app.manage(scheduler);
+        }
+        Err(e) => println!("[Scheduler] Failed to start background jobs: {}", e),
+    }
+}
+
+fn find_insights(app: &AppHandle, _ctx: &JobContext) -> Result<String, String> {
+    let insight = app.with_db(graph_analysis::find_connections).ok_or("Database is unavailable")?;
+    match insight {
+        Some(insight) => {
+            println!("[Subconscious] Insight found: {}", insight.description);
+            let message = insight.description.clone();
+            app.emit_all("insight-found", insight).map_err(|e| e.to_string())?;
+            Ok(message)
+        }
+        None => Ok("No new insights".to_string()),
+    }
+}
+
+fn check_saved_searches(app: &AppHandle, _ctx: &JobContext) -> Result<String, String> {
+    let matches = app
+        .with_db(saved_search_service::check_for_new_matches)
+        .ok_or("Database is unavailable")??;
+    let count = matches.len();
+    for m in matches {
+        println!("[Subconscious] {} new note(s) match \"{}\"", m.note_ids.len(), m.name);
+        app.emit_all("saved-search-match", m).map_err(|e| e.to_string())?;
+    }
+    Ok(format!("{} saved search(es) with new matches", count))
+}
+
+/// Embed new and changed notes; the DB lock is only held to read and write, not during inference
+fn embed_changed_notes(app: &AppHandle, ctx: &JobContext) -> Result<String, String> {
+    let embedder = app.state::<EmbeddingState>();
+    if !embedder.ensure_loaded().map_err(|e| format!("Failed to load embedding model: {}", e))? {
+        return Ok("No embedding model installed".to_string());
+    }
+    let model = embedder.model_name().unwrap_or_default();
+
+    let pending = app
+        .with_db(|conn| embedding_service::pending_notes(conn, &model, EMBED_BATCH))
+        .ok_or("Database is unavailable")?
+        .map_err(|e| format!("Failed to list notes to embed: {}", e))?;
+
+    let mut embedded = 0;
+    for note in &pending {
+        if ctx.is_cancelled() {
+            break;
+        }
+        let chunks: anyhow::Result<Vec<_>> = embedding_service::chunk_text(&note.content)
+            .into_iter()
+            .map(|chunk| {
+                let vector = embedder.embed(&embedding_service::chunk_input(&note.title, &chunk.text))?;
+                Ok((chunk, vector))
+            })
+            .collect();
+
+        match chunks {
+            Ok(chunks) => {
+                let stored = app.with_db(|conn| embedding_service::store_embeddings(conn, note, &model, &chunks));
+                match stored {
+                    Some(Ok(())) => embedded += 1,
+                    Some(Err(e)) => println!("[Subconscious] Failed to store embeddings for {}: {}", note.id, e),
+                    None => {}
                 }
             }
+            Err(e) => println!("[Subconscious] Failed to embed {}: {}", note.id, e),
+        }
+    }
+    Ok(format!("Embedded {} note(s)", embedded))
+}
+
+fn backup_database(app: &AppHandle, _ctx: &JobContext) -> Result<String, String> {
+    let dir = Path::new(BACKUP_DIR);
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let path = dir.join(format!("knowledge_base-{}.db", Local::now().format("%Y%m%d-%H%M%S")));
+
+    let passphrase_state = app.state::<Mutex<PassphraseState>>();
+    app.with_db(|conn| backup_service::create_backup(conn, &path, Some(passphrase_state.inner())))
+        .ok_or("Database is unavailable")?
+        .map_err(|e| e.to_string())?;
+
+    // File names sort by date, so the oldest come first
+    let mut backups: Vec<_> = std::fs::read_dir(dir)
+        .map_err(|e| e.to_string())?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|p| p.file_name().is_some_and(|n| n.to_string_lossy().starts_with("knowledge_base-")))
+        .collect();
+    backups.sort();
+    let excess = backups.len().saturating_sub(BACKUPS_KEPT);
+    for old in &backups[..excess] {
+        let _ = std::fs::remove_file(old);
+    }
+    Ok(format!("Backed up to {}", path.display()))
+}
+
+fn optimize_index(app: &AppHandle, _ctx: &JobContext) -> Result<String, String> {
+    let stats = app.with_db(search_index::optimize).ok_or("Database is unavailable")?.map_err(|e| e.to_string())?;
+    Ok(format!("Optimized index of {} note(s)", stats.indexed_notes))
+}
+
+fn resolve_links(app: &AppHandle, ctx: &JobContext) -> Result<String, String> {
+    let result = app
+        .with_db(|conn| link_service::resolve_links(conn, ctx.last_run_at.as_deref()))
+        .ok_or("Database is unavailable")?
+        .map_err(|e| e.to_string())?;
+    Ok(format!(
+        "Read links of {} note(s); {} link(s), {} unresolved",
+        result.sources, result.links, result.unresolved
+    ))
+}
+
+fn purge_trash(app: &AppHandle, _ctx: &JobContext) -> Result<String, String> {
+    let purged = app
+        .with_db(|conn| trash_service::purge_older_than(conn, TRASH_RETENTION_DAYS))
+        .ok_or("Database is unavailable")?
+        .map_err(|e| e.to_string())?;
+    Ok(format!("Purged {} note(s) from the trash", purged))
+}
+
+/// Generate queued note suggestions until the queue is empty; requesting one triggers a run.
+/// The DB lock is only held to claim and store each one, not during generation.
+fn generate_note_suggestions(app: &AppHandle, ctx: &JobContext) -> Result<String, String> {
+    let llm = app.state::<LocalLLMState>();
+    let passphrase_state = app.state::<Mutex<PassphraseState>>();
+    let (mut ready, mut failed) = (0, 0);
+    while !ctx.is_cancelled() {
+        let claimed = app.with_db(|conn| note_ai_service::claim_next(conn, passphrase_state.inner()));
+        let Some(pending) = claimed.ok_or("Database is unavailable")?? else {
+            break;
+        };
+
+        let mut model = None;
+        let result = if pending.settings.provider == ProviderKind::Embedded && !pending.model.downloaded {
+            Err(format!("{} is not downloaded yet", pending.model.name))
+        } else {
+            llm.provider(&pending.settings, pending.model.clone()).and_then(|provider| {
+                model = Some(provider.name());
+                note_ai_service::generate(provider.as_ref(), &pending, ctx.cancel_flag())
+            })
+        };
+        match &result {
+            Ok(_) => ready += 1,
+            Err(_) => failed += 1,
+        }
+
+        let suggestion = app
+            .with_db(|conn| note_ai_service::finish(conn, &pending.id, result, model.as_deref()))
+            .ok_or("Database is unavailable")?
+            .map_err(|e| e.to_string())?;
+        if let Some(suggestion) = suggestion {
+            app.emit_all("note-suggestion-ready", suggestion).map_err(|e| e.to_string())?;
         }
-    });
+    }
+    Ok(format!("{} suggestion(s) ready, {} failed", ready, failed))
 }
@@ -45,21 +45,57 @@ pub fn create_tables(conn: &Connection) -> Result<()> {
         [],
     )?;
 
+    // cards_fts stores its own content, so rows are removed by rowid; the
+    // 'delete' command only works for external-content tables
+    conn.execute_batch(
+        "DROP TRIGGER IF EXISTS cards_ad;
+         DROP TRIGGER IF EXISTS cards_au;
+
+         CREATE TRIGGER cards_ad AFTER DELETE ON cards BEGIN
+            DELETE FROM cards_fts WHERE rowid = old.id;
+         END;
+
+         CREATE TRIGGER cards_au AFTER UPDATE ON cards BEGIN
+            DELETE FROM cards_fts WHERE rowid = old.id;
+            INSERT INTO cards_fts(rowid, content, type_id, metadata)
+            VALUES (new.id, new.content, new.type_id, new.metadata);
+         END;",
+    )?;
+
+    // 4. Scheduler state for spaced repetition (one row per reviewable card)
     conn.execute(
-        "CREATE TRIGGER IF NOT EXISTS cards_ad AFTER DELETE ON cards BEGIN
-            INSERT INTO cards_fts(cards_fts, rowid, content, type_id, metadata)
-            VALUES('delete', old.id, old.content, old.type_id, old.metadata);
-        END",
+        "CREATE TABLE IF NOT EXISTS card_schedule (
+            card_id INTEGER PRIMARY KEY,
+            state TEXT NOT NULL DEFAULT 'new',
+            due_at TEXT,
+            interval_days INTEGER NOT NULL DEFAULT 0,
+            ease_factor REAL NOT NULL DEFAULT 2.5,
+            reps INTEGER NOT NULL DEFAULT 0,
+            lapses INTEGER NOT NULL DEFAULT 0,
+            FOREIGN KEY (card_id) REFERENCES cards(id) ON DELETE CASCADE
+        )",
         [],
     )?;
 
+    // 5. Review history (one row per answer given)
     conn.execute(
-        "CREATE TRIGGER IF NOT EXISTS cards_au AFTER UPDATE ON cards BEGIN
-            INSERT INTO cards_fts(cards_fts, rowid, content, type_id, metadata)
-            VALUES('delete', old.id, old.content, old.type_id, old.metadata);
-            INSERT INTO cards_fts(rowid, content, type_id, metadata)
-            VALUES (new.id, new.content, new.type_id, new.metadata);
-        END",
+        "CREATE TABLE IF NOT EXISTS card_reviews (
+            id INTEGER PRIMARY KEY,
+            card_id INTEGER NOT NULL,
+            reviewed_at TEXT NOT NULL,
+            rating INTEGER NOT NULL,
+            interval_days INTEGER NOT NULL DEFAULT 0,
+            last_interval_days INTEGER NOT NULL DEFAULT 0,
+            ease_factor REAL NOT NULL DEFAULT 2.5,
+            time_ms INTEGER NOT NULL DEFAULT 0,
+            review_type TEXT NOT NULL DEFAULT 'review',
+            FOREIGN KEY (card_id) REFERENCES cards(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_card_reviews_card ON card_reviews(card_id)",
         [],
     )?;
 
@@ -0,0 +1,523 @@
+//! Chat sessions over the user's notes
+//!
+//! A session keeps its message history and a [`NoteScope`] limiting which notes
+//! answers draw on. Every answer stores the sources it was given. When the
+//! history no longer fits beside the sources in the model's context, its
+//! oldest messages are folded into a running summary; `summarized_count`
+//! says how many messages the summary covers.
+
+use chrono::Local;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::services::db_service;
+use crate::services::llm_provider::{ChatMessage, Role};
+use crate::services::organization_service::NoteScope;
+use crate::services::rag_service::{self, Source};
+
+/// Title of a session until its first question names it
+pub const DEFAULT_TITLE: &str = "New chat";
+/// History (summary included) may use at most this share of the context budget
+const HISTORY_SHARE: f64 = 0.4;
+/// Length limit of generated titles, in characters
+const MAX_TITLE_CHARS: usize = 80;
+
+/// Added to the answering instructions when no notes matched a follow-up question
+const NO_SOURCES_PROMPT: &str = "No notes matched this question. Answer from the conversation so far, and say so \
+if it does not cover the question.";
+
+const SUMMARY_PROMPT: &str = "Summarize the conversation below between a user and an assistant answering from \
+the user's notes. Keep the questions asked, the facts and conclusions given, and anything the user may refer back \
+to. Write a short paragraph and nothing else.";
+
+pub fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS chat_sessions (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            scope TEXT NOT NULL DEFAULT '{\"type\":\"all\"}',
+            summary TEXT,
+            summarized_count INTEGER NOT NULL DEFAULT 0,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS chat_messages (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            session_id TEXT NOT NULL,
+            role TEXT NOT NULL,
+            content TEXT NOT NULL,
+            sources TEXT NOT NULL DEFAULT '[]',
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (session_id) REFERENCES chat_sessions(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_chat_messages_session ON chat_messages(session_id, id)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatSession {
+    pub id: String,
+    pub title: String,
+    pub scope: NoteScope,
+    /// Summary of the first `summarized_count` messages
+    pub summary: Option<String>,
+    pub summarized_count: usize,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatMessageRecord {
+    pub id: i64,
+    pub session_id: String,
+    pub role: Role,
+    pub content: String,
+    /// What an assistant message was given to answer from; empty for user messages
+    pub sources: Vec<Source>,
+    pub created_at: Option<String>,
+}
+
+fn now() -> String {
+    Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn to_sql_error(e: serde_json::Error) -> rusqlite::Error {
+    rusqlite::Error::ToSqlConversionFailure(Box::new(e))
+}
+
+const SESSION_COLUMNS: &str = "id, title, scope, summary, summarized_count, created_at, updated_at";
+
+fn map_session(row: &rusqlite::Row) -> Result<ChatSession> {
+    let scope: String = row.get(2)?;
+    let summarized_count: i64 = row.get(4)?;
+    Ok(ChatSession {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        scope: serde_json::from_str(&scope).unwrap_or(NoteScope::All),
+        summary: row.get(3)?,
+        summarized_count: summarized_count as usize,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+    })
+}
+
+const MESSAGE_COLUMNS: &str = "id, session_id, role, content, sources, created_at";
+
+fn map_message(row: &rusqlite::Row) -> Result<ChatMessageRecord> {
+    let role: String = row.get(2)?;
+    let sources: String = row.get(4)?;
+    Ok(ChatMessageRecord {
+        id: row.get(0)?,
+        session_id: row.get(1)?,
+        role: Role::parse(&role),
+        content: row.get(3)?,
+        sources: serde_json::from_str(&sources).unwrap_or_default(),
+        created_at: row.get(5)?,
+    })
+}
+
+pub fn create_session(conn: &Connection, title: Option<&str>, scope: &NoteScope) -> Result<ChatSession> {
+    let id = Uuid::new_v4().to_string();
+    let title = title.map(str::trim).filter(|t| !t.is_empty()).unwrap_or(DEFAULT_TITLE);
+    let scope = serde_json::to_string(scope).map_err(to_sql_error)?;
+    let now = now();
+    conn.execute(
+        "INSERT INTO chat_sessions (id, title, scope, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?4)",
+        params![id, title, scope, now],
+    )?;
+    get_session(conn, &id).map(|s| s.expect("session was just inserted"))
+}
+
+pub fn get_session(conn: &Connection, id: &str) -> Result<Option<ChatSession>> {
+    conn.query_row(
+        &format!("SELECT {} FROM chat_sessions WHERE id = ?", SESSION_COLUMNS),
+        params![id],
+        map_session,
+    )
+    .optional()
+}
+
+/// Sessions, most recently active first
+pub fn list_sessions(conn: &Connection) -> Result<Vec<ChatSession>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM chat_sessions ORDER BY updated_at DESC, created_at DESC",
+        SESSION_COLUMNS
+    ))?;
+    let sessions = stmt.query_map([], map_session)?;
+    sessions.collect()
+}
+
+/// Rename a session and/or change its scope; `None` leaves a field as it is
+pub fn update_session(
+    conn: &Connection,
+    id: &str,
+    title: Option<&str>,
+    scope: Option<&NoteScope>,
+) -> Result<Option<ChatSession>> {
+    if let Some(title) = title.map(str::trim).filter(|t| !t.is_empty()) {
+        conn.execute("UPDATE chat_sessions SET title = ? WHERE id = ?", params![title, id])?;
+    }
+    if let Some(scope) = scope {
+        let scope = serde_json::to_string(scope).map_err(to_sql_error)?;
+        conn.execute("UPDATE chat_sessions SET scope = ? WHERE id = ?", params![scope, id])?;
+    }
+    get_session(conn, id)
+}
+
+/// Delete a session and its messages. Returns whether it existed.
+pub fn delete_session(conn: &Connection, id: &str) -> Result<bool> {
+    conn.execute("DELETE FROM chat_messages WHERE session_id = ?", params![id])?;
+    Ok(conn.execute("DELETE FROM chat_sessions WHERE id = ?", params![id])? > 0)
+}
+
+/// A session's messages, oldest first
+pub fn list_messages(conn: &Connection, session_id: &str) -> Result<Vec<ChatMessageRecord>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM chat_messages WHERE session_id = ? ORDER BY id",
+        MESSAGE_COLUMNS
+    ))?;
+    let messages = stmt.query_map(params![session_id], map_message)?;
+    messages.collect()
+}
+
+pub fn get_message(conn: &Connection, id: i64) -> Result<Option<ChatMessageRecord>> {
+    conn.query_row(
+        &format!("SELECT {} FROM chat_messages WHERE id = ?", MESSAGE_COLUMNS),
+        params![id],
+        map_message,
+    )
+    .optional()
+}
+
+/// Append a message to a session. A session still called [`DEFAULT_TITLE`] is named
+/// after its first question.
+pub fn add_message(
+    conn: &Connection,
+    session_id: &str,
+    role: Role,
+    content: &str,
+    sources: &[Source],
+) -> Result<ChatMessageRecord> {
+    let sources = serde_json::to_string(sources).map_err(to_sql_error)?;
+    let now = now();
+    conn.execute(
+        "INSERT INTO chat_messages (session_id, role, content, sources, created_at) VALUES (?, ?, ?, ?, ?)",
+        params![session_id, role.as_str(), content, sources, now],
+    )?;
+    let id = conn.last_insert_rowid();
+
+    conn.execute("UPDATE chat_sessions SET updated_at = ? WHERE id = ?", params![now, session_id])?;
+    if role == Role::User {
+        conn.execute(
+            "UPDATE chat_sessions SET title = ? WHERE id = ? AND title = ?",
+            params![title_from(content), session_id, DEFAULT_TITLE],
+        )?;
+    }
+    get_message(conn, id).map(|m| m.expect("message was just inserted"))
+}
+
+/// Store a question and its answer together, so a failed generation leaves no
+/// unanswered question in the history. `summary` is the session's new summary and
+/// the number of messages it covers, if the history was folded to answer.
+pub fn add_exchange(
+    conn: &Connection,
+    session_id: &str,
+    question: &str,
+    answer: &str,
+    sources: &[Source],
+    summary: Option<(&str, usize)>,
+) -> Result<(ChatMessageRecord, ChatMessageRecord)> {
+    let tx = conn.unchecked_transaction()?;
+    if let Some((summary, summarized_count)) = summary {
+        record_summary(&tx, session_id, summary, summarized_count)?;
+    }
+    let question = add_message(&tx, session_id, Role::User, question, &[])?;
+    let answer = add_message(&tx, session_id, Role::Assistant, answer, sources)?;
+    tx.commit()?;
+    Ok((question, answer))
+}
+
+/// First line of `text`, cut to `MAX_TITLE_CHARS` at a word boundary
+fn title_from(text: &str) -> String {
+    let line = text.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or(DEFAULT_TITLE);
+    if line.chars().count() <= MAX_TITLE_CHARS {
+        return line.to_string();
+    }
+    let cut: String = line.chars().take(MAX_TITLE_CHARS - 1).collect();
+    let cut = cut.rsplit_once(' ').map(|(head, _)| head).filter(|h| !h.is_empty()).unwrap_or(&cut);
+    format!("{}…", cut.trim_end())
+}
+
+/// Remember that `summary` now covers the session's first `summarized_count` messages
+fn record_summary(conn: &Connection, session_id: &str, summary: &str, summarized_count: usize) -> Result<()> {
+    conn.execute(
+        "UPDATE chat_sessions SET summary = ?, summarized_count = ? WHERE id = ?",
+        params![summary, summarized_count as i64, session_id],
+    )?;
+    Ok(())
+}
+
+/// Tokens of the context `budget` that history may use
+pub fn history_budget(budget: usize) -> usize {
+    (budget as f64 * HISTORY_SHARE) as usize
+}
+
+/// How many of `messages` (oldest first) must be summarized so that the rest, with the
+/// summary, fit in `budget` tokens. The newest messages are kept verbatim.
+pub fn messages_to_summarize(
+    summary: Option<&str>,
+    messages: &[ChatMessageRecord],
+    budget: usize,
+    count_tokens: &dyn Fn(&str) -> usize,
+) -> usize {
+    let mut used = summary.map(count_tokens).unwrap_or(0);
+    let mut kept = 0;
+    for message in messages.iter().rev() {
+        used += count_tokens(&message.content);
+        if used > budget {
+            break;
+        }
+        kept += 1;
+    }
+    messages.len() - kept
+}
+
+/// The conversation asking the model to fold `messages` into `previous`
+pub fn summary_prompt(previous: Option<&str>, messages: &[ChatMessageRecord]) -> Vec<ChatMessage> {
+    let mut transcript = String::new();
+    if let Some(previous) = previous {
+        transcript.push_str(&format!("Earlier conversation (summary): {}\n\n", previous));
+    }
+    for message in messages {
+        let speaker = if message.role == Role::Assistant { "Assistant" } else { "User" };
+        transcript.push_str(&format!("{}: {}\n\n", speaker, message.content));
+    }
+    vec![ChatMessage::system(SUMMARY_PROMPT), ChatMessage::user(transcript.trim_end())]
+}
+
+/// Search text for a follow-up question: the previous question supplies what words like
+/// "it" or "that one" refer to
+pub fn retrieval_query(history: &[ChatMessageRecord], question: &str) -> String {
+    match history.iter().rev().find(|m| m.role == Role::User) {
+        Some(previous) => format!("{} {}", previous.content, question),
+        None => question.to_string(),
+    }
+}
+
+/// The messages sent to the model: the answering instructions with the summary of older
+/// turns, the recent `history`, and `question` with its `sources`. Without sources the
+/// model answers from the conversation alone.
+pub fn conversation(
+    summary: Option<&str>,
+    history: &[ChatMessageRecord],
+    question: &str,
+    sources: &[Source],
+) -> Vec<ChatMessage> {
+    let mut messages = rag_service::build_messages(question, sources);
+    if sources.is_empty() {
+        messages[0].content.push_str(&format!("\n\n{}", NO_SOURCES_PROMPT));
+        messages[1].content = format!("Question: {}", question);
+    }
+    if let Some(summary) = summary {
+        messages[0].content.push_str(&format!("\n\nSummary of the earlier conversation: {}", summary));
+    }
+    let turns = history
+        .iter()
+        .filter(|m| m.role != Role::System)
+        .map(|m| ChatMessage { role: m.role, content: m.content.clone() });
+    messages.splice(1..1, turns);
+    messages
+}
+
+/// Save an assistant answer as a new note titled after the question. The note ends with
+/// wiki links to the notes it cited (or to every source, if it cited none) and records
+/// the session and source spans in its properties. Returns the new note's id.
+pub fn save_answer_as_note(conn: &Connection, message_id: i64) -> Result<String, String> {
+    let message = get_message(conn, message_id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Message {} not found", message_id))?;
+    if message.role != Role::Assistant {
+        return Err("Only answers can be saved as notes".to_string());
+    }
+
+    let question: Option<String> = conn
+        .query_row(
+            "SELECT content FROM chat_messages WHERE session_id = ? AND id < ? AND role = 'user'
+             ORDER BY id DESC LIMIT 1",
+            params![message.session_id, message_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let title = question.as_deref().map(title_from).unwrap_or_else(|| "Chat answer".to_string());
+
+    let cited: Vec<&Source> = message.sources.iter().filter(|s| s.cited).collect();
+    let linked = if cited.is_empty() { message.sources.iter().collect() } else { cited };
+
+    let mut content = message.content.trim().to_string();
+    if !linked.is_empty() {
+        content.push_str("\n\n## Sources\n");
+        for source in &linked {
+            let heading = source.heading.as_deref().map(|h| format!(" › {}", h)).unwrap_or_default();
+            content.push_str(&format!("- [{}] [[{}]]{}\n", source.marker, source.title, heading));
+        }
+    }
+
+    let properties = serde_json::json!({
+        "chat_session": message.session_id,
+        "sources": linked
+            .iter()
+            .map(|s| serde_json::json!({ "marker": s.marker, "note_id": s.note_id, "start": s.start, "end": s.end }))
+            .collect::<Vec<_>>(),
+    });
+
+    let note_id = db_service::create_note(conn, &title, content.trim_end())?;
+    conn.execute(
+        "UPDATE notes SET properties = ? WHERE id = ?",
+        params![properties.to_string(), note_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(note_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (
+                internal_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                id TEXT UNIQUE NOT NULL,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                folder_id TEXT,
+                properties TEXT
+            );",
+        )
+        .unwrap();
+        create_tables(&conn).unwrap();
+        conn
+    }
+
+    fn source(marker: usize, title: &str, cited: bool) -> Source {
+        Source {
+            marker,
+            note_id: format!("note-{}", marker),
+            title: title.to_string(),
+            heading: None,
+            start: 0,
+            end: 10,
+            text: "passage".to_string(),
+            cited,
+        }
+    }
+
+    #[test]
+    fn test_session_history_and_summary() {
+        let conn = setup();
+        let session = create_session(&conn, None, &NoteScope::Tag { name: "work".into() }).unwrap();
+        assert_eq!(session.title, DEFAULT_TITLE);
+        assert_eq!(session.scope, NoteScope::Tag { name: "work".into() });
+
+        let (question, answer) = add_exchange(
+            &conn,
+            &session.id,
+            "What did we decide about pricing?",
+            "Ten euros per seat [1].",
+            &[source(1, "Pricing", true)],
+            None,
+        )
+        .unwrap();
+        assert_eq!((question.role, answer.role), (Role::User, Role::Assistant));
+        add_message(&conn, &session.id, Role::User, "And discounts?", &[]).unwrap();
+
+        let session = get_session(&conn, &session.id).unwrap().unwrap();
+        assert_eq!(session.title, "What did we decide about pricing?");
+        let messages = list_messages(&conn, &session.id).unwrap();
+        assert_eq!(messages.len(), 3);
+        assert!(messages[1].sources[0].cited);
+        assert_eq!(retrieval_query(&messages[..2], "And discounts?"), "What did we decide about pricing? And discounts?");
+
+        // A word per token: only the newest message fits beside the summary
+        let count = |text: &str| text.split_whitespace().count();
+        let older = messages_to_summarize(Some("Talked about pricing."), &messages, 6, &count);
+        assert_eq!(older, 2);
+        assert_eq!(messages_to_summarize(None, &messages, 100, &count), 0);
+
+        let prompt = summary_prompt(None, &messages[..older]);
+        assert!(prompt[1].content.contains("User: What did we decide about pricing?"));
+        record_summary(&conn, &session.id, "Pricing is ten euros per seat.", older).unwrap();
+
+        let session = get_session(&conn, &session.id).unwrap().unwrap();
+        let history = &messages[session.summarized_count..messages.len() - 1];
+        assert!(history.is_empty());
+        let sent = conversation(session.summary.as_deref(), &messages[..2], "And discounts?", &[]);
+        assert_eq!(sent.len(), 4);
+        assert!(sent[0].content.ends_with("Summary of the earlier conversation: Pricing is ten euros per seat."));
+        assert_eq!(sent[2].role, Role::Assistant);
+        // No notes matched the follow-up, so it is answered from the conversation
+        assert!(sent[0].content.contains(NO_SOURCES_PROMPT));
+        assert_eq!(sent[3].content, "Question: And discounts?");
+        let sent = conversation(None, &messages[..2], "And discounts?", &[source(2, "Discounts", false)]);
+        assert!(sent[3].content.starts_with("Sources:") && !sent[0].content.contains(NO_SOURCES_PROMPT));
+
+        update_session(&conn, &session.id, Some("Pricing"), Some(&NoteScope::All)).unwrap();
+        assert_eq!(list_sessions(&conn).unwrap()[0].scope, NoteScope::All);
+        assert!(delete_session(&conn, &session.id).unwrap());
+        assert!(list_messages(&conn, &session.id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_save_answer_as_note() {
+        let conn = setup();
+        let session = create_session(&conn, Some("Planning"), &NoteScope::All).unwrap();
+        let question = add_message(&conn, &session.id, Role::User, "Where is the offsite?", &[]).unwrap();
+        let answer = add_message(
+            &conn,
+            &session.id,
+            Role::Assistant,
+            "In Lyon [2].",
+            &[source(1, "Budget", false), source(2, "Offsite", true)],
+        )
+        .unwrap();
+
+        assert!(save_answer_as_note(&conn, question.id).is_err());
+        let note_id = save_answer_as_note(&conn, answer.id).unwrap();
+        let (title, content, properties): (String, String, String) = conn
+            .query_row("SELECT title, content, properties FROM notes WHERE id = ?", [&note_id], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(title, "Where is the offsite?");
+        assert_eq!(content, "In Lyon [2].\n\n## Sources\n- [2] [[Offsite]]");
+        let properties: serde_json::Value = serde_json::from_str(&properties).unwrap();
+        assert_eq!(properties["chat_session"], session.id.as_str());
+        assert_eq!(properties["sources"][0]["note_id"], "note-2");
+        // A session keeps a title it was given
+        assert_eq!(get_session(&conn, &session.id).unwrap().unwrap().title, "Planning");
+    }
+
+    #[test]
+    fn test_title_from_long_question() {
+        let question = "word ".repeat(30);
+        let title = title_from(&question);
+        assert!(title.chars().count() <= MAX_TITLE_CHARS);
+        assert!(title.ends_with("word…"));
+    }
+}
@@ -156,46 +156,89 @@ pub fn init_db() -> Result<Connection> {
         [],
     )?;
 
-    // FTS5 Virtual Table
-    conn.execute(
-        "CREATE VIRTUAL TABLE IF NOT EXISTS notes_fts USING fts5(
-            title,
-            content,
-            tags,
-            properties,
-            content='notes',
-            content_rowid='internal_id'
-        )",
-        [],
+    // Indexes behind search filters and facet counts
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_notes_folder_id ON notes(folder_id);
+         CREATE INDEX IF NOT EXISTS idx_notes_created_at ON notes(created_at);
+         CREATE INDEX IF NOT EXISTS idx_notes_updated_at ON notes(updated_at);
+         CREATE INDEX IF NOT EXISTS idx_note_tags_tag_id ON note_tags(tag_id);",
     )?;
 
-    // Synchronization Triggers (Updated to handle encrypted content)
-    conn.execute_batch("
-        CREATE TRIGGER IF NOT EXISTS notes_ai AFTER INSERT ON notes BEGIN
-          INSERT INTO notes_fts(rowid, title, content, properties) 
-          VALUES (new.internal_id, new.title, COALESCE(new.content_plaintext, new.content), new.properties);
-        END;
-
-        CREATE TRIGGER IF NOT EXISTS notes_ad AFTER DELETE ON notes BEGIN
-          INSERT INTO notes_fts(notes_fts, rowid, title, content, properties) 
-          VALUES('delete', old.internal_id, old.title, COALESCE(old.content_plaintext, old.content), old.properties);
-        END;
-
-        CREATE TRIGGER IF NOT EXISTS notes_au AFTER UPDATE ON notes BEGIN
-          INSERT INTO notes_fts(notes_fts, rowid, title, content, properties) 
-          VALUES('delete', old.internal_id, old.title, COALESCE(old.content_plaintext, old.content), old.properties);
-          INSERT INTO notes_fts(rowid, title, content, properties) 
-          VALUES (new.internal_id, new.title, COALESCE(new.content_plaintext, new.content), new.properties);
-        END;
-    ")?;
+    // Full-text index and its sync triggers
+    use crate::services::search_index;
+    search_index::create_tables(&conn)?;
 
     // Initialize Cards Schema
     use crate::services::cards;
     cards::create_tables(&conn)?;
 
+    // Roles and their search/dashboard rules
+    use crate::services::role_service;
+    role_service::create_tables(&conn)?;
+
+    // Saved searches (smart folders)
+    use crate::services::saved_search_service;
+    saved_search_service::create_tables(&conn)?;
+
+    // Undoable revision batches (find and replace, merges)
+    use crate::services::revision_service;
+    revision_service::create_tables(&conn)?;
+
+    // Note chunk embeddings for semantic search
+    use crate::services::embedding_service;
+    embedding_service::create_tables(&conn)?;
+
+    // Trashed notes, kept for restoring
+    use crate::services::trash_service;
+    trash_service::create_tables(&conn)?;
+
+    // Suggested links between notes
+    use crate::services::graph_analysis;
+    graph_analysis::create_tables(&conn)?;
+
+    // Resolved wiki-links, kept up to date by the link resolution job
+    use crate::services::link_service;
+    link_service::create_tables(&conn)?;
+
+    // Background job settings and run history
+    use crate::services::job_scheduler;
+    job_scheduler::create_tables(&conn)?;
+
+    // Local LLMs and the model chosen for each task
+    use crate::services::model_registry;
+    model_registry::create_tables(&conn)?;
+
+    // Which LLM provider generates text, and where its server is
+    use crate::services::llm_provider;
+    llm_provider::create_tables(&conn)?;
+
+    // AI chat sessions and their messages
+    use crate::services::chat_service;
+    chat_service::create_tables(&conn)?;
+
+    // Action items extracted from notes
+    use crate::services::task_service;
+    task_service::create_tables(&conn)?;
+
+    // AI suggestions for notes, queued and waiting for review
+    use crate::services::note_ai_service;
+    note_ai_service::create_tables(&conn)?;
+
     Ok(conn)
 }
 
+/// Check whether `table` has a column named `column`
+///
+/// Several columns are added by migrations, so code that reads them on
+/// older databases has to check first.
+pub fn table_has_column(conn: &Connection, table: &str, column: &str) -> bool {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2)",
+        [table, column],
+        |row| row.get(0),
+    ).unwrap_or(false)
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Note {
     pub id: String,
@@ -0,0 +1,292 @@
+//! Resumable, verified file downloads
+//!
+//! Bytes go to `<dest>.part` and the file only moves to `dest` once it is
+//! complete and its SHA-256 matches, so anything at `dest` is a whole file.
+//! An interrupted download keeps its `.part` and the next attempt resumes it
+//! with an HTTP Range request.
+
+use futures_util::StreamExt;
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::StatusCode;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Minimum time between progress reports
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadProgress {
+    /// Bytes on disk, including any resumed from an earlier attempt
+    pub downloaded: u64,
+    /// `None` when the server does not say
+    pub total: Option<u64>,
+    pub bytes_per_second: f64,
+    pub eta_seconds: Option<u64>,
+}
+
+/// Where an unfinished download of `dest` is kept
+pub fn part_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+/// Download `url` to `dest`, resuming from `<dest>.part` if an earlier attempt left one.
+///
+/// When `expected_sha256` is given the finished file must match it; a mismatch deletes
+/// the partial file so the next attempt starts over. Returns the file's SHA-256 as hex.
+pub async fn download(
+    url: &str,
+    dest: &Path,
+    expected_sha256: Option<&str>,
+    mut on_progress: impl FnMut(&DownloadProgress),
+) -> Result<String, String> {
+    let part = part_path(dest);
+    let existing = std::fs::metadata(&part).map(|m| m.len()).unwrap_or(0);
+
+    let mut request = reqwest::Client::new().get(url);
+    if existing > 0 {
+        request = request.header(RANGE, format!("bytes={}-", existing));
+    }
+    let response = request.send().await.map_err(|e| format!("Failed to download {}: {}", url, e))?;
+    let status = response.status();
+
+    // The range starts at or past the end: the partial file already has every byte
+    if status == StatusCode::RANGE_NOT_SATISFIABLE && existing > 0 {
+        let hash = hash_file(&part)?;
+        return finish(&part, dest, hash, expected_sha256);
+    }
+    if !status.is_success() {
+        return Err(format!("Failed to download file: {}", status));
+    }
+
+    // A server that ignores Range sends the whole file with 200
+    let resumed = existing > 0 && status == StatusCode::PARTIAL_CONTENT;
+    let offset = if resumed { existing } else { 0 };
+    let total = if resumed {
+        response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit('/').next())
+            .and_then(|t| t.parse().ok())
+            .or_else(|| response.content_length().map(|len| len + offset))
+    } else {
+        response.content_length()
+    };
+
+    let mut hasher = Sha256::new();
+    let mut file = if resumed {
+        hash_into(&part, &mut hasher)?;
+        OpenOptions::new().append(true).open(&part)
+    } else {
+        File::create(&part)
+    }
+    .map_err(|e| format!("Failed to open {}: {}", part.display(), e))?;
+
+    let start = Instant::now();
+    let mut last_report: Option<Instant> = None;
+    let mut downloaded = offset;
+    let mut stream = response.bytes_stream();
+    let mut result = Ok(());
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                result = Err(format!("Download interrupted: {}", e));
+                break;
+            }
+        };
+        file.write_all(&chunk).map_err(|e| format!("Failed to write {}: {}", part.display(), e))?;
+        hasher.update(&chunk);
+        downloaded += chunk.len() as u64;
+
+        if last_report.is_none_or(|t| t.elapsed() >= PROGRESS_INTERVAL) {
+            on_progress(&progress(downloaded, offset, total, start.elapsed()));
+            last_report = Some(Instant::now());
+        }
+    }
+    file.sync_all().map_err(|e| e.to_string())?;
+    drop(file);
+    result?;
+
+    if let Some(total) = total.filter(|t| downloaded < *t) {
+        return Err(format!("Download interrupted after {} of {} bytes", downloaded, total));
+    }
+    on_progress(&progress(downloaded, offset, total, start.elapsed()));
+
+    let hash = hex(&hasher.finalize());
+    finish(&part, dest, hash, expected_sha256)
+}
+
+fn progress(downloaded: u64, offset: u64, total: Option<u64>, elapsed: Duration) -> DownloadProgress {
+    let secs = elapsed.as_secs_f64();
+    let bytes_per_second = if secs > 0.0 { (downloaded - offset) as f64 / secs } else { 0.0 };
+    let eta_seconds = total
+        .filter(|_| bytes_per_second > 0.0)
+        .map(|t| (t.saturating_sub(downloaded) as f64 / bytes_per_second).ceil() as u64);
+    DownloadProgress { downloaded, total, bytes_per_second, eta_seconds }
+}
+
+/// Check the hash and move the partial file into place
+fn finish(part: &Path, dest: &Path, hash: String, expected_sha256: Option<&str>) -> Result<String, String> {
+    if let Some(expected) = expected_sha256 {
+        if !hash.eq_ignore_ascii_case(expected.trim()) {
+            let _ = std::fs::remove_file(part);
+            return Err(format!(
+                "Checksum mismatch for {}: expected {}, got {}",
+                dest.display(),
+                expected,
+                hash
+            ));
+        }
+    }
+    std::fs::rename(part, dest).map_err(|e| format!("Failed to move download into place: {}", e))?;
+    Ok(hash)
+}
+
+/// SHA-256 of a file on disk, as hex
+pub fn hash_file(path: &Path) -> Result<String, String> {
+    let mut hasher = Sha256::new();
+    hash_into(path, &mut hasher)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hash_into(path: &Path, hasher: &mut Sha256) -> Result<(), String> {
+    let mut file = File::open(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut buf = vec![0u8; 1 << 16];
+    loop {
+        let n = file.read(&mut buf).map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Ok(());
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serves `body` over HTTP with Range support. If `cut_first` is set the first
+    /// response promises the whole body but the connection drops halfway through.
+    /// Returns the URL and the Range start of every request received.
+    async fn mock_server(body: Vec<u8>, cut_first: bool) -> (String, Arc<Mutex<Vec<Option<u64>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/model.gguf", listener.local_addr().unwrap());
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let seen = ranges.clone();
+        tokio::spawn(async move {
+            let mut first = true;
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let request = String::from_utf8_lossy(&request).to_lowercase();
+                let range: Option<u64> = request
+                    .lines()
+                    .find_map(|l| l.strip_prefix("range: bytes="))
+                    .and_then(|r| r.trim_end_matches('-').parse().ok());
+                seen.lock().unwrap().push(range);
+
+                let len = body.len();
+                let (head, content) = match range {
+                    Some(start) if start as usize >= len => (
+                        format!("HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{}\r\nContent-Length: 0\r\n", len),
+                        &body[..0],
+                    ),
+                    Some(start) => (
+                        format!(
+                            "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\n",
+                            start,
+                            len - 1,
+                            len,
+                            len - start as usize
+                        ),
+                        &body[start as usize..],
+                    ),
+                    None if cut_first && first => {
+                        (format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n", len), &body[..len / 2])
+                    }
+                    None => (format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n", len), &body[..]),
+                };
+                first = false;
+                socket.write_all(head.as_bytes()).await.unwrap();
+                socket.write_all(b"Connection: close\r\n\r\n").await.unwrap();
+                socket.write_all(content).await.unwrap();
+                socket.shutdown().await.ok();
+            }
+        });
+        (url, ranges)
+    }
+
+    fn body() -> Vec<u8> {
+        (0..200_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn sha256(bytes: &[u8]) -> String {
+        hex(&Sha256::digest(bytes))
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_download_resumes() {
+        let body = body();
+        let (url, ranges) = mock_server(body.clone(), true).await;
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("model.gguf");
+
+        let err = download(&url, &dest, Some(&sha256(&body)), |_| {}).await.unwrap_err();
+        assert!(err.contains("interrupted"), "{}", err);
+        assert!(!dest.exists(), "a partial file must not look downloaded");
+        assert_eq!(std::fs::metadata(part_path(&dest)).unwrap().len(), body.len() as u64 / 2);
+
+        let mut reports = Vec::new();
+        let hash = download(&url, &dest, Some(&sha256(&body)), |p| reports.push(p.clone())).await.unwrap();
+        assert_eq!(hash, sha256(&body));
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+        assert!(!part_path(&dest).exists());
+        assert_eq!(*ranges.lock().unwrap(), vec![None, Some(body.len() as u64 / 2)]);
+
+        let last = reports.last().unwrap();
+        assert_eq!(last.downloaded, body.len() as u64);
+        assert_eq!(last.total, Some(body.len() as u64));
+        assert_eq!(last.eta_seconds.unwrap_or(0), 0);
+    }
+
+    #[tokio::test]
+    async fn test_checksum_mismatch_discards_download() {
+        let body = body();
+        let (url, _) = mock_server(body.clone(), false).await;
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("model.gguf");
+
+        let err = download(&url, &dest, Some(&sha256(b"something else")), |_| {}).await.unwrap_err();
+        assert!(err.contains("Checksum mismatch"), "{}", err);
+        assert!(!dest.exists());
+        assert!(!part_path(&dest).exists());
+
+        // A complete .part left behind is verified without downloading it again
+        std::fs::write(part_path(&dest), &body).unwrap();
+        let hash = download(&url, &dest, None, |_| {}).await.unwrap();
+        assert_eq!(hash, sha256(&body));
+        assert!(dest.exists());
+    }
+}
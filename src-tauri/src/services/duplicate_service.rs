@@ -0,0 +1,597 @@
+//! Near-duplicate detection and merging
+//!
+//! Every note gets a 64-bit fingerprint: a SimHash of its word shingles, or,
+//! with `use_embeddings`, a random-hyperplane hash of its stored embedding.
+//! Notes whose fingerprints agree on one of their eight bytes are scored
+//! exactly, and pairs at or above the threshold are joined into clusters.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use rusqlite::{params, params_from_iter, Connection};
+use serde::{Deserialize, Serialize};
+
+use crate::services::embedding_service;
+use crate::services::encrypted_note_service;
+use crate::services::link_service;
+use crate::services::passphrase_service::PassphraseState;
+use crate::services::revision_service::{self, RevisionBatch};
+use crate::services::trash_service;
+
+pub const DEFAULT_THRESHOLD: f64 = 0.9;
+/// Words per shingle hashed into the SimHash
+const SHINGLE_WORDS: usize = 3;
+/// Shorter notes (empty captures, one-liners) look alike without being duplicates
+const MIN_WORDS: usize = 5;
+/// Candidate pairs must share one of this many fingerprint bytes
+const BANDS: usize = 8;
+/// Fingerprints up to `BANDS - 1` bits apart always share a band, so text
+/// thresholds are clamped to the similarity that distance represents
+const MIN_TEXT_THRESHOLD: f64 = 1.0 - (BANDS - 1) as f64 / 64.0;
+/// Embedding pairs whose hashes put them this far below the threshold aren't scored
+const EMBEDDING_SLACK: f64 = 0.05;
+const HYPERPLANE_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateMethod {
+    Text,
+    Embedding,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateNote {
+    pub id: String,
+    pub title: String,
+    pub updated_at: Option<String>,
+    pub word_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateCluster {
+    pub notes: Vec<DuplicateNote>,
+    /// Lowest score among the pairs that joined the cluster, 0.0 to 1.0
+    pub similarity: f64,
+    pub method: DuplicateMethod,
+}
+
+/// How [`merge_notes`] combines the notes' content
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    /// Keep the first note and append each other note under its title
+    Append,
+    /// Keep the note with the most text
+    KeepLongest,
+    /// Keep the most recently updated note
+    KeepNewest,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeResult {
+    pub note_id: String,
+    pub title: String,
+    /// Notes merged in and moved to the trash
+    pub merged: Vec<String>,
+    /// Notes whose links to a merged note now point at `note_id`
+    pub retargeted: Vec<String>,
+    /// Encrypted notes linking to a merged note that couldn't be unlocked
+    pub locked_notes: Vec<String>,
+    /// Undoes the content changes; merged notes are restored from the trash
+    pub batch: RevisionBatch,
+}
+
+struct Fingerprinted {
+    note: DuplicateNote,
+    hash: u64,
+    /// Normalized embedding, for exact scoring
+    vector: Option<Vec<f32>>,
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// FNV-1a; stable across runs, unlike `DefaultHasher`
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// SimHash over overlapping word shingles
+pub fn simhash(words: &[String]) -> u64 {
+    let mut weights = [0i64; 64];
+    let size = SHINGLE_WORDS.min(words.len()).max(1);
+    for shingle in words.windows(size) {
+        let hash = fnv1a(&shingle.join(" "));
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            *weight += if hash >> bit & 1 == 1 { 1 } else { -1 };
+        }
+    }
+    weights
+        .iter()
+        .enumerate()
+        .fold(0u64, |hash, (bit, &weight)| if weight > 0 { hash | 1 << bit } else { hash })
+}
+
+/// Similarity of two SimHashes: the share of bits they agree on
+pub fn simhash_similarity(a: u64, b: u64) -> f64 {
+    1.0 - (a ^ b).count_ones() as f64 / 64.0
+}
+
+/// 64 fixed pseudo-random hyperplanes of dimension `dim`
+fn hyperplanes(dim: usize) -> Vec<Vec<f32>> {
+    let mut state = HYPERPLANE_SEED;
+    let mut next = move || {
+        // xorshift64*
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        let bits = state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 40;
+        bits as f32 / (1u64 << 23) as f32 - 1.0
+    };
+    (0..64).map(|_| (0..dim).map(|_| next()).collect()).collect()
+}
+
+/// Which side of each hyperplane the vector falls on; close vectors share most bits
+fn vector_hash(vector: &[f32], planes: &[Vec<f32>]) -> u64 {
+    planes.iter().enumerate().fold(0u64, |hash, (bit, plane)| {
+        let dot: f32 = plane.iter().zip(vector).map(|(p, v)| p * v).sum();
+        if dot > 0.0 { hash | 1 << bit } else { hash }
+    })
+}
+
+/// Pairs at or above `threshold`, with their scores, found by banding the fingerprints
+fn similar_pairs(notes: &[Fingerprinted], method: DuplicateMethod, threshold: f64) -> Vec<(usize, usize, f64)> {
+    let mut pairs = Vec::new();
+    for band in 0..BANDS {
+        let mut buckets: HashMap<u8, Vec<usize>> = HashMap::new();
+        for (i, note) in notes.iter().enumerate() {
+            buckets.entry((note.hash >> (band * 8)) as u8).or_default().push(i);
+        }
+        for bucket in buckets.values() {
+            for (x, &i) in bucket.iter().enumerate() {
+                for &j in &bucket[x + 1..] {
+                    let diff = notes[i].hash ^ notes[j].hash;
+                    // Scored once, in the first band the pair shares
+                    if (0..band).any(|b| (diff >> (b * 8)) as u8 == 0) {
+                        continue;
+                    }
+                    let score = match method {
+                        DuplicateMethod::Text => simhash_similarity(notes[i].hash, notes[j].hash),
+                        DuplicateMethod::Embedding => {
+                            let estimate = (std::f64::consts::PI * diff.count_ones() as f64 / 64.0).cos();
+                            if estimate < threshold - EMBEDDING_SLACK {
+                                continue;
+                            }
+                            match (&notes[i].vector, &notes[j].vector) {
+                                (Some(a), Some(b)) => embedding_service::cosine(a, b) as f64,
+                                _ => continue,
+                            }
+                        }
+                    };
+                    if score >= threshold {
+                        pairs.push((i, j, score));
+                    }
+                }
+            }
+        }
+    }
+    pairs
+}
+
+fn find(parent: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parent[root] != root {
+        root = parent[root];
+    }
+    let mut i = i;
+    while parent[i] != root {
+        let next = parent[i];
+        parent[i] = root;
+        i = next;
+    }
+    root
+}
+
+/// Join pairs into clusters of two or more notes
+fn clusters(notes: Vec<Fingerprinted>, pairs: Vec<(usize, usize, f64)>, method: DuplicateMethod) -> Vec<DuplicateCluster> {
+    let mut parent: Vec<usize> = (0..notes.len()).collect();
+    for &(i, j, _) in &pairs {
+        let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+        if a != b {
+            parent[a] = b;
+        }
+    }
+
+    let mut lowest: HashMap<usize, f64> = HashMap::new();
+    for &(i, _, score) in &pairs {
+        let root = find(&mut parent, i);
+        let entry = lowest.entry(root).or_insert(score);
+        *entry = entry.min(score);
+    }
+
+    let mut members: HashMap<usize, Vec<DuplicateNote>> = HashMap::new();
+    for (i, note) in notes.into_iter().enumerate() {
+        let root = find(&mut parent, i);
+        if lowest.contains_key(&root) {
+            members.entry(root).or_default().push(note.note);
+        }
+    }
+
+    members
+        .into_iter()
+        .map(|(root, mut notes)| {
+            notes.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then_with(|| a.id.cmp(&b.id)));
+            DuplicateCluster { notes, similarity: lowest[&root], method }
+        })
+        .collect()
+}
+
+/// Clusters of near-duplicate notes, most similar first
+///
+/// With `use_embeddings`, embedded notes are compared by embedding and the
+/// rest by text; notes are only ever compared using the same method.
+pub fn find_duplicates(
+    conn: &Connection,
+    threshold: Option<f64>,
+    use_embeddings: bool,
+    limit: usize,
+) -> Result<Vec<DuplicateCluster>, String> {
+    let threshold = threshold.unwrap_or(DEFAULT_THRESHOLD).clamp(0.0, 1.0);
+
+    let mut stmt = conn
+        .prepare("SELECT id, title, COALESCE(content_plaintext, content, ''), updated_at FROM notes")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, Option<String>>(3)?))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut vectors = if use_embeddings {
+        embedding_service::note_vectors(conn).map_err(|e| e.to_string())?
+    } else {
+        HashMap::new()
+    };
+    let mut planes: Option<(String, Vec<Vec<f32>>)> = None;
+    let mut by_text = Vec::new();
+    let mut by_embedding = Vec::new();
+    for row in rows {
+        let (id, title, content, updated_at) = row.map_err(|e| e.to_string())?;
+        let words = words(&content);
+        if words.len() < MIN_WORDS {
+            continue;
+        }
+        let note = DuplicateNote { id, title, updated_at, word_count: words.len() };
+
+        match vectors.remove(&note.id) {
+            // Vectors from another model live in another space; compare those notes by text
+            Some((vector, model)) if planes.as_ref().is_none_or(|(m, _)| *m == model) => {
+                let (_, planes) = planes.get_or_insert_with(|| (model, hyperplanes(vector.len())));
+                let hash = vector_hash(&vector, planes);
+                by_embedding.push(Fingerprinted { note, hash, vector: Some(vector) });
+            }
+            _ => by_text.push(Fingerprinted { note, hash: simhash(&words), vector: None }),
+        }
+    }
+
+    let text_pairs = similar_pairs(&by_text, DuplicateMethod::Text, threshold.max(MIN_TEXT_THRESHOLD));
+    let embedding_pairs = similar_pairs(&by_embedding, DuplicateMethod::Embedding, threshold);
+    let mut found = clusters(by_text, text_pairs, DuplicateMethod::Text);
+    found.extend(clusters(by_embedding, embedding_pairs, DuplicateMethod::Embedding));
+
+    found.sort_by(|a, b| {
+        b.similarity
+            .total_cmp(&a.similarity)
+            .then_with(|| b.notes.len().cmp(&a.notes.len()))
+            .then_with(|| a.notes[0].id.cmp(&b.notes[0].id))
+    });
+    found.truncate(limit);
+    Ok(found)
+}
+
+struct MergeNote {
+    id: String,
+    title: String,
+    content: String,
+    updated_at: Option<String>,
+    encrypted: bool,
+}
+
+/// Point links to any of `old_titles` at `new_title`, keeping headings and aliases
+fn retarget_links(content: &str, old_titles: &HashSet<String>, new_title: &str) -> String {
+    link_service::replace_wiki_links(content, |link| {
+        if !old_titles.contains(&link_service::normalize_title(&link.target)) {
+            return content[link.start..link.end].to_string();
+        }
+        let mut out = format!("[[{}", new_title);
+        if let Some(heading) = &link.heading {
+            out.push('#');
+            out.push_str(heading);
+        }
+        if let Some(alias) = &link.alias {
+            out.push('|');
+            out.push_str(alias);
+        }
+        out.push_str("]]");
+        out
+    })
+}
+
+/// Merge notes into one: combine content, union tags, retarget links, trash the rest
+///
+/// Runs in one transaction and records a revision batch covering the content
+/// changes, the added tags and the trashed notes, so undoing it reverses the
+/// whole merge. The merged note is encrypted if any of its sources was.
+pub fn merge_notes(
+    conn: &Connection,
+    passphrase_state: &Mutex<PassphraseState>,
+    ids: &[String],
+    strategy: MergeStrategy,
+) -> Result<MergeResult, String> {
+    let mut unique: Vec<&String> = Vec::new();
+    for id in ids {
+        if !unique.contains(&id) {
+            unique.push(id);
+        }
+    }
+    if unique.len() < 2 {
+        return Err("Select at least two notes to merge".to_string());
+    }
+
+    let state_guard = passphrase_state.lock().map_err(|e| e.to_string())?;
+    let mut notes = Vec::new();
+    for id in unique {
+        let (title, content, encrypted, nonce, updated_at): (String, String, Option<String>, Option<String>, Option<String>) = conn
+            .query_row(
+                "SELECT title, content, content_encrypted, nonce, updated_at FROM notes WHERE id = ?",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+            )
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => format!("Note {} not found", id),
+                e => e.to_string(),
+            })?;
+        let is_encrypted = encrypted.is_some();
+        let content = encrypted_note_service::load_content(&state_guard, content, encrypted, nonce)
+            .ok_or_else(|| format!("Unlock encrypted note \"{}\" before merging it", title))?;
+        notes.push(MergeNote { id: id.clone(), title, content, updated_at, encrypted: is_encrypted });
+    }
+
+    let target_index = match strategy {
+        MergeStrategy::Append => 0,
+        MergeStrategy::KeepLongest => (0..notes.len())
+            .max_by_key(|&i| (notes[i].content.trim().chars().count(), std::cmp::Reverse(i)))
+            .unwrap_or(0),
+        MergeStrategy::KeepNewest => (0..notes.len())
+            .max_by(|&a, &b| notes[a].updated_at.cmp(&notes[b].updated_at).then(b.cmp(&a)))
+            .unwrap_or(0),
+    };
+    let target = notes.remove(target_index);
+    let merged_ids: Vec<String> = notes.iter().map(|n| n.id.clone()).collect();
+    let old_titles: HashSet<String> = notes
+        .iter()
+        .map(|n| link_service::normalize_title(&n.title))
+        .filter(|t| *t != link_service::normalize_title(&target.title))
+        .collect();
+
+    let mut content = target.content.clone();
+    if strategy == MergeStrategy::Append {
+        for note in &notes {
+            if note.content.trim().is_empty() || note.content.trim() == content.trim() {
+                continue;
+            }
+            content = format!("{}\n\n## {}\n\n{}", content.trim_end(), note.title, note.content.trim());
+        }
+    }
+    let content = retarget_links(&content, &old_titles, &target.title);
+    let encrypted = target.encrypted || notes.iter().any(|n| n.encrypted);
+    let now = revision_service::batch_timestamp();
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let batch_id = revision_service::begin_batch(
+        &tx,
+        "merge",
+        &format!("Merge {} notes into \"{}\"", merged_ids.len() + 1, target.title),
+    )
+    .map_err(|e| e.to_string())?;
+
+    revision_service::record_revision(&tx, &batch_id, &target.id, &now).map_err(|e| e.to_string())?;
+    encrypted_note_service::write_content(&tx, &state_guard, &target.id, &content, encrypted, &now)?;
+
+    // Tags of the merged notes the target lacks, recorded so undo can take them off again
+    let placeholders = vec!["?"; merged_ids.len()].join(", ");
+    let mut tag_params = vec![target.id.clone()];
+    tag_params.extend(merged_ids.iter().cloned());
+    let added_tags: Vec<String> = {
+        let mut stmt = tx
+            .prepare(&format!(
+                "SELECT DISTINCT tag_id FROM note_tags
+                 WHERE tag_id NOT IN (SELECT tag_id FROM note_tags WHERE note_id = ?) AND note_id IN ({})",
+                placeholders
+            ))
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params_from_iter(tag_params.iter()), |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())?
+    };
+    for tag_id in &added_tags {
+        tx.execute("INSERT INTO note_tags (note_id, tag_id) VALUES (?, ?)", params![target.id, tag_id])
+            .map_err(|e| e.to_string())?;
+        revision_service::record_tag_added(&tx, &batch_id, &target.id, tag_id).map_err(|e| e.to_string())?;
+    }
+
+    let mut retargeted = Vec::new();
+    let mut locked_notes = Vec::new();
+    if !old_titles.is_empty() {
+        let mut exclude = vec![target.id.clone()];
+        exclude.extend(merged_ids.iter().cloned());
+        let sql = format!(
+            "SELECT id, content, content_encrypted, nonce FROM notes
+             WHERE id NOT IN ({}) AND COALESCE(content_plaintext, content, '') LIKE '%[[%'",
+            vec!["?"; exclude.len()].join(", ")
+        );
+        let linking: Vec<(String, String, Option<String>, Option<String>)> = {
+            let mut stmt = tx.prepare(&sql).map_err(|e| e.to_string())?;
+            let rows = stmt
+                .query_map(params_from_iter(exclude.iter()), |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+                })
+                .map_err(|e| e.to_string())?;
+            rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())?
+        };
+
+        for (id, content, encrypted, nonce) in linking {
+            let is_encrypted = encrypted.is_some();
+            let plaintext: Option<String> = if is_encrypted {
+                tx.query_row("SELECT content_plaintext FROM notes WHERE id = ?", params![id], |row| row.get(0))
+                    .map_err(|e| e.to_string())?
+            } else {
+                Some(content.clone())
+            };
+            // Cheap check before decrypting
+            let links_here = plaintext.as_deref().map_or(is_encrypted, |text| {
+                link_service::extract_wiki_links(text)
+                    .iter()
+                    .any(|l| old_titles.contains(&link_service::normalize_title(&l.target)))
+            });
+            if !links_here {
+                continue;
+            }
+            let Some(current) = encrypted_note_service::load_content(&state_guard, content, encrypted, nonce) else {
+                locked_notes.push(id);
+                continue;
+            };
+            let updated = retarget_links(&current, &old_titles, &target.title);
+            if updated == current {
+                continue;
+            }
+            revision_service::record_revision(&tx, &batch_id, &id, &now).map_err(|e| e.to_string())?;
+            encrypted_note_service::write_content(&tx, &state_guard, &id, &updated, is_encrypted, &now)?;
+            retargeted.push(id);
+        }
+    }
+
+    let reason = format!("Merged into {}", target.title);
+    for id in &merged_ids {
+        if trash_service::move_to_trash(&tx, id, Some(&reason)).map_err(|e| e.to_string())? {
+            revision_service::record_trashed(&tx, &batch_id, id).map_err(|e| e.to_string())?;
+        }
+    }
+
+    let batch = revision_service::finish_batch(&tx, &batch_id).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(MergeResult {
+        note_id: target.id,
+        title: target.title,
+        merged: merged_ids,
+        retargeted,
+        locked_notes,
+        batch,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (
+                internal_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                id TEXT UNIQUE NOT NULL,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                folder_id TEXT,
+                is_daily_note BOOLEAN DEFAULT FALSE,
+                properties TEXT,
+                word_count INTEGER DEFAULT 0,
+                reading_time INTEGER DEFAULT 0,
+                content_encrypted BLOB,
+                nonce BLOB,
+                content_plaintext TEXT
+            );
+            CREATE TABLE tags (id TEXT PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+            CREATE TABLE note_tags (note_id TEXT NOT NULL, tag_id TEXT NOT NULL, PRIMARY KEY (note_id, tag_id));
+            INSERT INTO notes (id, title, content, updated_at) VALUES
+                ('a', 'Tokio runtime', 'The tokio runtime schedules async tasks across worker threads using work stealing.', '2024-01-01'),
+                ('b', 'Tokio runtime (clip)', 'The tokio runtime schedules async tasks across worker threads using work stealing!', '2024-02-01'),
+                ('c', 'Sourdough', 'Feed the starter twice a day and keep it somewhere warm before baking bread.', '2024-01-01'),
+                ('d', 'Reading list', 'See [[tokio runtime (clip)#Workers|the clip]] and [[Sourdough]].', '2024-01-01');
+            INSERT INTO tags (id, name) VALUES ('t1', 'rust'), ('t2', 'async');
+            INSERT INTO note_tags (note_id, tag_id) VALUES ('a', 't1'), ('b', 't1'), ('b', 't2');",
+        )
+        .unwrap();
+        revision_service::create_tables(&conn).unwrap();
+        trash_service::create_tables(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_simhash_similarity() {
+        let a = simhash(&words("the quick brown fox jumps over the lazy dog near the river bank"));
+        let b = simhash(&words("The quick brown fox jumps over the lazy dog near the river bank."));
+        let c = simhash(&words("completely different text about baking bread with a sourdough starter"));
+        assert_eq!(simhash_similarity(a, b), 1.0);
+        assert!(simhash_similarity(a, c) < MIN_TEXT_THRESHOLD);
+    }
+
+    #[test]
+    fn test_find_and_merge_duplicates() {
+        let conn = setup();
+        let clusters = find_duplicates(&conn, None, false, 10).unwrap();
+        assert_eq!(clusters.len(), 1);
+        let ids: Vec<&str> = clusters[0].notes.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, ["b", "a"]);
+
+        let state = PassphraseState::new();
+        let result = merge_notes(&conn, &state, &["a".to_string(), "b".to_string()], MergeStrategy::KeepNewest).unwrap();
+        assert_eq!((result.note_id.as_str(), result.merged.as_slice()), ("b", &["a".to_string()][..]));
+        assert!(result.retargeted.is_empty());
+
+        let result = merge_notes(&conn, &state, &["c".to_string(), "b".to_string()], MergeStrategy::Append).unwrap();
+        assert_eq!(result.retargeted, ["d"]);
+        let content: String = conn.query_row("SELECT content FROM notes WHERE id = 'c'", [], |r| r.get(0)).unwrap();
+        assert!(content.ends_with("## Tokio runtime (clip)\n\nThe tokio runtime schedules async tasks across worker threads using work stealing!"));
+        let link: String = conn.query_row("SELECT content FROM notes WHERE id = 'd'", [], |r| r.get(0)).unwrap();
+        assert_eq!(link, "See [[Sourdough#Workers|the clip]] and [[Sourdough]].");
+
+        let tags: i64 = conn.query_row("SELECT COUNT(*) FROM note_tags WHERE note_id = 'c'", [], |r| r.get(0)).unwrap();
+        assert_eq!(tags, 2);
+        let trash: Vec<String> = trash_service::list_trash(&conn).unwrap().into_iter().map(|n| n.id).collect();
+        assert_eq!(trash.len(), 2);
+
+        trash_service::restore_note(&conn, "b").unwrap();
+        let restored_tags: i64 = conn.query_row("SELECT COUNT(*) FROM note_tags WHERE note_id = 'b'", [], |r| r.get(0)).unwrap();
+        assert_eq!(restored_tags, 2);
+        assert!(merge_notes(&conn, &state, &["c".to_string()], MergeStrategy::Append).is_err());
+    }
+
+    #[test]
+    fn test_undo_merge_restores_trashed_notes_and_tags() {
+        let conn = setup();
+        let state = PassphraseState::new();
+        let result = merge_notes(&conn, &state, &["c".to_string(), "b".to_string()], MergeStrategy::Append).unwrap();
+        assert_eq!(result.batch.note_count, 3);
+
+        let undo = revision_service::undo_batch(&conn, &result.batch.id).unwrap();
+        assert_eq!((undo.restored, undo.skipped.len()), (3, 0));
+        let content: String = conn.query_row("SELECT content FROM notes WHERE id = 'c'", [], |r| r.get(0)).unwrap();
+        assert!(content.starts_with("Feed the starter") && !content.contains("tokio"));
+        let tags = |id: &str| -> i64 {
+            conn.query_row("SELECT COUNT(*) FROM note_tags WHERE note_id = ?", params![id], |r| r.get(0)).unwrap()
+        };
+        assert_eq!((tags("b"), tags("c")), (2, 0));
+        assert!(trash_service::list_trash(&conn).unwrap().is_empty());
+        let link: String = conn.query_row("SELECT content FROM notes WHERE id = 'd'", [], |r| r.get(0)).unwrap();
+        assert!(link.contains("[[tokio runtime (clip)#Workers|the clip]]"));
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::{Error as E, Result};
+use candle_core::{Device, IndexOp, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config, DTYPE};
+use tokenizers::{Tokenizer, TruncationParams};
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use serde::Serialize;
+
+/// Sentence-transformers layout: a BERT-class model (MiniLM, BGE, ...) in safetensors
+const MODEL_DIR: &str = "resources/embeddings";
+const CONFIG_FILENAME: &str = "config.json";
+const TOKENIZER_FILENAME: &str = "tokenizer.json";
+const WEIGHTS_FILENAME: &str = "model.safetensors";
+/// Written by sentence-transformers; says whether the model pools by CLS token
+const POOLING_CONFIG: &str = "1_Pooling/config.json";
+const MAX_TOKENS: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pooling {
+    /// Average of all token vectors (MiniLM and most sentence-transformers)
+    Mean,
+    /// The [CLS] token vector (BGE)
+    Cls,
+}
+
+#[derive(Serialize, Clone)]
+pub struct EmbeddingModelStatus {
+    pub installed: bool,
+    pub loaded: bool,
+    pub model_dir: String,
+    pub model: Option<String>,
+}
+
+pub struct EmbeddingModel {
+    model: BertModel,
+    tokenizer: Tokenizer,
+    pooling: Pooling,
+    /// Stored with every vector so switching models re-embeds the vault
+    pub name: String,
+}
+
+impl EmbeddingModel {
+    pub fn load(dir: &Path) -> Result<Self> {
+        let config_text = std::fs::read_to_string(dir.join(CONFIG_FILENAME))?;
+        let config: Config = serde_json::from_str(&config_text)?;
+        let raw: serde_json::Value = serde_json::from_str(&config_text)?;
+        let name = raw
+            .get("_name_or_path")
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default());
+
+        let pooling = std::fs::read_to_string(dir.join(POOLING_CONFIG))
+            .ok()
+            .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+            .and_then(|v| v.get("pooling_mode_cls_token").and_then(|b| b.as_bool()))
+            .map(|cls| if cls { Pooling::Cls } else { Pooling::Mean })
+            .unwrap_or(Pooling::Mean);
+
+        let mut tokenizer = Tokenizer::from_file(dir.join(TOKENIZER_FILENAME)).map_err(E::msg)?;
+        tokenizer
+            .with_padding(None)
+            .with_truncation(Some(TruncationParams { max_length: MAX_TOKENS, ..Default::default() }))
+            .map_err(E::msg)?;
+
+        let start = std::time::Instant::now();
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[dir.join(WEIGHTS_FILENAME)], DTYPE, &Device::Cpu)? };
+        let model = BertModel::load(vb, &config)?;
+        println!("Embedding model {} loaded in {:.2}s", name, start.elapsed().as_secs_f64());
+
+        Ok(Self { model, tokenizer, pooling, name })
+    }
+
+    /// L2-normalized sentence embedding of `text`
+    pub fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let tokens = self.tokenizer.encode(text, true).map_err(E::msg)?;
+        let input_ids = Tensor::new(tokens.get_ids(), &Device::Cpu)?.unsqueeze(0)?;
+        let token_type_ids = input_ids.zeros_like()?;
+
+        // (1, tokens, hidden); one text at a time, so there is no padding to mask
+        let output = self.model.forward(&input_ids, &token_type_ids)?;
+        let pooled = match self.pooling {
+            Pooling::Mean => {
+                let (_, n_tokens, _) = output.dims3()?;
+                (output.sum(1)? / n_tokens as f64)?
+            }
+            Pooling::Cls => output.i((.., 0))?,
+        };
+        let norm = pooled.sqr()?.sum_keepdim(1)?.sqrt()?;
+        let normalized = pooled.broadcast_div(&norm)?;
+        Ok(normalized.squeeze(0)?.to_vec1::<f32>()?)
+    }
+}
+
+/// Lazily loaded embedding model, shared by commands and the background worker
+pub struct EmbeddingState {
+    pub model: Mutex<Option<EmbeddingModel>>,
+    model_dir: PathBuf,
+}
+
+impl EmbeddingState {
+    pub fn new() -> Self {
+        Self {
+            model: Mutex::new(None),
+            model_dir: PathBuf::from(MODEL_DIR),
+        }
+    }
+
+    pub fn is_installed(&self) -> bool {
+        [CONFIG_FILENAME, TOKENIZER_FILENAME, WEIGHTS_FILENAME]
+            .iter()
+            .all(|f| self.model_dir.join(f).exists())
+    }
+
+    pub fn get_status(&self) -> EmbeddingModelStatus {
+        let model_guard = self.model.lock().unwrap();
+        EmbeddingModelStatus {
+            installed: self.is_installed(),
+            loaded: model_guard.is_some(),
+            model_dir: self.model_dir.to_string_lossy().to_string(),
+            model: model_guard.as_ref().map(|m| m.name.clone()),
+        }
+    }
+
+    /// Load the model if its files are present; `Ok(false)` means not installed
+    pub fn ensure_loaded(&self) -> Result<bool> {
+        let mut model_guard = self.model.lock().unwrap();
+        if model_guard.is_some() {
+            return Ok(true);
+        }
+        if !self.is_installed() {
+            return Ok(false);
+        }
+        *model_guard = Some(EmbeddingModel::load(&self.model_dir)?);
+        Ok(true)
+    }
+
+    pub fn model_name(&self) -> Option<String> {
+        self.model.lock().unwrap().as_ref().map(|m| m.name.clone())
+    }
+
+    pub fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let model_guard = self.model.lock().unwrap();
+        let model = model_guard.as_ref().ok_or(E::msg("Embedding model not loaded"))?;
+        model.embed(text)
+    }
+}
@@ -0,0 +1,441 @@
+//! Stored note embeddings and semantic search
+//!
+//! Notes are split into overlapping word windows ([`chunk_text`]), each chunk
+//! is embedded by `embedding_model`, and the normalized vectors are stored in
+//! `note_embeddings` as little-endian f32 blobs. `note_embedding_state`
+//! remembers the content hash and model each note was embedded with, so
+//! unchanged notes are skipped by [`pending_notes`].
+
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Words per chunk; keeps chunks well under BERT's 512-token limit
+pub const CHUNK_WORDS: usize = 200;
+/// Words shared by consecutive chunks
+pub const CHUNK_OVERLAP: usize = 40;
+const SNIPPET_CHARS: usize = 200;
+
+pub fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS note_embeddings (
+            note_id TEXT NOT NULL,
+            chunk_index INTEGER NOT NULL,
+            -- byte range of the chunk in the note's searchable content
+            chunk_start INTEGER NOT NULL,
+            chunk_end INTEGER NOT NULL,
+            model TEXT NOT NULL,
+            dim INTEGER NOT NULL,
+            vector BLOB NOT NULL,
+            PRIMARY KEY (note_id, chunk_index)
+         );
+
+         CREATE TABLE IF NOT EXISTS note_embedding_state (
+            note_id TEXT PRIMARY KEY,
+            content_hash TEXT NOT NULL,
+            model TEXT NOT NULL,
+            -- notes.updated_at when last checked, so unchanged notes aren't re-hashed
+            source_updated_at DATETIME,
+            embedded_at DATETIME DEFAULT CURRENT_TIMESTAMP
+         );
+
+         DROP TRIGGER IF EXISTS note_embeddings_ad;
+         CREATE TRIGGER note_embeddings_ad AFTER DELETE ON notes BEGIN
+           DELETE FROM note_embeddings WHERE note_id = old.id;
+           DELETE FROM note_embedding_state WHERE note_id = old.id;
+         END;",
+    )
+}
+
+/// A chunk of note content; `start`/`end` are byte offsets into the content
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextChunk {
+    pub index: usize,
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// A note whose embeddings are missing or out of date
+#[derive(Debug, Clone)]
+pub struct PendingNote {
+    pub id: String,
+    pub title: String,
+    pub content: String,
+    pub content_hash: String,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SemanticHit {
+    pub note_id: String,
+    pub title: String,
+    /// Cosine similarity of the best-matching chunk
+    pub score: f32,
+    pub chunk_index: usize,
+    pub snippet: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EmbeddingStats {
+    pub embedded_notes: usize,
+    pub total_notes: usize,
+    pub chunks: usize,
+}
+
+/// Split content into windows of `CHUNK_WORDS` words overlapping by `CHUNK_OVERLAP`
+///
+/// Blank content gives one empty chunk, so the note is still embedded by title.
+pub fn chunk_text(content: &str) -> Vec<TextChunk> {
+    let words: Vec<(usize, usize)> = content
+        .split_whitespace()
+        .map(|w| {
+            let start = w.as_ptr() as usize - content.as_ptr() as usize;
+            (start, start + w.len())
+        })
+        .collect();
+
+    if words.is_empty() {
+        return vec![TextChunk { index: 0, start: 0, end: 0, text: String::new() }];
+    }
+
+    let mut chunks = Vec::new();
+    let mut first = 0;
+    while first < words.len() {
+        let last = (first + CHUNK_WORDS).min(words.len()) - 1;
+        let (start, end) = (words[first].0, words[last].1);
+        chunks.push(TextChunk {
+            index: chunks.len(),
+            start,
+            end,
+            text: content[start..end].to_string(),
+        });
+        if last + 1 == words.len() {
+            break;
+        }
+        first += CHUNK_WORDS - CHUNK_OVERLAP;
+    }
+    chunks
+}
+
+/// Text handed to the model for a chunk; the title gives every chunk its topic
+pub fn chunk_input(title: &str, chunk: &str) -> String {
+    if chunk.is_empty() {
+        title.to_string()
+    } else {
+        format!("{}\n{}", title, chunk)
+    }
+}
+
+pub fn content_hash(title: &str, content: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(title.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(content.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+fn encode_vector(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn decode_vector(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+/// Dot product; equal to cosine similarity for the normalized vectors stored here
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Up to `limit` notes that need (re-)embedding with `model`
+///
+/// Notes whose `updated_at` moved but whose text hashes the same are marked
+/// as checked instead of being returned.
+pub fn pending_notes(conn: &Connection, model: &str, limit: usize) -> Result<Vec<PendingNote>> {
+    let mut stmt = conn.prepare(
+        "SELECT n.id, n.title, COALESCE(n.content_plaintext, n.content, ''), n.updated_at,
+                s.content_hash, s.model
+         FROM notes n LEFT JOIN note_embedding_state s ON s.note_id = n.id
+         WHERE s.note_id IS NULL OR s.model != ?1 OR s.source_updated_at IS NOT n.updated_at
+         ORDER BY n.updated_at DESC",
+    )?;
+    let mut rows = stmt.query(params![model])?;
+
+    let mut pending = Vec::new();
+    let mut unchanged = Vec::new();
+    while let Some(row) = rows.next()? {
+        let title: String = row.get(1)?;
+        let content: String = row.get(2)?;
+        let hash = content_hash(&title, &content);
+        let stored_hash: Option<String> = row.get(4)?;
+        let stored_model: Option<String> = row.get(5)?;
+        let updated_at: Option<String> = row.get(3)?;
+
+        if stored_hash.as_deref() == Some(hash.as_str()) && stored_model.as_deref() == Some(model) {
+            unchanged.push((row.get::<_, String>(0)?, updated_at));
+            continue;
+        }
+        pending.push(PendingNote { id: row.get(0)?, title, content, content_hash: hash, updated_at });
+        if pending.len() >= limit {
+            break;
+        }
+    }
+    drop(rows);
+
+    for (note_id, updated_at) in unchanged {
+        conn.execute(
+            "UPDATE note_embedding_state SET source_updated_at = ? WHERE note_id = ?",
+            params![updated_at, note_id],
+        )?;
+    }
+    Ok(pending)
+}
+
+/// Replace a note's chunk vectors and record what they were computed from
+pub fn store_embeddings(
+    conn: &Connection,
+    note: &PendingNote,
+    model: &str,
+    chunks: &[(TextChunk, Vec<f32>)],
+) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    // The note may have been deleted while it was being embedded
+    let exists: bool = tx.query_row("SELECT EXISTS(SELECT 1 FROM notes WHERE id = ?)", params![note.id], |row| row.get(0))?;
+    if !exists {
+        return Ok(());
+    }
+
+    tx.execute("DELETE FROM note_embeddings WHERE note_id = ?", params![note.id])?;
+    for (chunk, vector) in chunks {
+        tx.execute(
+            "INSERT INTO note_embeddings (note_id, chunk_index, chunk_start, chunk_end, model, dim, vector)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![
+                note.id,
+                chunk.index as i64,
+                chunk.start as i64,
+                chunk.end as i64,
+                model,
+                vector.len() as i64,
+                encode_vector(vector),
+            ],
+        )?;
+    }
+    tx.execute(
+        "INSERT INTO note_embedding_state (note_id, content_hash, model, source_updated_at, embedded_at)
+         VALUES (?1, ?2, ?3, ?4, CURRENT_TIMESTAMP)
+         ON CONFLICT(note_id) DO UPDATE SET
+            content_hash = excluded.content_hash,
+            model = excluded.model,
+            source_updated_at = excluded.source_updated_at,
+            embedded_at = excluded.embedded_at",
+        params![note.id, note.content_hash, model, note.updated_at],
+    )?;
+    tx.commit()
+}
+
+/// The `k` notes closest to `query_vector`, scored by their best chunk
+pub fn semantic_search(conn: &Connection, query_vector: &[f32], model: &str, k: usize) -> Result<Vec<SemanticHit>> {
+    let mut stmt = conn.prepare(
+        "SELECT note_id, chunk_index, vector FROM note_embeddings WHERE model = ? AND dim = ?",
+    )?;
+    let mut rows = stmt.query(params![model, query_vector.len() as i64])?;
+
+    let mut best: std::collections::HashMap<String, (f32, usize)> = std::collections::HashMap::new();
+    while let Some(row) = rows.next()? {
+        let note_id: String = row.get(0)?;
+        let chunk_index: i64 = row.get(1)?;
+        let vector: Vec<u8> = row.get(2)?;
+        let score = cosine(query_vector, &decode_vector(&vector));
+        let entry = best.entry(note_id).or_insert((f32::MIN, 0));
+        if score > entry.0 {
+            *entry = (score, chunk_index as usize);
+        }
+    }
+
+    let mut ranked: Vec<(String, f32, usize)> = best.into_iter().map(|(id, (s, c))| (id, s, c)).collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.truncate(k);
+
+    let mut hits = Vec::with_capacity(ranked.len());
+    for (note_id, score, chunk_index) in ranked {
+        let note: Option<(String, String, i64, i64)> = conn
+            .query_row(
+                "SELECT n.title, COALESCE(n.content_plaintext, n.content, ''), e.chunk_start, e.chunk_end
+                 FROM notes n JOIN note_embeddings e ON e.note_id = n.id
+                 WHERE n.id = ? AND e.chunk_index = ?",
+                params![note_id, chunk_index as i64],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()?;
+        if let Some((title, content, start, end)) = note {
+            let snippet = content
+                .get(start as usize..end as usize)
+                .unwrap_or_default()
+                .chars()
+                .take(SNIPPET_CHARS)
+                .collect();
+            hits.push(SemanticHit { note_id, title, score, chunk_index, snippet });
+        }
+    }
+    Ok(hits)
+}
+
+/// Normalized mean of a note's chunk vectors, with the model that produced them
+pub fn note_vector(conn: &Connection, note_id: &str) -> Result<Option<(Vec<f32>, String)>> {
+    let mut stmt = conn.prepare("SELECT vector, model FROM note_embeddings WHERE note_id = ?")?;
+    let mut rows = stmt.query(params![note_id])?;
+
+    let mut sum: Vec<f32> = Vec::new();
+    let mut model = String::new();
+    while let Some(row) = rows.next()? {
+        let vector = decode_vector(&row.get::<_, Vec<u8>>(0)?);
+        if sum.is_empty() {
+            sum = vec![0.0; vector.len()];
+            model = row.get(1)?;
+        }
+        sum.iter_mut().zip(&vector).for_each(|(s, v)| *s += v);
+    }
+
+    Ok(normalize(sum).map(|vector| (vector, model)))
+}
+
+/// [`note_vector`] of every embedded note, read in one pass
+pub fn note_vectors(conn: &Connection) -> Result<std::collections::HashMap<String, (Vec<f32>, String)>> {
+    let mut stmt = conn.prepare("SELECT note_id, vector, model FROM note_embeddings")?;
+    let mut rows = stmt.query([])?;
+
+    let mut sums: std::collections::HashMap<String, (Vec<f32>, String)> = std::collections::HashMap::new();
+    while let Some(row) = rows.next()? {
+        let vector = decode_vector(&row.get::<_, Vec<u8>>(1)?);
+        let (sum, _) = match sums.entry(row.get(0)?) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => entry.insert((vec![0.0; vector.len()], row.get(2)?)),
+        };
+        sum.iter_mut().zip(&vector).for_each(|(s, v)| *s += v);
+    }
+
+    Ok(sums
+        .into_iter()
+        .filter_map(|(note_id, (sum, model))| normalize(sum).map(|vector| (note_id, (vector, model))))
+        .collect())
+}
+
+/// Scale a summed vector to unit length; `None` if it is all zeros
+fn normalize(sum: Vec<f32>) -> Option<Vec<f32>> {
+    let norm = sum.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return None;
+    }
+    Some(sum.into_iter().map(|v| v / norm).collect())
+}
+
+/// A note's chunk vectors from `model`, with each chunk's byte range in the note's content
+pub fn chunk_vectors(conn: &Connection, note_id: &str, model: &str) -> Result<Vec<(usize, usize, Vec<f32>)>> {
+    let mut stmt = conn.prepare(
+        "SELECT chunk_start, chunk_end, vector FROM note_embeddings
+         WHERE note_id = ? AND model = ? ORDER BY chunk_index",
+    )?;
+    let chunks = stmt.query_map(params![note_id, model], |row| {
+        let start: i64 = row.get(0)?;
+        let end: i64 = row.get(1)?;
+        let vector: Vec<u8> = row.get(2)?;
+        Ok((start as usize, end as usize, decode_vector(&vector)))
+    })?;
+    chunks.collect()
+}
+
+pub fn stats(conn: &Connection) -> Result<EmbeddingStats> {
+    let embedded: i64 = conn.query_row("SELECT COUNT(*) FROM note_embedding_state", [], |row| row.get(0))?;
+    let total: i64 = conn.query_row("SELECT COUNT(*) FROM notes", [], |row| row.get(0))?;
+    let chunks: i64 = conn.query_row("SELECT COUNT(*) FROM note_embeddings", [], |row| row.get(0))?;
+    Ok(EmbeddingStats {
+        embedded_notes: embedded as usize,
+        total_notes: total as usize,
+        chunks: chunks as usize,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (
+                internal_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                id TEXT UNIQUE NOT NULL,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                content_plaintext TEXT
+            );
+            INSERT INTO notes (id, title, content, updated_at) VALUES
+                ('a', 'Cats', 'cats purr', '2024-01-01'),
+                ('b', 'Dogs', 'dogs bark', '2024-01-01');",
+        )
+        .unwrap();
+        create_tables(&conn).unwrap();
+        conn
+    }
+
+    /// Stand-in for the model: a unit vector per note topic
+    fn fake_embed(text: &str) -> Vec<f32> {
+        if text.contains("cat") || text.contains("Cat") { vec![1.0, 0.0] } else { vec![0.0, 1.0] }
+    }
+
+    fn embed_pending(conn: &Connection) -> usize {
+        let pending = pending_notes(conn, "test", 10).unwrap();
+        for note in &pending {
+            let chunks: Vec<_> = chunk_text(&note.content)
+                .into_iter()
+                .map(|c| {
+                    let v = fake_embed(&chunk_input(&note.title, &c.text));
+                    (c, v)
+                })
+                .collect();
+            store_embeddings(conn, note, "test", &chunks).unwrap();
+        }
+        pending.len()
+    }
+
+    #[test]
+    fn test_chunk_text_overlaps() {
+        let content = (0..450).map(|i| format!("w{}", i)).collect::<Vec<_>>().join(" ");
+        let chunks = chunk_text(&content);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks[0].text.starts_with("w0 ") && chunks[0].text.ends_with(" w199"));
+        assert!(chunks[1].text.starts_with("w160 "));
+        assert_eq!(&content[chunks[2].start..chunks[2].end], chunks[2].text);
+        assert_eq!(chunk_text("   ")[0].text, "");
+    }
+
+    #[test]
+    fn test_incremental_embedding_and_search() {
+        let conn = setup();
+        assert_eq!(embed_pending(&conn), 2);
+        assert_eq!(embed_pending(&conn), 0);
+
+        // Touching a note without changing its text doesn't re-embed it
+        conn.execute("UPDATE notes SET updated_at = '2024-02-01' WHERE id = 'a'", []).unwrap();
+        assert_eq!(embed_pending(&conn), 0);
+        conn.execute("UPDATE notes SET content = 'cats nap', updated_at = '2024-03-01' WHERE id = 'a'", []).unwrap();
+        assert_eq!(embed_pending(&conn), 1);
+
+        let hits = semantic_search(&conn, &[1.0, 0.0], "test", 1).unwrap();
+        assert_eq!(hits[0].note_id, "a");
+        assert_eq!(hits[0].snippet, "cats nap");
+        assert_eq!(note_vector(&conn, "b").unwrap(), Some((vec![0.0, 1.0], "test".to_string())));
+        assert_eq!(note_vector(&conn, "missing").unwrap(), None);
+        assert_eq!(note_vectors(&conn).unwrap().get("b"), Some(&(vec![0.0, 1.0], "test".to_string())));
+
+        conn.execute("DELETE FROM notes WHERE id = 'a'", []).unwrap();
+        let stats = stats(&conn).unwrap();
+        assert_eq!((stats.embedded_notes, stats.total_notes, stats.chunks), (1, 1, 1));
+    }
+}
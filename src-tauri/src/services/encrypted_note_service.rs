@@ -1,4 +1,4 @@
-use rusqlite::{Connection, Result};
+use rusqlite::{params, Connection, Result};
 use crate::services::passphrase_service::PassphraseState;
 use std::sync::Mutex;
 
@@ -201,6 +201,71 @@ pub fn get_all_encrypted_notes(
     Ok(result)
 }
 
+/// Encrypt note content for storage
+///
+/// # Returns
+/// Base64 (content_encrypted, nonce) column values
+pub fn encrypt_content(state: &PassphraseState, content: &str) -> Result<(String, String), String> {
+    let (nonce, encrypted) = state.encrypt(content.as_bytes())
+        .map_err(|e| format!("Encryption failed: {}", e))?;
+    Ok((base64::encode(&encrypted), base64::encode(&nonce)))
+}
+
+/// Decrypt stored (base64) content_encrypted and nonce column values
+pub fn decrypt_content(state: &PassphraseState, encrypted_b64: &str, nonce_b64: &str) -> Result<String, String> {
+    let encrypted = base64::decode(encrypted_b64)
+        .map_err(|e| format!("Invalid base64: {}", e))?;
+    let nonce = base64::decode(nonce_b64)
+        .map_err(|e| format!("Invalid base64: {}", e))?;
+    let decrypted = state.decrypt(&encrypted, &nonce)?;
+    String::from_utf8(decrypted).map_err(|e| format!("Invalid UTF-8: {}", e))
+}
+
+/// Readable content of a note row, `None` if it is encrypted and locked
+///
+/// Takes the row's `content`, `content_encrypted` and `nonce` columns.
+pub fn load_content(
+    state: &PassphraseState,
+    content: String,
+    encrypted: Option<String>,
+    nonce: Option<String>,
+) -> Option<String> {
+    match encrypted {
+        Some(encrypted) if state.is_enabled() => decrypt_content(state, &encrypted, &nonce.unwrap_or_default()).ok(),
+        Some(_) => None,
+        None => Some(content),
+    }
+}
+
+/// Write `content` to a note, encrypting it if `encrypted`
+///
+/// Encrypted notes keep a plaintext copy in `content_plaintext` for search.
+pub fn write_content(
+    conn: &Connection,
+    state: &PassphraseState,
+    note_id: &str,
+    content: &str,
+    encrypted: bool,
+    now: &str,
+) -> Result<(), String> {
+    if encrypted {
+        let (encrypted, nonce) = encrypt_content(state, content)?;
+        conn.execute(
+            "UPDATE notes SET content = '', updated_at = ?, content_encrypted = ?, nonce = ?, content_plaintext = ?
+             WHERE id = ?",
+            params![now, encrypted, nonce, content, note_id],
+        )
+    } else {
+        // notes_au re-indexes the note in notes_fts and notes_trigram
+        conn.execute(
+            "UPDATE notes SET content = ?, updated_at = ? WHERE id = ?",
+            params![content, now, note_id],
+        )
+    }
+    .map(|_| ())
+    .map_err(|e| format!("Database error: {}", e))
+}
+
 /// Delete encrypted note (same as regular delete)
 pub fn delete_encrypted_note(conn: &Connection, id: &str) -> Result<(), String> {
     conn.execute("DELETE FROM notes WHERE id = ?", [id])
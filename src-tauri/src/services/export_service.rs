@@ -45,7 +45,7 @@ pub fn export_notes(conn: &Connection, export_dir: &Path) -> Result<()> {
     Ok(())
 }
 
-fn sanitize_filename(name: &str) -> String {
+pub(crate) fn sanitize_filename(name: &str) -> String {
     name.chars()
         .map(|c| if c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' { c } else { '_' })
         .collect::<String>()
@@ -0,0 +1,303 @@
+//! Vault-wide find and replace
+//!
+//! [`preview`] lists every match with surrounding context; [`apply`] makes
+//! the same replacements in one transaction. Encrypted notes are decrypted,
+//! edited and re-encrypted, so they need the passphrase to be set. Every
+//! changed note is snapshotted in a `revision_service` batch, so the whole
+//! operation can be undone at once.
+
+use std::sync::Mutex;
+
+use regex::{Regex, RegexBuilder};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+use crate::services::encrypted_note_service;
+use crate::services::organization_service::{self, NoteScope};
+use crate::services::passphrase_service::PassphraseState;
+use crate::services::revision_service::{self, RevisionBatch};
+
+/// Characters of context shown on each side of a match
+const CONTEXT_CHARS: usize = 40;
+/// Preview stops listing matches after this many (counts stay exact)
+const MAX_PREVIEW_MATCHES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchField {
+    Title,
+    Content,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FindReplaceMatch {
+    pub note_id: String,
+    pub note_title: String,
+    pub field: MatchField,
+    /// 1-based line of the match
+    pub line: usize,
+    pub before: String,
+    pub matched: String,
+    pub replacement: String,
+    pub after: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FindReplacePreview {
+    pub matches: Vec<FindReplaceMatch>,
+    pub match_count: usize,
+    pub note_count: usize,
+    /// Matches beyond `MAX_PREVIEW_MATCHES` were counted but not listed
+    pub truncated: bool,
+    /// Encrypted notes in scope that can't be read without the passphrase
+    pub locked_notes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FindReplaceResult {
+    pub batch: RevisionBatch,
+    pub match_count: usize,
+    pub locked_notes: Vec<String>,
+}
+
+/// A note in scope, with its content readable
+struct ScopedNote {
+    id: String,
+    title: String,
+    content: String,
+    encrypted: bool,
+}
+
+fn build_pattern(pattern: &str, regex: bool) -> Result<Regex, String> {
+    if pattern.is_empty() {
+        return Err("Search pattern cannot be empty".to_string());
+    }
+    let source = if regex { pattern.to_string() } else { regex::escape(pattern) };
+    let re = RegexBuilder::new(&source)
+        .multi_line(true)
+        .build()
+        .map_err(|e| format!("Invalid pattern: {}", e))?;
+    // A pattern like `a*` would "replace" between every pair of characters
+    if re.is_match("") {
+        return Err("Pattern must not match empty text".to_string());
+    }
+    Ok(re)
+}
+
+/// Replacement text for one match; `$1`-style references only expand in regex mode
+fn expand(caps: &regex::Captures, replacement: &str, regex: bool) -> String {
+    if regex {
+        let mut out = String::new();
+        caps.expand(replacement, &mut out);
+        out
+    } else {
+        replacement.to_string()
+    }
+}
+
+fn replace_all(re: &Regex, text: &str, replacement: &str, regex: bool) -> (String, usize) {
+    let count = re.find_iter(text).count();
+    if count == 0 {
+        return (text.to_string(), 0);
+    }
+    let replaced = if regex {
+        re.replace_all(text, replacement).into_owned()
+    } else {
+        re.replace_all(text, regex::NoExpand(replacement)).into_owned()
+    };
+    (replaced, count)
+}
+
+/// Load the notes in scope; encrypted notes that can't be decrypted go to `locked`
+fn load_scope(
+    conn: &Connection,
+    passphrase_state: &Mutex<PassphraseState>,
+    scope: &NoteScope,
+) -> Result<(Vec<ScopedNote>, Vec<String>), String> {
+    let ids = organization_service::note_ids_in_scope(conn, scope).map_err(|e| e.to_string())?;
+    let state_guard = passphrase_state.lock().map_err(|e| e.to_string())?;
+
+    let mut stmt = conn
+        .prepare("SELECT title, content, content_encrypted, nonce FROM notes WHERE id = ?")
+        .map_err(|e| e.to_string())?;
+    let mut notes = Vec::new();
+    let mut locked = Vec::new();
+    for id in ids {
+        let (title, content, encrypted, nonce): (String, String, Option<String>, Option<String>) = stmt
+            .query_row(params![id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+            .map_err(|e| e.to_string())?;
+
+        let is_encrypted = encrypted.is_some();
+        match encrypted_note_service::load_content(&state_guard, content, encrypted, nonce) {
+            Some(content) => notes.push(ScopedNote { id, title, content, encrypted: is_encrypted }),
+            None => locked.push(id),
+        }
+    }
+    Ok((notes, locked))
+}
+
+/// Text before `start`, cut to `CONTEXT_CHARS` characters and the current line
+fn context_before(text: &str, start: usize) -> String {
+    let line = &text[text[..start].rfind('\n').map_or(0, |i| i + 1)..start];
+    let skip = line.chars().count().saturating_sub(CONTEXT_CHARS);
+    line.chars().skip(skip).collect()
+}
+
+/// Text after `end`, cut to `CONTEXT_CHARS` characters and the current line
+fn context_after(text: &str, end: usize) -> String {
+    let rest = &text[end..];
+    let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+    line.chars().take(CONTEXT_CHARS).collect()
+}
+
+/// List every match of `pattern` in the scoped notes' titles and content
+pub fn preview(
+    conn: &Connection,
+    passphrase_state: &Mutex<PassphraseState>,
+    pattern: &str,
+    replacement: &str,
+    scope: &NoteScope,
+    regex: bool,
+) -> Result<FindReplacePreview, String> {
+    let re = build_pattern(pattern, regex)?;
+    let (notes, locked_notes) = load_scope(conn, passphrase_state, scope)?;
+
+    let mut preview = FindReplacePreview { locked_notes, ..Default::default() };
+    for note in &notes {
+        let mut note_matched = false;
+        for (field, text) in [(MatchField::Title, &note.title), (MatchField::Content, &note.content)] {
+            for caps in re.captures_iter(text) {
+                let m = caps.get(0).expect("group 0 always matches");
+                note_matched = true;
+                preview.match_count += 1;
+                if preview.matches.len() >= MAX_PREVIEW_MATCHES {
+                    preview.truncated = true;
+                    continue;
+                }
+                preview.matches.push(FindReplaceMatch {
+                    note_id: note.id.clone(),
+                    note_title: note.title.clone(),
+                    field,
+                    line: text[..m.start()].matches('\n').count() + 1,
+                    before: context_before(text, m.start()),
+                    matched: m.as_str().to_string(),
+                    replacement: expand(&caps, replacement, regex),
+                    after: context_after(text, m.end()),
+                });
+            }
+        }
+        if note_matched {
+            preview.note_count += 1;
+        }
+    }
+    Ok(preview)
+}
+
+/// Replace every match in one transaction, recording an undoable revision batch
+///
+/// Encrypted notes the passphrase can't unlock are left unchanged and listed
+/// in `locked_notes`.
+pub fn apply(
+    conn: &Connection,
+    passphrase_state: &Mutex<PassphraseState>,
+    pattern: &str,
+    replacement: &str,
+    scope: &NoteScope,
+    regex: bool,
+) -> Result<FindReplaceResult, String> {
+    let re = build_pattern(pattern, regex)?;
+    let (notes, locked_notes) = load_scope(conn, passphrase_state, scope)?;
+    let state_guard = passphrase_state.lock().map_err(|e| e.to_string())?;
+    let now = revision_service::batch_timestamp();
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let batch_id = revision_service::begin_batch(
+        &tx,
+        "find_replace",
+        &format!("Replace \"{}\" with \"{}\"", pattern, replacement),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let mut match_count = 0;
+    for note in notes {
+        let (title, title_count) = replace_all(&re, &note.title, replacement, regex);
+        let (content, content_count) = replace_all(&re, &note.content, replacement, regex);
+        if title_count + content_count == 0 {
+            continue;
+        }
+        match_count += title_count + content_count;
+
+        revision_service::record_revision(&tx, &batch_id, &note.id, &now).map_err(|e| e.to_string())?;
+        if title_count > 0 {
+            tx.execute("UPDATE notes SET title = ? WHERE id = ?", params![title, note.id])
+                .map_err(|e| format!("Database error: {}", e))?;
+        }
+        encrypted_note_service::write_content(&tx, &state_guard, &note.id, &content, note.encrypted, &now)?;
+    }
+
+    let batch = revision_service::finish_batch(&tx, &batch_id).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(FindReplaceResult { batch, match_count, locked_notes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::search_index;
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (
+                internal_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                id TEXT UNIQUE NOT NULL,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                folder_id TEXT,
+                content_encrypted BLOB,
+                nonce BLOB,
+                content_plaintext TEXT,
+                properties TEXT
+            );
+            CREATE TABLE folders (id TEXT PRIMARY KEY, name TEXT NOT NULL, parent_id TEXT);
+            CREATE TABLE tags (id TEXT PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+            CREATE TABLE note_tags (note_id TEXT NOT NULL, tag_id TEXT NOT NULL, PRIMARY KEY (note_id, tag_id));
+            INSERT INTO notes (id, title, content, updated_at) VALUES
+                ('a', 'Acme roadmap', 'Acme v2 ships in May.\nAsk Dana about Acme pricing.', '2024-01-01 00:00:00'),
+                ('b', 'Unrelated', 'nothing here', '2024-01-01 00:00:00');",
+        )
+        .unwrap();
+        revision_service::create_tables(&conn).unwrap();
+        search_index::create_tables(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_preview_apply_and_undo() {
+        let conn = setup();
+        let state = PassphraseState::new();
+
+        let preview = preview(&conn, &state, "Acme", "Globex", &NoteScope::All, false).unwrap();
+        assert_eq!((preview.match_count, preview.note_count), (3, 1));
+        let pricing = &preview.matches[2];
+        assert_eq!((pricing.field, pricing.line), (MatchField::Content, 2));
+        assert_eq!((pricing.before.as_str(), pricing.after.as_str()), ("Ask Dana about ", " pricing."));
+
+        let scope = NoteScope::Query { query: "nothing".to_string() };
+        let unrelated = super::preview(&conn, &state, "Acme|nothing", "", &scope, true).unwrap();
+        assert_eq!((unrelated.match_count, unrelated.note_count), (1, 1));
+
+        let result = apply(&conn, &state, r"Acme (v\d)", "Globex $1", &NoteScope::All, true).unwrap();
+        assert_eq!((result.match_count, result.batch.note_count), (1, 1));
+        let content: String = conn.query_row("SELECT content FROM notes WHERE id = 'a'", [], |r| r.get(0)).unwrap();
+        assert!(content.starts_with("Globex v2 ships"));
+
+        let undo = revision_service::undo_batch(&conn, &result.batch.id).unwrap();
+        assert_eq!(undo.restored, 1);
+        let content: String = conn.query_row("SELECT content FROM notes WHERE id = 'a'", [], |r| r.get(0)).unwrap();
+        assert!(content.starts_with("Acme v2 ships"));
+        assert!(revision_service::undo_batch(&conn, &result.batch.id).is_err());
+    }
+}
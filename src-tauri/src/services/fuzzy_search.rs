@@ -0,0 +1,257 @@
+//! Typo-tolerant search helpers
+//!
+//! Substring matches go through the `notes_trigram` index, and spelling
+//! suggestions come from the `notes_vocab` term list (both created by
+//! `search_index`).
+
+use crate::services::search_query::{self, QueryNode, TextColumn};
+use rusqlite::{params, Connection, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// The trigram tokenizer can't match anything shorter than this
+const MIN_TRIGRAM_LEN: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TermSuggestion {
+    pub term: String,
+    pub distance: usize,
+    pub doc_count: i64,
+}
+
+/// Edit distance between two strings, counted in characters
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[b.len()]
+}
+
+/// Largest edit distance worth suggesting for a word of this length
+fn max_distance(len: usize) -> usize {
+    match len {
+        0..=3 => 0,
+        4..=5 => 1,
+        _ => 2,
+    }
+}
+
+/// Whether any indexed word starts with `word`, which is what a prefix term matches
+pub fn term_exists(conn: &Connection, word: &str) -> Result<bool> {
+    let word = word.to_lowercase();
+    let upper = format!("{}\u{10FFFF}", word);
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM notes_vocab WHERE term >= ?1 AND term < ?2)",
+        params![word, upper],
+        |row| row.get(0),
+    )
+}
+
+/// Indexed words within a small edit distance of `word`, closest and most common first
+///
+/// Only words sharing the first letter are compared, so the vocabulary is
+/// read as one range instead of scanned in full. Typos in the first letter
+/// get no suggestion.
+pub fn suggest_terms(conn: &Connection, word: &str, limit: usize) -> Result<Vec<TermSuggestion>> {
+    let word = word.to_lowercase();
+    let len = word.chars().count();
+    let max = max_distance(len);
+    let Some(first) = word.chars().next() else {
+        return Ok(Vec::new());
+    };
+    if max == 0 {
+        return Ok(Vec::new());
+    }
+
+    let lower = first.to_string();
+    let upper = format!("{}\u{10FFFF}", first);
+    let mut stmt = conn.prepare(
+        "SELECT term, doc FROM notes_vocab
+         WHERE term >= ?1 AND term < ?2 AND length(term) BETWEEN ?3 AND ?4",
+    )?;
+    let rows = stmt.query_map(params![lower, upper, (len - max) as i64, (len + max) as i64], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+    })?;
+
+    let mut suggestions = Vec::new();
+    for row in rows {
+        let (term, doc_count) = row?;
+        if term == word {
+            continue;
+        }
+        let distance = levenshtein(&word, &term);
+        if distance <= max {
+            suggestions.push(TermSuggestion { term, distance, doc_count });
+        }
+    }
+
+    suggestions.sort_by(|a, b| a.distance.cmp(&b.distance).then(b.doc_count.cmp(&a.doc_count)));
+    suggestions.truncate(limit);
+    Ok(suggestions)
+}
+
+/// Spelling corrections for query words that match nothing in the index
+///
+/// Returns a map from the typed word (lowercased) to its best suggestion.
+pub fn corrections(conn: &Connection, node: &QueryNode) -> Result<HashMap<String, String>> {
+    let mut corrections = HashMap::new();
+    for term in search_query::text_terms(node) {
+        // Multi-word phrases and punctuated words don't map onto single vocabulary terms
+        if !term.chars().all(|c| c.is_alphanumeric()) {
+            continue;
+        }
+        let key = term.to_lowercase();
+        if corrections.contains_key(&key) || term_exists(conn, &key)? {
+            continue;
+        }
+        if let Some(best) = suggest_terms(conn, &key, 1)?.into_iter().next() {
+            corrections.insert(key, best.term);
+        }
+    }
+    Ok(corrections)
+}
+
+/// Apply `corrections` to the words of a query
+pub fn corrected_query(node: &QueryNode, corrections: &HashMap<String, String>) -> QueryNode {
+    search_query::map_terms(node, &|word: &str| corrections.get(&word.to_lowercase()).cloned())
+}
+
+/// Rewrite the query string the user typed with `corrections`, for "did you mean"
+pub fn corrected_query_text(query: &str, corrections: &HashMap<String, String>) -> String {
+    query
+        .split(' ')
+        .map(|word| {
+            let (prefix, rest) = word.split_at(word.len() - word.trim_start_matches(['-', '(']).len());
+            let core = rest.trim_end_matches(')');
+            let suffix = &rest[core.len()..];
+            match corrections.get(&core.to_lowercase()) {
+                Some(fixed) => format!("{}{}{}", prefix, fixed, suffix),
+                None => word.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `notes_trigram` MATCH expression for the positive text of a query
+///
+/// Keeps the query's AND/OR structure and `title:`/`content:` scopes; every
+/// word becomes a substring to look for, and NEAR groups just require all of
+/// their words. Filters and negations are left to the caller's SQL. Returns
+/// `None` when there is no text or a word is too short for trigram matching.
+pub fn substring_match_expr(node: &QueryNode) -> Option<String> {
+    let parts: Vec<&QueryNode> = match node {
+        QueryNode::And(parts) => parts.iter().filter(|p| search_query::is_text(p)).collect(),
+        other if search_query::is_text(other) => vec![other],
+        _ => Vec::new(),
+    };
+    if parts.is_empty() {
+        return None;
+    }
+    let exprs = parts.into_iter().map(trigram_expr).collect::<Option<Vec<_>>>()?;
+    Some(exprs.join(" AND "))
+}
+
+fn trigram_expr(node: &QueryNode) -> Option<String> {
+    let substring = |text: &str, column: Option<TextColumn>| {
+        if text.chars().count() < MIN_TRIGRAM_LEN {
+            return None;
+        }
+        let quoted = format!("\"{}\"", text.replace('"', "\"\""));
+        Some(match column {
+            Some(c) => format!("{} : {}", c.fts_name(), quoted),
+            None => quoted,
+        })
+    };
+    let group = |parts: Vec<Option<String>>, op: &str| {
+        parts.into_iter().collect::<Option<Vec<_>>>().map(|p| format!("({})", p.join(op)))
+    };
+
+    match node {
+        QueryNode::Term { text, column } | QueryNode::Phrase { text, column } => substring(text, *column),
+        QueryNode::Near { terms, .. } => group(terms.iter().map(|t| substring(t, None)).collect(), " AND "),
+        QueryNode::And(parts) => group(parts.iter().map(trigram_expr).collect(), " AND "),
+        QueryNode::Or(parts) => group(parts.iter().map(trigram_expr).collect(), " OR "),
+        QueryNode::Filter(_) | QueryNode::Not(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE notes_fts USING fts5(title, content, tags, properties);
+             CREATE VIRTUAL TABLE notes_vocab USING fts5vocab(notes_fts, row);
+             INSERT INTO notes_fts(title, content) VALUES ('Kubernetes', 'deploying kubernetes clusters');
+             INSERT INTO notes_fts(title, content) VALUES ('Notes', 'kubernetes and docker');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("kubernets", "kubernetes"), 1);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_suggest_terms() {
+        let conn = setup();
+        let suggestions = suggest_terms(&conn, "Kubernets", 3).unwrap();
+        assert_eq!(suggestions[0].term, "kubernetes");
+        assert_eq!(suggestions[0].doc_count, 2);
+        assert!(term_exists(&conn, "kube").unwrap());
+    }
+
+    #[test]
+    fn test_corrections_rewrite_query() {
+        let conn = setup();
+        let node = search_query::parse_query("kubernets dockr -clusters").unwrap().unwrap();
+        let fixes = corrections(&conn, &node).unwrap();
+
+        assert_eq!(fixes.get("kubernets").map(String::as_str), Some("kubernetes"));
+        assert_eq!(fixes.get("dockr").map(String::as_str), Some("docker"));
+        assert_eq!(
+            corrected_query_text("Kubernets (dockr) -clusters", &fixes),
+            "kubernetes (docker) -clusters"
+        );
+        assert_eq!(substring_match_expr(&node).as_deref(), Some("\"kubernets\" AND \"dockr\""));
+    }
+
+    #[test]
+    fn test_suggestions_share_the_first_letter() {
+        let conn = setup();
+        assert_eq!(suggest_terms(&conn, "dockor", 3).unwrap()[0].term, "docker");
+        assert!(suggest_terms(&conn, "jocker", 3).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_substring_expr_keeps_or_groups_and_columns() {
+        let parse = |q: &str| search_query::parse_query(q).unwrap().unwrap();
+
+        assert_eq!(
+            substring_match_expr(&parse("(title:kube OR dock) tag:ops")).as_deref(),
+            Some("(title : \"kube\" OR \"dock\")")
+        );
+        assert_eq!(
+            substring_match_expr(&parse("content:\"deploy cluster\" netes")).as_deref(),
+            Some("content : \"deploy cluster\" AND \"netes\"")
+        );
+        assert_eq!(substring_match_expr(&parse("kube OR k8")), None);
+        assert_eq!(substring_match_expr(&parse("tag:ops -kube")), None);
+    }
+}
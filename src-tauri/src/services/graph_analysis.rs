@@ -1,49 +1,270 @@
-use rusqlite::Connection;
-use crate::services::cards::Card;
-use rand::seq::SliceRandom;
+//! Insight engine: suggests links between notes that belong together
+//!
+//! Each cycle scans a few recently changed notes ("anchors") and scores the
+//! unlinked notes around them on three signals: embedding similarity, shared
+//! rare terms and shared tags weighted by rarity. The best pairs are stored
+//! in `insights`, one row per pair, so a pair is never suggested twice.
+//! Accepting an insight adds a wiki-link; accepted and dismissed insights
+//! raise or lower the weight of the signals they were based on.
 
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use chrono::{Duration, Local};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::services::embedding_service;
+use crate::services::encrypted_note_service;
+use crate::services::link_service;
+use crate::services::passphrase_service::PassphraseState;
+use crate::services::revision_service;
+
+/// Anchors scanned per cycle
+const ANCHORS_PER_CYCLE: usize = 5;
+/// Insights stored per anchor
+const INSIGHTS_PER_ANCHOR: usize = 3;
+/// No new insights while this many are waiting for an answer
+const MAX_PENDING: i64 = 50;
+/// Pairs scoring below this aren't worth interrupting anyone for
+const MIN_SCORE: f64 = 0.35;
+/// Embedding similarity below this doesn't count as a signal
+const MIN_SIMILARITY: f32 = 0.5;
+/// Embedding neighbours considered per anchor
+const SEMANTIC_CANDIDATES: usize = 20;
+/// Rarest terms of an anchor looked up per cycle
+const RARE_TERMS: usize = 8;
+/// Notes fetched per rare term
+const TERM_CANDIDATES: i64 = 50;
+/// Summed idf at which the term and tag signals reach 1.0
+const TERM_SATURATION: f64 = 8.0;
+const TAG_SATURATION: f64 = 6.0;
+/// Base signal weights, before feedback
+const SEMANTIC_WEIGHT: f64 = 0.8;
+const TERM_WEIGHT: f64 = 0.6;
+const TAG_WEIGHT: f64 = 0.4;
+
+pub fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS insights (
+            id TEXT PRIMARY KEY,
+            -- the pair is stored with note_a_id < note_b_id
+            note_a_id TEXT NOT NULL,
+            note_b_id TEXT NOT NULL,
+            description TEXT NOT NULL,
+            score REAL NOT NULL,
+            semantic REAL NOT NULL DEFAULT 0,
+            term_score REAL NOT NULL DEFAULT 0,
+            tag_score REAL NOT NULL DEFAULT 0,
+            shared_terms TEXT,
+            shared_tags TEXT,
+            -- pending, accepted, dismissed or snoozed
+            status TEXT NOT NULL DEFAULT 'pending',
+            snoozed_until DATETIME,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            resolved_at DATETIME,
+            UNIQUE (note_a_id, note_b_id)
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_insights_status ON insights(status)", [])?;
+
+    // When each note was last scanned as an anchor
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS insight_scans (
+            note_id TEXT PRIMARY KEY,
+            scanned_at DATETIME NOT NULL
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Insight {
+    pub id: String,
     pub title: String,
     pub description: String,
-    pub card_a_id: String,
-    pub card_b_id: String,
+    pub note_a_id: String,
+    pub note_a_title: String,
+    pub note_b_id: String,
+    pub note_b_title: String,
+    pub score: f64,
+    /// Short explanations, e.g. "Shared terms: tokio, reactor"
+    pub reasons: Vec<String>,
+    pub status: String,
+    pub snoozed_until: Option<String>,
+    pub created_at: Option<String>,
 }
 
-pub fn find_connections(conn: &Connection) -> Option<Insight> {
-    // 1. Fetch random pair of cards (MVP heuristic: Serendipity)
-    let mut stmt = conn.prepare("SELECT id, content FROM cards ORDER BY RANDOM() LIMIT 2").ok()?;
-    
-    let card_iter = stmt.query_map([], |row| {
-        Ok(Card {
-            id: row.get(0)?,
-            type_id: "note".to_string(), // dummy
-            content: row.get(1)?,
-            metadata: "".to_string(),
-            role_context: "all".to_string(),
-            created_at: "".to_string(),
+/// Signal values for one candidate pair, each 0.0 to 1.0
+#[derive(Default)]
+struct Signals {
+    semantic: f64,
+    terms: Vec<(String, f64)>,
+    tags: Vec<(String, f64)>,
+}
+
+impl Signals {
+    fn term_score(&self) -> f64 {
+        (self.terms.iter().map(|(_, idf)| idf).sum::<f64>() / TERM_SATURATION).min(1.0)
+    }
+
+    fn tag_score(&self) -> f64 {
+        (self.tags.iter().map(|(_, idf)| idf).sum::<f64>() / TAG_SATURATION).min(1.0)
+    }
+}
+
+/// Multipliers for the semantic, term and tag weights learned from answered insights
+///
+/// Each is twice the smoothed acceptance rate of insights where the signal
+/// was present, so 1.0 with no history, up to 2.0 when always accepted.
+struct Feedback {
+    semantic: f64,
+    terms: f64,
+    tags: f64,
+}
+
+impl Feedback {
+    fn load(conn: &Connection) -> Result<Self> {
+        let rate = |column: &str| -> Result<f64> {
+            let (accepted, answered): (i64, i64) = conn.query_row(
+                &format!(
+                    "SELECT COALESCE(SUM(status = 'accepted'), 0), COUNT(*) FROM insights
+                     WHERE status IN ('accepted', 'dismissed') AND {} > 0",
+                    column
+                ),
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            Ok(2.0 * (accepted as f64 + 1.0) / (answered as f64 + 2.0))
+        };
+        Ok(Self {
+            semantic: rate("semantic")?,
+            terms: rate("term_score")?,
+            tags: rate("tag_score")?,
         })
-    }).ok()?;
+    }
+
+    /// Noisy-or of the weighted signals: one strong signal is enough, weak ones add up
+    fn score(&self, signals: &Signals) -> f64 {
+        let misses = [
+            ((SEMANTIC_WEIGHT * self.semantic).min(1.0), signals.semantic),
+            ((TERM_WEIGHT * self.terms).min(1.0), signals.term_score()),
+            ((TAG_WEIGHT * self.tags).min(1.0), signals.tag_score()),
+        ];
+        1.0 - misses.iter().map(|(weight, value)| 1.0 - weight * value).product::<f64>()
+    }
+}
 
-    let cards: Vec<Card> = card_iter.filter_map(Result::ok).collect();
+fn now() -> String {
+    Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
 
-    if cards.len() < 2 {
-        return None;
+fn ordered<'a>(a: &'a str, b: &'a str) -> (&'a str, &'a str) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() >= 3 && !w.chars().all(|c| c.is_numeric()))
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// The anchor's rarest terms that appear in at least one other note, with their idf
+fn rare_terms(conn: &Connection, text: &str, total_notes: f64) -> Result<Vec<(String, f64)>> {
+    let terms: Vec<String> = words(text).into_iter().collect();
+    if terms.is_empty() {
+        return Ok(Vec::new());
     }
+    let max_docs = ((total_notes / 50.0) as i64).max(3);
+    let mut stmt = conn.prepare(
+        "SELECT term, doc FROM notes_vocab
+         WHERE term IN (SELECT value FROM json_each(?1)) AND doc BETWEEN 2 AND ?2
+         ORDER BY doc, term LIMIT ?3",
+    )?;
+    let rows = stmt.query_map(
+        params![serde_json::to_string(&terms).unwrap_or_default(), max_docs, RARE_TERMS as i64],
+        |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)),
+    )?;
+    rows.map(|row| row.map(|(term, docs)| (term, (total_notes / docs as f64).ln())))
+        .collect()
+}
 
-    let card_a = &cards[0];
-    let card_b = &cards[1];
+/// Notes that should be linked to `anchor_id`, with their signals
+fn candidates(conn: &Connection, anchor_id: &str, anchor_text: &str, total_notes: f64) -> Result<HashMap<String, Signals>> {
+    let mut found: HashMap<String, Signals> = HashMap::new();
 
-    // MVP Heuristic: Just return them as a "Potential Connection"
-    // In a real/deeper implementation, we check for cosine similarity or shared tags
-    Some(Insight {
-        title: "Link Suggested".to_string(),
-        description: format!("Have you considered how '{}' relates to '{}'?", 
-            truncate(&card_a.content, 20), 
-            truncate(&card_b.content, 20)
-        ),
-        card_a_id: card_a.id.clone(),
-        card_b_id: card_b.id.clone(),
-    })
+    if let Some((vector, model)) = embedding_service::note_vector(conn, anchor_id)? {
+        for hit in embedding_service::semantic_search(conn, &vector, &model, SEMANTIC_CANDIDATES + 1)? {
+            if hit.note_id != anchor_id && hit.score >= MIN_SIMILARITY {
+                found.entry(hit.note_id).or_default().semantic = hit.score as f64;
+            }
+        }
+    }
+
+    for (term, idf) in rare_terms(conn, anchor_text, total_notes)? {
+        let mut stmt = conn.prepare_cached(
+            "SELECT n.id FROM notes_fts JOIN notes n ON n.internal_id = notes_fts.rowid
+             WHERE notes_fts MATCH ?1 AND n.id != ?2 LIMIT ?3",
+        )?;
+        let ids = stmt.query_map(
+            params![format!("\"{}\"", term.replace('"', "\"\"")), anchor_id, TERM_CANDIDATES],
+            |row| row.get::<_, String>(0),
+        )?;
+        for id in ids {
+            found.entry(id?).or_default().terms.push((term.clone(), idf));
+        }
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT nt2.note_id, t.name,
+                (SELECT COUNT(*) FROM note_tags c WHERE c.tag_id = nt1.tag_id)
+         FROM note_tags nt1
+         JOIN note_tags nt2 ON nt2.tag_id = nt1.tag_id AND nt2.note_id != nt1.note_id
+         JOIN tags t ON t.id = nt1.tag_id
+         WHERE nt1.note_id = ?",
+    )?;
+    let rows = stmt.query_map(params![anchor_id], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?))
+    })?;
+    for row in rows {
+        let (id, tag, uses) = row?;
+        let idf = (total_notes / uses.max(1) as f64).ln();
+        found.entry(id).or_default().tags.push((tag, idf));
+    }
+
+    Ok(found)
+}
+
+/// Whether either note already wiki-links to the other
+fn already_linked(a_title: &str, a_text: &str, b_title: &str, b_text: &str) -> bool {
+    let links_to = |text: &str, title: &str| {
+        let title = link_service::normalize_title(title);
+        link_service::extract_wiki_links(text)
+            .iter()
+            .any(|l| link_service::normalize_title(&l.target) == title)
+    };
+    links_to(a_text, b_title) || links_to(b_text, a_title)
+}
+
+fn reasons(signals: &Signals) -> Vec<String> {
+    let mut reasons = Vec::new();
+    if signals.semantic > 0.0 {
+        reasons.push(format!("Similar content ({:.0}%)", signals.semantic * 100.0));
+    }
+    if !signals.terms.is_empty() {
+        let terms: Vec<&str> = signals.terms.iter().map(|(t, _)| t.as_str()).collect();
+        reasons.push(format!("Shared terms: {}", terms.join(", ")));
+    }
+    if !signals.tags.is_empty() {
+        let tags: Vec<String> = signals.tags.iter().map(|(t, _)| format!("#{}", t)).collect();
+        reasons.push(format!("Shared tags: {}", tags.join(" ")));
+    }
+    reasons
 }
 
 fn truncate(s: &str, max_chars: usize) -> String {
@@ -52,3 +273,329 @@ fn truncate(s: &str, max_chars: usize) -> String {
         Some((idx, _)) => format!("{}...", &s[..idx]),
     }
 }
+
+fn note_text(conn: &Connection, note_id: &str) -> Result<Option<(String, String)>> {
+    conn.query_row(
+        "SELECT title, COALESCE(content_plaintext, content, '') FROM notes WHERE id = ?",
+        params![note_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+}
+
+/// Scan the next few anchors and store their best new insights; returns the ids stored
+pub fn generate_insights(conn: &Connection) -> Result<Vec<String>> {
+    let pending: i64 = conn.query_row("SELECT COUNT(*) FROM insights WHERE status = 'pending'", [], |row| row.get(0))?;
+    if pending >= MAX_PENDING {
+        return Ok(Vec::new());
+    }
+
+    // Notes changed since they were last scanned, most recent first
+    let anchors: Vec<String> = {
+        let mut stmt = conn.prepare(
+            "SELECT n.id FROM notes n LEFT JOIN insight_scans s ON s.note_id = n.id
+             WHERE s.scanned_at IS NULL OR s.scanned_at < n.updated_at
+             ORDER BY n.updated_at DESC LIMIT ?",
+        )?;
+        let ids = stmt.query_map(params![ANCHORS_PER_CYCLE as i64], |row| row.get(0))?;
+        ids.collect::<Result<_>>()?
+    };
+    if anchors.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let total_notes: f64 = conn.query_row("SELECT COUNT(*) FROM notes", [], |row| row.get::<_, i64>(0))? as f64;
+    let feedback = Feedback::load(conn)?;
+    let scanned_at = now();
+    let mut stored = Vec::new();
+
+    for anchor_id in anchors {
+        conn.execute(
+            "INSERT OR REPLACE INTO insight_scans (note_id, scanned_at) VALUES (?, ?)",
+            params![anchor_id, scanned_at],
+        )?;
+        let Some((anchor_title, anchor_text)) = note_text(conn, &anchor_id)? else {
+            continue;
+        };
+
+        let mut ranked: Vec<(String, Signals, f64)> = candidates(conn, &anchor_id, &format!("{} {}", anchor_title, anchor_text), total_notes)?
+            .into_iter()
+            .map(|(id, signals)| {
+                let score = feedback.score(&signals);
+                (id, signals, score)
+            })
+            .filter(|(_, _, score)| *score >= MIN_SCORE)
+            .collect();
+        ranked.sort_by(|a, b| b.2.total_cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+
+        let mut kept = 0;
+        for (other_id, signals, score) in ranked {
+            if kept == INSIGHTS_PER_ANCHOR {
+                break;
+            }
+            let (a, b) = ordered(&anchor_id, &other_id);
+            let known: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM insights WHERE note_a_id = ? AND note_b_id = ?)",
+                params![a, b],
+                |row| row.get(0),
+            )?;
+            if known {
+                continue;
+            }
+            let Some((other_title, other_text)) = note_text(conn, &other_id)? else {
+                continue;
+            };
+            if already_linked(&anchor_title, &anchor_text, &other_title, &other_text) {
+                continue;
+            }
+
+            let id = Uuid::new_v4().to_string();
+            let terms: Vec<&str> = signals.terms.iter().map(|(t, _)| t.as_str()).collect();
+            let tags: Vec<&str> = signals.tags.iter().map(|(t, _)| t.as_str()).collect();
+            conn.execute(
+                "INSERT INTO insights (id, note_a_id, note_b_id, description, score, semantic, term_score, tag_score,
+                                       shared_terms, shared_tags)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    id,
+                    a,
+                    b,
+                    format!(
+                        "Have you considered how '{}' relates to '{}'?",
+                        truncate(&anchor_title, 40),
+                        truncate(&other_title, 40)
+                    ),
+                    score,
+                    signals.semantic,
+                    signals.term_score(),
+                    signals.tag_score(),
+                    terms.join(","),
+                    tags.join(","),
+                ],
+            )?;
+            stored.push(id);
+            kept += 1;
+        }
+    }
+
+    Ok(stored)
+}
+
+fn map_insight(row: &rusqlite::Row) -> Result<Insight> {
+    let semantic: f64 = row.get(8)?;
+    let shared_terms: Option<String> = row.get(9)?;
+    let shared_tags: Option<String> = row.get(10)?;
+    let split = |s: Option<String>| -> Vec<(String, f64)> {
+        s.unwrap_or_default()
+            .split(',')
+            .filter(|t| !t.is_empty())
+            .map(|t| (t.to_string(), 0.0))
+            .collect()
+    };
+    let signals = Signals { semantic, terms: split(shared_terms), tags: split(shared_tags) };
+
+    Ok(Insight {
+        id: row.get(0)?,
+        title: "Link Suggested".to_string(),
+        description: row.get(1)?,
+        note_a_id: row.get(2)?,
+        note_a_title: row.get(3)?,
+        note_b_id: row.get(4)?,
+        note_b_title: row.get(5)?,
+        score: row.get(6)?,
+        reasons: reasons(&signals),
+        status: row.get(7)?,
+        snoozed_until: row.get(11)?,
+        created_at: row.get(12)?,
+    })
+}
+
+const INSIGHT_SELECT: &str =
+    "SELECT i.id, i.description, i.note_a_id, a.title, i.note_b_id, b.title, i.score, i.status,
+            i.semantic, i.shared_terms, i.shared_tags, i.snoozed_until, i.created_at
+     FROM insights i
+     JOIN notes a ON a.id = i.note_a_id
+     JOIN notes b ON b.id = i.note_b_id";
+
+pub fn get_insight(conn: &Connection, id: &str) -> Result<Option<Insight>> {
+    conn.query_row(&format!("{} WHERE i.id = ?", INSIGHT_SELECT), params![id], map_insight)
+        .optional()
+}
+
+/// Open insights, best first: pending ones and snoozed ones whose snooze has run out
+///
+/// With `include_answered`, accepted and dismissed insights follow, newest first.
+pub fn list_insights(conn: &Connection, include_answered: bool, limit: usize) -> Result<Vec<Insight>> {
+    let mut stmt = conn.prepare(&format!(
+        "{} WHERE i.status = 'pending'
+               OR (i.status = 'snoozed' AND i.snoozed_until <= ?1)
+               OR (?2 AND i.status IN ('accepted', 'dismissed'))
+         ORDER BY i.status IN ('accepted', 'dismissed'), i.score DESC, i.resolved_at DESC
+         LIMIT ?3",
+        INSIGHT_SELECT
+    ))?;
+    let insights = stmt.query_map(params![now(), include_answered, limit as i64], map_insight)?;
+    insights.collect()
+}
+
+/// Generate insights and return the best new one, for the background notification
+pub fn find_connections(conn: &Connection) -> Option<Insight> {
+    let stored = generate_insights(conn).ok()?;
+    stored
+        .iter()
+        .filter_map(|id| get_insight(conn, id).ok().flatten())
+        .max_by(|a, b| a.score.total_cmp(&b.score))
+}
+
+fn load_open(conn: &Connection, id: &str) -> Result<Insight, String> {
+    let insight = get_insight(conn, id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Insight {} not found", id))?;
+    if insight.status == "accepted" || insight.status == "dismissed" {
+        return Err(format!("This insight was already {}", insight.status));
+    }
+    Ok(insight)
+}
+
+fn resolve(conn: &Connection, id: &str, status: &str, snoozed_until: Option<String>) -> Result<Insight, String> {
+    conn.execute(
+        "UPDATE insights SET status = ?, snoozed_until = ?, resolved_at = ? WHERE id = ?",
+        params![status, snoozed_until, now(), id],
+    )
+    .map_err(|e| e.to_string())?;
+    get_insight(conn, id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Insight {} not found", id))
+}
+
+/// Accept an insight by appending a wiki-link to the other note
+///
+/// The link goes into note A unless A is encrypted and locked. The edit is
+/// recorded as a revision batch so it can be undone.
+pub fn accept_insight(
+    conn: &Connection,
+    passphrase_state: &Mutex<PassphraseState>,
+    id: &str,
+) -> Result<Insight, String> {
+    let insight = load_open(conn, id)?;
+    let state_guard = passphrase_state.lock().map_err(|e| e.to_string())?;
+
+    // Readable content of a note and whether it is encrypted, `None` if it is locked
+    let load = |note_id: &str| -> Result<Option<(String, bool)>, String> {
+        let (content, encrypted, nonce): (String, Option<String>, Option<String>) = conn
+            .query_row(
+                "SELECT content, content_encrypted, nonce FROM notes WHERE id = ?",
+                params![note_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .map_err(|e| e.to_string())?;
+        let is_encrypted = encrypted.is_some();
+        Ok(encrypted_note_service::load_content(&state_guard, content, encrypted, nonce).map(|c| (c, is_encrypted)))
+    };
+
+    let (source_id, target_title, content, encrypted) = match load(&insight.note_a_id)? {
+        Some((content, encrypted)) => (&insight.note_a_id, &insight.note_b_title, content, encrypted),
+        None => match load(&insight.note_b_id)? {
+            Some((content, encrypted)) => (&insight.note_b_id, &insight.note_a_title, content, encrypted),
+            None => return Err("Unlock encrypted notes to link them".to_string()),
+        },
+    };
+    let content = format!("{}\n\nRelated: [[{}]]", content.trim_end(), target_title);
+    let now = revision_service::batch_timestamp();
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let batch_id = revision_service::begin_batch(
+        &tx,
+        "insight",
+        &format!("Link '{}' and '{}'", insight.note_a_title, insight.note_b_title),
+    )
+    .map_err(|e| e.to_string())?;
+    revision_service::record_revision(&tx, &batch_id, source_id, &now).map_err(|e| e.to_string())?;
+    encrypted_note_service::write_content(&tx, &state_guard, source_id, &content, encrypted, &now)?;
+    revision_service::finish_batch(&tx, &batch_id).map_err(|e| e.to_string())?;
+    let accepted = resolve(&tx, id, "accepted", None)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(accepted)
+}
+
+/// Dismiss an insight; the pair won't be suggested again
+pub fn dismiss_insight(conn: &Connection, id: &str) -> Result<Insight, String> {
+    load_open(conn, id)?;
+    resolve(conn, id, "dismissed", None)
+}
+
+/// Hide an insight for `days` days
+pub fn snooze_insight(conn: &Connection, id: &str, days: i64) -> Result<Insight, String> {
+    load_open(conn, id)?;
+    let until = (Local::now() + Duration::days(days.max(1))).format("%Y-%m-%d %H:%M:%S").to_string();
+    resolve(conn, id, "snoozed", Some(until))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::search_index;
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (
+                internal_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                id TEXT UNIQUE NOT NULL,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                properties TEXT,
+                content_encrypted BLOB,
+                nonce BLOB,
+                content_plaintext TEXT
+            );
+            CREATE TABLE tags (id TEXT PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+            CREATE TABLE note_tags (note_id TEXT NOT NULL, tag_id TEXT NOT NULL, PRIMARY KEY (note_id, tag_id));",
+        )
+        .unwrap();
+        search_index::create_tables(&conn).unwrap();
+        embedding_service::create_tables(&conn).unwrap();
+        revision_service::create_tables(&conn).unwrap();
+        create_tables(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO notes (id, title, content) VALUES
+                ('a', 'Reactor pattern', 'The epoll reactor wakes futures when sockets are ready.'),
+                ('b', 'Runtime internals', 'Inside the runtime, the epoll reactor drives every socket.'),
+                ('c', 'Bread', 'Sourdough needs a warm kitchen and patience.'),
+                ('d', 'Linked', 'See [[Reactor pattern]] for details.');
+             WITH RECURSIVE s(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM s WHERE i < 30)
+             INSERT INTO notes (id, title, content) SELECT 'f' || i, 'Filler ' || i, 'filler note number ' || i FROM s;",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_generate_and_answer_insights() {
+        let conn = setup();
+        for _ in 0..10 {
+            generate_insights(&conn).unwrap();
+        }
+
+        let insights = list_insights(&conn, false, 10).unwrap();
+        assert_eq!(insights.len(), 1, "d already links to a and shares too little with b");
+        let insight = &insights[0];
+        assert_eq!((insight.note_a_id.as_str(), insight.note_b_id.as_str()), ("a", "b"));
+        assert!(insight.reasons[0].contains("epoll") && insight.reasons[0].contains("reactor"));
+
+        // Already scanned and already suggested: nothing new
+        assert!(generate_insights(&conn).unwrap().is_empty());
+
+        let state = PassphraseState::new();
+        let accepted = accept_insight(&conn, &state, &insight.id).unwrap();
+        assert_eq!(accepted.status, "accepted");
+        let content: String = conn.query_row("SELECT content FROM notes WHERE id = 'a'", [], |r| r.get(0)).unwrap();
+        assert!(content.ends_with("Related: [[Runtime internals]]"));
+        assert!(dismiss_insight(&conn, &insight.id).is_err());
+
+        // One accepted insight with shared terms raises the term weight
+        let feedback = Feedback::load(&conn).unwrap();
+        assert!(feedback.terms > 1.0 && feedback.semantic == 1.0);
+    }
+}
@@ -0,0 +1,771 @@
+//! Background job scheduler
+//!
+//! Jobs are registered as [`JobDef`]s: a name, a default schedule and a run
+//! function. Their settings (schedule, paused, run rules) and last-run state
+//! live in the `jobs` table and every run is logged in `job_runs`, so both
+//! survive restarts and can be changed from the UI. A ticker thread starts
+//! due jobs, each on its own thread; a job never overlaps itself.
+//!
+//! Schedules are either `every <n><s|m|h|d>` or a five-field cron expression
+//! (`minute hour day-of-month month day-of-week`, local time).
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration as StdDuration, Instant};
+
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, Timelike};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+/// How often the ticker checks for due jobs
+const TICK: StdDuration = StdDuration::from_secs(5);
+/// Runs kept in `job_runs` per job
+const RUN_HISTORY: i64 = 50;
+/// Without note edits for this long the user counts as idle
+const IDLE_AFTER_MINUTES: i64 = 5;
+/// Cron searches further ahead than this are treated as "never"
+const CRON_HORIZON_DAYS: i64 = 366 * 4;
+
+pub fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS jobs (
+            name TEXT PRIMARY KEY,
+            schedule TEXT NOT NULL,
+            paused BOOLEAN NOT NULL DEFAULT FALSE,
+            skip_on_battery BOOLEAN NOT NULL DEFAULT FALSE,
+            only_when_idle BOOLEAN NOT NULL DEFAULT FALSE,
+            -- ok, error, cancelled, interrupted or running
+            last_status TEXT,
+            last_message TEXT,
+            last_run_at DATETIME,
+            last_duration_ms INTEGER,
+            run_count INTEGER NOT NULL DEFAULT 0,
+            created_at DATETIME NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS job_runs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            job_name TEXT NOT NULL,
+            started_at DATETIME NOT NULL,
+            finished_at DATETIME,
+            status TEXT NOT NULL,
+            message TEXT,
+            -- schedule, manual
+            trigger TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_job_runs_job ON job_runs(job_name, id)", [])?;
+
+    Ok(())
+}
+
+fn now() -> NaiveDateTime {
+    Local::now().naive_local()
+}
+
+fn format_time(time: NaiveDateTime) -> String {
+    time.format(TIME_FORMAT).to_string()
+}
+
+fn parse_time(text: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(text, TIME_FORMAT).ok()
+}
+
+/// A set of allowed values for one cron field
+#[derive(Debug, Clone, PartialEq)]
+struct CronField {
+    allowed: Vec<bool>,
+    /// `*` (or `*/1`), which matters for the day-of-month/day-of-week rule
+    any: bool,
+}
+
+impl CronField {
+    fn parse(text: &str, min: u32, max: u32) -> Result<Self, String> {
+        let mut allowed = vec![false; max as usize + 1];
+        let mut any = false;
+        for part in text.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (
+                    range,
+                    step.parse::<u32>().ok().filter(|s| *s > 0).ok_or_else(|| format!("Invalid step in '{}'", part))?,
+                ),
+                None => (part, 1),
+            };
+            let (start, end) = if range == "*" {
+                any |= step == 1;
+                (min, max)
+            } else if let Some((a, b)) = range.split_once('-') {
+                (parse_cron_value(a, min, max)?, parse_cron_value(b, min, max)?)
+            } else {
+                let value = parse_cron_value(range, min, max)?;
+                // "5/15" means from 5 to the end in steps of 15
+                (value, if part.contains('/') { max } else { value })
+            };
+            if start > end {
+                return Err(format!("Invalid range '{}'", part));
+            }
+            for value in (start..=end).step_by(step as usize) {
+                allowed[value as usize] = true;
+            }
+        }
+        Ok(Self { allowed, any })
+    }
+
+    fn matches(&self, value: u32) -> bool {
+        self.allowed.get(value as usize).copied().unwrap_or(false)
+    }
+}
+
+fn parse_cron_value(text: &str, min: u32, max: u32) -> Result<u32, String> {
+    text.parse::<u32>()
+        .ok()
+        .filter(|v| (min..=max).contains(v))
+        .ok_or_else(|| format!("'{}' is not between {} and {}", text, min, max))
+}
+
+/// When a job runs
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+    Every(Duration),
+    Cron(Box<CronFieldSet>),
+}
+
+/// The five parsed fields of a cron expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronFieldSet {
+    minute: CronField,
+    hour: CronField,
+    day: CronField,
+    month: CronField,
+    weekday: CronField,
+}
+
+impl CronFieldSet {
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = self.day.matches(date.day());
+        let weekday = self.weekday.matches(date.weekday().num_days_from_sunday());
+        // Standard cron: with both fields restricted, either one may match
+        if self.day.any || self.weekday.any {
+            day && weekday
+        } else {
+            day || weekday
+        }
+    }
+
+    /// First matching minute strictly after `after`
+    fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut time = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = after + Duration::days(CRON_HORIZON_DAYS);
+        while time <= limit {
+            if !self.month.matches(time.month()) || !self.day_matches(time.date()) {
+                time = time.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if !self.hour.matches(time.hour()) {
+                time = time.with_minute(0)? + Duration::hours(1);
+            } else if !self.minute.matches(time.minute()) {
+                time += Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+}
+
+impl Schedule {
+    /// Parse `every 30s` / `every 15m` / `every 6h` / `every 1d` or a cron expression
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        if let Some(interval) = text.strip_prefix("every ") {
+            let interval = interval.trim();
+            let (number, unit) = interval.split_at(interval.char_indices().last().map_or(0, |(i, _)| i));
+            let n: i64 = number
+                .trim()
+                .parse()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| format!("Invalid interval '{}'", interval))?;
+            let duration = match unit {
+                "s" => Duration::seconds(n),
+                "m" => Duration::minutes(n),
+                "h" => Duration::hours(n),
+                "d" => Duration::days(n),
+                _ => return Err(format!("Interval unit must be s, m, h or d, got '{}'", unit)),
+            };
+            return Ok(Schedule::Every(duration));
+        }
+
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("Expected 'every <n><s|m|h|d>' or a five-field cron expression, got '{}'", text));
+        }
+        let mut weekday = CronField::parse(fields[4], 0, 7)?;
+        // 7 is Sunday too
+        if weekday.allowed[7] {
+            weekday.allowed[0] = true;
+        }
+        Ok(Schedule::Cron(Box::new(CronFieldSet {
+            minute: CronField::parse(fields[0], 0, 59)?,
+            hour: CronField::parse(fields[1], 0, 23)?,
+            day: CronField::parse(fields[2], 1, 31)?,
+            month: CronField::parse(fields[3], 1, 12)?,
+            weekday,
+        })))
+    }
+
+    /// When the job is next due, given when it last ran (or was first registered)
+    pub fn next_run(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        match self {
+            Schedule::Every(interval) => Some(after + *interval),
+            Schedule::Cron(fields) => fields.next_after(after),
+        }
+    }
+}
+
+/// Whether the machine is running on battery; `false` when it can't tell
+pub fn on_battery() -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        let Ok(entries) = std::fs::read_dir("/sys/class/power_supply") else {
+            return false;
+        };
+        let mut has_battery = false;
+        for entry in entries.flatten() {
+            let read = |file: &str| std::fs::read_to_string(entry.path().join(file)).unwrap_or_default();
+            match read("type").trim() {
+                "Mains" | "USB" if read("online").trim() == "1" => return false,
+                "Battery" => has_battery = true,
+                _ => {}
+            }
+        }
+        has_battery
+    }
+    #[cfg(target_os = "macos")]
+    {
+        std::process::Command::new("pmset")
+            .args(["-g", "batt"])
+            .output()
+            .map(|out| String::from_utf8_lossy(&out.stdout).contains("'Battery Power'"))
+            .unwrap_or(false)
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        false
+    }
+}
+
+/// Whether no note has been edited for a while
+fn user_idle(conn: &Connection, now: NaiveDateTime) -> bool {
+    let last_edit: Option<String> = conn
+        .query_row("SELECT MAX(updated_at) FROM notes", [], |row| row.get(0))
+        .unwrap_or(None);
+    match last_edit.as_deref().and_then(parse_time) {
+        Some(last_edit) => now - last_edit >= Duration::minutes(IDLE_AFTER_MINUTES),
+        None => true,
+    }
+}
+
+/// Passed to a running job
+pub struct JobContext {
+    cancel: Arc<AtomicBool>,
+    /// When the job last started before this run
+    pub last_run_at: Option<String>,
+}
+
+impl JobContext {
+    /// Long jobs check this between steps and stop early when it is set
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    /// The flag behind [`is_cancelled`](Self::is_cancelled), for work that polls it itself
+    pub fn cancel_flag(&self) -> &AtomicBool {
+        &self.cancel
+    }
+}
+
+/// Where jobs get their database connection, e.g. the Tauri app handle
+pub trait JobHost: Clone + Send + Sync + 'static {
+    /// Run `f` with the shared connection; `None` if it can't be locked
+    fn with_db<R>(&self, f: impl FnOnce(&Connection) -> R) -> Option<R>;
+}
+
+/// A job the scheduler can run
+pub struct JobDef<H> {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub default_schedule: &'static str,
+    pub skip_on_battery: bool,
+    pub only_when_idle: bool,
+    /// Returns a short summary of what was done
+    pub run: fn(&H, &JobContext) -> Result<String, String>,
+}
+
+/// Settings and last-run state of a job
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    pub name: String,
+    pub description: String,
+    pub schedule: String,
+    pub paused: bool,
+    pub skip_on_battery: bool,
+    pub only_when_idle: bool,
+    pub running: bool,
+    pub last_status: Option<String>,
+    pub last_message: Option<String>,
+    pub last_run_at: Option<String>,
+    pub last_duration_ms: Option<i64>,
+    pub run_count: i64,
+    pub next_run_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobRun {
+    pub id: i64,
+    pub job_name: String,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    pub status: String,
+    pub message: Option<String>,
+    pub trigger: String,
+}
+
+/// Changes to a job's settings; `None` leaves a field as it is
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct JobSettingsInput {
+    pub schedule: Option<String>,
+    pub paused: Option<bool>,
+    pub skip_on_battery: Option<bool>,
+    pub only_when_idle: Option<bool>,
+}
+
+#[derive(Default)]
+struct Runtime {
+    running: bool,
+    cancel: Arc<AtomicBool>,
+    triggered: bool,
+}
+
+pub struct Scheduler<H: JobHost> {
+    host: H,
+    jobs: Vec<JobDef<H>>,
+    runtime: Mutex<HashMap<&'static str, Runtime>>,
+}
+
+fn map_status(row: &rusqlite::Row) -> Result<JobStatus> {
+    Ok(JobStatus {
+        name: row.get(0)?,
+        description: String::new(),
+        schedule: row.get(1)?,
+        paused: row.get(2)?,
+        skip_on_battery: row.get(3)?,
+        only_when_idle: row.get(4)?,
+        running: false,
+        last_status: row.get(5)?,
+        last_message: row.get(6)?,
+        last_run_at: row.get(7)?,
+        last_duration_ms: row.get(8)?,
+        run_count: row.get(9)?,
+        next_run_at: row.get(10)?,
+    })
+}
+
+/// Stored settings and state of `name`, with `next_run_at` holding the schedule's anchor time
+fn load_job(conn: &Connection, name: &str) -> Result<Option<JobStatus>> {
+    conn.query_row(
+        "SELECT name, schedule, paused, skip_on_battery, only_when_idle, last_status, last_message,
+                last_run_at, last_duration_ms, run_count, COALESCE(last_run_at, created_at)
+         FROM jobs WHERE name = ?",
+        params![name],
+        map_status,
+    )
+    .optional()
+}
+
+impl<H: JobHost> Scheduler<H> {
+    /// Register `jobs`, adding rows with their defaults for new ones
+    ///
+    /// Runs left as `running` by a previous session are marked `interrupted`.
+    pub fn new(host: H, jobs: Vec<JobDef<H>>) -> Result<Arc<Self>, String> {
+        host.with_db(|conn| -> Result<()> {
+            let now = format_time(now());
+            for job in &jobs {
+                conn.execute(
+                    "INSERT OR IGNORE INTO jobs (name, schedule, skip_on_battery, only_when_idle, created_at)
+                     VALUES (?, ?, ?, ?, ?)",
+                    params![job.name, job.default_schedule, job.skip_on_battery, job.only_when_idle, now],
+                )?;
+            }
+            conn.execute("UPDATE jobs SET last_status = 'interrupted' WHERE last_status = 'running'", [])?;
+            conn.execute(
+                "UPDATE job_runs SET status = 'interrupted', finished_at = ? WHERE status = 'running'",
+                params![now],
+            )?;
+            Ok(())
+        })
+        .ok_or("Database is unavailable")?
+        .map_err(|e| e.to_string())?;
+
+        let runtime = jobs.iter().map(|job| (job.name, Runtime::default())).collect();
+        Ok(Arc::new(Self { host, jobs, runtime: Mutex::new(runtime) }))
+    }
+
+    /// Start the ticker thread
+    pub fn start(self: &Arc<Self>) {
+        let scheduler = Arc::clone(self);
+        thread::spawn(move || loop {
+            thread::sleep(TICK);
+            scheduler.tick(now());
+        });
+    }
+
+    fn job(&self, name: &str) -> Result<&JobDef<H>, String> {
+        self.jobs.iter().find(|job| job.name == name).ok_or_else(|| format!("Unknown job '{}'", name))
+    }
+
+    /// Why a job that is due may not run right now
+    fn blocked_by(&self, conn: &Connection, status: &JobStatus, now: NaiveDateTime) -> Option<&'static str> {
+        if status.skip_on_battery && on_battery() {
+            Some("on battery")
+        } else if status.only_when_idle && !user_idle(conn, now) {
+            Some("waiting for idle")
+        } else {
+            None
+        }
+    }
+
+    fn is_due(status: &JobStatus, now: NaiveDateTime) -> bool {
+        let anchor = status.next_run_at.as_deref().and_then(parse_time);
+        match (Schedule::parse(&status.schedule), anchor) {
+            (Ok(schedule), Some(anchor)) => schedule.next_run(anchor).is_some_and(|next| next <= now),
+            _ => false,
+        }
+    }
+
+    /// Start every job that was triggered, or is due, unpaused and allowed to run; returns their names
+    pub fn tick(self: &Arc<Self>, now: NaiveDateTime) -> Vec<&'static str> {
+        let mut started = Vec::new();
+        for job in &self.jobs {
+            let triggered = {
+                let runtime = self.runtime.lock().unwrap();
+                let state = &runtime[job.name];
+                if state.running {
+                    continue;
+                }
+                state.triggered
+            };
+            let should_run = triggered
+                || self
+                    .host
+                    .with_db(|conn| match load_job(conn, job.name) {
+                        Ok(Some(status)) => {
+                            !status.paused && Self::is_due(&status, now) && self.blocked_by(conn, &status, now).is_none()
+                        }
+                        _ => false,
+                    })
+                    .unwrap_or(false);
+            if should_run && self.spawn(job.name, if triggered { "manual" } else { "schedule" }) {
+                started.push(job.name);
+            }
+        }
+        started
+    }
+
+    /// Mark the job running and run it on its own thread; `false` if it was already running
+    fn spawn(self: &Arc<Self>, name: &'static str, trigger: &'static str) -> bool {
+        let cancel = {
+            let mut runtime = self.runtime.lock().unwrap();
+            let state = runtime.get_mut(name).expect("registered job");
+            if state.running {
+                return false;
+            }
+            state.running = true;
+            state.triggered = false;
+            state.cancel = Arc::new(AtomicBool::new(false));
+            Arc::clone(&state.cancel)
+        };
+        let scheduler = Arc::clone(self);
+        thread::spawn(move || scheduler.run(name, trigger, cancel));
+        true
+    }
+
+    fn run(&self, name: &'static str, trigger: &str, cancel: Arc<AtomicBool>) {
+        let job = self.job(name).expect("registered job");
+        let started_at = format_time(now());
+        let started = self.host.with_db(|conn| -> Result<(Option<String>, i64)> {
+            let last_run_at: Option<String> =
+                conn.query_row("SELECT last_run_at FROM jobs WHERE name = ?", params![name], |row| row.get(0))?;
+            conn.execute(
+                "UPDATE jobs SET last_status = 'running', last_run_at = ? WHERE name = ?",
+                params![started_at, name],
+            )?;
+            conn.execute(
+                "INSERT INTO job_runs (job_name, started_at, status, trigger) VALUES (?, ?, 'running', ?)",
+                params![name, started_at, trigger],
+            )?;
+            Ok((last_run_at, conn.last_insert_rowid()))
+        });
+
+        if let Some(Ok((last_run_at, run_id))) = started {
+            let clock = Instant::now();
+            let context = JobContext { cancel, last_run_at };
+            let result = (job.run)(&self.host, &context);
+            let (status, message) = match result {
+                _ if context.is_cancelled() => ("cancelled", None),
+                Ok(message) => ("ok", Some(message)),
+                Err(e) => ("error", Some(e)),
+            };
+            if status == "error" {
+                println!("[Scheduler] Job {} failed: {}", name, message.as_deref().unwrap_or_default());
+            }
+
+            self.host.with_db(|conn| -> Result<()> {
+                conn.execute(
+                    "UPDATE jobs SET last_status = ?, last_message = ?, last_duration_ms = ?, run_count = run_count + 1
+                     WHERE name = ?",
+                    params![status, message, clock.elapsed().as_millis() as i64, name],
+                )?;
+                conn.execute(
+                    "UPDATE job_runs SET status = ?, message = ?, finished_at = ? WHERE id = ?",
+                    params![status, message, format_time(now()), run_id],
+                )?;
+                conn.execute(
+                    "DELETE FROM job_runs WHERE job_name = ?1 AND id NOT IN
+                        (SELECT id FROM job_runs WHERE job_name = ?1 ORDER BY id DESC LIMIT ?2)",
+                    params![name, RUN_HISTORY],
+                )?;
+                Ok(())
+            });
+        }
+
+        if let Some(state) = self.runtime.lock().unwrap().get_mut(name) {
+            state.running = false;
+        }
+    }
+
+    /// Every job with its settings, last run and next due time
+    pub fn list(&self) -> Result<Vec<JobStatus>, String> {
+        let now = now();
+        let runtime = self.runtime.lock().unwrap();
+        self.host
+            .with_db(|conn| -> Result<Vec<JobStatus>> {
+                let mut jobs = Vec::new();
+                for job in &self.jobs {
+                    let Some(mut status) = load_job(conn, job.name)? else {
+                        continue;
+                    };
+                    status.description = job.description.to_string();
+                    status.running = runtime.get(job.name).is_some_and(|state| state.running);
+                    let anchor = status.next_run_at.take().as_deref().and_then(parse_time);
+                    status.next_run_at = match (Schedule::parse(&status.schedule), anchor) {
+                        (Ok(schedule), Some(anchor)) if !status.paused => {
+                            schedule.next_run(anchor).map(|next| format_time(next.max(now)))
+                        }
+                        _ => None,
+                    };
+                    jobs.push(status);
+                }
+                Ok(jobs)
+            })
+            .ok_or("Database is unavailable")?
+            .map_err(|e| e.to_string())
+    }
+
+    /// Run a job at the next tick, even if paused or blocked by its rules
+    pub fn trigger(&self, name: &str) -> Result<(), String> {
+        let job = self.job(name)?;
+        let mut runtime = self.runtime.lock().unwrap();
+        let state = runtime.get_mut(job.name).expect("registered job");
+        if state.running {
+            return Err(format!("Job '{}' is already running", name));
+        }
+        state.triggered = true;
+        Ok(())
+    }
+
+    /// Run a job at the next tick, or again right after its current run finishes
+    ///
+    /// For work that must not be missed, e.g. a note saved while the previous
+    /// run was already past it. Like [`Scheduler::trigger`], this ignores the job's rules.
+    pub fn request(&self, name: &str) -> Result<(), String> {
+        let job = self.job(name)?;
+        let mut runtime = self.runtime.lock().unwrap();
+        runtime.get_mut(job.name).expect("registered job").triggered = true;
+        Ok(())
+    }
+
+    /// Ask a running job to stop; `false` if it wasn't running
+    pub fn cancel(&self, name: &str) -> Result<bool, String> {
+        let job = self.job(name)?;
+        let runtime = self.runtime.lock().unwrap();
+        let state = &runtime[job.name];
+        if state.running {
+            state.cancel.store(true, Ordering::Relaxed);
+        }
+        Ok(state.running)
+    }
+
+    pub fn update(&self, name: &str, input: JobSettingsInput) -> Result<JobStatus, String> {
+        let job = self.job(name)?;
+        if let Some(schedule) = &input.schedule {
+            Schedule::parse(schedule)?;
+        }
+        self.host
+            .with_db(|conn| -> Result<()> {
+                conn.execute(
+                    "UPDATE jobs SET schedule = COALESCE(?, schedule), paused = COALESCE(?, paused),
+                            skip_on_battery = COALESCE(?, skip_on_battery), only_when_idle = COALESCE(?, only_when_idle)
+                     WHERE name = ?",
+                    params![
+                        input.schedule.as_deref().map(str::trim),
+                        input.paused,
+                        input.skip_on_battery,
+                        input.only_when_idle,
+                        job.name
+                    ],
+                )?;
+                Ok(())
+            })
+            .ok_or("Database is unavailable")?
+            .map_err(|e| e.to_string())?;
+        self.list()?
+            .into_iter()
+            .find(|status| status.name == job.name)
+            .ok_or_else(|| format!("Unknown job '{}'", name))
+    }
+
+    /// Most recent runs of a job first
+    pub fn runs(&self, name: &str, limit: usize) -> Result<Vec<JobRun>, String> {
+        let job = self.job(name)?;
+        self.host
+            .with_db(|conn| -> Result<Vec<JobRun>> {
+                let mut stmt = conn.prepare(
+                    "SELECT id, job_name, started_at, finished_at, status, message, trigger
+                     FROM job_runs WHERE job_name = ? ORDER BY id DESC LIMIT ?",
+                )?;
+                let runs = stmt.query_map(params![job.name, limit as i64], |row| {
+                    Ok(JobRun {
+                        id: row.get(0)?,
+                        job_name: row.get(1)?,
+                        started_at: row.get(2)?,
+                        finished_at: row.get(3)?,
+                        status: row.get(4)?,
+                        message: row.get(5)?,
+                        trigger: row.get(6)?,
+                    })
+                })?;
+                runs.collect()
+            })
+            .ok_or("Database is unavailable")?
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone)]
+    struct TestHost(Arc<Mutex<Connection>>);
+
+    impl JobHost for TestHost {
+        fn with_db<R>(&self, f: impl FnOnce(&Connection) -> R) -> Option<R> {
+            self.0.lock().ok().map(|conn| f(&conn))
+        }
+    }
+
+    fn at(text: &str) -> NaiveDateTime {
+        parse_time(text).unwrap()
+    }
+
+    fn wait_for(scheduler: &Arc<Scheduler<TestHost>>, name: &str) {
+        while scheduler.runtime.lock().unwrap()[name].running {
+            thread::sleep(StdDuration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_schedules() {
+        assert_eq!(Schedule::parse("every 15m").unwrap(), Schedule::Every(Duration::minutes(15)));
+        assert!(Schedule::parse("every 0s").is_err());
+        assert!(Schedule::parse("61 * * * *").is_err());
+
+        let nightly = Schedule::parse("30 3 * * *").unwrap();
+        assert_eq!(nightly.next_run(at("2024-05-01 03:30:00")), Some(at("2024-05-02 03:30:00")));
+        let weekdays = Schedule::parse("*/20 9-10 * * 1-5").unwrap();
+        // 2024-05-03 is a Friday
+        assert_eq!(weekdays.next_run(at("2024-05-03 10:45:10")), Some(at("2024-05-06 09:00:00")));
+        let first_or_sunday = Schedule::parse("0 0 1 * 7").unwrap();
+        assert_eq!(first_or_sunday.next_run(at("2024-05-01 12:00:00")), Some(at("2024-05-05 00:00:00")));
+        assert_eq!(Schedule::parse("0 0 30 2 *").unwrap().next_run(at("2024-01-01 00:00:00")), None);
+    }
+
+    #[test]
+    fn test_scheduler_runs_records_and_cancels() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE notes (id TEXT, updated_at DATETIME)", []).unwrap();
+        create_tables(&conn).unwrap();
+        let host = TestHost(Arc::new(Mutex::new(conn)));
+
+        let jobs = vec![
+            JobDef {
+                name: "count",
+                description: "Counts notes",
+                default_schedule: "every 1m",
+                skip_on_battery: false,
+                only_when_idle: false,
+                run: |host: &TestHost, _ctx: &JobContext| {
+                    let n: i64 = host
+                        .with_db(|conn| conn.query_row("SELECT COUNT(*) FROM notes", [], |row| row.get(0)))
+                        .unwrap()
+                        .map_err(|e| e.to_string())?;
+                    Ok(format!("{} notes", n))
+                },
+            },
+            JobDef {
+                name: "slow",
+                description: "Waits to be cancelled",
+                default_schedule: "0 3 1 1 *",
+                skip_on_battery: false,
+                only_when_idle: false,
+                run: |_host: &TestHost, ctx: &JobContext| {
+                    while !ctx.is_cancelled() {
+                        thread::sleep(StdDuration::from_millis(5));
+                    }
+                    Ok("stopped".to_string())
+                },
+            },
+        ];
+        let scheduler = Scheduler::new(host, jobs).unwrap();
+
+        assert!(scheduler.tick(now()).is_empty(), "nothing is due right after registering");
+        assert_eq!(scheduler.tick(now() + Duration::minutes(2)), ["count"]);
+        wait_for(&scheduler, "count");
+        let count = &scheduler.list().unwrap()[0];
+        assert_eq!((count.last_status.as_deref(), count.last_message.as_deref()), (Some("ok"), Some("0 notes")));
+
+        scheduler.update("count", JobSettingsInput { paused: Some(true), ..Default::default() }).unwrap();
+        assert!(scheduler.tick(now() + Duration::minutes(10)).is_empty());
+        assert!(scheduler.update("count", JobSettingsInput { schedule: Some("every 5x".into()), ..Default::default() }).is_err());
+
+        scheduler.trigger("slow").unwrap();
+        assert_eq!(scheduler.tick(now()), ["slow"]);
+        assert!(scheduler.trigger("slow").is_err());
+        scheduler.request("slow").unwrap();
+        assert!(scheduler.cancel("slow").unwrap());
+        wait_for(&scheduler, "slow");
+        let runs = scheduler.runs("slow", 10).unwrap();
+        assert_eq!((runs[0].status.as_str(), runs[0].trigger.as_str()), ("cancelled", "manual"));
+
+        // The request made during the run starts it again
+        assert_eq!(scheduler.tick(now()), ["slow"]);
+        assert!(scheduler.cancel("slow").unwrap());
+        wait_for(&scheduler, "slow");
+    }
+}
@@ -0,0 +1,184 @@
+use regex::{Captures, Regex};
+use rusqlite::{params, Connection, Result};
+use serde::Serialize;
+use std::sync::OnceLock;
+
+/// `note_links` holds one row per wiki-link target of each note. `target_id`
+/// is null while no note has that title, and is filled in by
+/// [`resolve_links`] once one does.
+pub fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS note_links (
+            source_id TEXT NOT NULL,
+            -- normalized title the link points at
+            target_title TEXT NOT NULL,
+            target_id TEXT,
+            PRIMARY KEY (source_id, target_title)
+        );
+        CREATE INDEX IF NOT EXISTS idx_note_links_target ON note_links(target_id);
+        CREATE INDEX IF NOT EXISTS idx_notes_title_normalized ON notes(lower(trim(title)));",
+    )
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LinkResolution {
+    /// Notes whose links were re-read
+    pub sources: usize,
+    pub links: usize,
+    /// Links whose target note doesn't exist
+    pub unresolved: usize,
+}
+
+/// A `[[Target#Heading|Alias]]` style wiki-link found in note content
+#[derive(Debug, Clone, PartialEq)]
+pub struct WikiLink {
+    pub target: String,
+    pub heading: Option<String>,
+    pub alias: Option<String>,
+    /// Byte range of the whole `[[...]]` token in the source text
+    pub start: usize,
+    pub end: usize,
+}
+
+impl WikiLink {
+    /// Text shown to the reader: the alias if present, otherwise the target
+    pub fn label(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.target)
+    }
+}
+
+fn wiki_link_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"\[\[([^\[\]|#]+)(?:#([^\[\]|]*))?(?:\|([^\[\]]*))?\]\]").unwrap()
+    })
+}
+
+fn to_link(caps: &Captures) -> WikiLink {
+    let whole = caps.get(0).unwrap();
+    WikiLink {
+        target: caps[1].trim().to_string(),
+        heading: caps.get(2).map(|m| m.as_str().trim().to_string()).filter(|h| !h.is_empty()),
+        alias: caps.get(3).map(|m| m.as_str().trim().to_string()).filter(|a| !a.is_empty()),
+        start: whole.start(),
+        end: whole.end(),
+    }
+}
+
+/// Extract all wiki-links from note content, in order of appearance
+pub fn extract_wiki_links(content: &str) -> Vec<WikiLink> {
+    wiki_link_regex()
+        .captures_iter(content)
+        .map(|caps| to_link(&caps))
+        .collect()
+}
+
+/// Replace every wiki-link in `content` with the string returned by `replacer`
+pub fn replace_wiki_links<F>(content: &str, mut replacer: F) -> String
+where
+    F: FnMut(&WikiLink) -> String,
+{
+    wiki_link_regex()
+        .replace_all(content, |caps: &Captures| replacer(&to_link(caps)))
+        .into_owned()
+}
+
+/// Normalize a note title for link resolution (wiki-links match case-insensitively)
+pub fn normalize_title(title: &str) -> String {
+    title.trim().to_lowercase()
+}
+
+/// Bring `note_links` up to date with note content
+///
+/// Re-reads the links of notes updated after `since` (all notes if `None`),
+/// drops rows of deleted notes and re-resolves every target title, so links
+/// to a note created or renamed since the last run are picked up.
+pub fn resolve_links(conn: &Connection, since: Option<&str>) -> Result<LinkResolution> {
+    let tx = conn.unchecked_transaction()?;
+    let mut result = LinkResolution::default();
+    {
+        let mut stmt = tx.prepare(
+            "SELECT id, COALESCE(content_plaintext, content, '') FROM notes
+             WHERE ?1 IS NULL OR updated_at >= ?1",
+        )?;
+        let mut rows = stmt.query(params![since])?;
+        while let Some(row) = rows.next()? {
+            let id: String = row.get(0)?;
+            let content: String = row.get(1)?;
+            tx.execute("DELETE FROM note_links WHERE source_id = ?", params![id])?;
+            for link in extract_wiki_links(&content) {
+                tx.execute(
+                    "INSERT OR IGNORE INTO note_links (source_id, target_title) VALUES (?, ?)",
+                    params![id, normalize_title(&link.target)],
+                )?;
+            }
+            result.sources += 1;
+        }
+    }
+
+    tx.execute("DELETE FROM note_links WHERE source_id NOT IN (SELECT id FROM notes)", [])?;
+    tx.execute(
+        "UPDATE note_links SET target_id =
+            (SELECT n.id FROM notes n WHERE lower(trim(n.title)) = note_links.target_title
+             ORDER BY n.created_at, n.id LIMIT 1)",
+        [],
+    )?;
+    result.links = tx.query_row("SELECT COUNT(*) FROM note_links", [], |row| row.get::<_, i64>(0))? as usize;
+    result.unresolved =
+        tx.query_row("SELECT COUNT(*) FROM note_links WHERE target_id IS NULL", [], |row| row.get::<_, i64>(0))? as usize;
+    tx.commit()?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_plain_and_aliased_links() {
+        let links = extract_wiki_links("See [[Rust Notes]] and [[Tokio#Runtime|the runtime]].");
+
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].target, "Rust Notes");
+        assert_eq!(links[0].label(), "Rust Notes");
+        assert_eq!(links[1].target, "Tokio");
+        assert_eq!(links[1].heading.as_deref(), Some("Runtime"));
+        assert_eq!(links[1].label(), "the runtime");
+    }
+
+    #[test]
+    fn test_replace_wiki_links() {
+        let out = replace_wiki_links("A [[One]] B [[Two|2]]", |link| link.label().to_uppercase());
+        assert_eq!(out, "A ONE B 2");
+    }
+
+    #[test]
+    fn test_resolve_links() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (id TEXT PRIMARY KEY, title TEXT NOT NULL, content TEXT NOT NULL,
+                                 content_plaintext TEXT, created_at DATETIME, updated_at DATETIME);
+             INSERT INTO notes (id, title, content, updated_at) VALUES
+                ('a', 'Alpha', 'See [[beta]] and [[Gamma#Intro|gamma]].', '2024-01-01 00:00:00'),
+                ('b', 'Beta', 'Back to [[Alpha]]', '2024-01-01 00:00:00');",
+        )
+        .unwrap();
+        create_tables(&conn).unwrap();
+
+        let first = resolve_links(&conn, None).unwrap();
+        assert_eq!((first.sources, first.links, first.unresolved), (2, 3, 1));
+
+        // A new note resolves the dangling link without re-reading the others
+        conn.execute(
+            "INSERT INTO notes (id, title, content, updated_at) VALUES ('g', 'gamma', '', '2024-02-01 00:00:00')",
+            [],
+        )
+        .unwrap();
+        let second = resolve_links(&conn, Some("2024-02-01 00:00:00")).unwrap();
+        assert_eq!((second.sources, second.unresolved), (1, 0));
+        let target: String = conn
+            .query_row("SELECT target_id FROM note_links WHERE target_title = 'gamma'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(target, "g");
+    }
+}
@@ -0,0 +1,628 @@
+//! Text generation backends
+//!
+//! The embedded Candle model, an Ollama server and any OpenAI-compatible
+//! endpoint (llama.cpp server, LM Studio) all implement [`LlmProvider`], so
+//! callers stream and cancel the same way whichever one the user picked in
+//! `llm_settings`. The embedded provider lives in `local_llm`.
+
+use reqwest::blocking::{Client, Response};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::services::db_service::table_has_column;
+use crate::services::encrypted_note_service::{decrypt_content, encrypt_content};
+use crate::services::local_llm::{GenerationOptions, GenerationStats};
+use crate::services::passphrase_service::PassphraseState;
+
+const OLLAMA_URL: &str = "http://localhost:11434";
+/// llama.cpp's server; LM Studio listens on http://localhost:1234/v1
+const OPENAI_URL: &str = "http://localhost:8080/v1";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn create_tables(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS llm_settings (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            provider TEXT NOT NULL DEFAULT 'embedded',
+            -- Server address for ollama and openai; NULL uses the provider's default
+            base_url TEXT,
+            -- Model name on the server; the embedded provider uses the model registry instead
+            model TEXT,
+            -- Only ever set by versions that stored the key in plaintext; see get_settings
+            api_key TEXT,
+            -- The API key, encrypted with the vault key like note content
+            api_key_encrypted TEXT,
+            api_key_nonce TEXT,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+    for column in ["api_key_encrypted", "api_key_nonce"] {
+        if !table_has_column(conn, "llm_settings", column) {
+            conn.execute(&format!("ALTER TABLE llm_settings ADD COLUMN {} TEXT", column), [])?;
+        }
+    }
+    conn.execute("INSERT OR IGNORE INTO llm_settings (id) VALUES (1)", [])?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    /// The Candle model from the model registry
+    Embedded,
+    Ollama,
+    /// Any server with an OpenAI-style `/chat/completions` endpoint
+    OpenAi,
+}
+
+impl ProviderKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ProviderKind::Embedded => "embedded",
+            ProviderKind::Ollama => "ollama",
+            ProviderKind::OpenAi => "openai",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        match s {
+            "ollama" => ProviderKind::Ollama,
+            "openai" => ProviderKind::OpenAi,
+            _ => ProviderKind::Embedded,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderSettings {
+    pub provider: ProviderKind,
+    pub base_url: Option<String>,
+    pub model: Option<String>,
+    pub api_key: Option<String>,
+    /// A key is stored but the vault is locked, so `api_key` is empty. Saving with
+    /// no key keeps the stored one while this is set.
+    #[serde(default)]
+    pub api_key_locked: bool,
+}
+
+/// The settings, with the API key decrypted when the vault is unlocked
+pub fn get_settings(conn: &Connection, passphrase_state: &Mutex<PassphraseState>) -> Result<ProviderSettings, String> {
+    let (provider, base_url, model, plaintext_key, encrypted_key): (String, _, _, Option<String>, Option<(String, String)>) = conn
+        .query_row(
+            "SELECT provider, base_url, model, api_key, api_key_encrypted, api_key_nonce FROM llm_settings WHERE id = 1",
+            [],
+            |row| {
+                let encrypted = match (row.get::<_, Option<String>>(4)?, row.get::<_, Option<String>>(5)?) {
+                    (Some(key), Some(nonce)) => Some((key, nonce)),
+                    _ => None,
+                };
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, encrypted))
+            },
+        )
+        .map_err(|e| e.to_string())?;
+    let state = passphrase_state.lock().map_err(|e| e.to_string())?;
+
+    let (api_key, api_key_locked) = match (encrypted_key, plaintext_key) {
+        (Some((key, nonce)), _) if state.is_enabled() => (Some(decrypt_content(&state, &key, &nonce)?), false),
+        (Some(_), _) => (None, true),
+        // Left in plaintext by an older version: encrypt it as soon as there is a vault key
+        (None, Some(key)) => {
+            if state.is_enabled() {
+                store_api_key(conn, &state, Some(&key))?;
+            }
+            (Some(key), false)
+        }
+        (None, None) => (None, false),
+    };
+    Ok(ProviderSettings {
+        provider: ProviderKind::parse(&provider),
+        base_url,
+        model,
+        api_key,
+        api_key_locked,
+    })
+}
+
+pub fn save_settings(
+    conn: &Connection,
+    passphrase_state: &Mutex<PassphraseState>,
+    settings: &ProviderSettings,
+) -> Result<(), String> {
+    let blank_to_none = |s: &Option<String>| s.as_deref().map(str::trim).filter(|s| !s.is_empty()).map(str::to_string);
+    let base_url = blank_to_none(&settings.base_url);
+    if let Some(url) = &base_url {
+        reqwest::Url::parse(url).map_err(|e| format!("Invalid server URL {}: {}", url, e))?;
+    }
+    let api_key = blank_to_none(&settings.api_key);
+    if api_key.is_some() || !settings.api_key_locked {
+        let state = passphrase_state.lock().map_err(|e| e.to_string())?;
+        store_api_key(conn, &state, api_key.as_deref())?;
+    }
+    conn.execute(
+        "UPDATE llm_settings SET provider = ?1, base_url = ?2, model = ?3, updated_at = CURRENT_TIMESTAMP
+         WHERE id = 1",
+        params![settings.provider.as_str(), base_url, blank_to_none(&settings.model)],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Encrypt `api_key` with the vault key, or clear the stored key when it is `None`
+fn store_api_key(conn: &Connection, state: &PassphraseState, api_key: Option<&str>) -> Result<(), String> {
+    let encrypted = match api_key {
+        Some(_) if !state.is_enabled() => {
+            return Err("Set a vault passphrase and unlock it to store an API key".to_string())
+        }
+        Some(key) => Some(encrypt_content(state, key)?),
+        None => None,
+    };
+    let (key, nonce) = encrypted.unzip();
+    conn.execute(
+        "UPDATE llm_settings SET api_key = NULL, api_key_encrypted = ?1, api_key_nonce = ?2 WHERE id = 1",
+        params![key, nonce],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "system" => Role::System,
+            "assistant" => Role::Assistant,
+            _ => Role::User,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self { role: Role::System, content: content.into() }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self { role: Role::User, content: content.into() }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self { role: Role::Assistant, content: content.into() }
+    }
+}
+
+/// A backend that turns a conversation into a reply
+pub trait LlmProvider {
+    /// Shown in the UI and logs, e.g. "ollama (llama3.1:8b)"
+    fn name(&self) -> String;
+
+    /// Generate the assistant's next message, passing text to `on_token` as it arrives.
+    /// Stops early, returning what it has so far, once `cancel` is set.
+    fn generate(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+        cancel: &AtomicBool,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<(String, GenerationStats), String>;
+
+    /// Tokens the model can attend to, prompt and reply together; `None` when unknown
+    fn context_length(&self) -> Option<usize> {
+        None
+    }
+
+    /// Tokens `text` takes up in a prompt. Remote servers don't expose their tokenizer,
+    /// so by default this estimates about four characters per token.
+    fn count_tokens(&self, text: &str) -> usize {
+        text.chars().count().div_ceil(4)
+    }
+}
+
+/// An Ollama server's `/api/chat`
+pub struct OllamaProvider {
+    client: Client,
+    base_url: String,
+    model: String,
+}
+
+impl OllamaProvider {
+    pub fn new(settings: &ProviderSettings) -> Result<Self, String> {
+        let model = settings.model.clone().ok_or("Choose an Ollama model in the AI settings")?;
+        Ok(Self {
+            client: client()?,
+            base_url: base_url(settings, OLLAMA_URL),
+            model,
+        })
+    }
+}
+
+impl LlmProvider for OllamaProvider {
+    fn name(&self) -> String {
+        format!("ollama ({})", self.model)
+    }
+
+    fn generate(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+        cancel: &AtomicBool,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<(String, GenerationStats), String> {
+        let body = json!({
+            "model": self.model,
+            "messages": messages_json(messages),
+            "stream": true,
+            "options": {
+                "num_predict": options.max_tokens,
+                "temperature": options.temperature.unwrap_or(0.0),
+                "top_p": options.top_p,
+                "seed": options.seed,
+                "repeat_penalty": options.repeat_penalty,
+            },
+        });
+        let response = send(self.client.post(format!("{}/api/chat", self.base_url)).json(&body), "Ollama")?;
+
+        // One JSON object per line; the last has "done": true and the token count
+        let mut stream = TextStream::new();
+        let mut eval_count = None;
+        for_each_line(response, cancel, &mut stream, |line, stream| {
+            let chunk: Value = serde_json::from_str(line).map_err(|e| format!("Bad response from Ollama: {}", e))?;
+            if let Some(error) = chunk.get("error").and_then(|e| e.as_str()) {
+                return Err(format!("Ollama: {}", error));
+            }
+            if let Some(text) = chunk.pointer("/message/content").and_then(|c| c.as_str()) {
+                stream.push(text, on_token);
+            }
+            if chunk.get("done").and_then(|d| d.as_bool()) == Some(true) {
+                eval_count = chunk.get("eval_count").and_then(|c| c.as_u64());
+                return Ok(false);
+            }
+            Ok(true)
+        })?;
+        Ok(stream.finish(eval_count, cancel))
+    }
+}
+
+/// An OpenAI-compatible `/chat/completions` endpoint
+pub struct OpenAiProvider {
+    client: Client,
+    base_url: String,
+    model: Option<String>,
+    api_key: Option<String>,
+}
+
+impl OpenAiProvider {
+    pub fn new(settings: &ProviderSettings) -> Result<Self, String> {
+        if settings.api_key_locked {
+            return Err("Unlock the vault to use the stored API key".to_string());
+        }
+        Ok(Self {
+            client: client()?,
+            base_url: base_url(settings, OPENAI_URL),
+            model: settings.model.clone(),
+            api_key: settings.api_key.clone(),
+        })
+    }
+}
+
+impl LlmProvider for OpenAiProvider {
+    fn name(&self) -> String {
+        match &self.model {
+            Some(model) => format!("{} ({})", self.base_url, model),
+            None => self.base_url.clone(),
+        }
+    }
+
+    fn generate(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+        cancel: &AtomicBool,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<(String, GenerationStats), String> {
+        let mut body = json!({
+            "messages": messages_json(messages),
+            "stream": true,
+            "max_tokens": options.max_tokens,
+            "temperature": options.temperature.unwrap_or(0.0),
+        });
+        // llama.cpp serves whatever it loaded and ignores the name; LM Studio needs it
+        if let Some(model) = &self.model {
+            body["model"] = json!(model);
+        }
+        if let Some(top_p) = options.top_p {
+            body["top_p"] = json!(top_p);
+        }
+        if let Some(seed) = options.seed {
+            body["seed"] = json!(seed);
+        }
+
+        let mut request = self.client.post(format!("{}/chat/completions", self.base_url)).json(&body);
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let response = send(request, "The server")?;
+
+        // Server-sent events: "data: {json}" lines, ending with "data: [DONE]"
+        let mut stream = TextStream::new();
+        for_each_line(response, cancel, &mut stream, |line, stream| {
+            let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+                return Ok(true);
+            };
+            if data == "[DONE]" {
+                return Ok(false);
+            }
+            let chunk: Value = serde_json::from_str(data).map_err(|e| format!("Bad response from server: {}", e))?;
+            if let Some(error) = chunk.pointer("/error/message").and_then(|e| e.as_str()) {
+                return Err(error.to_string());
+            }
+            if let Some(text) = chunk.pointer("/choices/0/delta/content").and_then(|c| c.as_str()) {
+                stream.push(text, on_token);
+            }
+            Ok(true)
+        })?;
+        Ok(stream.finish(None, cancel))
+    }
+}
+
+fn client() -> Result<Client, String> {
+    // No overall timeout: a long answer keeps the response open for as long as it takes
+    Client::builder().connect_timeout(CONNECT_TIMEOUT).timeout(None).build().map_err(|e| e.to_string())
+}
+
+fn base_url(settings: &ProviderSettings, default: &str) -> String {
+    settings.base_url.as_deref().unwrap_or(default).trim_end_matches('/').to_string()
+}
+
+fn messages_json(messages: &[ChatMessage]) -> Value {
+    Value::Array(
+        messages
+            .iter()
+            .map(|m| json!({ "role": m.role.as_str(), "content": m.content }))
+            .collect(),
+    )
+}
+
+fn send(request: reqwest::blocking::RequestBuilder, server: &str) -> Result<Response, String> {
+    let response = request.send().map_err(|e| format!("Could not reach the model server: {}", e))?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().unwrap_or_default();
+        return Err(format!("{} returned {}: {}", server, status, body.trim()));
+    }
+    Ok(response)
+}
+
+/// Feed each non-empty line of a streamed response to `handle` until it returns
+/// `Ok(false)`, the stream ends, or `cancel` is set. Dropping the response closes the
+/// connection, which stops generation on the server.
+fn for_each_line(
+    response: Response,
+    cancel: &AtomicBool,
+    stream: &mut TextStream,
+    mut handle: impl FnMut(&str, &mut TextStream) -> Result<bool, String>,
+) -> Result<(), String> {
+    for line in BufReader::new(response).lines() {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        let line = line.map_err(|e| format!("Connection to the model server was lost: {}", e))?;
+        let line = line.trim();
+        if !line.is_empty() && !handle(line, stream)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Collects streamed text and times it
+struct TextStream {
+    text: String,
+    chunks: usize,
+    start: Instant,
+}
+
+impl TextStream {
+    fn new() -> Self {
+        Self { text: String::new(), chunks: 0, start: Instant::now() }
+    }
+
+    fn push(&mut self, piece: &str, on_token: &mut dyn FnMut(&str)) {
+        if piece.is_empty() {
+            return;
+        }
+        on_token(piece);
+        self.text.push_str(piece);
+        self.chunks += 1;
+    }
+
+    /// Servers stream about one token per chunk, so chunks stand in for tokens
+    /// when the server does not report a count
+    fn finish(self, tokens: Option<u64>, cancel: &AtomicBool) -> (String, GenerationStats) {
+        let tokens = tokens.map(|t| t as usize).unwrap_or(self.chunks);
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let stats = GenerationStats {
+            tokens,
+            elapsed_ms: (elapsed * 1000.0) as u64,
+            tokens_per_second: if elapsed > 0.0 { tokens as f64 / elapsed } else { 0.0 },
+            cancelled: cancel.load(Ordering::Relaxed),
+        };
+        (self.text, stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Answers one request with `body` after the headers, sent one line at a time
+    fn mock_server(content_type: &str, lines: Vec<String>) -> (String, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let content_type = content_type.to_string();
+        let handle = std::thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            // Headers, then a body of Content-Length bytes
+            loop {
+                let n = socket.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let length: usize = text
+                        .lines()
+                        .find_map(|l| l.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap()))
+                        .unwrap_or(0);
+                    if request.len() >= end + 4 + length {
+                        break;
+                    }
+                }
+            }
+            write!(socket, "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nConnection: close\r\n\r\n", content_type).unwrap();
+            for line in lines {
+                // The client may hang up after cancelling
+                if socket.write_all(line.as_bytes()).is_err() {
+                    break;
+                }
+                socket.flush().ok();
+            }
+            String::from_utf8_lossy(&request).to_string()
+        });
+        (url, handle)
+    }
+
+    fn settings(url: &str, model: Option<&str>) -> ProviderSettings {
+        ProviderSettings {
+            provider: ProviderKind::OpenAi,
+            base_url: Some(url.to_string()),
+            model: model.map(str::to_string),
+            api_key: Some("secret".to_string()),
+            api_key_locked: false,
+        }
+    }
+
+    #[test]
+    fn test_api_key_is_stored_encrypted() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_tables(&conn).unwrap();
+        let state = PassphraseState::new();
+
+        // Without a vault key there is nothing to encrypt it with
+        assert!(save_settings(&conn, &state, &settings("http://localhost:1234/v1", None)).is_err());
+
+        state.lock().unwrap().set_passphrase("vault").unwrap();
+        save_settings(&conn, &state, &settings("http://localhost:1234/v1", None)).unwrap();
+        let (plaintext, encrypted): (Option<String>, String) = conn
+            .query_row("SELECT api_key, api_key_encrypted FROM llm_settings", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!(plaintext, None);
+        assert!(!encrypted.contains("secret"));
+        assert_eq!(get_settings(&conn, &state).unwrap().api_key.as_deref(), Some("secret"));
+
+        // Locked: the key is withheld, and saving without one keeps it
+        state.lock().unwrap().clear_passphrase();
+        let locked = get_settings(&conn, &state).unwrap();
+        assert_eq!(locked.api_key, None);
+        assert!(locked.api_key_locked);
+        assert!(OpenAiProvider::new(&locked).is_err());
+        save_settings(&conn, &state, &ProviderSettings { model: Some("other".to_string()), ..locked }).unwrap();
+        state.lock().unwrap().set_passphrase("vault").unwrap();
+        let unlocked = get_settings(&conn, &state).unwrap();
+        assert_eq!(unlocked.api_key.as_deref(), Some("secret"));
+        assert_eq!(unlocked.model.as_deref(), Some("other"));
+    }
+
+    #[test]
+    fn test_openai_compatible_streaming() {
+        let lines = ["Hel", "lo", " there"]
+            .iter()
+            .map(|t| format!("data: {}\n\n", json!({"choices": [{"delta": {"content": t}}]})))
+            .chain(std::iter::once("data: [DONE]\n\n".to_string()))
+            .collect();
+        let (url, server) = mock_server("text/event-stream", lines);
+        let provider = OpenAiProvider::new(&settings(&format!("{}/v1/", url), Some("local-model"))).unwrap();
+
+        let mut tokens = Vec::new();
+        let messages = [ChatMessage::system("Be brief"), ChatMessage::user("Hi")];
+        let options = GenerationOptions { seed: Some(7), ..Default::default() };
+        let (text, stats) = provider
+            .generate(&messages, &options, &AtomicBool::new(false), &mut |t| tokens.push(t.to_string()))
+            .unwrap();
+        assert_eq!(text, "Hello there");
+        assert_eq!(tokens, vec!["Hel", "lo", " there"]);
+        assert_eq!(stats.tokens, 3);
+        assert!(!stats.cancelled);
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /v1/chat/completions"));
+        assert!(request.to_lowercase().contains("authorization: bearer secret"));
+        let body: Value = serde_json::from_str(&request[request.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+        assert_eq!(body["model"], "local-model");
+        assert_eq!(body["seed"], 7);
+        assert_eq!(body["messages"][0]["role"], "system");
+    }
+
+    #[test]
+    fn test_ollama_streaming_and_cancel() {
+        let mut lines: Vec<String> = (0..50)
+            .map(|i| format!("{}\n", json!({"message": {"role": "assistant", "content": format!("w{} ", i)}, "done": false})))
+            .collect();
+        lines.push(format!("{}\n", json!({"done": true, "eval_count": 50})));
+        let (url, server) = mock_server("application/x-ndjson", lines);
+        let provider = OllamaProvider::new(&ProviderSettings {
+            provider: ProviderKind::Ollama,
+            model: Some("llama3.1:8b".to_string()),
+            ..settings(&url, None)
+        })
+        .unwrap();
+
+        let cancel = AtomicBool::new(false);
+        let mut seen = 0;
+        let (text, stats) = provider
+            .generate(&[ChatMessage::user("Count")], &GenerationOptions::default(), &cancel, &mut |_| {
+                seen += 1;
+                if seen == 3 {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            })
+            .unwrap();
+        assert_eq!(text, "w0 w1 w2 ");
+        assert_eq!(stats.tokens, 3);
+        assert!(stats.cancelled);
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /api/chat"));
+
+        // Without a model name the provider cannot be used
+        assert!(OllamaProvider::new(&settings(&url, None)).is_err());
+    }
+}
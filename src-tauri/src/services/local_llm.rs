@@ -1,22 +1,27 @@
 use anyhow::{Error as E, Result};
-use candle_core::{Tensor, Device, DType};
+use candle_core::quantized::gguf_file;
+use candle_core::{Tensor, Device};
 use candle_transformers::generation::LogitsProcessor;
-use candle_transformers::models::quantized_llama as model;
-use model::ModelWeights;
+use candle_transformers::models::{quantized_llama, quantized_qwen2};
 use tokenizers::Tokenizer;
 
+use std::collections::HashMap;
 use std::path::Path;
-use std::sync::Mutex;
-use std::io::Write;
-use futures_util::StreamExt;
-use serde::Serialize;
-
-// Qwen 2.5 0.5B Instruct - A high quality small model
-const MODEL_URL: &str = "https://huggingface.co/Qwen/Qwen2.5-0.5B-Instruct-GGUF/resolve/main/qwen2.5-0.5b-instruct-q4_k_m.gguf";
-const TOKENIZER_URL: &str = "https://huggingface.co/Qwen/Qwen2.5-0.5B-Instruct/resolve/main/tokenizer.json";
-
-const MODEL_FILENAME: &str = "qwen2.5-0.5b-instruct-q4_k_m.gguf";
-const TOKENIZER_FILENAME: &str = "tokenizer.json";
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+
+use crate::services::download_service::{self, DownloadProgress};
+use crate::services::llm_provider::{
+    ChatMessage, LlmProvider, OllamaProvider, OpenAiProvider, ProviderKind, ProviderSettings,
+};
+use crate::services::model_registry::{GgufInfo, ModelEntry};
+
+/// Upper bound on `max_tokens`, whatever the caller asks for
+const MAX_TOKENS_LIMIT: usize = 4096;
+/// How many recent tokens the repeat penalty looks back over
+const REPEAT_LAST_N: usize = 64;
 
 #[derive(Serialize, Clone)]
 pub struct ModelStatus {
@@ -25,169 +30,421 @@ pub struct ModelStatus {
     pub model_size: u64,
 }
 
-pub struct LocalLLMState {
-    pub model: Mutex<Option<ModelWeights>>,
-    pub tokenizer: Mutex<Option<Tokenizer>>,
+/// Sampling settings for one generation
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GenerationOptions {
+    pub max_tokens: usize,
+    /// `None` or 0 samples greedily
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    /// Fixed seed for reproducible output; random when `None`
+    pub seed: Option<u64>,
+    /// 1.0 disables the penalty
+    pub repeat_penalty: f32,
 }
 
-impl LocalLLMState {
-    pub fn new() -> Self {
+impl Default for GenerationOptions {
+    fn default() -> Self {
         Self {
-            model: Mutex::new(None),
-            tokenizer: Mutex::new(None),
+            max_tokens: 200,
+            temperature: Some(0.7),
+            top_p: Some(0.95),
+            seed: None,
+            repeat_penalty: 1.1,
         }
     }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GenerationStats {
+    pub tokens: usize,
+    pub elapsed_ms: u64,
+    pub tokens_per_second: f64,
+    pub cancelled: bool,
+}
+
+/// SHA-256 of each file a download fetched; `None` for files that were already on disk
+#[derive(Debug, Default)]
+pub struct DownloadedFiles {
+    pub model_sha256: Option<String>,
+    pub tokenizer_sha256: Option<String>,
+}
+
+/// Quantized weights, by the architecture named in the GGUF header
+enum ModelWeights {
+    /// Llama and the models that share its layout in GGUF, such as Mistral
+    Llama(quantized_llama::ModelWeights),
+    Qwen2(quantized_qwen2::ModelWeights),
+}
 
-    pub fn get_status(&self) -> ModelStatus {
-        let resource_dir = Path::new("resources");
-        let model_path = resource_dir.join(MODEL_FILENAME);
-        
-        let downloaded = model_path.exists();
-        let model_size = if downloaded {
-            std::fs::metadata(&model_path).map(|m| m.len()).unwrap_or(0)
-        } else {
-            0
+impl ModelWeights {
+    fn from_gguf(content: gguf_file::Content, file: &mut std::fs::File, device: &Device) -> Result<Self> {
+        let architecture = content
+            .metadata
+            .get("general.architecture")
+            .and_then(|v| v.to_string().ok())
+            .cloned()
+            .unwrap_or_default();
+        let weights = match architecture.as_str() {
+            "llama" => quantized_llama::ModelWeights::from_gguf(content, file, device).map(ModelWeights::Llama),
+            "qwen2" => quantized_qwen2::ModelWeights::from_gguf(content, file, device).map(ModelWeights::Qwen2),
+            "" => return Err(E::msg("The model file does not name its architecture")),
+            other => return Err(E::msg(format!("Unsupported model architecture: {}", other))),
         };
+        weights.map_err(|e| E::msg(format!("Failed to load {} model: {}", architecture, e)))
+    }
 
-        ModelStatus {
-            downloaded,
-            model_path: model_path.to_string_lossy().to_string(),
-            model_size,
+    fn forward(&mut self, input: &Tensor, position: usize) -> candle_core::Result<Tensor> {
+        match self {
+            ModelWeights::Llama(model) => model.forward(input, position),
+            ModelWeights::Qwen2(model) => model.forward(input, position),
         }
     }
+}
 
-    pub fn delete_model(&self) -> Result<()> {
-        let resource_dir = Path::new("resources");
-        let model_path = resource_dir.join(MODEL_FILENAME);
-        let tokenizer_path = resource_dir.join(TOKENIZER_FILENAME);
+struct LoadedModel {
+    /// Registry id of the model
+    id: String,
+    weights: ModelWeights,
+    tokenizer: Tokenizer,
+    eos_token_ids: Vec<u32>,
+}
 
-        if model_path.exists() {
-            std::fs::remove_file(model_path)?;
-        }
-        if tokenizer_path.exists() {
-            std::fs::remove_file(tokenizer_path)?;
-        }
-        
-        // Clear loaded state
-        let mut model_guard = self.model.lock().unwrap();
-        let mut tokenizer_guard = self.tokenizer.lock().unwrap();
-        *model_guard = None;
-        *tokenizer_guard = None;
+/// Holds one model in memory at a time; loading a different one replaces it
+pub struct LocalLLMState {
+    loaded: Mutex<Option<LoadedModel>>,
+    /// Cancel flags of running generations, by request id
+    requests: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
 
-        Ok(())
+impl LocalLLMState {
+    pub fn new() -> Self {
+        Self {
+            loaded: Mutex::new(None),
+            requests: Mutex::new(HashMap::new()),
+        }
     }
 
-    /// Checks if model files exist, downloads them if not.
-    pub async fn check_and_download(&self) -> Result<(String, String)> {
-        let resource_dir = Path::new("resources");
-        if !resource_dir.exists() {
-            std::fs::create_dir_all(resource_dir)?;
+    pub fn get_status(&self, model: &ModelEntry) -> ModelStatus {
+        ModelStatus {
+            downloaded: model.downloaded,
+            model_path: model.model_path.clone(),
+            model_size: model.model_size,
         }
-
-        let model_path = resource_dir.join(MODEL_FILENAME);
-        let tokenizer_path = resource_dir.join(TOKENIZER_FILENAME);
-
-        self.download_if_missing(&model_path, MODEL_URL).await?;
-        self.download_if_missing(&tokenizer_path, TOKENIZER_URL).await?;
-
-        Ok((
-            model_path.to_string_lossy().to_string(),
-            tokenizer_path.to_string_lossy().to_string()
-        ))
     }
 
-    async fn download_if_missing(&self, path: &Path, url: &str) -> Result<()> {
-        if path.exists() {
+    /// Delete a downloaded model's files. Models added from disk are only unloaded;
+    /// their files belong to the user.
+    pub fn delete_model(&self, model: &ModelEntry) -> Result<()> {
+        self.unload(&model.id);
+        if model.source != "catalog" {
             return Ok(());
         }
 
-        println!("Downloading {} to {:?}", url, path);
-        
-        let response = reqwest::get(url).await?;
-        if !response.status().is_success() {
-            return Err(E::msg(format!("Failed to download file: {}", response.status())));
+        for path in [&model.model_path, &model.tokenizer_path] {
+            let path = Path::new(path);
+            for file in [path.to_path_buf(), download_service::part_path(path)] {
+                if file.exists() {
+                    std::fs::remove_file(file)?;
+                }
+            }
         }
+        Ok(())
+    }
 
-        let mut file = std::fs::File::create(path)?;
-        let mut stream = response.bytes_stream();
-
-        while let Some(item) = stream.next().await {
-            let chunk = item?;
-            file.write_all(&chunk)?;
+    /// Drop `model_id` from memory if it is the loaded model
+    pub fn unload(&self, model_id: &str) {
+        let mut loaded = self.loaded.lock().unwrap();
+        if loaded.as_ref().is_some_and(|m| m.id == model_id) {
+            *loaded = None;
         }
-
-        println!("Download complete: {:?}", path);
-        Ok(())
     }
 
-    pub fn load_model(&self, model_path: &str, tokenizer_path: &str) -> Result<()> {
-        let mut model_guard = self.model.lock().unwrap();
-        let mut tokenizer_guard = self.tokenizer.lock().unwrap();
+    /// Checks if model files exist, downloads them if not.
+    ///
+    /// Downloads resume from an earlier attempt and are checked against the registry's
+    /// SHA-256 when it has one. Returns the hashes of files downloaded by this call.
+    pub async fn check_and_download(
+        &self,
+        model: &ModelEntry,
+        mut on_progress: impl FnMut(&str, &DownloadProgress),
+    ) -> Result<DownloadedFiles> {
+        let mut downloaded = DownloadedFiles::default();
+        let files = [
+            ("model", &model.model_path, &model.model_url, &model.model_sha256, &mut downloaded.model_sha256),
+            ("tokenizer", &model.tokenizer_path, &model.tokenizer_url, &model.tokenizer_sha256, &mut downloaded.tokenizer_sha256),
+        ];
+        for (file, path, url, expected, hash) in files {
+            let path = Path::new(path);
+            if path.exists() {
+                continue;
+            }
+            let Some(url) = url else {
+                return Err(E::msg(format!("{} is missing for model {}", path.display(), model.name)));
+            };
+            if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir)?;
+            }
+
+            println!("Downloading {} to {:?}", url, path);
+            let sha256 = download_service::download(url, path, expected.as_deref(), |p| on_progress(file, p))
+                .await
+                .map_err(E::msg)?;
+            println!("Download complete: {:?}", path);
+            *hash = Some(sha256);
+        }
+        Ok(downloaded)
+    }
 
-        if model_guard.is_some() {
+    pub fn load_model(&self, model: &ModelEntry) -> Result<()> {
+        let mut loaded = self.loaded.lock().unwrap();
+        if loaded.as_ref().is_some_and(|m| m.id == model.id) {
             return Ok(()); // Already loaded
         }
+        // Without EOS tokens generation would only stop at max_tokens; they are
+        // recorded when the model is added or downloaded
+        if model.eos_token_ids.is_empty() {
+            return Err(E::msg(format!("{} is not set up yet; download it from Settings", model.name)));
+        }
+        // Free the previous model before reading the next one
+        *loaded = None;
+
+        println!("Loading model {} from: {}", model.id, model.model_path);
+
+        let mut file = std::fs::File::open(&model.model_path)?;
+        let start = Instant::now();
+
+        // Load the GGUF model (CPU only for now)
+        let content = gguf_file::Content::read(&mut file)?;
+        let weights = ModelWeights::from_gguf(content, &mut file, &Device::Cpu)?;
 
-        println!("Loading model from: {}", model_path);
-        
-        let mut file = std::fs::File::open(model_path)?;
-        let start = std::time::Instant::now();
-        
-        // Load the GGUF model
-        let model = model::ModelWeights::from_gguf(
-            &mut file,
-            &mut file,
-            &Device::Cpu, // Start with CPU for safety, upgrade to Metal/CUDA later
-        )?;
-        
         println!("Model loaded in {:.2}s", start.elapsed().as_secs_f64());
 
-        let tokenizer = Tokenizer::from_file(tokenizer_path).map_err(E::msg)?;
+        let tokenizer = Tokenizer::from_file(&model.tokenizer_path).map_err(E::msg)?;
 
-        *model_guard = Some(model);
-        *tokenizer_guard = Some(tokenizer);
+        *loaded = Some(LoadedModel {
+            id: model.id.clone(),
+            weights,
+            tokenizer,
+            eos_token_ids: model.eos_token_ids.clone(),
+        });
 
         Ok(())
     }
 
-    pub fn generate(&self, prompt: &str) -> Result<String> {
-        let model_guard = self.model.lock().unwrap();
-        let tokenizer_guard = self.tokenizer.lock().unwrap();
-
-        let model = model_guard.as_ref().ok_or(E::msg("Model not loaded"))?;
-        let tokenizer = tokenizer_guard.as_ref().ok_or(E::msg("Tokenizer not loaded"))?;
+    /// Generate a completion, passing each piece of decoded text to `on_token` as it is produced.
+    /// Stops at an EOS token, after `options.max_tokens`, or as soon as `cancel` is set.
+    pub fn generate_stream(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+        cancel: &AtomicBool,
+        mut on_token: impl FnMut(&str),
+    ) -> Result<(String, GenerationStats)> {
+        let mut loaded = self.loaded.lock().unwrap();
+        let LoadedModel { weights: model, tokenizer, eos_token_ids, .. } =
+            loaded.as_mut().ok_or(E::msg("Model not loaded"))?;
 
-        // Simple generation loop
         let tokens = tokenizer.encode(prompt, true).map_err(E::msg)?;
         let prompt_tokens = tokens.get_ids().to_vec();
-        let mut all_tokens = vec![];
-        
-        let mut logits_processor = LogitsProcessor::new(299792458, Some(0.7), Some(0.95), Some(1.1)); // Seed, Temp, Top-P, Repeat Penalty
-
-        let mut next_token = *prompt_tokens.last().unwrap();
-        
-        // Pre-fill the model with prompt
-        // NOTE: This is a simplified sequential pre-fill. 
-        // Real implementation needs to handle KV-cache for efficiency.
+        if prompt_tokens.is_empty() {
+            return Err(E::msg("Prompt is empty"));
+        }
+
+        let seed = options.seed.unwrap_or_else(rand::random);
+        let temperature = options.temperature.filter(|t| *t > 0.0);
+        let mut logits_processor = LogitsProcessor::new(seed, temperature, options.top_p);
+        let max_tokens = options.max_tokens.clamp(1, MAX_TOKENS_LIMIT);
+
+        let start = Instant::now();
+        let mut all_tokens: Vec<u32> = Vec::new();
+        let mut text = String::new();
+        let mut cancelled = false;
+
+        // The whole prompt goes through in one pass; after that the model's KV cache
+        // means only the newest token has to be fed in
         let mut input = Tensor::new(prompt_tokens.as_slice(), &Device::Cpu)?.unsqueeze(0)?;
-        let logits = model.forward(&input, 0)?;
-        let logits = logits.squeeze(0)?;
-        next_token = logits_processor.sample(&logits)?;
-        all_tokens.push(next_token);
-
-        // Generate 200 tokens max for now
-        for index in 0..200 {
-            let input = Tensor::new(&[next_token], &Device::Cpu)?.unsqueeze(0)?;
-            let logits = model.forward(&input, prompt_tokens.len() + index)?;
-            let logits = logits.squeeze(0)?;
-            next_token = logits_processor.sample(&logits)?;
+        for index in 0..max_tokens {
+            if cancel.load(Ordering::Relaxed) {
+                cancelled = true;
+                break;
+            }
+            let position = if index == 0 { 0 } else { prompt_tokens.len() + index - 1 };
+            let logits = model.forward(&input, position)?.squeeze(0)?;
+            let logits = if options.repeat_penalty == 1.0 {
+                logits
+            } else {
+                let from = all_tokens.len().saturating_sub(REPEAT_LAST_N);
+                candle_transformers::utils::apply_repeat_penalty(&logits, options.repeat_penalty, &all_tokens[from..])?
+            };
+            let next_token = logits_processor.sample(&logits)?;
+            if eos_token_ids.contains(&next_token) {
+                break;
+            }
             all_tokens.push(next_token);
-            
-            // Check for EOS token (simplified)
-            if next_token == 151645 || next_token == 151643 { break; } // Qwen EOS tokens
+
+            // Decode everything so far rather than token by token, since one character can span tokens
+            let decoded = tokenizer.decode(&all_tokens, true).map_err(E::msg)?;
+            if let Some(piece) = new_text(&decoded, text.len()) {
+                on_token(piece);
+                text.push_str(piece);
+            }
+            input = Tensor::new(&[next_token], &Device::Cpu)?.unsqueeze(0)?;
         }
 
-        let output = tokenizer.decode(&all_tokens, true).map_err(E::msg)?;
-        Ok(output)
+        // Flush anything held back waiting for the rest of a character
+        let decoded = tokenizer.decode(&all_tokens, true).map_err(E::msg)?;
+        if let Some(rest) = decoded.get(text.len()..).filter(|rest| !rest.is_empty()) {
+            on_token(rest);
+            text.push_str(rest);
+        }
+
+        let elapsed = start.elapsed().as_secs_f64();
+        let stats = GenerationStats {
+            tokens: all_tokens.len(),
+            elapsed_ms: (elapsed * 1000.0) as u64,
+            tokens_per_second: if elapsed > 0.0 { all_tokens.len() as f64 / elapsed } else { 0.0 },
+            cancelled,
+        };
+        Ok((text, stats))
+    }
+
+    pub fn generate(&self, prompt: &str, options: &GenerationOptions) -> Result<String> {
+        let (text, _) = self.generate_stream(prompt, options, &AtomicBool::new(false), |_| {})?;
+        Ok(text)
+    }
+
+    /// Register a generation under `request_id` and return the flag that cancels it
+    pub fn start_request(&self, request_id: &str) -> Arc<AtomicBool> {
+        let flag = Arc::new(AtomicBool::new(false));
+        self.requests.lock().unwrap().insert(request_id.to_string(), flag.clone());
+        flag
+    }
+
+    pub fn finish_request(&self, request_id: &str) {
+        self.requests.lock().unwrap().remove(request_id);
+    }
+
+    /// Ask a running generation to stop; `false` if no generation has this id
+    pub fn cancel(&self, request_id: &str) -> bool {
+        match self.requests.lock().unwrap().get(request_id) {
+            Some(flag) => {
+                flag.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl LocalLLMState {
+    /// The provider chosen in settings. `model` is the registry model for the task,
+    /// which only the embedded provider uses.
+    pub fn provider(&self, settings: &ProviderSettings, model: ModelEntry) -> Result<Box<dyn LlmProvider + '_>, String> {
+        Ok(match settings.provider {
+            ProviderKind::Embedded => Box::new(EmbeddedProvider { llm: self, model }),
+            ProviderKind::Ollama => Box::new(OllamaProvider::new(settings)?),
+            ProviderKind::OpenAi => Box::new(OpenAiProvider::new(settings)?),
+        })
+    }
+}
+
+/// The in-process Candle model; its files must already be downloaded
+pub struct EmbeddedProvider<'a> {
+    llm: &'a LocalLLMState,
+    model: ModelEntry,
+}
+
+impl LlmProvider for EmbeddedProvider<'_> {
+    fn name(&self) -> String {
+        self.model.name.clone()
+    }
+
+    fn generate(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+        cancel: &AtomicBool,
+        on_token: &mut dyn FnMut(&str),
+    ) -> std::result::Result<(String, GenerationStats), String> {
+        self.llm
+            .load_model(&self.model)
+            .map_err(|e| format!("Error loading brain: {}", e))?;
+        let prompt = self.model.prompt_format.render(messages);
+        self.llm
+            .generate_stream(&prompt, options, cancel, on_token)
+            .map_err(|e| format!("Brain freeze: {}", e))
+    }
+
+    fn context_length(&self) -> Option<usize> {
+        self.model.context_length.map(|n| n as usize)
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        let counted = self.llm.load_model(&self.model).ok().and_then(|_| {
+            let loaded = self.llm.loaded.lock().unwrap();
+            let encoding = loaded.as_ref()?.tokenizer.encode(text, false).ok()?;
+            Some(encoding.len())
+        });
+        counted.unwrap_or_else(|| text.chars().count().div_ceil(4))
+    }
+}
+
+/// Read a GGUF file's metadata without loading its weights
+pub fn read_gguf_info(path: &Path) -> Result<GgufInfo> {
+    let mut file = std::fs::File::open(path)?;
+    let content = gguf_file::Content::read(&mut file)
+        .map_err(|e| E::msg(format!("{} is not a GGUF model: {}", path.display(), e)))?;
+    let metadata = &content.metadata;
+    let string = |key: &str| metadata.get(key).and_then(|v| v.to_string().ok()).cloned();
+    let number = |key: &str| {
+        metadata
+            .get(key)
+            .and_then(|v| v.to_u32().ok().or_else(|| v.to_u64().ok().map(|n| n as u32)))
+    };
+
+    let architecture = string("general.architecture");
+    let context_length = architecture.as_ref().and_then(|arch| number(&format!("{}.context_length", arch)));
+    Ok(GgufInfo {
+        name: string("general.name"),
+        chat_template: string("tokenizer.chat_template"),
+        eos_token_id: number("tokenizer.ggml.eos_token_id"),
+        architecture,
+        context_length,
+    })
+}
+
+/// The part of `decoded` after the first `emitted` bytes, unless it is empty or ends in a
+/// partial character (decoded as U+FFFD until its remaining bytes arrive)
+fn new_text(decoded: &str, emitted: usize) -> Option<&str> {
+    let piece = decoded.get(emitted..)?;
+    if piece.is_empty() || piece.ends_with('\u{FFFD}') {
+        None
+    } else {
+        Some(piece)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_text_holds_back_partial_characters() {
+        assert_eq!(new_text("Hello wor", 5), Some(" wor"));
+        assert_eq!(new_text("Hello", 5), None);
+        assert_eq!(new_text("Hello \u{FFFD}", 5), None);
+        assert_eq!(new_text("Hello \u{e9}", 5), Some(" \u{e9}"));
+        // Earlier text re-decoded differently: wait rather than slice mid-character
+        assert_eq!(new_text("\u{e9}t\u{e9}", 1), None);
+    }
+
+    #[test]
+    fn test_generation_options_defaults() {
+        let options: GenerationOptions = serde_json::from_str(r#"{"temperature": 0.2}"#).unwrap();
+        assert_eq!(options.temperature, Some(0.2));
+        assert_eq!(options.max_tokens, 200);
+        assert_eq!(options.seed, None);
     }
 }
@@ -1,7 +1,6 @@
 pub mod synthesis_service;
 pub mod db_service;
 pub mod cards;
-pub mod ollama;
 pub mod background;
 pub mod graph_analysis;
 pub mod server;
@@ -16,6 +15,25 @@ pub mod passphrase_service;
 pub mod encrypted_note_service;
 pub mod role_service;
 pub mod graph_service;
-
-
-
+pub mod link_service;
+pub mod publish_service;
+pub mod anki_service;
+pub mod vault_service;
+pub mod search_query;
+pub mod search_index;
+pub mod fuzzy_search;
+pub mod saved_search_service;
+pub mod revision_service;
+pub mod find_replace_service;
+pub mod embedding_model;
+pub mod embedding_service;
+pub mod trash_service;
+pub mod duplicate_service;
+pub mod job_scheduler;
+pub mod model_registry;
+pub mod download_service;
+pub mod llm_provider;
+pub mod rag_service;
+pub mod chat_service;
+pub mod task_service;
+pub mod note_ai_service;
@@ -0,0 +1,683 @@
+//! Registry of local LLMs
+//!
+//! Models are either downloaded into `resources/` from a catalog entry or
+//! registered from GGUF and tokenizer files already on disk. Each model's
+//! architecture, prompt format, context length and EOS tokens are read
+//! from its files once, when it is added or, for catalog models, once it
+//! is downloaded, and stored in `llm_models`.
+//! `llm_task_models` picks the model used for each task.
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::services::llm_provider::{ChatMessage, Role};
+
+/// Catalog model used when a task has no model chosen
+pub const DEFAULT_MODEL_ID: &str = "qwen2.5-0.5b-instruct";
+
+/// Special tokens that end a turn in common chat models, used when the tokenizer
+/// config does not name an EOS token
+const KNOWN_EOS_TOKENS: &[&str] = &[
+    "</s>",
+    "<|endoftext|>",
+    "<|im_end|>",
+    "<|eot_id|>",
+    "<|end_of_text|>",
+    "<|end|>",
+    "<end_of_turn>",
+];
+
+pub fn create_tables(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS llm_models (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            -- 'catalog' models are downloaded into resources/, 'local' ones stay where the user keeps them
+            source TEXT NOT NULL,
+            model_path TEXT NOT NULL,
+            tokenizer_path TEXT NOT NULL,
+            model_url TEXT,
+            tokenizer_url TEXT,
+            -- Expected SHA-256 of each download; recorded on first download when unknown
+            model_sha256 TEXT,
+            tokenizer_sha256 TEXT,
+            architecture TEXT,
+            chat_template TEXT,
+            prompt_format TEXT NOT NULL DEFAULT 'plain',
+            context_length INTEGER,
+            -- JSON array of token ids
+            eos_token_ids TEXT NOT NULL DEFAULT '[]',
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS llm_task_models (
+            task TEXT PRIMARY KEY,
+            model_id TEXT NOT NULL,
+            FOREIGN KEY (model_id) REFERENCES llm_models(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    // Qwen 2.5 0.5B Instruct - A high quality small model. Its architecture, prompt
+    // format and EOS tokens are filled in from its files by `record_model_info`.
+    conn.execute(
+        "INSERT OR IGNORE INTO llm_models
+            (id, name, source, model_path, tokenizer_path, model_url, tokenizer_url)
+         VALUES (?1, 'Qwen 2.5 0.5B Instruct', 'catalog', ?2, ?3, ?4, ?5)",
+        params![
+            DEFAULT_MODEL_ID,
+            "resources/qwen2.5-0.5b-instruct-q4_k_m.gguf",
+            "resources/qwen2.5-0.5b-instruct/tokenizer.json",
+            "https://huggingface.co/Qwen/Qwen2.5-0.5B-Instruct-GGUF/resolve/main/qwen2.5-0.5b-instruct-q4_k_m.gguf",
+            "https://huggingface.co/Qwen/Qwen2.5-0.5B-Instruct/resolve/main/tokenizer.json",
+        ],
+    )?;
+    // Catalog tokenizers used to share resources/tokenizer.json; give the default model its own
+    conn.execute(
+        "UPDATE llm_models SET tokenizer_path = ?2
+         WHERE id = ?1 AND tokenizer_path = 'resources/tokenizer.json'",
+        params![DEFAULT_MODEL_ID, "resources/qwen2.5-0.5b-instruct/tokenizer.json"],
+    )?;
+
+    Ok(())
+}
+
+/// What a model is used for; each task can use a different model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelTask {
+    Chat,
+    Summarize,
+    Tag,
+}
+
+impl ModelTask {
+    pub const ALL: [ModelTask; 3] = [ModelTask::Chat, ModelTask::Summarize, ModelTask::Tag];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModelTask::Chat => "chat",
+            ModelTask::Summarize => "summarize",
+            ModelTask::Tag => "tag",
+        }
+    }
+}
+
+/// How a model expects a conversation to be laid out, detected from its chat template
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PromptFormat {
+    /// `<|im_start|>` turns (Qwen, many fine-tunes)
+    ChatMl,
+    /// `<|start_header_id|>` turns (Llama 3)
+    Llama3,
+    /// `[INST]` blocks (Llama 2, Mistral)
+    Llama2,
+    /// `<start_of_turn>` turns (Gemma)
+    Gemma,
+    /// `<|user|>` turns (Phi-3, Zephyr)
+    Phi3,
+    /// No template; plain text with role labels
+    Plain,
+}
+
+impl PromptFormat {
+    pub fn detect(chat_template: Option<&str>) -> Self {
+        let Some(template) = chat_template else {
+            return PromptFormat::Plain;
+        };
+        if template.contains("<|im_start|>") {
+            PromptFormat::ChatMl
+        } else if template.contains("<|start_header_id|>") {
+            PromptFormat::Llama3
+        } else if template.contains("<start_of_turn>") {
+            PromptFormat::Gemma
+        } else if template.contains("[INST]") {
+            PromptFormat::Llama2
+        } else if template.contains("<|user|>") {
+            PromptFormat::Phi3
+        } else {
+            PromptFormat::Plain
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PromptFormat::ChatMl => "chatml",
+            PromptFormat::Llama3 => "llama3",
+            PromptFormat::Llama2 => "llama2",
+            PromptFormat::Gemma => "gemma",
+            PromptFormat::Phi3 => "phi3",
+            PromptFormat::Plain => "plain",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        match s {
+            "chatml" => PromptFormat::ChatMl,
+            "llama3" => PromptFormat::Llama3,
+            "llama2" => PromptFormat::Llama2,
+            "gemma" => PromptFormat::Gemma,
+            "phi3" => PromptFormat::Phi3,
+            _ => PromptFormat::Plain,
+        }
+    }
+
+    /// A prompt for one system message and one user turn, ending where the model's answer starts
+    pub fn prompt(&self, system: &str, user: &str) -> String {
+        self.render(&[ChatMessage::system(system), ChatMessage::user(user)])
+    }
+
+    /// Lay out a conversation, ending where the model's next answer starts
+    pub fn render(&self, messages: &[ChatMessage]) -> String {
+        let mut out = String::new();
+        match self {
+            PromptFormat::ChatMl => {
+                for m in messages {
+                    out.push_str(&format!("<|im_start|>{}\n{}<|im_end|>\n", m.role.as_str(), m.content));
+                }
+                out.push_str("<|im_start|>assistant\n");
+            }
+            PromptFormat::Llama3 => {
+                out.push_str("<|begin_of_text|>");
+                for m in messages {
+                    out.push_str(&format!(
+                        "<|start_header_id|>{}<|end_header_id|>\n\n{}<|eot_id|>",
+                        m.role.as_str(),
+                        m.content
+                    ));
+                }
+                out.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+            }
+            PromptFormat::Llama2 => {
+                let mut system = None;
+                for m in messages {
+                    match m.role {
+                        Role::System => system = Some(m.content.as_str()),
+                        Role::User => match system.take() {
+                            Some(sys) => out.push_str(&format!("[INST] <<SYS>>\n{}\n<</SYS>>\n\n{} [/INST]", sys, m.content)),
+                            None => out.push_str(&format!("[INST] {} [/INST]", m.content)),
+                        },
+                        Role::Assistant => out.push_str(&format!(" {} </s><s>", m.content)),
+                    }
+                }
+            }
+            // Gemma has no system role; the instructions go in the user turn
+            PromptFormat::Gemma => {
+                let mut system = None;
+                for m in messages {
+                    match m.role {
+                        Role::System => system = Some(m.content.as_str()),
+                        Role::User => match system.take() {
+                            Some(sys) => out.push_str(&format!("<start_of_turn>user\n{}\n\n{}<end_of_turn>\n", sys, m.content)),
+                            None => out.push_str(&format!("<start_of_turn>user\n{}<end_of_turn>\n", m.content)),
+                        },
+                        Role::Assistant => out.push_str(&format!("<start_of_turn>model\n{}<end_of_turn>\n", m.content)),
+                    }
+                }
+                out.push_str("<start_of_turn>model\n");
+            }
+            PromptFormat::Phi3 => {
+                for m in messages {
+                    out.push_str(&format!("<|{}|>\n{}<|end|>\n", m.role.as_str(), m.content));
+                }
+                out.push_str("<|assistant|>\n");
+            }
+            PromptFormat::Plain => {
+                for m in messages {
+                    match m.role {
+                        Role::System => out.push_str(&format!("{}\n\n", m.content)),
+                        Role::User => out.push_str(&format!("User: {}\n", m.content)),
+                        Role::Assistant => out.push_str(&format!("Assistant: {}\n", m.content)),
+                    }
+                }
+                out.push_str("Assistant:");
+            }
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelEntry {
+    pub id: String,
+    pub name: String,
+    pub source: String,
+    pub model_path: String,
+    pub tokenizer_path: String,
+    pub model_url: Option<String>,
+    pub tokenizer_url: Option<String>,
+    pub model_sha256: Option<String>,
+    pub tokenizer_sha256: Option<String>,
+    pub architecture: Option<String>,
+    pub chat_template: Option<String>,
+    pub prompt_format: PromptFormat,
+    pub context_length: Option<u32>,
+    pub eos_token_ids: Vec<u32>,
+    /// Both files are on disk
+    pub downloaded: bool,
+    pub model_size: u64,
+}
+
+impl ModelEntry {
+    pub fn is_eos(&self, token: u32) -> bool {
+        self.eos_token_ids.contains(&token)
+    }
+}
+
+/// Metadata read from a GGUF file's header
+#[derive(Debug, Clone, Default)]
+pub struct GgufInfo {
+    pub name: Option<String>,
+    pub architecture: Option<String>,
+    pub chat_template: Option<String>,
+    pub context_length: Option<u32>,
+    pub eos_token_id: Option<u32>,
+}
+
+const MODEL_COLUMNS: &str = "id, name, source, model_path, tokenizer_path, model_url, tokenizer_url,
+     model_sha256, tokenizer_sha256, architecture, chat_template, prompt_format, context_length, eos_token_ids";
+
+fn model_from_row(row: &Row) -> rusqlite::Result<ModelEntry> {
+    let model_path: String = row.get(3)?;
+    let tokenizer_path: String = row.get(4)?;
+    let prompt_format: String = row.get(11)?;
+    let eos: String = row.get(13)?;
+    let model_size = std::fs::metadata(&model_path).map(|m| m.len()).ok();
+    Ok(ModelEntry {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        source: row.get(2)?,
+        downloaded: model_size.is_some() && Path::new(&tokenizer_path).exists(),
+        model_size: model_size.unwrap_or(0),
+        model_path,
+        tokenizer_path,
+        model_url: row.get(5)?,
+        tokenizer_url: row.get(6)?,
+        model_sha256: row.get(7)?,
+        tokenizer_sha256: row.get(8)?,
+        architecture: row.get(9)?,
+        chat_template: row.get(10)?,
+        prompt_format: PromptFormat::parse(&prompt_format),
+        context_length: row.get(12)?,
+        eos_token_ids: serde_json::from_str(&eos).unwrap_or_default(),
+    })
+}
+
+pub fn list_models(conn: &Connection) -> Result<Vec<ModelEntry>, String> {
+    let mut stmt = conn
+        .prepare(&format!("SELECT {} FROM llm_models ORDER BY source, name", MODEL_COLUMNS))
+        .map_err(|e| e.to_string())?;
+    let models = stmt
+        .query_map([], model_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(|e| e.to_string())?;
+    Ok(models)
+}
+
+pub fn get_model(conn: &Connection, id: &str) -> Result<Option<ModelEntry>, String> {
+    conn.query_row(
+        &format!("SELECT {} FROM llm_models WHERE id = ?", MODEL_COLUMNS),
+        params![id],
+        model_from_row,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+/// Register a GGUF model and its tokenizer from disk. `info` is the GGUF header,
+/// read by [`crate::services::local_llm::read_gguf_info`].
+pub fn add_local_model(
+    conn: &Connection,
+    name: Option<&str>,
+    model_path: &Path,
+    tokenizer_path: &Path,
+    info: &GgufInfo,
+) -> Result<ModelEntry, String> {
+    if !model_path.is_file() {
+        return Err(format!("Model file {} not found", model_path.display()));
+    }
+    if !tokenizer_path.is_file() {
+        return Err(format!("Tokenizer file {} not found", tokenizer_path.display()));
+    }
+
+    let eos = required_eos_token_ids(tokenizer_path, info)?;
+
+    let file_stem = model_path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let name = name
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty())
+        .or_else(|| info.name.clone())
+        .unwrap_or_else(|| file_stem.clone());
+    let id = unique_id(conn, &slug(&file_stem))?;
+    let prompt_format = PromptFormat::detect(info.chat_template.as_deref());
+
+    conn.execute(
+        "INSERT INTO llm_models
+            (id, name, source, model_path, tokenizer_path, architecture, chat_template,
+             prompt_format, context_length, eos_token_ids)
+         VALUES (?1, ?2, 'local', ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            id,
+            name,
+            model_path.to_string_lossy(),
+            tokenizer_path.to_string_lossy(),
+            info.architecture,
+            info.chat_template,
+            prompt_format.as_str(),
+            info.context_length,
+            serde_json::to_string(&eos).map_err(|e| e.to_string())?,
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+
+    get_model(conn, &id)?.ok_or_else(|| "Model was not saved".to_string())
+}
+
+/// Fill in a registered model's architecture, prompt format, context length and EOS
+/// tokens from its GGUF header and tokenizer, e.g. once a catalog model is downloaded
+pub fn record_model_info(conn: &Connection, id: &str, info: &GgufInfo) -> Result<ModelEntry, String> {
+    let model = get_model(conn, id)?.ok_or_else(|| format!("Model {} not found", id))?;
+    let eos = required_eos_token_ids(Path::new(&model.tokenizer_path), info)?;
+    conn.execute(
+        "UPDATE llm_models SET architecture = ?2, chat_template = ?3, prompt_format = ?4,
+                               context_length = ?5, eos_token_ids = ?6
+         WHERE id = ?1",
+        params![
+            id,
+            info.architecture,
+            info.chat_template,
+            PromptFormat::detect(info.chat_template.as_deref()).as_str(),
+            info.context_length,
+            serde_json::to_string(&eos).map_err(|e| e.to_string())?,
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    get_model(conn, id)?.ok_or_else(|| format!("Model {} not found", id))
+}
+
+/// [`eos_token_ids`], failing when there are none, since generation would never stop
+fn required_eos_token_ids(tokenizer_path: &Path, info: &GgufInfo) -> Result<Vec<u32>, String> {
+    let eos = eos_token_ids(tokenizer_path, info.eos_token_id)?;
+    if eos.is_empty() {
+        return Err("Could not find an end-of-sequence token in the tokenizer or model".to_string());
+    }
+    Ok(eos)
+}
+
+/// Remember the hashes of downloaded files so later downloads are checked against them.
+/// Hashes the registry already has are kept.
+pub fn record_checksums(
+    conn: &Connection,
+    id: &str,
+    model_sha256: Option<&str>,
+    tokenizer_sha256: Option<&str>,
+) -> Result<(), String> {
+    conn.execute(
+        "UPDATE llm_models SET model_sha256 = COALESCE(model_sha256, ?2),
+                               tokenizer_sha256 = COALESCE(tokenizer_sha256, ?3)
+         WHERE id = ?1",
+        params![id, model_sha256, tokenizer_sha256],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Unregister a model; tasks that used it fall back to the default. Files are left alone.
+pub fn remove_model(conn: &Connection, id: &str) -> Result<(), String> {
+    if id == DEFAULT_MODEL_ID {
+        return Err("The default model cannot be removed".to_string());
+    }
+    conn.execute("DELETE FROM llm_task_models WHERE model_id = ?", params![id])
+        .map_err(|e| e.to_string())?;
+    let removed = conn
+        .execute("DELETE FROM llm_models WHERE id = ?", params![id])
+        .map_err(|e| e.to_string())?;
+    if removed == 0 {
+        return Err(format!("Model {} not found", id));
+    }
+    Ok(())
+}
+
+/// The model chosen for each task, by task name
+pub fn task_models(conn: &Connection) -> Result<HashMap<String, String>, String> {
+    let mut chosen = HashMap::new();
+    for task in ModelTask::ALL {
+        chosen.insert(task.as_str().to_string(), model_for_task(conn, task)?.id);
+    }
+    Ok(chosen)
+}
+
+pub fn set_task_model(conn: &Connection, task: ModelTask, model_id: &str) -> Result<(), String> {
+    if get_model(conn, model_id)?.is_none() {
+        return Err(format!("Model {} not found", model_id));
+    }
+    conn.execute(
+        "INSERT INTO llm_task_models (task, model_id) VALUES (?1, ?2)
+         ON CONFLICT(task) DO UPDATE SET model_id = excluded.model_id",
+        params![task.as_str(), model_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// The model to use for `task`: the one chosen for it, else the default
+pub fn model_for_task(conn: &Connection, task: ModelTask) -> Result<ModelEntry, String> {
+    let chosen: Option<String> = conn
+        .query_row("SELECT model_id FROM llm_task_models WHERE task = ?", params![task.as_str()], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if let Some(model) = chosen.map(|id| get_model(conn, &id)).transpose()?.flatten() {
+        return Ok(model);
+    }
+    get_model(conn, DEFAULT_MODEL_ID)?.ok_or_else(|| "No model is registered".to_string())
+}
+
+/// EOS token ids for a tokenizer.
+///
+/// Looks at, in order: `eos_token` in a `tokenizer_config.json` next to the
+/// tokenizer, `eos_token_id` in `generation_config.json`, the id from the GGUF
+/// header, and finally well-known end-of-turn tokens the tokenizer defines.
+pub fn eos_token_ids(tokenizer_path: &Path, gguf_eos: Option<u32>) -> Result<Vec<u32>, String> {
+    let tokenizer = read_json(tokenizer_path)?.ok_or_else(|| format!("{} not found", tokenizer_path.display()))?;
+    let dir = tokenizer_path.parent().unwrap_or(Path::new("."));
+    let mut ids = Vec::new();
+
+    if let Some(config) = read_json(&dir.join("tokenizer_config.json"))? {
+        let name = match config.get("eos_token") {
+            Some(serde_json::Value::String(s)) => Some(s.as_str()),
+            Some(serde_json::Value::Object(o)) => o.get("content").and_then(|c| c.as_str()),
+            _ => None,
+        };
+        if let Some(id) = name.and_then(|n| token_id(&tokenizer, n)) {
+            ids.push(id);
+        }
+    }
+
+    if let Some(config) = read_json(&dir.join("generation_config.json"))? {
+        match config.get("eos_token_id") {
+            Some(serde_json::Value::Number(n)) => ids.extend(n.as_u64().map(|n| n as u32)),
+            Some(serde_json::Value::Array(a)) => ids.extend(a.iter().filter_map(|n| n.as_u64()).map(|n| n as u32)),
+            _ => {}
+        }
+    }
+
+    ids.extend(gguf_eos);
+
+    if ids.is_empty() {
+        ids.extend(KNOWN_EOS_TOKENS.iter().filter_map(|name| token_id(&tokenizer, name)));
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    ids.retain(|id| seen.insert(*id));
+    Ok(ids)
+}
+
+/// Id of `token` in a `tokenizer.json`, from its added tokens or its vocabulary
+fn token_id(tokenizer: &serde_json::Value, token: &str) -> Option<u32> {
+    let added = tokenizer
+        .get("added_tokens")
+        .and_then(|a| a.as_array())
+        .and_then(|tokens| {
+            tokens
+                .iter()
+                .find(|t| t.get("content").and_then(|c| c.as_str()) == Some(token))
+                .and_then(|t| t.get("id")?.as_u64())
+        });
+    added
+        .or_else(|| tokenizer.get("model")?.get("vocab")?.get(token)?.as_u64())
+        .map(|id| id as u32)
+}
+
+fn read_json(path: &Path) -> Result<Option<serde_json::Value>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    serde_json::from_str(&text)
+        .map(Some)
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+}
+
+fn slug(name: &str) -> String {
+    let slug: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '.' { c } else { '-' })
+        .collect();
+    let slug = slug.trim_matches('-').to_string();
+    if slug.is_empty() { "model".to_string() } else { slug }
+}
+
+fn unique_id(conn: &Connection, base: &str) -> Result<String, String> {
+    let mut id = base.to_string();
+    let mut n = 2;
+    while get_model(conn, &id)?.is_some() {
+        id = format!("{}-{}", base, n);
+        n += 1;
+    }
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, text: &str) {
+        std::fs::write(path, text).unwrap();
+    }
+
+    #[test]
+    fn test_eos_tokens_from_tokenizer_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let tokenizer = dir.path().join("tokenizer.json");
+        write(
+            &tokenizer,
+            r#"{"added_tokens": [{"id": 2, "content": "</s>"}, {"id": 7, "content": "<|im_end|>"}],
+                "model": {"vocab": {"hello": 10, "<|eot_id|>": 11}}}"#,
+        );
+
+        // Nothing but the tokenizer: well-known names
+        assert_eq!(eos_token_ids(&tokenizer, None).unwrap(), vec![2, 7, 11]);
+
+        write(&dir.path().join("tokenizer_config.json"), r#"{"eos_token": {"content": "<|im_end|>"}}"#);
+        write(&dir.path().join("generation_config.json"), r#"{"eos_token_id": [7, 2]}"#);
+        assert_eq!(eos_token_ids(&tokenizer, Some(2)).unwrap(), vec![7, 2]);
+    }
+
+    #[test]
+    fn test_prompt_formats() {
+        let conversation = [
+            ChatMessage::system("Be brief"),
+            ChatMessage::user("Hi"),
+            ChatMessage::assistant("Hello"),
+            ChatMessage::user("Bye"),
+        ];
+        assert_eq!(
+            PromptFormat::ChatMl.render(&conversation),
+            "<|im_start|>system\nBe brief<|im_end|>\n<|im_start|>user\nHi<|im_end|>\n\
+             <|im_start|>assistant\nHello<|im_end|>\n<|im_start|>user\nBye<|im_end|>\n<|im_start|>assistant\n"
+        );
+        assert_eq!(
+            PromptFormat::Llama2.render(&conversation),
+            "[INST] <<SYS>>\nBe brief\n<</SYS>>\n\nHi [/INST] Hello </s><s>[INST] Bye [/INST]"
+        );
+        assert_eq!(
+            PromptFormat::Gemma.prompt("Be brief", "Hi"),
+            "<start_of_turn>user\nBe brief\n\nHi<end_of_turn>\n<start_of_turn>model\n"
+        );
+        assert_eq!(PromptFormat::Plain.prompt("Be brief", "Hi"), "Be brief\n\nUser: Hi\nAssistant:");
+    }
+
+    #[test]
+    fn test_local_model_and_task_selection() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_tables(&conn).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let model_path = dir.path().join("Llama-3.2-1B-Instruct.Q4_K_M.gguf");
+        let tokenizer = dir.path().join("tokenizer.json");
+        write(&model_path, "GGUF");
+        write(&tokenizer, r#"{"added_tokens": [{"id": 128009, "content": "<|eot_id|>"}]}"#);
+
+        let info = GgufInfo {
+            architecture: Some("llama".to_string()),
+            chat_template: Some("{{ '<|start_header_id|>' + message['role'] }}".to_string()),
+            context_length: Some(131072),
+            ..Default::default()
+        };
+        let model = add_local_model(&conn, None, &model_path, &tokenizer, &info).unwrap();
+        assert_eq!(model.id, "llama-3.2-1b-instruct.q4-k-m");
+        assert_eq!(model.name, "Llama-3.2-1B-Instruct.Q4_K_M");
+        assert_eq!(model.prompt_format, PromptFormat::Llama3);
+        assert_eq!(model.eos_token_ids, vec![128009]);
+        assert!(model.downloaded);
+
+        // Adding the same file again gets a fresh id
+        let again = add_local_model(&conn, Some("Copy"), &model_path, &tokenizer, &info).unwrap();
+        assert_eq!(again.id, "llama-3.2-1b-instruct.q4-k-m-2");
+
+        let info = GgufInfo {
+            architecture: Some("qwen2".to_string()),
+            chat_template: Some("<|im_start|>".to_string()),
+            context_length: Some(32768),
+            ..Default::default()
+        };
+        let updated = record_model_info(&conn, &again.id, &info).unwrap();
+        assert_eq!(updated.architecture.as_deref(), Some("qwen2"));
+        assert_eq!((updated.prompt_format, updated.context_length), (PromptFormat::ChatMl, Some(32768)));
+        assert_eq!(updated.eos_token_ids, vec![128009]);
+
+        set_task_model(&conn, ModelTask::Tag, &model.id).unwrap();
+        assert_eq!(model_for_task(&conn, ModelTask::Tag).unwrap().id, model.id);
+        assert_eq!(model_for_task(&conn, ModelTask::Chat).unwrap().id, DEFAULT_MODEL_ID);
+        assert!(set_task_model(&conn, ModelTask::Chat, "missing").is_err());
+
+        // The catalog model's details come from its files once they are downloaded
+        let default = get_model(&conn, DEFAULT_MODEL_ID).unwrap().unwrap();
+        assert_eq!((default.architecture.as_deref(), default.prompt_format), (None, PromptFormat::Plain));
+        assert_ne!(default.tokenizer_path, "resources/tokenizer.json");
+
+        assert!(!default.downloaded);
+
+        // The first download's hashes are kept; later downloads must match them
+        record_checksums(&conn, DEFAULT_MODEL_ID, Some("aa"), None).unwrap();
+        record_checksums(&conn, DEFAULT_MODEL_ID, Some("bb"), Some("cc")).unwrap();
+        let default = get_model(&conn, DEFAULT_MODEL_ID).unwrap().unwrap();
+        assert_eq!(default.model_sha256.as_deref(), Some("aa"));
+        assert_eq!(default.tokenizer_sha256.as_deref(), Some("cc"));
+        // Reopening the database doesn't reset them
+        create_tables(&conn).unwrap();
+        let default = get_model(&conn, DEFAULT_MODEL_ID).unwrap().unwrap();
+        assert_eq!(default.model_sha256.as_deref(), Some("aa"));
+
+        remove_model(&conn, &model.id).unwrap();
+        assert_eq!(model_for_task(&conn, ModelTask::Tag).unwrap().id, DEFAULT_MODEL_ID);
+        assert!(remove_model(&conn, DEFAULT_MODEL_ID).is_err());
+    }
+}
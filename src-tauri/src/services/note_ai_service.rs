@@ -0,0 +1,695 @@
+//! AI actions on a note: TL;DR summary, title, tags and action items
+//!
+//! Requesting an action queues a suggestion; the `note_actions` background
+//! job generates it with the model chosen for the action's task and marks
+//! it `ready`. Nothing changes the note until the user applies the
+//! suggestion, optionally after editing it. A suggestion's output is a list
+//! of strings: one summary or title, or the tags or action items.
+//!
+//! Statuses: `queued` → `running` → `ready` or `failed`, then `applied` or
+//! `dismissed`. Requesting an action again dismisses its open suggestion.
+
+use chrono::Local;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::AtomicBool;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use crate::services::llm_provider::{self, ChatMessage, LlmProvider, ProviderSettings};
+use crate::services::local_llm::GenerationOptions;
+use crate::services::model_registry::{self, ModelEntry, ModelTask};
+use crate::services::passphrase_service::PassphraseState;
+use crate::services::rag_service;
+use crate::services::revision_service;
+use crate::services::task_service;
+
+/// Key of the summary in a note's `properties`
+pub const SUMMARY_PROPERTY: &str = "tldr";
+const MAX_TAGS: usize = 5;
+const MAX_TITLE_CHARS: usize = 100;
+
+pub fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS note_ai_suggestions (
+            id TEXT PRIMARY KEY,
+            note_id TEXT NOT NULL,
+            action TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'queued',
+            output TEXT NOT NULL DEFAULT '[]',
+            error TEXT,
+            model TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_note_ai_suggestions_note ON note_ai_suggestions(note_id, status)",
+        [],
+    )?;
+
+    // Generations cut off when the app last closed start over
+    conn.execute("UPDATE note_ai_suggestions SET status = 'queued' WHERE status = 'running'", [])?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteAction {
+    Summary,
+    Title,
+    Tags,
+    ActionItems,
+}
+
+impl NoteAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NoteAction::Summary => "summary",
+            NoteAction::Title => "title",
+            NoteAction::Tags => "tags",
+            NoteAction::ActionItems => "action_items",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "summary" => Some(NoteAction::Summary),
+            "title" => Some(NoteAction::Title),
+            "tags" => Some(NoteAction::Tags),
+            "action_items" => Some(NoteAction::ActionItems),
+            _ => None,
+        }
+    }
+
+    /// Whose model generates this action
+    pub fn task(&self) -> ModelTask {
+        match self {
+            NoteAction::Summary | NoteAction::Title => ModelTask::Summarize,
+            NoteAction::Tags => ModelTask::Tag,
+            NoteAction::ActionItems => ModelTask::Chat,
+        }
+    }
+
+    fn max_tokens(&self) -> usize {
+        match self {
+            NoteAction::Summary => 200,
+            NoteAction::Title => 30,
+            NoteAction::Tags => 60,
+            NoteAction::ActionItems => 300,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NoteSuggestion {
+    pub id: String,
+    pub note_id: String,
+    pub action: NoteAction,
+    pub status: String,
+    pub output: Vec<String>,
+    pub error: Option<String>,
+    /// The provider and model that generated it
+    pub model: Option<String>,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+/// A claimed suggestion with everything needed to generate it
+#[derive(Debug, Clone)]
+pub struct PendingSuggestion {
+    pub id: String,
+    pub note_id: String,
+    pub action: NoteAction,
+    pub title: String,
+    pub content: String,
+    /// Every tag name in the vault
+    pub existing_tags: Vec<String>,
+    /// Tags the note already has
+    pub note_tags: Vec<String>,
+    pub model: ModelEntry,
+    pub settings: ProviderSettings,
+}
+
+fn now() -> String {
+    Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+const SUGGESTION_COLUMNS: &str = "id, note_id, action, status, output, error, model, created_at, updated_at";
+
+fn map_suggestion(row: &rusqlite::Row) -> Result<NoteSuggestion> {
+    let action: String = row.get(2)?;
+    let output: String = row.get(4)?;
+    Ok(NoteSuggestion {
+        id: row.get(0)?,
+        note_id: row.get(1)?,
+        action: NoteAction::parse(&action).unwrap_or(NoteAction::Summary),
+        status: row.get(3)?,
+        output: serde_json::from_str(&output).unwrap_or_default(),
+        error: row.get(5)?,
+        model: row.get(6)?,
+        created_at: row.get(7)?,
+        updated_at: row.get(8)?,
+    })
+}
+
+pub fn get_suggestion(conn: &Connection, id: &str) -> Result<Option<NoteSuggestion>> {
+    conn.query_row(
+        &format!("SELECT {} FROM note_ai_suggestions WHERE id = ?", SUGGESTION_COLUMNS),
+        params![id],
+        map_suggestion,
+    )
+    .optional()
+}
+
+/// Queue `actions` for a note, dismissing any open suggestion for the same action
+pub fn request(conn: &Connection, note_id: &str, actions: &[NoteAction]) -> Result<Vec<NoteSuggestion>, String> {
+    let exists: bool = conn
+        .query_row("SELECT EXISTS (SELECT 1 FROM notes WHERE id = ?)", params![note_id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if !exists {
+        return Err(format!("Note {} not found", note_id));
+    }
+
+    let mut queued = Vec::new();
+    for action in actions {
+        let now = now();
+        conn.execute(
+            "UPDATE note_ai_suggestions SET status = 'dismissed', updated_at = ?
+             WHERE note_id = ? AND action = ? AND status IN ('queued', 'ready', 'failed')",
+            params![now, note_id, action.as_str()],
+        )
+        .map_err(|e| e.to_string())?;
+        let id = Uuid::new_v4().to_string();
+        conn.execute(
+            "INSERT INTO note_ai_suggestions (id, note_id, action, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?4)",
+            params![id, note_id, action.as_str(), now],
+        )
+        .map_err(|e| e.to_string())?;
+        queued.extend(get_suggestion(conn, &id).map_err(|e| e.to_string())?);
+    }
+    Ok(queued)
+}
+
+/// A note's suggestions that are waiting, being generated, or waiting for review
+pub fn list_for_note(conn: &Connection, note_id: &str) -> Result<Vec<NoteSuggestion>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM note_ai_suggestions
+         WHERE note_id = ? AND status IN ('queued', 'running', 'ready', 'failed')
+         ORDER BY created_at, rowid",
+        SUGGESTION_COLUMNS
+    ))?;
+    let suggestions = stmt.query_map(params![note_id], map_suggestion)?;
+    suggestions.collect()
+}
+
+/// Mark the oldest queued suggestion `running` and gather what generating it needs.
+/// A suggestion whose note is gone or locked fails instead.
+pub fn claim_next(
+    conn: &Connection,
+    passphrase_state: &Mutex<PassphraseState>,
+) -> Result<Option<PendingSuggestion>, String> {
+    loop {
+        let next = conn
+            .query_row(
+                "SELECT id, note_id, action FROM note_ai_suggestions WHERE status = 'queued'
+                 ORDER BY created_at, rowid LIMIT 1",
+                [],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?)),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let Some((id, note_id, action)) = next else { return Ok(None) };
+        let action = NoteAction::parse(&action).unwrap_or(NoteAction::Summary);
+        conn.execute(
+            "UPDATE note_ai_suggestions SET status = 'running', updated_at = ? WHERE id = ?",
+            params![now(), id],
+        )
+        .map_err(|e| e.to_string())?;
+
+        let note: Option<(String, Option<String>, bool)> = conn
+            .query_row(
+                "SELECT title, COALESCE(content_plaintext, CASE WHEN content_encrypted IS NULL THEN content END),
+                        content_encrypted IS NOT NULL
+                 FROM notes WHERE id = ?",
+                params![note_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let (title, content) = match note {
+            Some((title, Some(content), _)) => (title, content),
+            Some((_, None, true)) => {
+                finish(conn, &id, Err("The note is encrypted and locked".to_string()), None)
+                    .map_err(|e| e.to_string())?;
+                continue;
+            }
+            _ => {
+                finish(conn, &id, Err("The note no longer exists".to_string()), None).map_err(|e| e.to_string())?;
+                continue;
+            }
+        };
+
+        let existing_tags = tag_names(conn, "SELECT name FROM tags ORDER BY name", None)?;
+        let note_tags = tag_names(
+            conn,
+            "SELECT t.name FROM note_tags nt JOIN tags t ON t.id = nt.tag_id WHERE nt.note_id = ? ORDER BY t.name",
+            Some(&note_id),
+        )?;
+        return Ok(Some(PendingSuggestion {
+            id,
+            note_id,
+            action,
+            title,
+            content,
+            existing_tags,
+            note_tags,
+            model: model_registry::model_for_task(conn, action.task())?,
+            settings: llm_provider::get_settings(conn, passphrase_state)?,
+        }));
+    }
+}
+
+fn tag_names(conn: &Connection, sql: &str, note_id: Option<&str>) -> Result<Vec<String>, String> {
+    let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+    let names = stmt
+        .query_map(rusqlite::params_from_iter(note_id), |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    names.collect::<Result<_>>().map_err(|e| e.to_string())
+}
+
+/// Store a generation's result: its output, ready for review, or why it failed
+pub fn finish(
+    conn: &Connection,
+    id: &str,
+    result: Result<Vec<String>, String>,
+    model: Option<&str>,
+) -> Result<Option<NoteSuggestion>> {
+    let (status, output, error) = match result {
+        Ok(output) => ("ready", output, None),
+        Err(e) => ("failed", Vec::new(), Some(e)),
+    };
+    let output = serde_json::to_string(&output).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    // A suggestion dismissed while it was generating stays dismissed
+    conn.execute(
+        "UPDATE note_ai_suggestions SET status = ?, output = ?, error = ?, model = ?, updated_at = ?
+         WHERE id = ? AND status = 'running'",
+        params![status, output, error, model, now(), id],
+    )?;
+    get_suggestion(conn, id)
+}
+
+/// The conversation asking the model for `action` on a note
+pub fn prompt(action: NoteAction, title: &str, content: &str, candidate_tags: &[String]) -> Vec<ChatMessage> {
+    let instructions = match action {
+        NoteAction::Summary => "Write a TL;DR of the note: two or three sentences covering its main points. \
+            Reply with the summary only."
+            .to_string(),
+        NoteAction::Title => "Suggest a short, specific title for the note, at most eight words. \
+            Reply with the title only."
+            .to_string(),
+        NoteAction::Tags => format!(
+            "Choose up to {} tags that describe the note, ONLY from this list: {}. \
+             Reply with the chosen tags separated by commas, or \"none\" if none fit.",
+            MAX_TAGS,
+            candidate_tags.join(", ")
+        ),
+        NoteAction::ActionItems => "List the action items in the note: concrete things someone has to do. \
+            Reply with one item per line, each starting with \"- \", or \"none\" if there are none."
+            .to_string(),
+    };
+    vec![
+        ChatMessage::system(instructions),
+        ChatMessage::user(format!("Title: {}\n\n{}", title, content)),
+    ]
+}
+
+/// Remove a label such as "TL;DR:" or "Title:" the model may have put before its answer
+fn strip_label<'a>(text: &'a str, labels: &[&str]) -> &'a str {
+    let text = text.trim();
+    for label in labels {
+        if text.len() > label.len() && text.is_char_boundary(label.len()) && text[..label.len()].eq_ignore_ascii_case(label) {
+            return text[label.len()..].trim_start_matches([':', '-', ' ']).trim();
+        }
+    }
+    text
+}
+
+fn is_none(text: &str) -> bool {
+    let text = text.trim().trim_end_matches('.').to_lowercase();
+    text.is_empty() || text == "none" || text == "n/a"
+}
+
+/// Turn the model's reply into the suggestion's output. Tags are limited to existing
+/// ones the note doesn't have yet, spelled as they exist.
+pub fn parse_output(action: NoteAction, reply: &str, candidate_tags: &[String]) -> Vec<String> {
+    match action {
+        NoteAction::Summary => {
+            let summary = strip_label(reply, &["tl;dr", "tldr", "summary"]);
+            if is_none(summary) { Vec::new() } else { vec![summary.to_string()] }
+        }
+        NoteAction::Title => {
+            let line = reply.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or_default();
+            let title = strip_label(line, &["title"])
+                .trim_matches(|c: char| c == '"' || c == '\'' || c == '*' || c == '#' || c.is_whitespace())
+                .trim_end_matches('.');
+            let title: String = title.chars().take(MAX_TITLE_CHARS).collect();
+            if title.is_empty() { Vec::new() } else { vec![title] }
+        }
+        NoteAction::Tags => {
+            let mut tags: Vec<String> = Vec::new();
+            for word in reply.split([',', '\n']) {
+                let word = word.trim().trim_start_matches(['-', '*', ' ', '#']).trim().trim_end_matches('.');
+                if let Some(tag) = candidate_tags.iter().find(|t| t.eq_ignore_ascii_case(word)) {
+                    if !tags.contains(tag) && tags.len() < MAX_TAGS {
+                        tags.push(tag.clone());
+                    }
+                }
+            }
+            tags
+        }
+        NoteAction::ActionItems => {
+            let mut items: Vec<String> = Vec::new();
+            for line in reply.lines() {
+                let line = line.trim();
+                let item = line
+                    .strip_prefix("- [ ]")
+                    .or_else(|| line.strip_prefix(['-', '*', '•']))
+                    .or_else(|| {
+                        let rest = line.trim_start_matches(|c: char| c.is_ascii_digit());
+                        (rest.len() < line.len()).then(|| rest.strip_prefix(['.', ')'])).flatten()
+                    });
+                if let Some(item) = item.map(str::trim).filter(|i| !is_none(i)) {
+                    if !items.iter().any(|i| i == item) {
+                        items.push(item.to_string());
+                    }
+                }
+            }
+            items
+        }
+    }
+}
+
+/// Cut `text` at a character boundary until it takes at most `budget` tokens
+fn fit_to_tokens(text: &str, budget: usize, count_tokens: &dyn Fn(&str) -> usize) -> String {
+    let mut text = text.to_string();
+    loop {
+        let tokens = count_tokens(&text);
+        if tokens <= budget || text.is_empty() {
+            return text;
+        }
+        let keep = text.chars().count() * budget / tokens;
+        text = text.chars().take(keep.saturating_sub(1)).collect();
+    }
+}
+
+/// Generate a claimed suggestion's output with `provider`
+pub fn generate(
+    provider: &dyn LlmProvider,
+    pending: &PendingSuggestion,
+    cancel: &AtomicBool,
+) -> Result<Vec<String>, String> {
+    let candidate_tags: Vec<String> =
+        pending.existing_tags.iter().filter(|t| !pending.note_tags.contains(t)).cloned().collect();
+    if pending.action == NoteAction::Tags && candidate_tags.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let options = GenerationOptions {
+        max_tokens: pending.action.max_tokens(),
+        temperature: Some(0.3),
+        ..Default::default()
+    };
+    let mut messages = prompt(pending.action, &pending.title, "", &candidate_tags);
+    let overhead: usize = messages.iter().map(|m| provider.count_tokens(&m.content)).sum();
+    let budget = rag_service::context_budget(provider.context_length(), options.max_tokens).saturating_sub(overhead);
+    let content = fit_to_tokens(&pending.content, budget, &|text| provider.count_tokens(text));
+    messages[1].content.push_str(&content);
+
+    let (reply, stats) = provider.generate(&messages, &options, cancel, &mut |_| {})?;
+    if stats.cancelled {
+        return Err("Cancelled".to_string());
+    }
+    Ok(parse_output(pending.action, &reply, &candidate_tags))
+}
+
+/// Apply a ready suggestion to its note; `values` replaces the generated output, so the
+/// user can edit it or pick a subset first
+pub fn apply(conn: &Connection, id: &str, values: Option<Vec<String>>) -> Result<NoteSuggestion, String> {
+    let suggestion = get_suggestion(conn, id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Suggestion {} not found", id))?;
+    if suggestion.status != "ready" {
+        return Err(format!("Suggestion is {}, not ready to apply", suggestion.status));
+    }
+    let values: Vec<String> = values
+        .unwrap_or(suggestion.output)
+        .into_iter()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect();
+    let now = revision_service::batch_timestamp();
+
+    // Title and summary changes are undoable like other edits to a note
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let batch_id = match suggestion.action {
+        NoteAction::Summary | NoteAction::Title => {
+            let batch_id = revision_service::begin_batch(
+                &tx,
+                "note_ai",
+                &format!("Apply suggested {}", suggestion.action.as_str()),
+            )
+            .map_err(|e| e.to_string())?;
+            revision_service::record_revision(&tx, &batch_id, &suggestion.note_id, &now).map_err(|e| e.to_string())?;
+            Some(batch_id)
+        }
+        NoteAction::Tags | NoteAction::ActionItems => None,
+    };
+
+    match suggestion.action {
+        NoteAction::Summary => {
+            let summary = values.join("\n\n");
+            let properties: Option<String> = tx
+                .query_row("SELECT properties FROM notes WHERE id = ?", params![suggestion.note_id], |row| row.get(0))
+                .map_err(|e| e.to_string())?;
+            let mut properties = properties
+                .and_then(|p| serde_json::from_str::<serde_json::Value>(&p).ok())
+                .filter(|p| p.is_object())
+                .unwrap_or_else(|| serde_json::json!({}));
+            if summary.is_empty() {
+                properties.as_object_mut().unwrap().remove(SUMMARY_PROPERTY);
+            } else {
+                properties[SUMMARY_PROPERTY] = serde_json::Value::String(summary);
+            }
+            tx.execute(
+                "UPDATE notes SET properties = ?, updated_at = ? WHERE id = ?",
+                params![properties.to_string(), now, suggestion.note_id],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        NoteAction::Title => {
+            let title = values.first().ok_or("The title is empty")?;
+            tx.execute(
+                "UPDATE notes SET title = ?, updated_at = ? WHERE id = ?",
+                params![title, now, suggestion.note_id],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        NoteAction::Tags => {
+            for name in &values {
+                let tag_id: String = tx
+                    .query_row(
+                        "SELECT id FROM tags WHERE name = ? COLLATE NOCASE",
+                        params![name.trim_start_matches('#')],
+                        |row| row.get(0),
+                    )
+                    .optional()
+                    .map_err(|e| e.to_string())?
+                    .ok_or_else(|| format!("Tag '{}' does not exist", name))?;
+                tx.execute(
+                    "INSERT OR IGNORE INTO note_tags (note_id, tag_id) VALUES (?, ?)",
+                    params![suggestion.note_id, tag_id],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+        }
+        NoteAction::ActionItems => {
+            task_service::add_tasks(&tx, Some(&suggestion.note_id), &values).map_err(|e| e.to_string())?;
+        }
+    }
+
+    let output = serde_json::to_string(&values).map_err(|e| e.to_string())?;
+    tx.execute(
+        "UPDATE note_ai_suggestions SET status = 'applied', output = ?, updated_at = ? WHERE id = ?",
+        params![output, now, id],
+    )
+    .map_err(|e| e.to_string())?;
+    if let Some(batch_id) = batch_id {
+        revision_service::finish_batch(&tx, &batch_id).map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    get_suggestion(conn, id).map_err(|e| e.to_string())?.ok_or_else(|| format!("Suggestion {} not found", id))
+}
+
+/// Discard a suggestion, generated or not. Returns whether there was an open one.
+pub fn dismiss(conn: &Connection, id: &str) -> Result<bool> {
+    let changed = conn.execute(
+        "UPDATE note_ai_suggestions SET status = 'dismissed', updated_at = ?
+         WHERE id = ? AND status IN ('queued', 'running', 'ready', 'failed')",
+        params![now(), id],
+    )?;
+    Ok(changed > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::llm_provider::ProviderKind;
+    use crate::services::local_llm::GenerationStats;
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (
+                internal_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                id TEXT UNIQUE NOT NULL,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                properties TEXT,
+                content_encrypted BLOB,
+                nonce BLOB,
+                content_plaintext TEXT
+            );
+            CREATE TABLE tags (id TEXT PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+            CREATE TABLE note_tags (note_id TEXT NOT NULL, tag_id TEXT NOT NULL, PRIMARY KEY (note_id, tag_id));
+            INSERT INTO notes (id, title, content, properties) VALUES
+                ('n', 'meeting', 'Discussed the offsite. Sam will book the venue.', '{\"status\":\"draft\"}'),
+                ('locked', 'secret', '', NULL);
+            UPDATE notes SET content_encrypted = x'00' WHERE id = 'locked';
+            INSERT INTO tags (id, name) VALUES ('t1', 'Planning'), ('t2', 'travel'), ('t3', 'work');
+            INSERT INTO note_tags (note_id, tag_id) VALUES ('n', 't3');",
+        )
+        .unwrap();
+        create_tables(&conn).unwrap();
+        model_registry::create_tables(&conn).unwrap();
+        llm_provider::create_tables(&conn).unwrap();
+        task_service::create_tables(&conn).unwrap();
+        revision_service::create_tables(&conn).unwrap();
+        conn
+    }
+
+    /// Replies with canned text and records the prompt it was given
+    struct CannedProvider {
+        reply: &'static str,
+        prompt: std::sync::Mutex<String>,
+    }
+
+    impl LlmProvider for CannedProvider {
+        fn name(&self) -> String {
+            "canned".to_string()
+        }
+
+        fn generate(
+            &self,
+            messages: &[ChatMessage],
+            _options: &GenerationOptions,
+            _cancel: &AtomicBool,
+            _on_token: &mut dyn FnMut(&str),
+        ) -> Result<(String, GenerationStats), String> {
+            *self.prompt.lock().unwrap() = messages.iter().map(|m| m.content.as_str()).collect::<Vec<_>>().join("\n");
+            let stats = GenerationStats { tokens: 1, elapsed_ms: 1, tokens_per_second: 1.0, cancelled: false };
+            Ok((self.reply.to_string(), stats))
+        }
+    }
+
+    fn canned(reply: &'static str) -> CannedProvider {
+        CannedProvider { reply, prompt: Default::default() }
+    }
+
+    #[test]
+    fn test_parse_output() {
+        let tags = vec!["Planning".to_string(), "travel".to_string()];
+        assert_eq!(parse_output(NoteAction::Summary, "TL;DR: Offsite is booked.", &tags), vec!["Offsite is booked."]);
+        assert_eq!(parse_output(NoteAction::Title, "Title: \"Offsite planning.\"\nextra", &tags), vec!["Offsite planning"]);
+        assert_eq!(parse_output(NoteAction::Tags, "#planning, Travel, finance, planning", &tags), vec!["Planning", "travel"]);
+        assert_eq!(
+            parse_output(NoteAction::ActionItems, "Here you go:\n- [ ] Book venue\n2. Email Sam\n* Book venue", &tags),
+            vec!["Book venue", "Email Sam"]
+        );
+        assert!(parse_output(NoteAction::ActionItems, "None.", &tags).is_empty());
+    }
+
+    #[test]
+    fn test_queue_generate_and_apply() {
+        let conn = setup();
+        let first = request(&conn, "n", &[NoteAction::Summary]).unwrap();
+        let queued = request(&conn, "n", &[NoteAction::Summary, NoteAction::Tags, NoteAction::ActionItems, NoteAction::Title])
+            .unwrap();
+        assert!(request(&conn, "missing", &[NoteAction::Title]).is_err());
+        // Requesting again replaced the first summary
+        assert_eq!(get_suggestion(&conn, &first[0].id).unwrap().unwrap().status, "dismissed");
+        assert_eq!(list_for_note(&conn, "n").unwrap().len(), 4);
+
+        let replies = ["TL;DR: Offsite planning; Sam books the venue.", "planning, work, travel", "- Book the venue", "Offsite"];
+        for (suggestion, reply) in queued.iter().zip(replies) {
+            let pending = claim_next(&conn, &PassphraseState::new()).unwrap().unwrap();
+            assert_eq!(pending.id, suggestion.id);
+            assert_eq!(pending.settings.provider, ProviderKind::Embedded);
+            let provider = canned(reply);
+            let output = generate(&provider, &pending, &AtomicBool::new(false)).unwrap();
+            if pending.action == NoteAction::Tags {
+                // The note's own tag is not offered again
+                assert!(provider.prompt.lock().unwrap().contains("list: Planning, travel."));
+            }
+            finish(&conn, &pending.id, Ok(output), Some("canned")).unwrap();
+        }
+        assert!(claim_next(&conn, &PassphraseState::new()).unwrap().is_none());
+
+        let ready = list_for_note(&conn, "n").unwrap();
+        assert!(ready.iter().all(|s| s.status == "ready"));
+        assert_eq!(ready[1].output, vec!["Planning", "travel"]);
+
+        apply(&conn, &ready[0].id, None).unwrap();
+        // Only the tags the user kept are applied
+        apply(&conn, &ready[1].id, Some(vec!["travel".to_string()])).unwrap();
+        apply(&conn, &ready[2].id, None).unwrap();
+        dismiss(&conn, &ready[3].id).unwrap();
+        assert!(apply(&conn, &ready[3].id, None).is_err());
+
+        let (title, properties): (String, String) = conn
+            .query_row("SELECT title, properties FROM notes WHERE id = 'n'", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!(title, "meeting");
+        let properties: serde_json::Value = serde_json::from_str(&properties).unwrap();
+        assert_eq!(properties["status"], "draft");
+        assert_eq!(properties[SUMMARY_PROPERTY], "Offsite planning; Sam books the venue.");
+        let tags: i64 = conn.query_row("SELECT COUNT(*) FROM note_tags WHERE note_id = 'n'", [], |row| row.get(0)).unwrap();
+        assert_eq!(tags, 2);
+        let tasks = task_service::list_tasks(&conn, Some("n")).unwrap();
+        assert_eq!(tasks[0].text, "Book the venue");
+        assert!(list_for_note(&conn, "n").unwrap().is_empty());
+
+        // The summary was recorded as an undoable revision
+        let batches = revision_service::list_batches(&conn, 10).unwrap();
+        assert_eq!(batches.len(), 1);
+        revision_service::undo_batch(&conn, &batches[0].id).unwrap();
+        let properties: String =
+            conn.query_row("SELECT properties FROM notes WHERE id = 'n'", [], |row| row.get(0)).unwrap();
+        assert_eq!(properties, "{\"status\":\"draft\"}");
+    }
+
+    #[test]
+    fn test_locked_note_fails() {
+        let conn = setup();
+        let queued = request(&conn, "locked", &[NoteAction::Title]).unwrap();
+        assert!(claim_next(&conn, &PassphraseState::new()).unwrap().is_none());
+        let failed = get_suggestion(&conn, &queued[0].id).unwrap().unwrap();
+        assert_eq!(failed.status, "failed");
+        assert_eq!(failed.error.as_deref(), Some("The note is encrypted and locked"));
+    }
+}
@@ -1,7 +1,9 @@
-use rusqlite::{params, Connection, Result};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Result};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::services::search_query;
+
 #[derive(Serialize, Deserialize)]
 pub struct Folder {
     pub id: String,
@@ -15,6 +17,22 @@ pub struct Tag {
     pub name: String,
 }
 
+/// A selection of notes that an operation (publish, find & replace, ...) applies to
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NoteScope {
+    /// Every note in the vault
+    All,
+    /// A folder and all of its subfolders
+    Folder { id: String },
+    /// Notes carrying the tag with this name; a leading `#` is ignored
+    Tag { name: String },
+    /// An explicit list of notes
+    Notes { ids: Vec<String> },
+    /// Notes matching a search query
+    Query { query: String },
+}
+
 pub fn create_folder(conn: &Connection, name: &str, parent_id: Option<String>) -> Result<Folder> {
     let id = Uuid::new_v4().to_string();
     conn.execute(
@@ -116,3 +134,102 @@ pub fn unlink_tag_from_note(conn: &Connection, note_id: &str, tag_id: &str) -> R
     Ok(())
 }
 
+
+/// Rename a tag; fails if another tag already has the new name (merge instead)
+///
+/// Returns `None` if no tag has this id.
+pub fn rename_tag(conn: &Connection, tag_id: &str, new_name: &str) -> Result<Option<Tag>> {
+    let renamed = conn.execute(
+        "UPDATE tags SET name = ? WHERE id = ?",
+        params![new_name, tag_id],
+    )?;
+    Ok((renamed > 0).then(|| Tag { id: tag_id.to_string(), name: new_name.to_string() }))
+}
+
+/// Move every note from the source tags onto `target_id` and delete the sources
+pub fn merge_tags(conn: &Connection, source_ids: &[String], target_id: &str) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    let mut moved = 0;
+    for source_id in source_ids.iter().filter(|id| id.as_str() != target_id) {
+        moved += tx.execute(
+            "INSERT OR IGNORE INTO note_tags (note_id, tag_id)
+             SELECT note_id, ? FROM note_tags WHERE tag_id = ?",
+            params![target_id, source_id],
+        )?;
+        tx.execute("DELETE FROM note_tags WHERE tag_id = ?", params![source_id])?;
+        tx.execute("DELETE FROM tags WHERE id = ?", params![source_id])?;
+    }
+    tx.commit()?;
+    Ok(moved)
+}
+
+/// Resolve a scope to the ids of the notes it contains
+pub fn note_ids_in_scope(conn: &Connection, scope: &NoteScope) -> Result<Vec<String>> {
+    let mut ids = Vec::new();
+    match scope {
+        NoteScope::All => {
+            let mut stmt = conn.prepare("SELECT id FROM notes ORDER BY title")?;
+            for id in stmt.query_map([], |row| row.get(0))? {
+                ids.push(id?);
+            }
+        }
+        NoteScope::Folder { id } => {
+            let mut stmt = conn.prepare(
+                "WITH RECURSIVE subtree(id) AS (
+                    SELECT ?1
+                    UNION
+                    SELECT f.id FROM folders f JOIN subtree s ON f.parent_id = s.id
+                 )
+                 SELECT n.id FROM notes n WHERE n.folder_id IN (SELECT id FROM subtree) ORDER BY n.title"
+            )?;
+            for id in stmt.query_map(params![id], |row| row.get(0))? {
+                ids.push(id?);
+            }
+        }
+        NoteScope::Tag { name } => {
+            let mut stmt = conn.prepare(
+                "SELECT n.id FROM notes n
+                 JOIN note_tags nt ON nt.note_id = n.id
+                 JOIN tags t ON t.id = nt.tag_id
+                 WHERE t.name = ?1 COLLATE NOCASE
+                 ORDER BY n.title"
+            )?;
+            for id in stmt.query_map(params![name.trim_start_matches('#')], |row| row.get(0))? {
+                ids.push(id?);
+            }
+        }
+        NoteScope::Notes { ids: wanted } => {
+            let mut stmt = conn.prepare("SELECT id FROM notes WHERE id = ?")?;
+            for id in wanted {
+                if let Some(found) = stmt.query_row(params![id], |row| row.get::<_, String>(0)).optional()? {
+                    ids.push(found);
+                }
+            }
+        }
+        NoteScope::Query { query } => {
+            let compiled = search_query::parse_query(query)
+                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?
+                .map(|node| search_query::compile(&node))
+                .unwrap_or_default();
+            if compiled.is_empty() {
+                return Ok(ids);
+            }
+            let mut clauses = Vec::new();
+            let mut query_params = Vec::new();
+            if let Some(fts_match) = compiled.fts_match {
+                clauses.push("n.internal_id IN (SELECT rowid FROM notes_fts WHERE notes_fts MATCH ?)".to_string());
+                query_params.push(fts_match);
+            }
+            clauses.extend(compiled.predicates);
+            query_params.extend(compiled.params);
+            let mut stmt = conn.prepare(&format!(
+                "SELECT n.id FROM notes n WHERE {} ORDER BY n.title",
+                clauses.join(" AND ")
+            ))?;
+            for id in stmt.query_map(params_from_iter(query_params.iter()), |row| row.get(0))? {
+                ids.push(id?);
+            }
+        }
+    }
+    Ok(ids)
+}
@@ -0,0 +1,570 @@
+//! Static HTML site publisher
+//!
+//! Renders a folder or tag of the vault into a self-contained static site:
+//! one page per note, tag index pages, a backlink index and a
+//! `search-index.json` consumed by the bundled client-side search.
+//! Encrypted notes and notes tagged `private` are never published, raw
+//! HTML in notes is escaped rather than passed through, and links or images
+//! with script-running URLs are neutralized.
+
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::services::export_service::sanitize_filename;
+use crate::services::link_service::{self, WikiLink};
+use crate::services::organization_service::{self, NoteScope};
+
+/// Tag that keeps a note out of every published site
+const PRIVATE_TAG: &str = "private";
+
+/// Summary of a publish run
+#[derive(Debug, Serialize)]
+pub struct PublishReport {
+    pub out_dir: String,
+    pub pages: usize,
+    pub tags: usize,
+    pub excluded: usize,
+}
+
+struct PublishedNote {
+    id: String,
+    title: String,
+    content: String,
+    updated_at: String,
+    tags: Vec<String>,
+    slug: String,
+}
+
+#[derive(Serialize)]
+struct SearchIndexEntry<'a> {
+    id: &'a str,
+    title: &'a str,
+    url: String,
+    tags: &'a [String],
+    text: String,
+}
+
+/// Publish the notes in `scope` as a static HTML site in `out_dir`
+pub fn publish_site(conn: &Connection, scope: &NoteScope, out_dir: &Path) -> Result<PublishReport, String> {
+    let ids = organization_service::note_ids_in_scope(conn, scope).map_err(|e| e.to_string())?;
+
+    let mut notes = Vec::new();
+    let mut excluded = 0;
+    let mut used_slugs = HashSet::new();
+
+    for id in &ids {
+        let (title, content, updated_at, encrypted): (String, String, String, bool) = conn
+            .query_row(
+                "SELECT title, content, updated_at, content_encrypted IS NOT NULL FROM notes WHERE id = ?",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .map_err(|e| e.to_string())?;
+
+        let tags: Vec<String> = organization_service::get_note_tags(conn, id)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|t| t.name)
+            .collect();
+
+        if encrypted || tags.iter().any(|t| t.eq_ignore_ascii_case(PRIVATE_TAG)) {
+            excluded += 1;
+            continue;
+        }
+
+        let slug = unique_slug(&title, id, &mut used_slugs);
+        notes.push(PublishedNote { id: id.clone(), title, content, updated_at, tags, slug });
+    }
+
+    // Wiki-links resolve by title, case-insensitively
+    let by_title: HashMap<String, usize> = notes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (link_service::normalize_title(&n.title), i))
+        .collect();
+
+    // Backlinks only count links between published notes
+    let mut backlinks: Vec<Vec<usize>> = vec![Vec::new(); notes.len()];
+    for (i, note) in notes.iter().enumerate() {
+        let mut seen = HashSet::new();
+        for link in link_service::extract_wiki_links(&note.content) {
+            if let Some(&target) = by_title.get(&link_service::normalize_title(&link.target)) {
+                if target != i && seen.insert(target) {
+                    backlinks[target].push(i);
+                }
+            }
+        }
+    }
+
+    let mut tag_index: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, note) in notes.iter().enumerate() {
+        for tag in &note.tags {
+            tag_index.entry(tag.clone()).or_default().push(i);
+        }
+    }
+
+    // `tags/index.html` is the tag list itself; tags that slug alike get a numbered suffix
+    let mut used_tag_slugs = HashSet::from(["index".to_string()]);
+    let tag_slugs: HashMap<&str, String> = tag_index
+        .keys()
+        .map(|tag| (tag.as_str(), numbered_slug(&tag_slug(tag), &mut used_tag_slugs)))
+        .collect();
+
+    let notes_dir = out_dir.join("notes");
+    let tags_dir = out_dir.join("tags");
+    fs::create_dir_all(&notes_dir).map_err(|e| format!("Failed to create {:?}: {}", notes_dir, e))?;
+    fs::create_dir_all(&tags_dir).map_err(|e| format!("Failed to create {:?}: {}", tags_dir, e))?;
+
+    // Note pages
+    for (i, note) in notes.iter().enumerate() {
+        let markdown = link_service::replace_wiki_links(&note.content, |link| {
+            render_wiki_link(link, &by_title, &notes)
+        });
+
+        let mut body = format!("<h1>{}</h1>\n", escape_html(&note.title));
+        body.push_str(&format!(
+            "<p class=\"meta\">Updated {}</p>\n",
+            escape_html(&note.updated_at)
+        ));
+        if !note.tags.is_empty() {
+            body.push_str("<p class=\"tags\">");
+            for tag in &note.tags {
+                body.push_str(&format!(
+                    "<a class=\"tag\" href=\"../tags/{}.html\">#{}</a> ",
+                    tag_slugs[tag.as_str()],
+                    escape_html(tag)
+                ));
+            }
+            body.push_str("</p>\n");
+        }
+        body.push_str("<article>\n");
+        body.push_str(&markdown_to_html(&markdown));
+        body.push_str("</article>\n");
+
+        if !backlinks[i].is_empty() {
+            body.push_str("<section class=\"backlinks\">\n<h2>Linked from</h2>\n<ul>\n");
+            for &source in &backlinks[i] {
+                body.push_str(&format!(
+                    "<li><a href=\"{}.html\">{}</a></li>\n",
+                    notes[source].slug,
+                    escape_html(&notes[source].title)
+                ));
+            }
+            body.push_str("</ul>\n</section>\n");
+        }
+
+        write_page(&notes_dir.join(format!("{}.html", note.slug)), &note.title, "../", &body)?;
+    }
+
+    // Tag pages
+    let mut tags_body = String::from("<h1>Tags</h1>\n<ul>\n");
+    for (tag, members) in &tag_index {
+        tags_body.push_str(&format!(
+            "<li><a href=\"{}.html\">#{}</a> ({})</li>\n",
+            tag_slugs[tag.as_str()],
+            escape_html(tag),
+            members.len()
+        ));
+
+        let mut body = format!("<h1>#{}</h1>\n<ul>\n", escape_html(tag));
+        for &i in members {
+            body.push_str(&format!(
+                "<li><a href=\"../notes/{}.html\">{}</a></li>\n",
+                notes[i].slug,
+                escape_html(&notes[i].title)
+            ));
+        }
+        body.push_str("</ul>\n");
+        write_page(&tags_dir.join(format!("{}.html", tag_slugs[tag.as_str()])), &format!("#{}", tag), "../", &body)?;
+    }
+    tags_body.push_str("</ul>\n");
+    write_page(&tags_dir.join("index.html"), "Tags", "../", &tags_body)?;
+
+    // Backlink index
+    let mut backlinks_body = String::from("<h1>Backlinks</h1>\n<dl>\n");
+    for (i, note) in notes.iter().enumerate() {
+        if backlinks[i].is_empty() {
+            continue;
+        }
+        backlinks_body.push_str(&format!(
+            "<dt><a href=\"notes/{}.html\">{}</a></dt>\n",
+            note.slug,
+            escape_html(&note.title)
+        ));
+        for &source in &backlinks[i] {
+            backlinks_body.push_str(&format!(
+                "<dd><a href=\"notes/{}.html\">{}</a></dd>\n",
+                notes[source].slug,
+                escape_html(&notes[source].title)
+            ));
+        }
+    }
+    backlinks_body.push_str("</dl>\n");
+    write_page(&out_dir.join("backlinks.html"), "Backlinks", "", &backlinks_body)?;
+
+    // Home page with client-side search
+    let mut index_body = String::from(
+        "<h1>Notes</h1>\n<input id=\"search\" type=\"search\" placeholder=\"Search...\">\n<ul id=\"results\"></ul>\n<ul id=\"all-notes\">\n",
+    );
+    for note in &notes {
+        index_body.push_str(&format!(
+            "<li><a href=\"notes/{}.html\">{}</a></li>\n",
+            note.slug,
+            escape_html(&note.title)
+        ));
+    }
+    index_body.push_str("</ul>\n<script>\n");
+    index_body.push_str(SEARCH_SCRIPT);
+    index_body.push_str("</script>\n");
+    write_page(&out_dir.join("index.html"), "Notes", "", &index_body)?;
+
+    // Search index
+    let entries: Vec<SearchIndexEntry> = notes
+        .iter()
+        .map(|note| SearchIndexEntry {
+            id: &note.id,
+            title: &note.title,
+            url: format!("notes/{}.html", note.slug),
+            tags: &note.tags,
+            text: link_service::replace_wiki_links(&note.content, |link| link.label().to_string()),
+        })
+        .collect();
+    let json = serde_json::to_string(&entries).map_err(|e| e.to_string())?;
+    fs::write(out_dir.join("search-index.json"), json).map_err(|e| format!("Failed to write search index: {}", e))?;
+
+    fs::write(out_dir.join("style.css"), STYLESHEET).map_err(|e| format!("Failed to write stylesheet: {}", e))?;
+
+    log::info!("Published {} notes to {:?} ({} excluded)", notes.len(), out_dir, excluded);
+
+    Ok(PublishReport {
+        out_dir: out_dir.to_string_lossy().to_string(),
+        pages: notes.len(),
+        tags: tag_index.len(),
+        excluded,
+    })
+}
+
+/// Turn a wiki-link into a relative Markdown link, or plain text if its target is not published
+fn render_wiki_link(link: &WikiLink, by_title: &HashMap<String, usize>, notes: &[PublishedNote]) -> String {
+    match by_title.get(&link_service::normalize_title(&link.target)) {
+        Some(&i) => {
+            let anchor = link
+                .heading
+                .as_ref()
+                .map(|h| format!("#{}", heading_anchor(h)))
+                .unwrap_or_default();
+            format!("[{}]({}.html{})", link.label(), notes[i].slug, anchor)
+        }
+        None => link.label().to_string(),
+    }
+}
+
+fn unique_slug(title: &str, id: &str, used: &mut HashSet<String>) -> String {
+    let mut slug = sanitize_filename(title).to_lowercase();
+    if slug.is_empty() {
+        slug = "untitled".to_string();
+    }
+    if !used.insert(slug.clone()) {
+        slug = format!("{}-{}", slug, &id[..id.len().min(8)]);
+        used.insert(slug.clone());
+    }
+    slug
+}
+
+fn tag_slug(tag: &str) -> String {
+    let slug = sanitize_filename(tag).to_lowercase();
+    if slug.is_empty() {
+        "tag".to_string()
+    } else {
+        slug
+    }
+}
+
+/// `base`, or `base-2`, `base-3`, ... if already taken
+fn numbered_slug(base: &str, used: &mut HashSet<String>) -> String {
+    let mut slug = base.to_string();
+    let mut n = 1;
+    while !used.insert(slug.clone()) {
+        n += 1;
+        slug = format!("{}-{}", base, n);
+    }
+    slug
+}
+
+fn heading_anchor(heading: &str) -> String {
+    heading
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_alphanumeric() => Some(c),
+            ' ' | '-' | '_' => Some('-'),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Render Markdown, giving each heading the id `heading_anchor` links point to
+fn markdown_to_html(markdown: &str) -> String {
+    let mut events = Vec::new();
+    let mut open_heading: Option<usize> = None;
+    let mut heading_text = String::new();
+    let mut used_ids = HashSet::new();
+
+    for event in Parser::new_ext(markdown, Options::all()) {
+        let event = match event {
+            Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+            Event::Start(Tag::Link { link_type, dest_url, title, id }) => {
+                Event::Start(Tag::Link { link_type, dest_url: safe_url(dest_url), title, id })
+            }
+            Event::Start(Tag::Image { link_type, dest_url, title, id }) => {
+                Event::Start(Tag::Image { link_type, dest_url: safe_url(dest_url), title, id })
+            }
+            event => event,
+        };
+        match &event {
+            Event::Start(Tag::Heading { .. }) => {
+                open_heading = Some(events.len());
+                heading_text.clear();
+            }
+            Event::Text(text) | Event::Code(text) if open_heading.is_some() => heading_text.push_str(text),
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some(Event::Start(Tag::Heading { id, .. })) = open_heading.take().map(|i| &mut events[i]) {
+                    match id {
+                        Some(id) => {
+                            used_ids.insert(id.to_string());
+                        }
+                        None => *id = Some(numbered_slug(&heading_anchor(&heading_text), &mut used_ids).into()),
+                    }
+                }
+            }
+            _ => {}
+        }
+        events.push(event);
+    }
+
+    let mut out = String::new();
+    html::push_html(&mut out, events.into_iter());
+    out
+}
+
+/// `url`, or `#` when its scheme could run script: `javascript:`, `vbscript:` and
+/// `data:` other than `data:image/`. Browsers ignore case, tabs and newlines in the
+/// scheme, so the check does too.
+fn safe_url(url: CowStr<'_>) -> CowStr<'_> {
+    let normalized: String = url
+        .trim_start_matches(|c: char| c.is_ascii_whitespace() || c.is_ascii_control())
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .collect::<String>()
+        .to_ascii_lowercase();
+    let Some((scheme, _)) = normalized.split_once(':') else { return url };
+    if scheme.contains(['/', '?', '#']) {
+        return url;
+    }
+    let unsafe_scheme = match scheme {
+        "javascript" | "vbscript" => true,
+        "data" => !normalized.starts_with("data:image/"),
+        _ => false,
+    };
+    if unsafe_scheme {
+        "#".into()
+    } else {
+        url
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn write_page(path: &Path, title: &str, root: &str, body: &str) -> Result<(), String> {
+    let page = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<link rel=\"stylesheet\" href=\"{root}style.css\">\n</head>\n<body>\n<nav><a href=\"{root}index.html\">Home</a> <a href=\"{root}tags/index.html\">Tags</a> <a href=\"{root}backlinks.html\">Backlinks</a></nav>\n<main>\n{body}</main>\n</body>\n</html>\n",
+        title = escape_html(title),
+        root = root,
+        body = body,
+    );
+    fs::write(path, page).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+const SEARCH_SCRIPT: &str = r#"fetch('search-index.json').then(r => r.json()).then(index => {
+  const input = document.getElementById('search');
+  const results = document.getElementById('results');
+  const all = document.getElementById('all-notes');
+  input.addEventListener('input', () => {
+    const q = input.value.trim().toLowerCase();
+    results.innerHTML = '';
+    all.style.display = q ? 'none' : '';
+    if (!q) return;
+    for (const entry of index) {
+      const haystack = (entry.title + ' ' + entry.tags.join(' ') + ' ' + entry.text).toLowerCase();
+      if (haystack.includes(q)) {
+        const li = document.createElement('li');
+        const a = document.createElement('a');
+        a.href = entry.url;
+        a.textContent = entry.title;
+        li.appendChild(a);
+        results.appendChild(li);
+      }
+    }
+  });
+});
+"#;
+
+const STYLESHEET: &str = "body { font-family: system-ui, sans-serif; max-width: 48rem; margin: 2rem auto; padding: 0 1rem; line-height: 1.6; }
+nav a { margin-right: 1rem; }
+.meta { color: #666; font-size: 0.9rem; }
+.tag { margin-right: 0.5rem; }
+.backlinks { border-top: 1px solid #ddd; margin-top: 2rem; }
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn setup_test_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (
+                internal_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                id TEXT UNIQUE NOT NULL,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                folder_id TEXT,
+                content_encrypted BLOB
+            );
+            CREATE TABLE folders (id TEXT PRIMARY KEY, name TEXT NOT NULL, parent_id TEXT);
+            CREATE TABLE tags (id TEXT PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+            CREATE TABLE note_tags (note_id TEXT NOT NULL, tag_id TEXT NOT NULL, PRIMARY KEY (note_id, tag_id));
+
+            INSERT INTO folders (id, name) VALUES ('docs', 'Docs');
+            INSERT INTO notes (id, title, content, folder_id) VALUES ('a', 'Intro', 'Read [[Setup]] and [[Secret]].', 'docs');
+            INSERT INTO notes (id, title, content, folder_id) VALUES ('b', 'Setup', 'Install it.', 'docs');
+            INSERT INTO notes (id, title, content, folder_id) VALUES ('c', 'Secret', 'Hidden.', 'docs');
+            INSERT INTO notes (id, title, content, folder_id, content_encrypted) VALUES ('d', 'Vault', '', 'docs', 'xyz');
+            INSERT INTO tags (id, name) VALUES ('t1', 'private');
+            INSERT INTO note_tags (note_id, tag_id) VALUES ('c', 't1');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_publish_site_excludes_private_and_encrypted() {
+        let conn = setup_test_db();
+        let dir = tempdir().unwrap();
+
+        let report = publish_site(&conn, &NoteScope::Folder { id: "docs".to_string() }, dir.path()).unwrap();
+
+        assert_eq!(report.pages, 2);
+        assert_eq!(report.excluded, 2);
+        assert!(dir.path().join("notes/intro.html").exists());
+        assert!(!dir.path().join("notes/secret.html").exists());
+        assert!(dir.path().join("search-index.json").exists());
+
+        let intro = fs::read_to_string(dir.path().join("notes/intro.html")).unwrap();
+        assert!(intro.contains("<a href=\"setup.html\">Setup</a>"));
+        assert!(!intro.contains("secret.html"));
+
+        let setup = fs::read_to_string(dir.path().join("notes/setup.html")).unwrap();
+        assert!(setup.contains("Linked from"));
+    }
+
+    #[test]
+    fn test_tag_pages_do_not_overwrite_each_other() {
+        let conn = setup_test_db();
+        conn.execute_batch(
+            "INSERT INTO tags (id, name) VALUES ('t2', 'index'), ('t3', 'Rust'), ('t4', 'rust'), ('t5', 'c++');
+             INSERT INTO note_tags (note_id, tag_id) VALUES ('a', 't2'), ('a', 't3'), ('b', 't4'), ('b', 't5');",
+        )
+        .unwrap();
+        let dir = tempdir().unwrap();
+
+        let report = publish_site(&conn, &NoteScope::Folder { id: "docs".to_string() }, dir.path()).unwrap();
+        assert_eq!(report.tags, 4);
+
+        let tags_dir = dir.path().join("tags");
+        assert_eq!(fs::read_dir(&tags_dir).unwrap().count(), 5);
+        let tag_list = fs::read_to_string(tags_dir.join("index.html")).unwrap();
+        assert!(tag_list.contains("<h1>Tags</h1>"));
+        assert!(tag_list.contains("<a href=\"index-2.html\">#index</a>"));
+        assert!(fs::read_to_string(tags_dir.join("rust.html")).unwrap().contains("<h1>#Rust</h1>"));
+        assert!(fs::read_to_string(tags_dir.join("rust-2.html")).unwrap().contains("<h1>#rust</h1>"));
+
+        let setup = fs::read_to_string(dir.path().join("notes/setup.html")).unwrap();
+        assert!(setup.contains("href=\"../tags/rust-2.html\""));
+        assert!(setup.contains("href=\"../tags/c__.html\""));
+    }
+
+    #[test]
+    fn test_backlinks_page_and_heading_anchors() {
+        let conn = setup_test_db();
+        conn.execute_batch(
+            "UPDATE notes SET content = '# Install Steps\n\nRun it.\n\n## Install Steps\n\nAgain.' WHERE id = 'b';
+             UPDATE notes SET content = 'See [[Setup#Install Steps|installing]].' WHERE id = 'a';",
+        )
+        .unwrap();
+        let dir = tempdir().unwrap();
+
+        publish_site(&conn, &NoteScope::Folder { id: "docs".to_string() }, dir.path()).unwrap();
+
+        let intro = fs::read_to_string(dir.path().join("notes/intro.html")).unwrap();
+        assert!(intro.contains("<a href=\"setup.html#install-steps\">installing</a>"));
+        let setup = fs::read_to_string(dir.path().join("notes/setup.html")).unwrap();
+        assert!(setup.contains("<h1 id=\"install-steps\">Install Steps</h1>"));
+        assert!(setup.contains("<h2 id=\"install-steps-2\">Install Steps</h2>"));
+
+        let backlinks = fs::read_to_string(dir.path().join("backlinks.html")).unwrap();
+        assert!(backlinks.contains("<dt><a href=\"notes/setup.html\">Setup</a></dt>\n<dd><a href=\"notes/intro.html\">Intro</a></dd>"));
+    }
+
+    #[test]
+    fn test_raw_html_is_escaped() {
+        let conn = setup_test_db();
+        conn.execute(
+            "UPDATE notes SET content = 'Hi <img src=x onerror=alert(1)>\n\n<script>alert(2)</script>' WHERE id = 'b'",
+            [],
+        )
+        .unwrap();
+        let dir = tempdir().unwrap();
+
+        publish_site(&conn, &NoteScope::Folder { id: "docs".to_string() }, dir.path()).unwrap();
+
+        let setup = fs::read_to_string(dir.path().join("notes/setup.html")).unwrap();
+        assert!(!setup.contains("<img") && !setup.contains("<script>alert"));
+        assert!(setup.contains("&lt;img src=x onerror=alert(1)&gt;"));
+        assert!(setup.contains("&lt;script&gt;alert(2)&lt;/script&gt;"));
+    }
+
+    #[test]
+    fn test_script_urls_are_neutralized() {
+        let conn = setup_test_db();
+        conn.execute(
+            "UPDATE notes SET content = '[x](javascript:alert(1)) [y](<JavaScript\t:alert(2)>) [z](vbscript:msgbox)
+             ![a](data:text/html;base64,PHNjcmlwdD4=) ![b](data:image/png;base64,iVBO) [c](https://example.com/a:b)'
+             WHERE id = 'b'",
+            [],
+        )
+        .unwrap();
+        let dir = tempdir().unwrap();
+
+        publish_site(&conn, &NoteScope::Folder { id: "docs".to_string() }, dir.path()).unwrap();
+
+        let setup = fs::read_to_string(dir.path().join("notes/setup.html")).unwrap().to_lowercase();
+        assert!(!setup.contains("javascript") && !setup.contains("vbscript") && !setup.contains("data:text"));
+        assert!(setup.contains("<a href=\"#\">x</a>"));
+        assert!(setup.contains("src=\"data:image/png;base64,ivbo\""));
+        assert!(setup.contains("href=\"https://example.com/a:b\""));
+    }
+}
@@ -0,0 +1,575 @@
+//! Retrieval for answering questions from notes
+//!
+//! Notes are split into passages along headings and paragraphs. Candidate
+//! notes come from hybrid search; their passages are ranked by keyword
+//! weight and by the stored embeddings of the chunks they overlap, fused
+//! with reciprocal rank fusion. The best passages are packed into the
+//! model's context as numbered sources, and the answer's `[n]` markers are
+//! mapped back to notes and character spans.
+
+use regex::Regex;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use crate::services::embedding_service;
+use crate::services::llm_provider::ChatMessage;
+use crate::services::organization_service::{self, NoteScope};
+use crate::services::search_service::{self, SearchOptions, SemanticQuery};
+
+/// Notes whose passages are considered
+const CANDIDATE_NOTES: usize = 20;
+/// Passages longer than this (in bytes) are split at sentence ends
+const MAX_PASSAGE_BYTES: usize = 1200;
+/// Embedding similarity that makes a passage relevant without a keyword match
+pub const MIN_SIMILARITY: f32 = 0.35;
+const RRF_K: f64 = 60.0;
+/// Context assumed when the model doesn't report one
+const DEFAULT_CONTEXT_TOKENS: usize = 4096;
+/// Longer contexts are slow on CPU, so sources never use more than this
+const MAX_CONTEXT_TOKENS: usize = 8192;
+/// Room for the system prompt, question and formatting around the sources
+const PROMPT_OVERHEAD_TOKENS: usize = 256;
+
+const STOP_WORDS: &[&str] = &[
+    "a", "about", "an", "and", "are", "as", "at", "be", "by", "can", "did", "do", "does", "for", "from", "had",
+    "has", "have", "how", "i", "in", "is", "it", "its", "me", "my", "near", "not", "of", "on", "or", "our", "say",
+    "so", "tell", "that", "the", "their", "there", "these", "this", "to", "was", "we", "were", "what", "when",
+    "where", "which", "who", "why", "will", "with", "you", "your",
+];
+
+const SYSTEM_PROMPT: &str = "You are a helpful second brain assistant. Answer the question using ONLY the numbered \
+sources from the user's notes. Cite the sources you use with their numbers in square brackets, like [1] or [2][3]. \
+If the sources do not contain the answer, say that the notes do not cover it.";
+
+/// Said instead of an answer when retrieval finds nothing
+pub const NOTHING_FOUND: &str = "I couldn't find anything in your notes about that.";
+
+/// Ids of the notes in `scope`; `None` for the whole vault
+fn scope_note_ids(conn: &Connection, scope: &NoteScope) -> rusqlite::Result<Option<HashSet<String>>> {
+    match scope {
+        NoteScope::All => Ok(None),
+        scope => Ok(Some(organization_service::note_ids_in_scope(conn, scope)?.into_iter().collect())),
+    }
+}
+
+/// A heading-and-paragraph section of a note; `start`/`end` are byte offsets
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassageSpan {
+    pub start: usize,
+    pub end: usize,
+    /// Enclosing headings, outermost first, joined with " › "
+    pub heading: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RankedPassage {
+    pub note_id: String,
+    pub title: String,
+    pub heading: Option<String>,
+    /// Character (not byte) offsets of the passage in the note's content
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    pub score: f64,
+}
+
+/// A passage given to the model as source `[marker]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Source {
+    pub marker: usize,
+    pub note_id: String,
+    pub title: String,
+    pub heading: Option<String>,
+    /// Character (not byte) offsets of the passage in the note's content
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    /// The answer refers to this source
+    pub cited: bool,
+}
+
+/// Split note content into passages: a new passage starts at every heading, paragraphs
+/// of a section are merged up to `MAX_PASSAGE_BYTES`, and longer paragraphs are split
+/// at sentence ends. Heading lines themselves are not part of any passage.
+pub fn split_passages(content: &str) -> Vec<PassageSpan> {
+    let mut passages = Vec::new();
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    // Paragraphs of the current section, as byte ranges
+    let mut paragraphs: Vec<(usize, usize)> = Vec::new();
+    let mut paragraph: Option<(usize, usize)> = None;
+    let mut in_fence = false;
+
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let trimmed = line.trim();
+        let end = start + line.trim_end().len();
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        let heading = if in_fence { None } else { heading_level(trimmed) };
+
+        if let Some((level, text)) = heading {
+            paragraphs.extend(paragraph.take());
+            flush_section(content, &mut paragraphs, &headings, &mut passages);
+            headings.retain(|(l, _)| *l < level);
+            headings.push((level, text.to_string()));
+        } else if trimmed.is_empty() && !in_fence {
+            paragraphs.extend(paragraph.take());
+        } else {
+            let begin = start + (line.len() - line.trim_start().len());
+            paragraph = Some(match paragraph {
+                Some((p_start, _)) => (p_start, end.max(begin)),
+                None => (begin, end.max(begin)),
+            });
+        }
+    }
+    paragraphs.extend(paragraph.take());
+    flush_section(content, &mut paragraphs, &headings, &mut passages);
+    passages
+}
+
+fn heading_level(line: &str) -> Option<(usize, &str)> {
+    let level = line.bytes().take_while(|b| *b == b'#').count();
+    if (1..=6).contains(&level) && line[level..].starts_with(' ') {
+        Some((level, line[level..].trim()))
+    } else {
+        None
+    }
+}
+
+fn flush_section(
+    content: &str,
+    paragraphs: &mut Vec<(usize, usize)>,
+    headings: &[(usize, String)],
+    passages: &mut Vec<PassageSpan>,
+) {
+    let heading = if headings.is_empty() {
+        None
+    } else {
+        Some(headings.iter().map(|(_, h)| h.as_str()).collect::<Vec<_>>().join(" › "))
+    };
+
+    let mut current: Option<(usize, usize)> = None;
+    for (start, end) in paragraphs.drain(..) {
+        for (start, end) in split_long(content, start, end) {
+            current = match current {
+                Some((c_start, _)) if end - c_start <= MAX_PASSAGE_BYTES => Some((c_start, end)),
+                Some(done) => {
+                    passages.push(PassageSpan { start: done.0, end: done.1, heading: heading.clone() });
+                    Some((start, end))
+                }
+                None => Some((start, end)),
+            };
+        }
+    }
+    if let Some((start, end)) = current {
+        passages.push(PassageSpan { start, end, heading });
+    }
+}
+
+/// Pieces of `content[start..end]` no longer than `MAX_PASSAGE_BYTES`, cut after a
+/// sentence end where possible, else at whitespace, else at a character boundary
+fn split_long(content: &str, mut start: usize, end: usize) -> Vec<(usize, usize)> {
+    let mut pieces = Vec::new();
+    while end - start > MAX_PASSAGE_BYTES {
+        let mut limit = start + MAX_PASSAGE_BYTES;
+        while !content.is_char_boundary(limit) {
+            limit -= 1;
+        }
+        let window = &content[start..limit];
+        let cut = window
+            .rmatch_indices(['.', '?', '!', '\n'])
+            .map(|(i, _)| i + 1)
+            .find(|i| *i > MAX_PASSAGE_BYTES / 3)
+            .or_else(|| window.rfind(char::is_whitespace).filter(|i| *i > 0))
+            .unwrap_or(window.len());
+        pieces.push((start, start + content[start..start + cut].trim_end().len()));
+        start += cut;
+        start += content[start..end].len() - content[start..end].trim_start().len();
+    }
+    if end > start {
+        pieces.push((start, end));
+    }
+    pieces
+}
+
+/// Content words of a question, lowercased
+pub fn keywords(question: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    for word in question.split(|c: char| !c.is_alphanumeric()) {
+        let word = word.to_lowercase();
+        if word.chars().count() >= 2 && !STOP_WORDS.contains(&word.as_str()) && !words.contains(&word) {
+            words.push(word);
+        }
+    }
+    words
+}
+
+/// The `limit` most relevant passages for `question` from notes in `scope`, best first.
+/// Empty when no passage matches a keyword or is similar enough to the question.
+pub fn retrieve(
+    conn: &Connection,
+    question: &str,
+    scope: &NoteScope,
+    semantic: Option<SemanticQuery>,
+    limit: usize,
+) -> Result<Vec<RankedPassage>, String> {
+    let words = keywords(question);
+    let in_scope = scope_note_ids(conn, scope).map_err(|e| e.to_string())?;
+    let allowed = |id: &String| in_scope.as_ref().is_none_or(|ids| ids.contains(id));
+
+    // Candidate notes: any keyword, ranked by hybrid search; without keywords, by meaning alone.
+    // Selected notes are few enough to all be candidates.
+    let note_ids: Vec<String> = if let (NoteScope::Notes { .. }, Some(ids)) = (scope, &in_scope) {
+        ids.iter().cloned().collect()
+    } else if !words.is_empty() {
+        // The folder filter narrows the search itself; the tag filter matches by substring,
+        // so its hits are narrowed again to the exact tag below
+        let mut query = format!("({})", words.join(" OR "));
+        match scope {
+            NoteScope::Folder { id } => query.push_str(&format!(" folder:\"{}\"", id.replace('"', ""))),
+            NoteScope::Tag { name } => query.push_str(&format!(" tag:\"{}\"", name.trim_start_matches('#').replace('"', ""))),
+            _ => {}
+        }
+        let options = SearchOptions {
+            limit: Some(CANDIDATE_NOTES),
+            skip_facets: true,
+            semantic_weight: if semantic.is_some() { 0.5 } else { 0.0 },
+            ..Default::default()
+        };
+        let semantic = semantic.as_ref().map(|s| SemanticQuery { vector: s.vector, model: s.model });
+        search_service::search_notes_hybrid(conn, &query, None, true, &options, semantic)
+            .map_err(|e| e.to_string())?
+            .results
+            .into_iter()
+            .map(|r| r.id)
+            .filter(allowed)
+            .collect()
+    } else if let Some(semantic) = &semantic {
+        let window = if in_scope.is_some() { CANDIDATE_NOTES * 5 } else { CANDIDATE_NOTES };
+        embedding_service::semantic_search(conn, semantic.vector, semantic.model, window)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .filter(|hit| hit.score >= MIN_SIMILARITY && allowed(&hit.note_id))
+            .map(|hit| hit.note_id)
+            .take(CANDIDATE_NOTES)
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    // Every passage of every candidate, with its best chunk similarity
+    let mut passages: Vec<(RankedPassage, f32)> = Vec::new();
+    for note_id in note_ids {
+        let note: Option<(String, String)> = conn
+            .query_row(
+                "SELECT title, COALESCE(content_plaintext, content, '') FROM notes WHERE id = ?",
+                params![note_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let Some((title, content)) = note else { continue };
+        let chunks = match &semantic {
+            Some(s) => embedding_service::chunk_vectors(conn, &note_id, s.model).map_err(|e| e.to_string())?,
+            None => Vec::new(),
+        };
+
+        // Spans come in content order, so character offsets are counted in one pass
+        let (mut byte, mut char_offset) = (0, 0);
+        let mut to_chars = |to: usize| {
+            char_offset += content[byte..to].chars().count();
+            byte = to;
+            char_offset
+        };
+        for span in split_passages(&content) {
+            let similarity = semantic
+                .as_ref()
+                .and_then(|s| {
+                    chunks
+                        .iter()
+                        .filter(|(start, end, _)| *start < span.end && span.start < *end)
+                        .map(|(_, _, vector)| embedding_service::cosine(s.vector, vector))
+                        .reduce(f32::max)
+                })
+                .unwrap_or(0.0);
+            passages.push((
+                RankedPassage {
+                    note_id: note_id.clone(),
+                    title: title.clone(),
+                    heading: span.heading,
+                    start: to_chars(span.start),
+                    end: to_chars(span.end),
+                    text: content[span.start..span.end].to_string(),
+                    score: 0.0,
+                },
+                similarity,
+            ));
+        }
+    }
+
+    // Keyword weight: idf of each keyword the passage (or its title and heading) contains
+    let texts: Vec<String> = passages
+        .iter()
+        .map(|(p, _)| format!("{} {} {}", p.title, p.heading.as_deref().unwrap_or(""), p.text).to_lowercase())
+        .collect();
+    let mut idf: HashMap<&str, f64> = HashMap::new();
+    for word in &words {
+        let df = texts.iter().filter(|t| contains_word(t, word)).count();
+        idf.insert(word, (1.0 + passages.len() as f64 / (df as f64 + 1.0)).ln());
+    }
+    let keyword_scores: Vec<f64> = texts
+        .iter()
+        .map(|t| words.iter().filter(|w| contains_word(t, w)).map(|w| idf[w.as_str()]).sum())
+        .collect();
+
+    let lexical_rank = ranks(&keyword_scores.iter().map(|s| (*s > 0.0).then_some(*s)).collect::<Vec<_>>());
+    let semantic_rank = ranks(
+        &passages
+            .iter()
+            .map(|(_, sim)| (*sim >= MIN_SIMILARITY).then_some(*sim as f64))
+            .collect::<Vec<_>>(),
+    );
+    let weight = if semantic.is_some() { 0.5 } else { 0.0 };
+
+    let mut ranked: Vec<RankedPassage> = passages
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, (mut passage, _))| {
+            let lexical = lexical_rank[i].map(|r| (1.0 - weight) / (RRF_K + r as f64));
+            let semantic = semantic_rank[i].map(|r| weight / (RRF_K + r as f64));
+            if lexical.is_none() && semantic.is_none() {
+                return None;
+            }
+            passage.score = lexical.unwrap_or(0.0) + semantic.unwrap_or(0.0);
+            Some(passage)
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| (&a.note_id, a.start).cmp(&(&b.note_id, b.start))));
+    ranked.truncate(limit);
+    Ok(ranked)
+}
+
+/// Whether `text` has a word starting with `word` (matching search's prefix semantics)
+fn contains_word(text: &str, word: &str) -> bool {
+    text.match_indices(word)
+        .any(|(i, _)| !text[..i].chars().next_back().is_some_and(|c| c.is_alphanumeric()))
+}
+
+/// 1-based rank of each scored entry, highest score first
+fn ranks(scores: &[Option<f64>]) -> Vec<Option<usize>> {
+    let mut order: Vec<usize> = (0..scores.len()).filter(|i| scores[*i].is_some()).collect();
+    order.sort_by(|a, b| scores[*b].unwrap().total_cmp(&scores[*a].unwrap()).then(a.cmp(b)));
+    let mut rank = vec![None; scores.len()];
+    for (r, i) in order.into_iter().enumerate() {
+        rank[i] = Some(r + 1);
+    }
+    rank
+}
+
+/// How a source appears in the prompt
+fn format_source(marker: usize, title: &str, heading: Option<&str>, text: &str) -> String {
+    match heading {
+        Some(heading) => format!("[{}] {} › {}\n{}\n\n", marker, title, heading, text),
+        None => format!("[{}] {}\n{}\n\n", marker, title, text),
+    }
+}
+
+/// Tokens left for sources once the prompt and a `max_tokens` reply fit in the context
+pub fn context_budget(context_length: Option<usize>, max_tokens: usize) -> usize {
+    context_length
+        .unwrap_or(DEFAULT_CONTEXT_TOKENS)
+        .min(MAX_CONTEXT_TOKENS)
+        .saturating_sub(max_tokens + PROMPT_OVERHEAD_TOKENS)
+}
+
+/// Take passages in rank order while the sources fit in `budget_tokens`, skipping any
+/// that would overflow it, and number them from 1
+pub fn pack_sources(
+    passages: Vec<RankedPassage>,
+    budget_tokens: usize,
+    count_tokens: &dyn Fn(&str) -> usize,
+) -> Vec<Source> {
+    let mut sources = Vec::new();
+    let mut used = 0;
+    for passage in passages {
+        let tokens =
+            count_tokens(&format_source(sources.len() + 1, &passage.title, passage.heading.as_deref(), &passage.text));
+        if used + tokens > budget_tokens {
+            continue;
+        }
+        used += tokens;
+        sources.push(Source {
+            marker: sources.len() + 1,
+            start: passage.start,
+            end: passage.end,
+            note_id: passage.note_id,
+            title: passage.title,
+            heading: passage.heading,
+            text: passage.text,
+            cited: false,
+        });
+    }
+    sources
+}
+
+/// The conversation asking the model to answer `question` from `sources`
+pub fn build_messages(question: &str, sources: &[Source]) -> Vec<ChatMessage> {
+    let context: String = sources
+        .iter()
+        .map(|s| format_source(s.marker, &s.title, s.heading.as_deref(), &s.text))
+        .collect();
+    vec![
+        ChatMessage::system(SYSTEM_PROMPT),
+        ChatMessage::user(format!("Sources:\n\n{}Question: {}", context, question)),
+    ]
+}
+
+/// Source numbers the answer cites, in order of first mention: `[2]`, `[1, 3]` and `[1][2]` all count
+pub fn cited_markers(answer: &str) -> Vec<usize> {
+    static MARKER: OnceLock<Regex> = OnceLock::new();
+    let marker = MARKER.get_or_init(|| Regex::new(r"\[(\d+(?:\s*,\s*\d+)*)\]").unwrap());
+    let mut cited = Vec::new();
+    for caps in marker.captures_iter(answer) {
+        for n in caps[1].split(',').filter_map(|n| n.trim().parse().ok()) {
+            if !cited.contains(&n) {
+                cited.push(n);
+            }
+        }
+    }
+    cited
+}
+
+/// Mark the sources the answer cites; markers with no such source are ignored
+pub fn mark_cited(answer: &str, sources: &mut [Source]) {
+    let cited = cited_markers(answer);
+    for source in sources {
+        source.cited = cited.contains(&source.marker);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::search_index;
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (
+                internal_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                id TEXT UNIQUE NOT NULL,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                folder_id TEXT,
+                is_daily_note BOOLEAN DEFAULT FALSE,
+                properties TEXT,
+                content_encrypted BLOB,
+                nonce BLOB,
+                content_plaintext TEXT
+            );
+            CREATE TABLE folders (id TEXT PRIMARY KEY, name TEXT NOT NULL, parent_id TEXT);
+            CREATE TABLE tags (id TEXT PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+            CREATE TABLE note_tags (note_id TEXT NOT NULL, tag_id TEXT NOT NULL, PRIMARY KEY (note_id, tag_id));",
+        )
+        .unwrap();
+        search_index::create_tables(&conn).unwrap();
+        embedding_service::create_tables(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_split_passages_by_heading_and_paragraph() {
+        let long = "Sentence number one is here. ".repeat(60);
+        let content = format!(
+            "Intro line\n\n# Plan\n## Pricing\nWe charge $10.\nPer seat.\n\nDiscounts later.\n\n```\n# not a heading\n```\n# Risks\n{}",
+            long
+        );
+        let passages = split_passages(&content);
+        let texts: Vec<&str> = passages.iter().map(|p| &content[p.start..p.end]).collect();
+
+        assert_eq!(texts[0], "Intro line");
+        assert_eq!(passages[0].heading, None);
+        assert_eq!(texts[1], "We charge $10.\nPer seat.\n\nDiscounts later.\n\n```\n# not a heading\n```");
+        assert_eq!(passages[1].heading.as_deref(), Some("Plan › Pricing"));
+        // The long paragraph is cut at sentence ends
+        assert!(passages[2..].iter().all(|p| p.heading.as_deref() == Some("Risks")));
+        assert!(passages.len() > 3);
+        assert!(texts[2..].iter().all(|t| t.len() <= MAX_PASSAGE_BYTES && t.ends_with('.')));
+    }
+
+    #[test]
+    fn test_retrieve_pack_and_cite() {
+        let conn = setup();
+        conn.execute_batch(
+            "INSERT INTO notes (id, title, content) VALUES
+                ('p', 'Pricing', 'Café notes first.\n\n# Decision\nWe decided the price is ten euros per seat.'),
+                ('q', 'Travel', 'Trains to Lyon leave hourly.'),
+                ('r', 'Seats', 'Seat count grows each quarter.');",
+        )
+        .unwrap();
+
+        let passages = retrieve(&conn, "What price did we decide per seat?", &NoteScope::All, None, 5).unwrap();
+        assert_eq!(passages[0].note_id, "p");
+        assert_eq!(passages[0].heading.as_deref(), Some("Decision"));
+        assert!(passages.iter().all(|p| p.note_id != "q"));
+
+        // Budget for the first source only
+        let count = |text: &str| text.len() / 4;
+        let first = count(&format_source(1, &passages[0].title, passages[0].heading.as_deref(), &passages[0].text));
+        let mut sources = pack_sources(passages, first, &count);
+        assert_eq!(sources.len(), 1);
+        let source = &sources[0];
+        // Character offsets: "Café" has a two-byte character before the passage
+        let content: Vec<char> = "Café notes first.\n\n# Decision\nWe decided the price is ten euros per seat.".chars().collect();
+        assert_eq!(content[source.start..source.end].iter().collect::<String>(), source.text);
+
+        let messages = build_messages("What price?", &sources);
+        assert!(messages[1].content.contains("[1] Pricing › Decision\nWe decided"));
+
+        mark_cited("Ten euros per seat [1][4].", &mut sources);
+        assert!(sources[0].cited);
+        assert_eq!(cited_markers("See [2], [1, 3] and [2]."), vec![2, 1, 3]);
+
+        assert_eq!(context_budget(Some(32768), 200), 8192 - 200 - PROMPT_OVERHEAD_TOKENS);
+        assert_eq!(context_budget(Some(512), 600), 0);
+
+        assert!(retrieve(&conn, "volcano eruptions", &NoteScope::All, None, 5).unwrap().is_empty());
+        assert!(retrieve(&conn, "what is it?", &NoteScope::All, None, 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_retrieve_within_scope() {
+        let conn = setup();
+        conn.execute_batch(
+            "INSERT INTO folders (id, name, parent_id) VALUES ('work', 'Work', NULL), ('plans', 'Plans', 'work');
+             INSERT INTO tags (id, name) VALUES ('t1', 'budget'), ('t2', 'budgets');
+             INSERT INTO notes (id, title, content, folder_id) VALUES
+                ('a', 'Q1', 'Budget review for marketing.', 'plans'),
+                ('b', 'Home', 'Budget for groceries.', NULL),
+                ('c', 'Q2', 'Budget review for sales.', NULL);
+             INSERT INTO note_tags (note_id, tag_id) VALUES ('b', 't1'), ('c', 't2');",
+        )
+        .unwrap();
+
+        let notes = |scope: NoteScope| -> Vec<String> {
+            let mut ids: Vec<String> =
+                retrieve(&conn, "budget", &scope, None, 10).unwrap().into_iter().map(|p| p.note_id).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(notes(NoteScope::All), vec!["a", "b", "c"]);
+        // Subfolders are part of a folder
+        assert_eq!(notes(NoteScope::Folder { id: "work".into() }), vec!["a"]);
+        // Exact tag only, not every tag containing the name
+        assert_eq!(notes(NoteScope::Tag { name: "#budget".into() }), vec!["b"]);
+        assert_eq!(notes(NoteScope::Notes { ids: vec!["c".into(), "b".into()] }), vec!["b", "c"]);
+    }
+}
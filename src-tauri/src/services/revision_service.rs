@@ -0,0 +1,452 @@
+//! Note revisions grouped into undoable batches
+//!
+//! Bulk edits (find and replace, merges) open a batch, snapshot every note
+//! they touch with [`record_revision`] before changing it, and finish with
+//! [`finish_batch`]. A snapshot holds the raw stored columns (properties
+//! included), so encrypted notes are restored without needing the passphrase. Changes to other rows,
+//! such as notes a merge trashed and tags it added, are recorded with
+//! [`record_trashed`] and [`record_tag_added`] so undo can reverse them too.
+//!
+//! Notes written by a batch get an `updated_at` from [`batch_timestamp`],
+//! which has millisecond precision, so an edit made in the same second as the
+//! batch still counts as a later change when undoing. Only the most recent
+//! [`KEPT_BATCHES`] batches are kept.
+
+use chrono::Local;
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::services::db_service::table_has_column;
+use crate::services::trash_service;
+
+/// Batches older than the newest this many are pruned with their snapshots
+pub const KEPT_BATCHES: usize = 100;
+
+pub fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS revision_batches (
+            id TEXT PRIMARY KEY,
+            kind TEXT NOT NULL,
+            description TEXT NOT NULL,
+            note_count INTEGER NOT NULL DEFAULT 0,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            undone_at DATETIME
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS note_revisions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            batch_id TEXT NOT NULL,
+            note_id TEXT NOT NULL,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            content_encrypted BLOB,
+            nonce BLOB,
+            content_plaintext TEXT,
+            properties TEXT,
+            updated_at DATETIME,
+            -- updated_at written by the batch, used to detect later edits
+            replaced_at DATETIME,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (batch_id) REFERENCES revision_batches(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+    if !table_has_column(conn, "note_revisions", "properties") {
+        conn.execute("ALTER TABLE note_revisions ADD COLUMN properties TEXT", [])?;
+    }
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS revision_changes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            batch_id TEXT NOT NULL,
+            -- 'trashed' (note_id) or 'tag_added' (note_id, tag_id)
+            kind TEXT NOT NULL,
+            note_id TEXT NOT NULL,
+            tag_id TEXT,
+            FOREIGN KEY (batch_id) REFERENCES revision_batches(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_revision_changes_batch ON revision_changes(batch_id)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_note_revisions_batch ON note_revisions(batch_id)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_note_revisions_note ON note_revisions(note_id)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RevisionBatch {
+    pub id: String,
+    pub kind: String,
+    pub description: String,
+    pub note_count: i64,
+    pub created_at: Option<String>,
+    pub undone_at: Option<String>,
+}
+
+/// Outcome of [`undo_batch`]
+#[derive(Debug, Clone, Serialize)]
+pub struct UndoResult {
+    pub batch_id: String,
+    pub restored: usize,
+    /// Notes edited or deleted after the batch, which are left alone
+    pub skipped: Vec<String>,
+}
+
+/// Stored columns of a note as they were before a batch
+struct Snapshot {
+    note_id: String,
+    title: String,
+    content: String,
+    content_encrypted: Value,
+    nonce: Value,
+    content_plaintext: Option<String>,
+    properties: Option<String>,
+    replaced_at: Option<String>,
+}
+
+fn now() -> String {
+    Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// `updated_at` for notes a batch (or an undo) writes
+pub fn batch_timestamp() -> String {
+    Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string()
+}
+
+/// Start a batch; `kind` names the operation, e.g. "find_replace"
+pub fn begin_batch(conn: &Connection, kind: &str, description: &str) -> Result<String> {
+    let id = Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO revision_batches (id, kind, description) VALUES (?, ?, ?)",
+        params![id, kind, description],
+    )?;
+    Ok(id)
+}
+
+/// Snapshot a note's stored columns before a batch changes it
+///
+/// `replaced_at` is the `updated_at` the batch is about to write.
+pub fn record_revision(conn: &Connection, batch_id: &str, note_id: &str, replaced_at: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO note_revisions
+            (batch_id, note_id, title, content, content_encrypted, nonce, content_plaintext, properties,
+             updated_at, replaced_at)
+         SELECT ?1, id, title, content, content_encrypted, nonce, content_plaintext, properties, updated_at, ?3
+         FROM notes WHERE id = ?2",
+        params![batch_id, note_id, replaced_at],
+    )?;
+    Ok(())
+}
+
+/// Note that a batch moved a note to the trash
+pub fn record_trashed(conn: &Connection, batch_id: &str, note_id: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO revision_changes (batch_id, kind, note_id) VALUES (?, 'trashed', ?)",
+        params![batch_id, note_id],
+    )?;
+    Ok(())
+}
+
+/// Note that a batch tagged a note
+pub fn record_tag_added(conn: &Connection, batch_id: &str, note_id: &str, tag_id: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO revision_changes (batch_id, kind, note_id, tag_id) VALUES (?, 'tag_added', ?, ?)",
+        params![batch_id, note_id, tag_id],
+    )?;
+    Ok(())
+}
+
+/// Store how many notes the batch changed and prune the oldest batches
+pub fn finish_batch(conn: &Connection, batch_id: &str) -> Result<RevisionBatch> {
+    conn.execute(
+        "UPDATE revision_batches
+         SET note_count = (SELECT COUNT(*) FROM note_revisions WHERE batch_id = ?1)
+            + (SELECT COUNT(*) FROM revision_changes WHERE batch_id = ?1 AND kind = 'trashed')
+         WHERE id = ?1",
+        params![batch_id],
+    )?;
+    prune_batches(conn)?;
+    get_batch(conn, batch_id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)
+}
+
+/// Delete batches beyond the newest [`KEPT_BATCHES`], with their snapshots
+fn prune_batches(conn: &Connection) -> Result<()> {
+    let old_batches = "SELECT id FROM revision_batches ORDER BY created_at DESC, rowid DESC LIMIT -1 OFFSET ?1";
+    conn.execute(
+        &format!("DELETE FROM note_revisions WHERE batch_id IN ({})", old_batches),
+        params![KEPT_BATCHES as i64],
+    )?;
+    conn.execute(
+        &format!("DELETE FROM revision_changes WHERE batch_id IN ({})", old_batches),
+        params![KEPT_BATCHES as i64],
+    )?;
+    conn.execute(
+        &format!("DELETE FROM revision_batches WHERE id IN ({})", old_batches),
+        params![KEPT_BATCHES as i64],
+    )?;
+    Ok(())
+}
+
+fn map_batch(row: &rusqlite::Row) -> Result<RevisionBatch> {
+    Ok(RevisionBatch {
+        id: row.get(0)?,
+        kind: row.get(1)?,
+        description: row.get(2)?,
+        note_count: row.get(3)?,
+        created_at: row.get(4)?,
+        undone_at: row.get(5)?,
+    })
+}
+
+pub fn get_batch(conn: &Connection, batch_id: &str) -> Result<Option<RevisionBatch>> {
+    conn.query_row(
+        "SELECT id, kind, description, note_count, created_at, undone_at FROM revision_batches WHERE id = ?",
+        params![batch_id],
+        map_batch,
+    )
+    .optional()
+}
+
+/// Most recent batches first
+pub fn list_batches(conn: &Connection, limit: usize) -> Result<Vec<RevisionBatch>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, kind, description, note_count, created_at, undone_at
+         FROM revision_batches ORDER BY created_at DESC, rowid DESC LIMIT ?",
+    )?;
+    let batches = stmt.query_map(params![limit as i64], map_batch)?;
+    batches.collect()
+}
+
+/// Put every note of a batch back the way it was before the batch ran
+///
+/// Runs in one transaction. Notes changed again since the batch are skipped
+/// so later edits aren't lost, as are trashed notes that were since restored
+/// or purged. Tags the batch added are removed. Restored notes get a new `updated_at`, since
+/// undoing is itself an edit; the original timestamp stays in the snapshot.
+pub fn undo_batch(conn: &Connection, batch_id: &str) -> Result<UndoResult, String> {
+    let batch = get_batch(conn, batch_id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Revision batch {} not found", batch_id))?;
+    if batch.undone_at.is_some() {
+        return Err("This change has already been undone".to_string());
+    }
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let revisions = {
+        let mut stmt = tx
+            .prepare(
+                "SELECT note_id, title, content, content_encrypted, nonce, content_plaintext, properties, replaced_at
+                 FROM note_revisions WHERE batch_id = ? ORDER BY id DESC",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![batch_id], |row| {
+                Ok(Snapshot {
+                    note_id: row.get(0)?,
+                    title: row.get(1)?,
+                    content: row.get(2)?,
+                    content_encrypted: row.get(3)?,
+                    nonce: row.get(4)?,
+                    content_plaintext: row.get(5)?,
+                    properties: row.get(6)?,
+                    replaced_at: row.get(7)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<Vec<_>>>().map_err(|e| e.to_string())?
+    };
+
+    let restored_at = batch_timestamp();
+    let mut restored = 0;
+    let mut skipped = Vec::new();
+    for rev in revisions {
+        let current: Option<Option<String>> = tx
+            .query_row("SELECT updated_at FROM notes WHERE id = ?", params![rev.note_id], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())?;
+        match current {
+            Some(current) if current == rev.replaced_at => {
+                tx.execute(
+                    "UPDATE notes SET title = ?, content = ?, content_encrypted = ?, nonce = ?,
+                     content_plaintext = ?, properties = ?, updated_at = ? WHERE id = ?",
+                    params![
+                        rev.title,
+                        rev.content,
+                        rev.content_encrypted,
+                        rev.nonce,
+                        rev.content_plaintext,
+                        rev.properties,
+                        restored_at,
+                        rev.note_id
+                    ],
+                )
+                .map_err(|e| e.to_string())?;
+                restored += 1;
+            }
+            _ => skipped.push(rev.note_id),
+        }
+    }
+
+    let changes: Vec<(String, String, Option<String>)> = {
+        let mut stmt = tx
+            .prepare("SELECT kind, note_id, tag_id FROM revision_changes WHERE batch_id = ? ORDER BY id DESC")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![batch_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_>>().map_err(|e| e.to_string())?
+    };
+    for (kind, note_id, tag_id) in changes {
+        match kind.as_str() {
+            "tag_added" => {
+                tx.execute(
+                    "DELETE FROM note_tags WHERE note_id = ? AND tag_id = ?",
+                    params![note_id, tag_id],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+            "trashed" => {
+                let restorable: bool = tx
+                    .query_row(
+                        "SELECT EXISTS(SELECT 1 FROM trashed_notes WHERE id = ?1)
+                            AND NOT EXISTS(SELECT 1 FROM notes WHERE id = ?1)",
+                        params![note_id],
+                        |row| row.get(0),
+                    )
+                    .map_err(|e| e.to_string())?;
+                if restorable {
+                    trash_service::restore_from_trash(&tx, &note_id)?;
+                    restored += 1;
+                } else {
+                    skipped.push(note_id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    tx.execute(
+        "UPDATE revision_batches SET undone_at = ? WHERE id = ?",
+        params![now(), batch_id],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(UndoResult {
+        batch_id: batch_id.to_string(),
+        restored,
+        skipped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (
+                id TEXT PRIMARY KEY,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                updated_at DATETIME,
+                content_encrypted BLOB,
+                nonce BLOB,
+                content_plaintext TEXT,
+                properties TEXT
+            );
+            INSERT INTO notes VALUES ('secret', 'Secret', '', '2024-01-01 00:00:00', x'0102', x'03', 'old text', NULL);
+            INSERT INTO notes VALUES ('plain', 'Plain', 'old text', '2024-01-01 00:00:00', NULL, NULL, NULL, NULL);",
+        )
+        .unwrap();
+        create_tables(&conn).unwrap();
+        conn
+    }
+
+    /// Rewrite both notes in one batch, the way find and replace does
+    fn edit_both(conn: &Connection) -> (String, String) {
+        let stamp = batch_timestamp();
+        let batch_id = begin_batch(conn, "test", "edit").unwrap();
+        for id in ["secret", "plain"] {
+            record_revision(conn, &batch_id, id, &stamp).unwrap();
+        }
+        conn.execute(
+            "UPDATE notes SET content_encrypted = x'0909', nonce = x'08', content_plaintext = 'new text', updated_at = ?1
+             WHERE id = 'secret'",
+            params![stamp],
+        )
+        .unwrap();
+        conn.execute("UPDATE notes SET content = 'new text', updated_at = ?1 WHERE id = 'plain'", params![stamp])
+            .unwrap();
+        finish_batch(conn, &batch_id).unwrap();
+        (batch_id, stamp)
+    }
+
+    #[test]
+    fn test_undo_restores_encrypted_columns_and_touches_notes() {
+        let conn = setup();
+        let (batch_id, _) = edit_both(&conn);
+
+        let undo = undo_batch(&conn, &batch_id).unwrap();
+        assert_eq!(undo.restored, 2);
+        let (encrypted, nonce, plaintext, updated_at): (Vec<u8>, Vec<u8>, String, String) = conn
+            .query_row(
+                "SELECT content_encrypted, nonce, content_plaintext, updated_at FROM notes WHERE id = 'secret'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!((encrypted, nonce, plaintext.as_str()), (vec![1, 2], vec![3], "old text"));
+        assert!(updated_at.as_str() > "2024-01-01 00:00:00");
+    }
+
+    #[test]
+    fn test_edit_in_the_same_second_is_not_undone() {
+        let conn = setup();
+        let (batch_id, stamp) = edit_both(&conn);
+
+        // A regular save writes a whole-second timestamp, possibly the batch's own second
+        conn.execute("UPDATE notes SET content = 'mine', updated_at = ?1 WHERE id = 'plain'", params![&stamp[..19]])
+            .unwrap();
+
+        let undo = undo_batch(&conn, &batch_id).unwrap();
+        assert_eq!(undo.restored, 1);
+        assert_eq!(undo.skipped, vec!["plain"]);
+    }
+
+    #[test]
+    fn test_old_batches_are_pruned() {
+        let conn = setup();
+        let (first, _) = edit_both(&conn);
+        for _ in 0..KEPT_BATCHES {
+            edit_both(&conn);
+        }
+
+        assert!(get_batch(&conn, &first).unwrap().is_none());
+        let (batches, revisions): (i64, i64) = conn
+            .query_row(
+                "SELECT (SELECT COUNT(*) FROM revision_batches), (SELECT COUNT(*) FROM note_revisions)",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((batches, revisions), (KEPT_BATCHES as i64, 2 * KEPT_BATCHES as i64));
+    }
+}
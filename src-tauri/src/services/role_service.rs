@@ -1,8 +1,182 @@
-use rusqlite::{Connection, Result};
+use rusqlite::{params, Connection, OptionalExtension, Result};
 use serde::{Deserialize, Serialize};
 use std::sync::Mutex;
 use crate::services::db_service::DbState;
 
+/// A user-defined role and the rules it applies to search and dashboards
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Role {
+    /// Identifier used by the frontend and in `role_dashboard_layouts` (lowercase)
+    pub name: String,
+    #[serde(default)]
+    pub display_name: String,
+    /// If non-empty, search only returns notes carrying at least one of these tags
+    #[serde(default)]
+    pub include_tags: Vec<String>,
+    /// Notes carrying any of these tags are hidden from search
+    #[serde(default)]
+    pub exclude_tags: Vec<String>,
+    /// Notes carrying any of these tags are ranked first
+    #[serde(default)]
+    pub boost_tags: Vec<String>,
+    /// Card `role_context` values visible under this role; empty means all cards
+    #[serde(default)]
+    pub card_contexts: Vec<String>,
+    /// Dashboard widgets used until the user saves a layout
+    #[serde(default)]
+    pub default_widgets: Vec<String>,
+    #[serde(default)]
+    pub updated_at: Option<String>,
+}
+
+pub fn create_tables(conn: &Connection) -> Result<()> {
+    let first_run: bool = conn.query_row(
+        "SELECT NOT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'roles')",
+        [],
+        |row| row.get(0),
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS roles (
+            name TEXT PRIMARY KEY,
+            display_name TEXT NOT NULL,
+            include_tags TEXT NOT NULL DEFAULT '[]',
+            exclude_tags TEXT NOT NULL DEFAULT '[]',
+            boost_tags TEXT NOT NULL DEFAULT '[]',
+            card_contexts TEXT NOT NULL DEFAULT '[]',
+            default_widgets TEXT NOT NULL DEFAULT '[]',
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    // Also created by migration 0006, which only runs from the source tree
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS role_dashboard_layouts (
+            role TEXT PRIMARY KEY,
+            widget_order TEXT NOT NULL,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    // Built-in roles, matching the behaviour from before roles were configurable.
+    // Seeded only with the table so that roles the user deleted stay deleted.
+    if !first_run {
+        return Ok(());
+    }
+    conn.execute_batch(
+        "INSERT OR IGNORE INTO roles (name, display_name, exclude_tags, card_contexts, default_widgets)
+         VALUES ('learner', 'Learner', '[\"work\"]', '[\"learner\",\"general\",\"all\",\"universal\"]',
+                 '[\"spaced-repetition\",\"reading-list\"]');
+         INSERT OR IGNORE INTO roles (name, display_name, boost_tags, card_contexts, default_widgets)
+         VALUES ('manager', 'Manager', '[\"project\"]', '[\"manager\",\"general\",\"all\",\"universal\"]',
+                 '[\"tasks-padding\",\"project-deadlines\"]');
+         INSERT OR IGNORE INTO roles (name, display_name, boost_tags, card_contexts, default_widgets)
+         VALUES ('coach', 'Coach', '[\"coaching\",\"template\"]', '[\"coach\",\"general\",\"all\",\"universal\"]', '[]');",
+    )?;
+    Ok(())
+}
+
+fn json_list(value: String) -> Vec<String> {
+    serde_json::from_str(&value).unwrap_or_default()
+}
+
+fn map_role(row: &rusqlite::Row) -> Result<Role> {
+    Ok(Role {
+        name: row.get(0)?,
+        display_name: row.get(1)?,
+        include_tags: json_list(row.get(2)?),
+        exclude_tags: json_list(row.get(3)?),
+        boost_tags: json_list(row.get(4)?),
+        card_contexts: json_list(row.get(5)?),
+        default_widgets: json_list(row.get(6)?),
+        updated_at: row.get(7)?,
+    })
+}
+
+const ROLE_COLUMNS: &str =
+    "name, display_name, include_tags, exclude_tags, boost_tags, card_contexts, default_widgets, updated_at";
+
+pub fn list_roles(conn: &Connection) -> Result<Vec<Role>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM roles ORDER BY name", ROLE_COLUMNS))?;
+    let roles = stmt.query_map([], map_role)?;
+    roles.collect()
+}
+
+/// Look up a role by name (case-insensitive)
+pub fn get_role(conn: &Connection, name: &str) -> Result<Option<Role>> {
+    conn.query_row(
+        &format!("SELECT {} FROM roles WHERE name = ?", ROLE_COLUMNS),
+        params![name.trim().to_lowercase()],
+        map_role,
+    )
+    .optional()
+}
+
+/// Create or update a role
+pub fn save_role(conn: &Connection, role: &Role) -> Result<Role, String> {
+    let name = role.name.trim().to_lowercase();
+    if name.is_empty() {
+        return Err("Role name cannot be empty".to_string());
+    }
+    if !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("Invalid role name '{}': use letters, digits, '-' or '_'", name));
+    }
+    let display_name = if role.display_name.trim().is_empty() {
+        name.clone()
+    } else {
+        role.display_name.trim().to_string()
+    };
+
+    let to_json = |list: &Vec<String>| {
+        let cleaned: Vec<&str> = list.iter().map(|s| s.trim()).filter(|s| !s.is_empty()).collect();
+        serde_json::to_string(&cleaned).map_err(|e| e.to_string())
+    };
+
+    conn.execute(
+        "INSERT INTO roles (name, display_name, include_tags, exclude_tags, boost_tags, card_contexts, default_widgets)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT(name) DO UPDATE SET
+            display_name = excluded.display_name,
+            include_tags = excluded.include_tags,
+            exclude_tags = excluded.exclude_tags,
+            boost_tags = excluded.boost_tags,
+            card_contexts = excluded.card_contexts,
+            default_widgets = excluded.default_widgets,
+            updated_at = CURRENT_TIMESTAMP",
+        params![
+            name,
+            display_name,
+            to_json(&role.include_tags)?,
+            to_json(&role.exclude_tags)?,
+            to_json(&role.boost_tags)?,
+            to_json(&role.card_contexts)?,
+            to_json(&role.default_widgets)?,
+        ],
+    )
+    .map_err(|e| format!("Failed to save role: {}", e))?;
+
+    get_role(conn, &name)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Role {} disappeared after saving", name))
+}
+
+/// Delete a role and its saved dashboard layout
+pub fn delete_role(conn: &Connection, name: &str) -> Result<bool> {
+    let name = name.trim().to_lowercase();
+    conn.execute("DELETE FROM role_dashboard_layouts WHERE role = ?", params![name])?;
+    Ok(conn.execute("DELETE FROM roles WHERE name = ?", params![name])? > 0)
+}
+
+/// Default widgets of a role, or none for unknown roles
+fn default_widgets(conn: &Connection, role: &str) -> Result<Vec<String>, String> {
+    Ok(get_role(conn, role)
+        .map_err(|e| e.to_string())?
+        .map(|r| r.default_widgets)
+        .unwrap_or_default())
+}
+
 /// Dashboard layout structure
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DashboardLayout {
@@ -28,9 +202,8 @@ impl RoleService {
             return Err("Widget order cannot be empty".to_string());
         }
         
-        // Validate role is one of the supported roles
-        let valid_roles = ["manager", "coach", "learner"];
-        if !valid_roles.contains(&role) {
+        // Validate role is one of the configured roles
+        if get_role(&conn, role).map_err(|e| e.to_string())?.is_none() {
             return Err(format!("Invalid role: {}", role));
         }
         
@@ -42,7 +215,7 @@ impl RoleService {
         conn.execute(
             "INSERT OR REPLACE INTO role_dashboard_layouts (role, widget_order, updated_at) 
              VALUES (?, ?, CURRENT_TIMESTAMP)",
-            &[&role, &widget_order_json],
+            params![role, widget_order_json],
         ).map_err(|e| format!("Failed to save layout: {}", e))?;
         
         Ok(())
@@ -79,12 +252,7 @@ impl RoleService {
             Ok(layout) => Ok(layout),
             Err(rusqlite::Error::QueryReturnedNoRows) => {
                 // Return default layout based on role
-                let default_layout = match role {
-                    "manager" => vec!["tasks-padding".to_string(), "project-deadlines".to_string()],
-                    "learner" => vec!["spaced-repetition".to_string(), "reading-list".to_string()],
-                    "coach" => Vec::new(),
-                    _ => Vec::new(),
-                };
+                let default_layout = default_widgets(&conn, role)?;
                 
                 Ok(DashboardLayout {
                     role: role.to_string(),
@@ -149,13 +317,53 @@ impl RoleService {
         let _ = Self::delete_layout(db_state, role);
         
         // Re-insert default
-        let default_layout = match role {
-            "manager" => vec!["tasks-padding".to_string(), "project-deadlines".to_string()],
-            "learner" => vec!["spaced-repetition".to_string(), "reading-list".to_string()],
-            "coach" => Vec::new(),
-            _ => Vec::new(),
+        let default_layout = {
+            let conn = db_state.0.lock().map_err(|e| e.to_string())?;
+            default_widgets(&conn, role)?
         };
         
         Self::save_layout(db_state, role, &default_layout)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_crud() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_tables(&conn).unwrap();
+        assert_eq!(get_role(&conn, "Learner").unwrap().unwrap().exclude_tags, vec!["work"]);
+
+        let saved = save_role(&conn, &Role {
+            name: " On-Call ".to_string(),
+            display_name: String::new(),
+            include_tags: vec!["incident".to_string(), " ".to_string()],
+            exclude_tags: Vec::new(),
+            boost_tags: vec!["runbook".to_string()],
+            card_contexts: vec!["on-call".to_string()],
+            default_widgets: vec!["reading-list".to_string()],
+            updated_at: None,
+        }).unwrap();
+        assert_eq!(saved.name, "on-call");
+        assert_eq!(saved.display_name, "on-call");
+        assert_eq!(saved.include_tags, vec!["incident"]);
+        assert_eq!(list_roles(&conn).unwrap().len(), 4);
+
+        assert!(save_role(&conn, &Role { name: "a b".to_string(), ..saved.clone() }).is_err());
+        assert!(delete_role(&conn, "on-call").unwrap());
+        assert!(get_role(&conn, "on-call").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_deleted_built_in_role_is_not_reseeded() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_tables(&conn).unwrap();
+        assert!(delete_role(&conn, "coach").unwrap());
+
+        create_tables(&conn).unwrap();
+        assert!(get_role(&conn, "coach").unwrap().is_none());
+        assert!(get_role(&conn, "learner").unwrap().is_some());
+    }
+}
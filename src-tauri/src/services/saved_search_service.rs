@@ -0,0 +1,487 @@
+//! Saved searches and smart folders
+//!
+//! A saved search is a named query in the search language. Pinned searches
+//! show up as smart folders in the folder tree, with a live count.
+//! Searches with `notify` set are checked by the background worker, and
+//! `saved_search_hits` records which notes each search has already reported.
+
+use chrono::Local;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::services::{role_service, search_query};
+use crate::services::search_service::{self, SearchOptions, SearchResultWithMetadata, SearchSort};
+
+const PAGE_SIZE: usize = 200;
+
+pub fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS saved_searches (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            query TEXT NOT NULL,
+            role TEXT,
+            sort TEXT NOT NULL DEFAULT 'relevance',
+            pinned BOOLEAN NOT NULL DEFAULT FALSE,
+            parent_folder_id TEXT,
+            position INTEGER NOT NULL DEFAULT 0,
+            notify BOOLEAN NOT NULL DEFAULT FALSE,
+            last_checked_at DATETIME,
+            last_count INTEGER NOT NULL DEFAULT 0,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS saved_search_hits (
+            saved_search_id TEXT NOT NULL,
+            note_id TEXT NOT NULL,
+            first_seen_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (saved_search_id, note_id),
+            FOREIGN KEY (saved_search_id) REFERENCES saved_searches(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SavedSearch {
+    pub id: String,
+    pub name: String,
+    pub query: String,
+    /// Role whose rules apply; `None` searches globally
+    pub role: Option<String>,
+    pub sort: SearchSort,
+    /// Shown as a smart folder in the folder tree
+    pub pinned: bool,
+    pub parent_folder_id: Option<String>,
+    pub position: i64,
+    /// Report new matches from the background worker
+    pub notify: bool,
+    pub last_checked_at: Option<String>,
+    pub last_count: i64,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+/// Fields a user can set when creating or editing a saved search
+#[derive(Debug, Clone, Deserialize)]
+pub struct SavedSearchInput {
+    pub name: String,
+    pub query: String,
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub sort: SearchSort,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub parent_folder_id: Option<String>,
+    #[serde(default)]
+    pub position: i64,
+    #[serde(default)]
+    pub notify: bool,
+}
+
+/// A pinned saved search as it appears in the folder tree
+#[derive(Debug, Clone, Serialize)]
+pub struct SmartFolder {
+    pub id: String,
+    pub name: String,
+    pub query: String,
+    pub parent_folder_id: Option<String>,
+    pub position: i64,
+    pub count: usize,
+}
+
+/// Notes that started matching a saved search since it was last checked
+#[derive(Debug, Clone, Serialize)]
+pub struct SavedSearchMatches {
+    pub saved_search_id: String,
+    pub name: String,
+    pub note_ids: Vec<String>,
+    pub titles: Vec<String>,
+}
+
+pub(crate) fn sort_to_str(sort: SearchSort) -> &'static str {
+    match sort {
+        SearchSort::Relevance => "relevance",
+        SearchSort::Updated => "updated",
+        SearchSort::Created => "created",
+        SearchSort::Title => "title",
+    }
+}
+
+fn sort_from_str(sort: &str) -> SearchSort {
+    match sort {
+        "updated" => SearchSort::Updated,
+        "created" => SearchSort::Created,
+        "title" => SearchSort::Title,
+        _ => SearchSort::Relevance,
+    }
+}
+
+const SAVED_SEARCH_COLUMNS: &str = "id, name, query, role, sort, pinned, parent_folder_id, position,
+     notify, last_checked_at, last_count, created_at, updated_at";
+
+fn map_saved_search(row: &rusqlite::Row) -> Result<SavedSearch> {
+    let sort: String = row.get(4)?;
+    Ok(SavedSearch {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        query: row.get(2)?,
+        role: row.get(3)?,
+        sort: sort_from_str(&sort),
+        pinned: row.get(5)?,
+        parent_folder_id: row.get(6)?,
+        position: row.get(7)?,
+        notify: row.get(8)?,
+        last_checked_at: row.get(9)?,
+        last_count: row.get(10)?,
+        created_at: row.get(11)?,
+        updated_at: row.get(12)?,
+    })
+}
+
+fn validate(conn: &Connection, input: &SavedSearchInput) -> Result<(), String> {
+    if input.name.trim().is_empty() {
+        return Err("Saved search name cannot be empty".to_string());
+    }
+    if let Some(role) = &input.role {
+        if role_service::get_role(conn, role).map_err(|e| e.to_string())?.is_none() {
+            return Err(format!("Unknown role '{}'", role));
+        }
+    }
+    match search_query::parse_query(&input.query) {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err("Saved search query cannot be empty".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub fn list_saved_searches(conn: &Connection) -> Result<Vec<SavedSearch>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM saved_searches ORDER BY position, name COLLATE NOCASE",
+        SAVED_SEARCH_COLUMNS
+    ))?;
+    let searches = stmt.query_map([], map_saved_search)?;
+    searches.collect()
+}
+
+pub fn get_saved_search(conn: &Connection, id: &str) -> Result<Option<SavedSearch>> {
+    conn.query_row(
+        &format!("SELECT {} FROM saved_searches WHERE id = ?", SAVED_SEARCH_COLUMNS),
+        params![id],
+        map_saved_search,
+    )
+    .optional()
+}
+
+pub fn create_saved_search(conn: &Connection, input: &SavedSearchInput) -> Result<SavedSearch, String> {
+    validate(conn, input)?;
+    let id = Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO saved_searches (id, name, query, role, sort, pinned, parent_folder_id, position, notify)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            id,
+            input.name.trim(),
+            input.query.trim(),
+            input.role,
+            sort_to_str(input.sort),
+            input.pinned,
+            input.parent_folder_id,
+            input.position,
+            input.notify,
+        ],
+    )
+    .map_err(|e| format!("Failed to save search: {}", e))?;
+
+    get_saved_search(conn, &id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Saved search disappeared after saving".to_string())
+}
+
+/// Update a saved search; changing the query or role resets its notification history
+pub fn update_saved_search(conn: &Connection, id: &str, input: &SavedSearchInput) -> Result<SavedSearch, String> {
+    validate(conn, input)?;
+    let existing = get_saved_search(conn, id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Saved search {} not found", id))?;
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    tx.execute(
+        "UPDATE saved_searches
+         SET name = ?2, query = ?3, role = ?4, sort = ?5, pinned = ?6, parent_folder_id = ?7,
+             position = ?8, notify = ?9, updated_at = CURRENT_TIMESTAMP
+         WHERE id = ?1",
+        params![
+            id,
+            input.name.trim(),
+            input.query.trim(),
+            input.role,
+            sort_to_str(input.sort),
+            input.pinned,
+            input.parent_folder_id,
+            input.position,
+            input.notify,
+        ],
+    )
+    .map_err(|e| format!("Failed to update saved search: {}", e))?;
+
+    if existing.query != input.query.trim() || existing.role != input.role {
+        tx.execute("DELETE FROM saved_search_hits WHERE saved_search_id = ?", params![id])
+            .map_err(|e| e.to_string())?;
+        tx.execute("UPDATE saved_searches SET last_checked_at = NULL WHERE id = ?", params![id])
+            .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+
+    get_saved_search(conn, id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Saved search {} not found", id))
+}
+
+pub fn delete_saved_search(conn: &Connection, id: &str) -> Result<bool> {
+    conn.execute("DELETE FROM saved_search_hits WHERE saved_search_id = ?", params![id])?;
+    Ok(conn.execute("DELETE FROM saved_searches WHERE id = ?", params![id])? > 0)
+}
+
+fn options_for(search: &SavedSearch, limit: usize, offset: usize, facets: bool) -> SearchOptions {
+    // Saved searches skip the typo fallbacks so counts and notifications are stable
+    SearchOptions {
+        limit: Some(limit),
+        offset,
+        sort: search.sort,
+        exact: true,
+        skip_facets: !facets,
+        ..Default::default()
+    }
+}
+
+/// Evaluate a saved search; fails if its role has been deleted since it was saved
+fn run(
+    conn: &Connection,
+    search: &SavedSearch,
+    limit: usize,
+    offset: usize,
+    facets: bool,
+) -> Result<SearchResultWithMetadata, String> {
+    if let Some(role) = &search.role {
+        if role_service::get_role(conn, role).map_err(|e| e.to_string())?.is_none() {
+            return Err(format!("Role '{}' of saved search '{}' no longer exists", role, search.name));
+        }
+    }
+    search_service::search_notes_with_options(
+        conn,
+        &search.query,
+        search.role.as_deref(),
+        search.role.is_none(),
+        &options_for(search, limit, offset, facets),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Evaluate a saved search and return one page of results
+pub fn run_saved_search(
+    conn: &Connection,
+    id: &str,
+    limit: Option<usize>,
+    offset: usize,
+) -> Result<SearchResultWithMetadata, String> {
+    let search = get_saved_search(conn, id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Saved search {} not found", id))?;
+    let result = run(conn, &search, limit.unwrap_or(20), offset, true)?;
+
+    conn.execute(
+        "UPDATE saved_searches SET last_count = ? WHERE id = ?",
+        params![result.total_count as i64, id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+/// Pinned saved searches with their live match counts
+pub fn get_smart_folders(conn: &Connection) -> Result<Vec<SmartFolder>, String> {
+    let searches = list_saved_searches(conn).map_err(|e| e.to_string())?;
+    let mut folders = Vec::new();
+    for search in searches.into_iter().filter(|s| s.pinned) {
+        // A search broken by a later change (e.g. a deleted role) still shows, with no matches
+        let count = run(conn, &search, 1, 0, false).map(|r| r.total_count).unwrap_or(0);
+        folders.push(SmartFolder {
+            id: search.id,
+            name: search.name,
+            query: search.query,
+            parent_folder_id: search.parent_folder_id,
+            position: search.position,
+            count,
+        });
+    }
+    Ok(folders)
+}
+
+/// Check every saved search with `notify` set and return the notes that newly match
+///
+/// The first check of a search only records the current matches, so turning
+/// notifications on doesn't report the whole backlog.
+pub fn check_for_new_matches(conn: &Connection) -> Result<Vec<SavedSearchMatches>, String> {
+    // Hits of deleted notes can never be reported again
+    conn.execute("DELETE FROM saved_search_hits WHERE note_id NOT IN (SELECT id FROM notes)", [])
+        .map_err(|e| e.to_string())?;
+
+    let searches = list_saved_searches(conn).map_err(|e| e.to_string())?;
+    let mut all_matches = Vec::new();
+
+    'searches: for search in searches.into_iter().filter(|s| s.notify) {
+        let mut hits = Vec::new();
+        let mut offset = 0;
+        loop {
+            // A search whose role was deleted is skipped rather than failing the others
+            let Ok(page) = run(conn, &search, PAGE_SIZE, offset, false) else {
+                continue 'searches;
+            };
+            hits.extend(page.results.into_iter().map(|r| (r.id, r.title)));
+            match page.next_offset {
+                Some(next) => offset = next,
+                None => break,
+            }
+        }
+
+        let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+        let mut new_ids = Vec::new();
+        let mut new_titles = Vec::new();
+        for (note_id, title) in &hits {
+            let inserted = tx
+                .execute(
+                    "INSERT OR IGNORE INTO saved_search_hits (saved_search_id, note_id) VALUES (?, ?)",
+                    params![search.id, note_id],
+                )
+                .map_err(|e| e.to_string())?;
+            if inserted > 0 {
+                new_ids.push(note_id.clone());
+                new_titles.push(title.clone());
+            }
+        }
+        tx.execute(
+            "UPDATE saved_searches SET last_checked_at = ?, last_count = ? WHERE id = ?",
+            params![Local::now().format("%Y-%m-%d %H:%M:%S").to_string(), hits.len() as i64, search.id],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+
+        if search.last_checked_at.is_some() && !new_ids.is_empty() {
+            all_matches.push(SavedSearchMatches {
+                saved_search_id: search.id,
+                name: search.name,
+                note_ids: new_ids,
+                titles: new_titles,
+            });
+        }
+    }
+
+    Ok(all_matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::search_index;
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (
+                internal_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                id TEXT UNIQUE NOT NULL,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                folder_id TEXT,
+                is_daily_note BOOLEAN DEFAULT FALSE,
+                properties TEXT,
+                content_encrypted BLOB,
+                nonce BLOB,
+                content_plaintext TEXT
+            );
+            CREATE TABLE folders (id TEXT PRIMARY KEY, name TEXT NOT NULL, parent_id TEXT);
+            CREATE TABLE tags (id TEXT PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+            CREATE TABLE note_tags (note_id TEXT NOT NULL, tag_id TEXT NOT NULL, PRIMARY KEY (note_id, tag_id));
+            INSERT INTO notes (id, title, content) VALUES ('a', 'Rust borrow checker', 'lifetimes explained');",
+        )
+        .unwrap();
+        search_index::create_tables(&conn).unwrap();
+        create_tables(&conn).unwrap();
+        conn
+    }
+
+    fn input(query: &str) -> SavedSearchInput {
+        SavedSearchInput {
+            name: "Rust notes".to_string(),
+            query: query.to_string(),
+            role: None,
+            sort: SearchSort::Updated,
+            pinned: true,
+            parent_folder_id: None,
+            position: 0,
+            notify: true,
+        }
+    }
+
+    #[test]
+    fn test_smart_folder_counts_and_new_matches() {
+        let conn = setup();
+        assert!(create_saved_search(&conn, &input("rust AND (")).is_err());
+        let search = create_saved_search(&conn, &input("rust")).unwrap();
+        assert_eq!(search.sort, SearchSort::Updated);
+
+        let folders = get_smart_folders(&conn).unwrap();
+        assert_eq!(folders.len(), 1);
+        assert_eq!(folders[0].count, 1);
+
+        // The first check only records what already matches
+        assert!(check_for_new_matches(&conn).unwrap().is_empty());
+
+        conn.execute(
+            "INSERT INTO notes (id, title, content) VALUES ('b', 'Async Rust', 'futures'), ('c', 'Go', 'channels')",
+            [],
+        )
+        .unwrap();
+        let matches = check_for_new_matches(&conn).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].note_ids, vec!["b"]);
+        assert!(check_for_new_matches(&conn).unwrap().is_empty());
+
+        let page = run_saved_search(&conn, &search.id, Some(1), 0).unwrap();
+        assert_eq!(page.total_count, 2);
+        assert_eq!(page.next_offset, Some(1));
+
+        assert!(delete_saved_search(&conn, &search.id).unwrap());
+        assert!(get_smart_folders(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_roles_are_validated_and_hits_pruned() {
+        let conn = setup();
+        role_service::create_tables(&conn).unwrap();
+        let with_role = |role: &str| SavedSearchInput { role: Some(role.to_string()), ..input("rust") };
+        assert!(create_saved_search(&conn, &with_role("nobody")).is_err());
+
+        let search = create_saved_search(&conn, &with_role("coach")).unwrap();
+        check_for_new_matches(&conn).unwrap();
+        role_service::delete_role(&conn, "coach").unwrap();
+        assert_eq!(get_smart_folders(&conn).unwrap()[0].count, 0);
+        assert!(run_saved_search(&conn, &search.id, None, 0).is_err());
+
+        conn.execute("DELETE FROM notes WHERE id = 'a'", []).unwrap();
+        check_for_new_matches(&conn).unwrap();
+        let hits: i64 = conn.query_row("SELECT COUNT(*) FROM saved_search_hits", [], |row| row.get(0)).unwrap();
+        assert_eq!(hits, 0);
+    }
+}
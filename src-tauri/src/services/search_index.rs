@@ -0,0 +1,388 @@
+//! Full-text index for notes
+//!
+//! `notes_fts` is an external-content FTS5 table over the `notes_fts_source`
+//! view (title, searchable content, space-separated tag names, properties),
+//! so note text is stored once, in `notes`. Triggers on `notes`, `note_tags`
+//! and `tags` keep it in sync: a BEFORE trigger removes the affected notes
+//! with the values the view still shows, and an AFTER trigger adds them back
+//! with the new ones. Tag links, unlinks, renames and merges are therefore
+//! searchable as soon as they happen.
+//!
+//! `notes_trigram` indexes title and content of the same view with the FTS5
+//! `trigram` tokenizer for substring matches, and `notes_vocab` exposes the
+//! terms of `notes_fts` for spelling suggestions (see `fuzzy_search`).
+//!
+//! Older databases used an external-content table over `notes`, which has
+//! no tags column, or content-storing tables that kept a second and third
+//! copy of every note. Both are replaced and rebuilt on startup.
+
+use rusqlite::{Connection, Result};
+use serde::Serialize;
+
+const FTS_COLUMNS: &str = "title, content, tags, properties";
+const TRIGRAM_COLUMNS: &str = "title, content";
+/// Content option both index tables are created with
+const EXTERNAL_CONTENT: &str = "content='notes_fts_source', content_rowid='internal_id'";
+
+/// Remove the note(s) selected by `filter`, a predicate over `notes_fts_source`,
+/// from `table` using the values the view shows now
+///
+/// An external-content index must be given exactly the values it indexed, so
+/// this runs in BEFORE triggers. Notes that aren't indexed (per the `_docsize`
+/// shadow table) are skipped, which keeps nested trigger paths safe.
+fn unindex_sql(table: &str, columns: &str, filter: &str) -> String {
+    format!(
+        "INSERT INTO {table}({table}, rowid, {cols})
+           SELECT 'delete', internal_id, {cols} FROM notes_fts_source
+            WHERE ({filter}) AND internal_id IN (SELECT id FROM {table}_docsize);",
+        table = table,
+        filter = filter,
+        cols = columns,
+    )
+}
+
+/// Add the note(s) selected by `filter` to `table`, unless already indexed
+fn index_sql(table: &str, columns: &str, filter: &str) -> String {
+    format!(
+        "INSERT INTO {table}(rowid, {cols})
+           SELECT internal_id, {cols} FROM notes_fts_source
+            WHERE ({filter}) AND internal_id NOT IN (SELECT id FROM {table}_docsize);",
+        table = table,
+        filter = filter,
+        cols = columns,
+    )
+}
+
+/// Tag changes only affect `notes_fts`
+fn tag_triggers(name: &str, event: &str, filter_before: &str, filter_after: &str) -> String {
+    format!(
+        "CREATE TRIGGER {name}_before BEFORE {event} BEGIN
+           {unindex}
+         END;
+         CREATE TRIGGER {name}_after AFTER {event} BEGIN
+           {index}
+         END;",
+        name = name,
+        event = event,
+        unindex = unindex_sql("notes_fts", FTS_COLUMNS, filter_before),
+        index = index_sql("notes_fts", FTS_COLUMNS, filter_after),
+    )
+}
+
+/// Note changes affect both the word and the trigram index
+fn note_triggers(name: &str, event: &str, unindex_filter: Option<&str>, index_filter: Option<&str>) -> String {
+    let mut sql = String::new();
+    if let Some(filter) = unindex_filter {
+        sql.push_str(&format!(
+            "CREATE TRIGGER {name}_before BEFORE {event} BEGIN
+               {fts}
+               {trigram}
+             END;\n",
+            name = name,
+            event = event,
+            fts = unindex_sql("notes_fts", FTS_COLUMNS, filter),
+            trigram = unindex_sql("notes_trigram", TRIGRAM_COLUMNS, filter),
+        ));
+    }
+    if let Some(filter) = index_filter {
+        sql.push_str(&format!(
+            "CREATE TRIGGER {name}_after AFTER {event} BEGIN
+               {fts}
+               {trigram}
+             END;\n",
+            name = name,
+            event = event,
+            fts = index_sql("notes_fts", FTS_COLUMNS, filter),
+            trigram = index_sql("notes_trigram", TRIGRAM_COLUMNS, filter),
+        ));
+    }
+    sql
+}
+
+/// Every trigger this module has installed, current or older
+const TRIGGERS: &[&str] = &[
+    "notes_ai", "notes_ad", "notes_au", "note_tags_fts_ai", "note_tags_fts_ad", "tags_fts_au", "tags_fts_ad",
+    "notes_fts_insert_after", "notes_fts_update_before", "notes_fts_update_after", "notes_fts_delete_before",
+    "note_tags_fts_insert_before", "note_tags_fts_insert_after", "note_tags_fts_delete_before",
+    "note_tags_fts_delete_after", "tags_fts_rename_before", "tags_fts_rename_after", "tags_fts_delete_before",
+    "tags_fts_delete_after",
+];
+
+pub fn create_tables(conn: &Connection) -> Result<()> {
+    // Anything but the current external-content layout is dropped and rebuilt
+    let outdated: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master
+                        WHERE name IN ('notes_fts', 'notes_trigram') AND sql NOT LIKE ?1)",
+        [format!("%{}%", EXTERNAL_CONTENT)],
+        |row| row.get(0),
+    )?;
+    let missing: bool = conn.query_row(
+        "SELECT COUNT(*) < 2 FROM sqlite_master WHERE name IN ('notes_fts', 'notes_trigram')",
+        [],
+        |row| row.get(0),
+    )?;
+
+    let mut drop = TRIGGERS.iter().map(|t| format!("DROP TRIGGER IF EXISTS {};", t)).collect::<Vec<_>>().join("\n");
+    if outdated {
+        log::info!("Migrating notes_fts and notes_trigram to external-content indexes");
+        drop.push_str(
+            "DROP TABLE IF EXISTS notes_vocab;
+             DROP TABLE IF EXISTS notes_fts;
+             DROP TABLE IF EXISTS notes_trigram;",
+        );
+    }
+    conn.execute_batch(&drop)?;
+
+    // Tag names are ordered so the indexed text is the same every time it is read
+    conn.execute_batch(&format!(
+        "DROP VIEW IF EXISTS notes_fts_source;
+         CREATE VIEW notes_fts_source AS
+         SELECT n.internal_id,
+                n.id,
+                n.title,
+                COALESCE(n.content_plaintext, n.content) AS content,
+                (SELECT GROUP_CONCAT(t.name, ' ' ORDER BY t.name, t.id)
+                   FROM note_tags nt JOIN tags t ON t.id = nt.tag_id
+                  WHERE nt.note_id = n.id) AS tags,
+                n.properties
+           FROM notes n;
+
+         CREATE VIRTUAL TABLE IF NOT EXISTS notes_fts USING fts5(
+            title,
+            content,
+            tags,
+            properties,
+            {content}
+         );
+
+         CREATE VIRTUAL TABLE IF NOT EXISTS notes_trigram USING fts5(
+            title,
+            content,
+            tokenize = 'trigram',
+            {content}
+         );
+
+         CREATE VIRTUAL TABLE IF NOT EXISTS notes_vocab USING fts5vocab(notes_fts, row);",
+        content = EXTERNAL_CONTENT,
+    ))?;
+
+    // Triggers are recreated on every start so older definitions
+    // (including the ones installed by migrations) are always replaced
+    let this_note = "internal_id = {}.internal_id";
+    let linked_notes = "id IN (SELECT note_id FROM note_tags WHERE tag_id = {}.id)";
+    conn.execute_batch(&[
+        note_triggers("notes_fts_insert", "INSERT ON notes", None, Some(&this_note.replace("{}", "new"))),
+        note_triggers(
+            "notes_fts_update",
+            "UPDATE ON notes",
+            Some(&this_note.replace("{}", "old")),
+            Some(&this_note.replace("{}", "new")),
+        ),
+        note_triggers("notes_fts_delete", "DELETE ON notes", Some(&this_note.replace("{}", "old")), None),
+        // An `INSERT OR IGNORE` of an existing link runs the BEFORE trigger but not the AFTER one
+        tag_triggers(
+            "note_tags_fts_insert",
+            "INSERT ON note_tags",
+            "id = new.note_id AND NOT EXISTS (SELECT 1 FROM note_tags WHERE note_id = new.note_id AND tag_id = new.tag_id)",
+            "id = new.note_id",
+        ),
+        tag_triggers("note_tags_fts_delete", "DELETE ON note_tags", "id = old.note_id", "id = old.note_id"),
+        tag_triggers(
+            "tags_fts_rename",
+            "UPDATE OF name ON tags",
+            &linked_notes.replace("{}", "old"),
+            &linked_notes.replace("{}", "new"),
+        ),
+        tag_triggers(
+            "tags_fts_delete",
+            "DELETE ON tags",
+            &linked_notes.replace("{}", "old"),
+            &linked_notes.replace("{}", "old"),
+        ),
+    ]
+    .join("\n"))?;
+
+    if outdated || missing {
+        rebuild(conn)?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchIndexStats {
+    pub indexed_notes: usize,
+    pub total_notes: usize,
+}
+
+/// Re-index every note of both indexes from `notes_fts_source`
+pub fn rebuild(conn: &Connection) -> Result<SearchIndexStats> {
+    let tx = conn.unchecked_transaction()?;
+    for table in ["notes_fts", "notes_trigram"] {
+        tx.execute(&format!("INSERT INTO {table}({table}) VALUES('rebuild')", table = table), [])?;
+    }
+    tx.commit()?;
+    stats(conn)
+}
+
+/// Merge the index b-trees, which keeps queries fast after many small updates
+pub fn optimize(conn: &Connection) -> Result<SearchIndexStats> {
+    conn.execute("INSERT INTO notes_fts(notes_fts) VALUES('optimize')", [])?;
+    conn.execute("INSERT INTO notes_trigram(notes_trigram) VALUES('optimize')", [])?;
+    stats(conn)
+}
+
+/// Row counts of the index and the notes table; a mismatch means the index drifted
+pub fn stats(conn: &Connection) -> Result<SearchIndexStats> {
+    // Counting notes_fts itself would count the content view, so count what is indexed
+    let indexed: i64 = conn.query_row("SELECT COUNT(*) FROM notes_fts_docsize", [], |row| row.get(0))?;
+    let total: i64 = conn.query_row("SELECT COUNT(*) FROM notes", [], |row| row.get(0))?;
+    Ok(SearchIndexStats {
+        indexed_notes: indexed as usize,
+        total_notes: total as usize,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+
+    fn matching_note_ids(conn: &Connection, fts_match: &str) -> Result<Vec<String>> {
+        let mut stmt = conn.prepare(
+            "SELECT n.id FROM notes_fts f JOIN notes n ON n.internal_id = f.rowid
+             WHERE notes_fts MATCH ? ORDER BY n.id",
+        )?;
+        let ids = stmt.query_map(params![fts_match], |row| row.get(0))?;
+        ids.collect()
+    }
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (
+                internal_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                id TEXT UNIQUE NOT NULL,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                properties TEXT,
+                content_plaintext TEXT
+             );
+             CREATE TABLE tags (id TEXT PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+             CREATE TABLE note_tags (note_id TEXT NOT NULL, tag_id TEXT NOT NULL, PRIMARY KEY (note_id, tag_id));
+             INSERT INTO notes (id, title, content) VALUES ('a', 'Alpha', 'first note'), ('b', 'Beta', 'second note');
+             INSERT INTO tags (id, name) VALUES ('t1', 'gardening');",
+        )
+        .unwrap();
+        create_tables(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_tag_changes_update_index() {
+        let conn = setup();
+        assert!(matching_note_ids(&conn, "tags:gardening").unwrap().is_empty());
+
+        conn.execute("INSERT INTO note_tags VALUES ('a', 't1')", []).unwrap();
+        assert_eq!(matching_note_ids(&conn, "gardening").unwrap(), vec!["a"]);
+
+        conn.execute("UPDATE tags SET name = 'horticulture' WHERE id = 't1'", []).unwrap();
+        assert!(matching_note_ids(&conn, "gardening").unwrap().is_empty());
+        assert_eq!(matching_note_ids(&conn, "horticulture").unwrap(), vec!["a"]);
+
+        conn.execute("DELETE FROM note_tags WHERE note_id = 'a'", []).unwrap();
+        assert!(matching_note_ids(&conn, "horticulture").unwrap().is_empty());
+        assert_eq!(matching_note_ids(&conn, "first").unwrap(), vec!["a"]);
+    }
+
+    /// FTS5's own check that the index matches the content view
+    fn integrity_check(conn: &Connection) {
+        for table in ["notes_fts", "notes_trigram"] {
+            conn.execute(&format!("INSERT INTO {t}({t}, rank) VALUES('integrity-check', 1)", t = table), [])
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_indexes_store_no_copy_of_note_text() {
+        let conn = setup();
+        let tables: Vec<String> = conn
+            .prepare("SELECT name FROM sqlite_master WHERE name LIKE 'notes_%_content'")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert!(tables.is_empty(), "unexpected content tables {:?}", tables);
+
+        // Snippets read the text from notes through the view
+        let snippet: String = conn
+            .query_row(
+                "SELECT snippet(notes_fts, 1, '[', ']', '', 5) FROM notes_fts WHERE notes_fts MATCH 'second'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(snippet, "[second] note");
+        let stats = stats(&conn).unwrap();
+        assert_eq!((stats.indexed_notes, stats.total_notes), (2, 2));
+    }
+
+    #[test]
+    fn test_index_stays_consistent_through_edits_and_deletes() {
+        let conn = setup();
+        conn.execute_batch(
+            "INSERT INTO tags (id, name) VALUES ('t2', 'botany');
+             INSERT INTO note_tags VALUES ('a', 't1'), ('a', 't2'), ('b', 't2');
+             UPDATE notes SET content = 'rewritten body', properties = '{\"x\": 1}' WHERE id = 'a';
+             UPDATE tags SET name = 'plants' WHERE id = 't2';
+             INSERT OR IGNORE INTO note_tags VALUES ('b', 't2');",
+        )
+        .unwrap();
+        integrity_check(&conn);
+        assert_eq!(matching_note_ids(&conn, "plants").unwrap(), vec!["a", "b"]);
+        assert_eq!(matching_note_ids(&conn, "rewritten AND gardening").unwrap(), vec!["a"]);
+
+        conn.execute_batch("DELETE FROM tags WHERE id = 't2'; DELETE FROM note_tags WHERE tag_id = 't2';").unwrap();
+        integrity_check(&conn);
+        assert!(matching_note_ids(&conn, "plants").unwrap().is_empty());
+
+        conn.execute("DELETE FROM notes WHERE id = 'a'", []).unwrap();
+        integrity_check(&conn);
+        assert!(matching_note_ids(&conn, "rewritten").unwrap().is_empty());
+        assert_eq!(rebuild(&conn).unwrap().indexed_notes, 1);
+        integrity_check(&conn);
+    }
+
+    #[test]
+    fn test_migrates_legacy_tables() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (internal_id INTEGER PRIMARY KEY, id TEXT UNIQUE, title TEXT, content TEXT, properties TEXT, content_plaintext TEXT);
+             CREATE TABLE tags (id TEXT PRIMARY KEY, name TEXT);
+             CREATE TABLE note_tags (note_id TEXT, tag_id TEXT);
+             CREATE VIRTUAL TABLE notes_fts USING fts5(title, content, tags, properties, content='notes', content_rowid='internal_id');
+             INSERT INTO notes (id, title, content) VALUES ('a', 'Alpha', 'legacy body');
+             INSERT INTO tags VALUES ('t1', 'archive');
+             INSERT INTO note_tags VALUES ('a', 't1');",
+        )
+        .unwrap();
+
+        create_tables(&conn).unwrap();
+
+        assert_eq!(matching_note_ids(&conn, "legacy AND archive").unwrap(), vec!["a"]);
+        let stats = optimize(&conn).unwrap();
+        assert_eq!((stats.indexed_notes, stats.total_notes), (1, 1));
+
+        // Content-storing tables from before are replaced too
+        conn.execute_batch(
+            "DROP TABLE notes_vocab; DROP TABLE notes_fts; DROP TABLE notes_trigram;
+             CREATE VIRTUAL TABLE notes_fts USING fts5(title, content, tags, properties);
+             CREATE VIRTUAL TABLE notes_trigram USING fts5(title, content, tokenize = 'trigram');",
+        )
+        .unwrap();
+        create_tables(&conn).unwrap();
+        assert_eq!(matching_note_ids(&conn, "legacy AND archive").unwrap(), vec!["a"]);
+        integrity_check(&conn);
+    }
+}
@@ -0,0 +1,857 @@
+//! Search query language
+//!
+//! Parses queries such as
+//!
+//! ```text
+//! "release plan" (rust OR tokio) -draft title:roadmap folder:Work
+//! tag:project is:encrypted has:link updated:>2026-01-01 created:2026-01-01..2026-03-31
+//! ```
+//!
+//! into a [`QueryNode`] tree, and compiles that tree into an FTS5 `MATCH`
+//! expression (used for ranking and snippets) plus SQL predicates over the
+//! `notes` table aliased as `n`.
+//!
+//! Syntax summary:
+//! - `word` matches words starting with `word`; `"a phrase"` matches exactly
+//! - terms are ANDed; `OR` and parentheses group alternatives
+//! - `-term`, `-field:value` or `NOT term` negate
+//! - `a NEAR b` / `a NEAR/5 b` match terms close to each other
+//! - fields: `title:`, `content:`, `tag:`, `folder:`, `is:encrypted`, `is:daily`,
+//!   `has:link`, `has:tag`, `created:` and `updated:`
+//! - dates: `2026-01-01`, `2026-01`, `>2026-01-01`, `<=2026-01-01`,
+//!   `2026-01-01..2026-02-01`, or `today` / `yesterday` / `week` / `month`,
+//!   which are whole local calendar days (`week` and `month` are the last 7 and
+//!   30 days including today)
+
+use chrono::{Duration, Local, Months, NaiveDate};
+use serde::Serialize;
+use std::fmt;
+
+const DEFAULT_NEAR_DISTANCE: u32 = 10;
+
+/// A parse error with the character offset where it was detected
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueryParseError {
+    pub message: String,
+    pub position: usize,
+}
+
+impl fmt::Display for QueryParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid search query at position {}: {}", self.position, self.message)
+    }
+}
+
+impl std::error::Error for QueryParseError {}
+
+fn error<T>(message: impl Into<String>, position: usize) -> Result<T, QueryParseError> {
+    Err(QueryParseError { message: message.into(), position })
+}
+
+/// Full-text column a term can be scoped to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextColumn {
+    Title,
+    Content,
+}
+
+impl TextColumn {
+    pub(crate) fn fts_name(self) -> &'static str {
+        match self {
+            TextColumn::Title => "title",
+            TextColumn::Content => "content",
+        }
+    }
+}
+
+/// Half-open date range `[from, to)` as `YYYY-MM-DD HH:MM:SS` strings
+#[derive(Debug, Clone, PartialEq)]
+pub struct DateRange {
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Tag(String),
+    Folder(String),
+    IsEncrypted,
+    IsDaily,
+    HasLink,
+    HasTag,
+    Created(DateRange),
+    Updated(DateRange),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryNode {
+    /// A word, matched as a prefix
+    Term { text: String, column: Option<TextColumn> },
+    /// An exact phrase
+    Phrase { text: String, column: Option<TextColumn> },
+    /// Terms within `distance` tokens of each other
+    Near { terms: Vec<String>, distance: u32 },
+    Filter(Filter),
+    Not(Box<QueryNode>),
+    And(Vec<QueryNode>),
+    Or(Vec<QueryNode>),
+}
+
+// ---------------------------------------------------------------------------
+// Lexer
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    Minus,
+    Or,
+    And,
+    Not,
+    Near(Option<u32>),
+    Word(String),
+    Phrase(String),
+    Field { name: String, value: String, quoted: bool },
+}
+
+const FIELDS: &[&str] = &["title", "content", "tag", "folder", "is", "has", "created", "updated"];
+
+fn lex(input: &str) -> Result<Vec<(Token, usize)>, QueryParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '(' {
+            tokens.push((Token::LParen, start));
+            i += 1;
+        } else if c == ')' {
+            tokens.push((Token::RParen, start));
+            i += 1;
+        } else if c == '"' {
+            let (text, next) = read_phrase(&chars, i)?;
+            tokens.push((Token::Phrase(text), start));
+            i = next;
+        } else if c == '-' && chars.get(i + 1).is_some_and(|n| !n.is_whitespace() && *n != ')') {
+            tokens.push((Token::Minus, start));
+            i += 1;
+        } else {
+            let mut word = String::new();
+            while i < chars.len() && !chars[i].is_whitespace() && !matches!(chars[i], '(' | ')' | '"') {
+                if chars[i] == ':' && FIELDS.contains(&word.to_lowercase().as_str()) {
+                    break;
+                }
+                word.push(chars[i]);
+                i += 1;
+            }
+
+            if i < chars.len() && chars[i] == ':' {
+                // Known field prefix
+                let name = word.to_lowercase();
+                i += 1;
+                if i < chars.len() && chars[i] == '"' {
+                    let (value, next) = read_phrase(&chars, i)?;
+                    tokens.push((Token::Field { name, value, quoted: true }, start));
+                    i = next;
+                } else {
+                    let mut value = String::new();
+                    while i < chars.len() && !chars[i].is_whitespace() && !matches!(chars[i], '(' | ')' | '"') {
+                        value.push(chars[i]);
+                        i += 1;
+                    }
+                    if value.is_empty() {
+                        return error(format!("Expected a value after '{}:'", name), i);
+                    }
+                    tokens.push((Token::Field { name, value, quoted: false }, start));
+                }
+                continue;
+            }
+
+            let token = match word.as_str() {
+                "OR" => Token::Or,
+                "AND" => Token::And,
+                "NOT" => Token::Not,
+                "NEAR" => Token::Near(None),
+                w if w.starts_with("NEAR/") => match w[5..].parse::<u32>() {
+                    Ok(distance) => Token::Near(Some(distance)),
+                    Err(_) => return error(format!("Invalid NEAR distance in '{}'", w), start),
+                },
+                _ => Token::Word(word),
+            };
+            tokens.push((token, start));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Read a double-quoted string starting at `chars[start] == '"'`
+fn read_phrase(chars: &[char], start: usize) -> Result<(String, usize), QueryParseError> {
+    let mut i = start + 1;
+    let mut text = String::new();
+    while i < chars.len() && chars[i] != '"' {
+        text.push(chars[i]);
+        i += 1;
+    }
+    if i >= chars.len() {
+        return error("Unterminated quoted phrase", start);
+    }
+    Ok((text, i + 1))
+}
+
+// ---------------------------------------------------------------------------
+// Parser
+// ---------------------------------------------------------------------------
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.pos).map(|(_, p)| *p).unwrap_or(self.end)
+    }
+
+    fn next(&mut self) -> Option<(Token, usize)> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Option<QueryNode>, QueryParseError> {
+        let mut branches = Vec::new();
+        if let Some(node) = self.parse_and()? {
+            branches.push(node);
+        }
+
+        while self.peek() == Some(&Token::Or) {
+            let position = self.position();
+            self.next();
+            if branches.is_empty() {
+                return error("OR needs a term on its left", position);
+            }
+            match self.parse_and()? {
+                Some(node) => branches.push(node),
+                None => return error("OR needs a term on its right", position),
+            }
+        }
+
+        Ok(match branches.len() {
+            0 => None,
+            1 => branches.pop(),
+            _ => Some(QueryNode::Or(branches)),
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Option<QueryNode>, QueryParseError> {
+        let mut parts = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(Token::Or) | Some(Token::RParen) => break,
+                Some(Token::And) => {
+                    let position = self.position();
+                    self.next();
+                    if parts.is_empty() || matches!(self.peek(), None | Some(Token::Or) | Some(Token::RParen)) {
+                        return error("AND needs a term on both sides", position);
+                    }
+                }
+                _ => {
+                    if let Some(node) = self.parse_unary()? {
+                        parts.push(node);
+                    }
+                }
+            }
+        }
+
+        Ok(match parts.len() {
+            0 => None,
+            1 => parts.pop(),
+            _ => Some(QueryNode::And(parts)),
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<Option<QueryNode>, QueryParseError> {
+        if matches!(self.peek(), Some(Token::Minus) | Some(Token::Not)) {
+            let position = self.position();
+            self.next();
+            return match self.parse_unary()? {
+                Some(node) => Ok(Some(QueryNode::Not(Box::new(node)))),
+                None => error("Nothing to exclude after negation", position),
+            };
+        }
+        self.parse_near()
+    }
+
+    fn parse_near(&mut self) -> Result<Option<QueryNode>, QueryParseError> {
+        let first = self.parse_primary()?;
+
+        let mut near_terms = Vec::new();
+        let mut distance = DEFAULT_NEAR_DISTANCE;
+        while let Some(Token::Near(d)) = self.peek().cloned() {
+            let position = self.position();
+            self.next();
+            if near_terms.is_empty() {
+                match &first {
+                    Some(node) => near_terms.push(near_operand(node, position)?),
+                    None => return error("NEAR needs a term on its left", position),
+                }
+            }
+            match self.parse_primary()? {
+                Some(node) => near_terms.push(near_operand(&node, position)?),
+                None => return error("NEAR needs a term on its right", position),
+            }
+            if let Some(d) = d {
+                distance = d;
+            }
+        }
+
+        if near_terms.is_empty() {
+            Ok(first)
+        } else {
+            Ok(Some(QueryNode::Near { terms: near_terms, distance }))
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Option<QueryNode>, QueryParseError> {
+        let Some((token, position)) = self.next() else {
+            return Ok(None);
+        };
+
+        match token {
+            Token::LParen => {
+                let inner = self.parse_or()?;
+                match self.next() {
+                    Some((Token::RParen, _)) => Ok(inner),
+                    _ => error("Missing closing parenthesis", position),
+                }
+            }
+            Token::RParen => error("Unexpected ')'", position),
+            Token::Word(word) => {
+                // Words made only of punctuation carry nothing to search for
+                if word.chars().any(|c| c.is_alphanumeric()) {
+                    Ok(Some(QueryNode::Term { text: word, column: None }))
+                } else {
+                    Ok(None)
+                }
+            }
+            Token::Phrase(text) => {
+                if text.trim().is_empty() {
+                    Ok(None)
+                } else {
+                    Ok(Some(QueryNode::Phrase { text, column: None }))
+                }
+            }
+            Token::Field { name, value, quoted } => parse_field(&name, &value, quoted, position).map(Some),
+            Token::Or | Token::And => error("Operator without a term before it", position),
+            Token::Near(_) => error("NEAR needs a term on its left", position),
+            Token::Minus | Token::Not => error("Unexpected negation", position),
+        }
+    }
+}
+
+fn near_operand(node: &QueryNode, position: usize) -> Result<String, QueryParseError> {
+    match node {
+        QueryNode::Term { text, column: None } | QueryNode::Phrase { text, column: None } => Ok(text.clone()),
+        _ => error("NEAR only works between plain terms or phrases", position),
+    }
+}
+
+fn parse_field(name: &str, value: &str, quoted: bool, position: usize) -> Result<QueryNode, QueryParseError> {
+    match name {
+        "title" | "content" => {
+            let column = Some(if name == "title" { TextColumn::Title } else { TextColumn::Content });
+            if quoted {
+                Ok(QueryNode::Phrase { text: value.to_string(), column })
+            } else {
+                Ok(QueryNode::Term { text: value.to_string(), column })
+            }
+        }
+        "tag" => Ok(QueryNode::Filter(Filter::Tag(value.trim_start_matches('#').to_string()))),
+        "folder" => Ok(QueryNode::Filter(Filter::Folder(value.to_string()))),
+        "is" => match value.to_lowercase().as_str() {
+            "encrypted" => Ok(QueryNode::Filter(Filter::IsEncrypted)),
+            "daily" => Ok(QueryNode::Filter(Filter::IsDaily)),
+            other => error(format!("Unknown 'is:' value '{}' (expected encrypted or daily)", other), position),
+        },
+        "has" => match value.to_lowercase().as_str() {
+            "link" | "links" => Ok(QueryNode::Filter(Filter::HasLink)),
+            "tag" | "tags" => Ok(QueryNode::Filter(Filter::HasTag)),
+            other => error(format!("Unknown 'has:' value '{}' (expected link or tag)", other), position),
+        },
+        "created" => parse_date_range(value, position).map(|r| QueryNode::Filter(Filter::Created(r))),
+        "updated" => parse_date_range(value, position).map(|r| QueryNode::Filter(Filter::Updated(r))),
+        _ => error(format!("Unknown field '{}'", name), position),
+    }
+}
+
+/// Parse a date, a `YYYY-MM` month, a comparison or a `from..to` range
+pub fn parse_date_range(value: &str, position: usize) -> Result<DateRange, QueryParseError> {
+    let value = value.trim();
+
+    if let Some(range) = relative_range(value, Local::now().date_naive()) {
+        return Ok(range);
+    }
+
+    if let Some((from, to)) = value.split_once("..") {
+        let from = if from.is_empty() { None } else { Some(parse_day_span(from, position)?.0) };
+        let to = if to.is_empty() { None } else { Some(parse_day_span(to, position)?.1) };
+        if from.is_none() && to.is_none() {
+            return error("Date range needs at least one end", position);
+        }
+        return Ok(DateRange { from: from.map(format_day), to: to.map(format_day) });
+    }
+
+    let (op, date) = if let Some(rest) = value.strip_prefix(">=") {
+        (">=", rest)
+    } else if let Some(rest) = value.strip_prefix("<=") {
+        ("<=", rest)
+    } else if let Some(rest) = value.strip_prefix('>') {
+        (">", rest)
+    } else if let Some(rest) = value.strip_prefix('<') {
+        ("<", rest)
+    } else {
+        ("=", value)
+    };
+
+    let (start, end) = parse_day_span(date, position)?;
+    let (from, to) = match op {
+        ">=" => (Some(start), None),
+        ">" => (Some(end), None),
+        "<=" => (None, Some(end)),
+        "<" => (None, Some(start)),
+        _ => (Some(start), Some(end)),
+    };
+    Ok(DateRange { from: from.map(format_day), to: to.map(format_day) })
+}
+
+/// `today`, `yesterday`, `week` or `month` as spans of whole days ending with `today`
+fn relative_range(keyword: &str, today: NaiveDate) -> Option<DateRange> {
+    let (from, to) = match keyword.to_lowercase().as_str() {
+        "today" => (today, None),
+        "yesterday" => (today - Duration::days(1), Some(today)),
+        "week" => (today - Duration::days(6), None),
+        "month" => (today - Duration::days(29), None),
+        _ => return None,
+    };
+    Some(DateRange { from: Some(format_day(from)), to: to.map(format_day) })
+}
+
+/// A `YYYY-MM-DD` day or `YYYY-MM` month as a half-open `[start, end)` span of days
+fn parse_day_span(value: &str, position: usize) -> Result<(NaiveDate, NaiveDate), QueryParseError> {
+    if let Ok(day) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok((day, day + Duration::days(1)));
+    }
+    if let Ok(month) = NaiveDate::parse_from_str(&format!("{}-01", value), "%Y-%m-%d") {
+        if let Some(next) = month.checked_add_months(Months::new(1)) {
+            return Ok((month, next));
+        }
+    }
+    error(format!("Invalid date '{}' (expected YYYY-MM-DD or YYYY-MM)", value), position)
+}
+
+fn format_day(day: NaiveDate) -> String {
+    format!("{} 00:00:00", day.format("%Y-%m-%d"))
+}
+
+/// Parse a query string; `Ok(None)` means there is nothing to search for
+pub fn parse_query(input: &str) -> Result<Option<QueryNode>, QueryParseError> {
+    let tokens = lex(input)?;
+    let mut parser = Parser { tokens, pos: 0, end: input.chars().count() };
+    let node = parser.parse_or()?;
+
+    if let Some((token, position)) = parser.next() {
+        return match token {
+            Token::RParen => error("Unmatched ')'", position),
+            _ => error("Unexpected input", position),
+        };
+    }
+    Ok(node)
+}
+
+// ---------------------------------------------------------------------------
+// Compiler
+// ---------------------------------------------------------------------------
+
+/// A query ready to be embedded into SQL over `notes n`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompiledQuery {
+    /// FTS5 MATCH expression for `notes_fts`, if the query has positive text terms
+    pub fts_match: Option<String>,
+    /// SQL predicates, all to be ANDed
+    pub predicates: Vec<String>,
+    /// Bound parameters for `predicates`, in placeholder order
+    pub params: Vec<String>,
+    /// Negated text folded into `fts_match`, for callers that match with another index
+    pub excluded_fts: Option<String>,
+}
+
+impl CompiledQuery {
+    pub fn is_empty(&self) -> bool {
+        self.fts_match.is_none() && self.predicates.is_empty()
+    }
+}
+
+/// Compile a parsed query into an FTS5 expression plus SQL predicates
+pub fn compile(node: &QueryNode) -> CompiledQuery {
+    let conjuncts: Vec<&QueryNode> = match node {
+        QueryNode::And(parts) => parts.iter().collect(),
+        other => vec![other],
+    };
+
+    let mut compiled = CompiledQuery::default();
+    let mut positive = Vec::new();
+    let mut negative = Vec::new();
+    let mut rest = Vec::new();
+
+    for part in conjuncts {
+        match part {
+            p if is_text(p) => positive.push(to_fts(p)),
+            QueryNode::Not(inner) if is_text(inner) => negative.push(inner.as_ref()),
+            p => rest.push(p),
+        }
+    }
+
+    if !positive.is_empty() {
+        // FTS5 only has a binary NOT, so negated text can join the MATCH when
+        // there is something positive to subtract it from
+        let mut expr = positive.join(" AND ");
+        let mut excluded = Vec::new();
+        for neg in negative.drain(..) {
+            let neg = to_fts(neg);
+            expr = format!("({}) NOT ({})", expr, neg);
+            excluded.push(neg);
+        }
+        compiled.fts_match = Some(expr);
+        if !excluded.is_empty() {
+            compiled.excluded_fts = Some(excluded.join(" OR "));
+        }
+    }
+
+    for neg in negative {
+        let sql = format!("NOT ({})", to_sql(neg, &mut compiled.params));
+        compiled.predicates.push(sql);
+    }
+    for part in rest {
+        let sql = to_sql(part, &mut compiled.params);
+        compiled.predicates.push(sql);
+    }
+
+    compiled
+}
+
+/// True for subtrees that FTS5 can evaluate on its own
+pub(crate) fn is_text(node: &QueryNode) -> bool {
+    match node {
+        QueryNode::Term { .. } | QueryNode::Phrase { .. } | QueryNode::Near { .. } => true,
+        QueryNode::And(parts) | QueryNode::Or(parts) => parts.iter().all(is_text),
+        QueryNode::Filter(_) | QueryNode::Not(_) => false,
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+fn to_fts(node: &QueryNode) -> String {
+    match node {
+        QueryNode::Term { text, column } => {
+            let term = format!("{}*", quote(text));
+            match column {
+                Some(c) => format!("{} : {}", c.fts_name(), term),
+                None => term,
+            }
+        }
+        QueryNode::Phrase { text, column } => match column {
+            Some(c) => format!("{} : {}", c.fts_name(), quote(text)),
+            None => quote(text),
+        },
+        QueryNode::Near { terms, distance } => {
+            let terms: Vec<String> = terms.iter().map(|t| quote(t)).collect();
+            format!("NEAR({}, {})", terms.join(" "), distance)
+        }
+        QueryNode::And(parts) => format!("({})", parts.iter().map(to_fts).collect::<Vec<_>>().join(" AND ")),
+        QueryNode::Or(parts) => format!("({})", parts.iter().map(to_fts).collect::<Vec<_>>().join(" OR ")),
+        QueryNode::Filter(_) | QueryNode::Not(_) => unreachable!("not a full-text node"),
+    }
+}
+
+fn to_sql(node: &QueryNode, params: &mut Vec<String>) -> String {
+    if is_text(node) {
+        params.push(to_fts(node));
+        return "n.internal_id IN (SELECT rowid FROM notes_fts WHERE notes_fts MATCH ?)".to_string();
+    }
+
+    match node {
+        QueryNode::Filter(filter) => filter_sql(filter, params),
+        QueryNode::Not(inner) => format!("NOT ({})", to_sql(inner, params)),
+        QueryNode::And(parts) => format!(
+            "({})",
+            parts.iter().map(|p| to_sql(p, params)).collect::<Vec<_>>().join(" AND ")
+        ),
+        QueryNode::Or(parts) => format!(
+            "({})",
+            parts.iter().map(|p| to_sql(p, params)).collect::<Vec<_>>().join(" OR ")
+        ),
+        _ => unreachable!("text nodes are handled above"),
+    }
+}
+
+fn filter_sql(filter: &Filter, params: &mut Vec<String>) -> String {
+    match filter {
+        // LIKE without wildcards: the exact name, ignoring case
+        Filter::Tag(name) => {
+            params.push(escape_like(name));
+            "EXISTS (SELECT 1 FROM note_tags nt JOIN tags t ON nt.tag_id = t.id WHERE nt.note_id = n.id AND t.name LIKE ? ESCAPE '\\')".to_string()
+        }
+        Filter::Folder(name) => {
+            params.push(name.clone());
+            params.push(name.clone());
+            // The IS NOT NULL keeps `-folder:` from also dropping notes without a folder
+            "n.folder_id IS NOT NULL AND n.folder_id IN (WITH RECURSIVE sub(id) AS (
+                SELECT id FROM folders WHERE name = ? COLLATE NOCASE OR id = ?
+                UNION SELECT f.id FROM folders f JOIN sub ON f.parent_id = sub.id
+             ) SELECT id FROM sub)".to_string()
+        }
+        Filter::IsEncrypted => "n.content_encrypted IS NOT NULL".to_string(),
+        Filter::IsDaily => "n.is_daily_note = 1".to_string(),
+        Filter::HasLink => "COALESCE(n.content_plaintext, n.content) LIKE '%[[%]]%'".to_string(),
+        Filter::HasTag => "EXISTS (SELECT 1 FROM note_tags nt WHERE nt.note_id = n.id)".to_string(),
+        Filter::Created(range) => range_sql("n.created_at", range, params),
+        Filter::Updated(range) => range_sql("n.updated_at", range, params),
+    }
+}
+
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+fn range_sql(column: &str, range: &DateRange, params: &mut Vec<String>) -> String {
+    let mut clauses = Vec::new();
+    if let Some(from) = &range.from {
+        clauses.push(format!("{} >= ?", column));
+        params.push(from.clone());
+    }
+    if let Some(to) = &range.to {
+        clauses.push(format!("{} < ?", column));
+        params.push(to.clone());
+    }
+    format!("({})", clauses.join(" AND "))
+}
+
+/// FTS5 expression for indexes without the notes columns (e.g. `cards_fts`)
+///
+/// Returns `None` if the query uses filters or column scopes, which only apply to notes.
+pub fn unscoped_fts_match(node: &QueryNode) -> Option<String> {
+    fn unscoped(node: &QueryNode) -> bool {
+        match node {
+            QueryNode::Term { column, .. } | QueryNode::Phrase { column, .. } => column.is_none(),
+            QueryNode::Near { .. } => true,
+            QueryNode::Not(inner) => unscoped(inner),
+            QueryNode::And(parts) | QueryNode::Or(parts) => parts.iter().all(unscoped),
+            QueryNode::Filter(_) => false,
+        }
+    }
+
+    if !unscoped(node) {
+        return None;
+    }
+    let compiled = compile(node);
+    if compiled.predicates.is_empty() {
+        compiled.fts_match
+    } else {
+        None
+    }
+}
+
+/// Positive (not negated) words and phrases of a query, in order
+pub fn text_terms(node: &QueryNode) -> Vec<String> {
+    let mut terms = Vec::new();
+    collect_terms(node, &mut terms);
+    terms
+}
+
+fn collect_terms(node: &QueryNode, terms: &mut Vec<String>) {
+    match node {
+        QueryNode::Term { text, .. } | QueryNode::Phrase { text, .. } => terms.push(text.clone()),
+        QueryNode::Near { terms: near, .. } => terms.extend(near.iter().cloned()),
+        QueryNode::And(parts) | QueryNode::Or(parts) => parts.iter().for_each(|p| collect_terms(p, terms)),
+        QueryNode::Filter(_) | QueryNode::Not(_) => {}
+    }
+}
+
+/// Copy of `node` with word terms replaced through `replace` (phrases are kept as typed)
+pub fn map_terms<F>(node: &QueryNode, replace: &F) -> QueryNode
+where
+    F: Fn(&str) -> Option<String>,
+{
+    match node {
+        QueryNode::Term { text, column } => QueryNode::Term {
+            text: replace(text).unwrap_or_else(|| text.clone()),
+            column: *column,
+        },
+        QueryNode::Near { terms, distance } => QueryNode::Near {
+            terms: terms.iter().map(|t| replace(t).unwrap_or_else(|| t.clone())).collect(),
+            distance: *distance,
+        },
+        QueryNode::And(parts) => QueryNode::And(parts.iter().map(|p| map_terms(p, replace)).collect()),
+        QueryNode::Or(parts) => QueryNode::Or(parts.iter().map(|p| map_terms(p, replace)).collect()),
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(text: &str) -> QueryNode {
+        QueryNode::Term { text: text.to_string(), column: None }
+    }
+
+    #[test]
+    fn test_parse_boolean_operators_and_phrases() {
+        let node = parse_query("\"release plan\" (rust OR tokio) -draft").unwrap().unwrap();
+        assert_eq!(
+            node,
+            QueryNode::And(vec![
+                QueryNode::Phrase { text: "release plan".to_string(), column: None },
+                QueryNode::Or(vec![term("rust"), term("tokio")]),
+                QueryNode::Not(Box::new(term("draft"))),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_fields() {
+        let node = parse_query("title:\"road map\" is:encrypted has:link updated:>2026-01-01").unwrap().unwrap();
+        assert_eq!(
+            node,
+            QueryNode::And(vec![
+                QueryNode::Phrase { text: "road map".to_string(), column: Some(TextColumn::Title) },
+                QueryNode::Filter(Filter::IsEncrypted),
+                QueryNode::Filter(Filter::HasLink),
+                QueryNode::Filter(Filter::Updated(DateRange {
+                    from: Some("2026-01-02 00:00:00".to_string()),
+                    to: None,
+                })),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_absolute_date_range() {
+        let range = parse_date_range("2026-01-01..2026-01-31", 0).unwrap();
+        assert_eq!(range.from.as_deref(), Some("2026-01-01 00:00:00"));
+        assert_eq!(range.to.as_deref(), Some("2026-02-01 00:00:00"));
+
+        let month = parse_date_range("2026-02", 0).unwrap();
+        assert_eq!(month.to.as_deref(), Some("2026-03-01 00:00:00"));
+    }
+
+    #[test]
+    fn test_relative_dates_are_calendar_days() {
+        let today = NaiveDate::from_ymd_opt(2026, 3, 10).unwrap();
+        let range = |keyword| {
+            let r = relative_range(keyword, today).unwrap();
+            (r.from.unwrap(), r.to)
+        };
+        assert_eq!(range("today"), ("2026-03-10 00:00:00".to_string(), None));
+        assert_eq!(
+            range("Yesterday"),
+            ("2026-03-09 00:00:00".to_string(), Some("2026-03-10 00:00:00".to_string()))
+        );
+        assert_eq!(range("week"), ("2026-03-04 00:00:00".to_string(), None));
+        assert_eq!(range("month"), ("2026-02-09 00:00:00".to_string(), None));
+        assert!(relative_range("fortnight", today).is_none());
+    }
+
+    #[test]
+    fn test_tag_filter_matches_exact_name() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (id TEXT);
+             CREATE TABLE tags (id TEXT, name TEXT);
+             CREATE TABLE note_tags (note_id TEXT, tag_id TEXT);
+             INSERT INTO notes VALUES ('1'), ('2'), ('3');
+             INSERT INTO tags VALUES ('a', 'a'), ('b', 'data'), ('c', 'my_tag'), ('d', 'myxtag');
+             INSERT INTO note_tags VALUES ('1', 'a'), ('2', 'b'), ('3', 'd');",
+        )
+        .unwrap();
+        let matching = |query: &str| -> Vec<String> {
+            let compiled = compile(&parse_query(query).unwrap().unwrap());
+            let sql = format!("SELECT id FROM notes n WHERE {} ORDER BY id", compiled.predicates.join(" AND "));
+            let mut stmt = conn.prepare(&sql).unwrap();
+            let ids = stmt.query_map(rusqlite::params_from_iter(&compiled.params), |row| row.get(0)).unwrap();
+            ids.collect::<rusqlite::Result<_>>().unwrap()
+        };
+        assert_eq!(matching("tag:a"), vec!["1"]);
+        assert_eq!(matching("tag:DATA"), vec!["2"]);
+        assert!(matching("tag:my_tag").is_empty());
+        assert!(matching("tag:%").is_empty());
+    }
+
+    #[test]
+    fn test_parse_near() {
+        let node = parse_query("rust NEAR/3 async").unwrap().unwrap();
+        assert_eq!(node, QueryNode::Near { terms: vec!["rust".to_string(), "async".to_string()], distance: 3 });
+    }
+
+    #[test]
+    fn test_unknown_prefix_is_a_plain_term() {
+        assert_eq!(parse_query("10:30").unwrap(), Some(term("10:30")));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse_query("\"open").unwrap_err().position, 0);
+        assert!(parse_query("(rust").unwrap_err().message.contains("closing parenthesis"));
+        assert!(parse_query("rust)").unwrap_err().message.contains("Unmatched"));
+        assert!(parse_query("rust OR").is_err());
+        assert!(parse_query("is:pinned").unwrap_err().message.contains("is:"));
+        assert!(parse_query("created:2026-13-01").unwrap_err().message.contains("Invalid date"));
+        assert!(parse_query("tag:").is_err());
+    }
+
+    #[test]
+    fn test_compile_text_and_filters() {
+        let node = parse_query("rust -draft title:plan folder:Work -tag:old").unwrap().unwrap();
+        let compiled = compile(&node);
+
+        assert_eq!(
+            compiled.fts_match.as_deref(),
+            Some("(\"rust\"* AND title : \"plan\"*) NOT (\"draft\"*)")
+        );
+        assert_eq!(compiled.predicates.len(), 2);
+        assert!(compiled.predicates[0].starts_with("n.folder_id IS NOT NULL AND n.folder_id IN"));
+        assert!(compiled.predicates[1].starts_with("NOT (EXISTS"));
+        assert_eq!(compiled.params, vec!["Work", "Work", "old"]);
+        assert_eq!(compiled.excluded_fts.as_deref(), Some("\"draft\"*"));
+    }
+
+    #[test]
+    fn test_compile_mixed_or_uses_subquery() {
+        let node = parse_query("rust OR tag:rust").unwrap().unwrap();
+        let compiled = compile(&node);
+
+        assert!(compiled.fts_match.is_none());
+        assert_eq!(compiled.predicates.len(), 1);
+        assert!(compiled.predicates[0].contains("notes_fts MATCH ?"));
+        assert_eq!(compiled.params, vec!["\"rust\"*", "rust"]);
+        assert_eq!(unscoped_fts_match(&node), None);
+        assert_eq!(
+            unscoped_fts_match(&parse_query("rust -draft").unwrap().unwrap()).as_deref(),
+            Some("(\"rust\"*) NOT (\"draft\"*)")
+        );
+    }
+}
@@ -1,13 +1,53 @@
-use rusqlite::{params, Connection, Result, params_from_iter};
-use serde::Serialize;
+use rusqlite::{params, Connection, OptionalExtension, Result, Row, params_from_iter};
+use serde::{Deserialize, Serialize};
 use regex::Regex;
-use chrono::{Local, Datelike, Duration};
+use crate::services::{embedding_service, fuzzy_search, role_service, search_query};
+
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 200;
+/// Separator for GROUP_CONCAT'd tag names (ASCII unit separator, never typed in a tag)
+const TAG_SEPARATOR: char = '\u{1f}';
+/// Most tag and folder facet values returned, by count
+const FACET_LIMIT: usize = 50;
+/// Candidates taken from each list before fusing a hybrid search
+const HYBRID_CANDIDATES: usize = 100;
+/// Deepest a hybrid search pages, since each page re-fuses everything before it
+const HYBRID_MAX_WINDOW: usize = 1000;
+/// Reciprocal rank fusion constant (shared with unified search)
+const RRF_K: f64 = 60.0;
 
 #[derive(Serialize, Clone)]
 pub struct SearchResult {
     pub id: String,
     pub title: String,
     pub snippet: String,
+    pub updated_at: Option<String>,
+    pub folder_id: Option<String>,
+    pub folder_name: Option<String>,
+    pub tags: Vec<String>,
+    pub matched_by: MatchPath,
+    pub hit_source: HitSource,
+}
+
+/// Which index produced a search hit
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchPath {
+    /// Word match in `notes_fts` (or a filter-only query)
+    Fulltext,
+    /// Substring match in `notes_trigram`
+    Substring,
+    /// Word match after spelling correction
+    Fuzzy,
+}
+
+/// Whether a hit came from keywords, meaning (embeddings), or both
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HitSource {
+    Keyword,
+    Semantic,
+    Both,
 }
 
 #[derive(Serialize, Clone)]
@@ -16,6 +56,169 @@ pub struct SearchResultWithMetadata {
     pub role_filter_applied: bool,
     pub role_filter_type: Option<String>,
     pub global_search_active: bool,
+    /// Total number of matching notes, ignoring pagination
+    pub total_count: usize,
+    /// Offset of the next page, if there are more results
+    pub next_offset: Option<usize>,
+    /// Spelling-corrected queries ("did you mean")
+    pub suggestions: Vec<String>,
+    /// Counts over all full-text matches, for drill-down filters
+    pub facets: SearchFacets,
+}
+
+/// One facet value; `value` is what the matching query filter takes
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FacetCount {
+    pub value: String,
+    pub label: String,
+    pub count: usize,
+}
+
+/// Facet counts for a search, honouring its query and role filter
+///
+/// Tags and folders are ordered by count, months newest first. `encryption`
+/// holds the "encrypted" and "plain" counts.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SearchFacets {
+    pub tags: Vec<FacetCount>,
+    pub folders: Vec<FacetCount>,
+    pub created_months: Vec<FacetCount>,
+    pub updated_months: Vec<FacetCount>,
+    pub encryption: Vec<FacetCount>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchSort {
+    #[default]
+    Relevance,
+    Updated,
+    Created,
+    Title,
+}
+
+/// Per-column BM25 weights for `notes_fts` (title, content, tags, properties)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchWeights {
+    pub title: f64,
+    pub content: f64,
+    pub tags: f64,
+    pub properties: f64,
+}
+
+impl Default for SearchWeights {
+    fn default() -> Self {
+        Self { title: 10.0, content: 1.0, tags: 5.0, properties: 0.5 }
+    }
+}
+
+impl SearchWeights {
+    /// `bm25()` ranking expression; lower is better
+    fn bm25_expr(&self) -> String {
+        // Weights are formatted into SQL, so only finite, non-negative values are allowed
+        let w = |v: f64| if v.is_finite() && v >= 0.0 { v } else { 0.0 };
+        format!(
+            "bm25(notes_fts, {:?}, {:?}, {:?}, {:?})",
+            w(self.title), w(self.content), w(self.tags), w(self.properties)
+        )
+    }
+}
+
+/// Pagination, sorting and ranking controls for [`search_notes_with_options`]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchOptions {
+    pub limit: Option<usize>,
+    pub offset: usize,
+    pub sort: SearchSort,
+    pub weights: SearchWeights,
+    /// Skip the substring and typo-tolerant fallbacks
+    pub exact: bool,
+    /// Don't compute facet counts
+    pub skip_facets: bool,
+    /// Hybrid search blend: 0 ranks by keywords only, 1 by meaning only
+    pub semantic_weight: f64,
+}
+
+impl SearchOptions {
+    fn page_size(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+    }
+}
+
+/// Columns selected for every [`SearchResult`], given the snippet expression
+fn result_columns(snippet_expr: &str) -> String {
+    format!(
+        "n.id, n.title, {}, n.updated_at, n.folder_id, fo.name,
+         (SELECT GROUP_CONCAT(t.name, char(31)) FROM note_tags nt JOIN tags t ON nt.tag_id = t.id WHERE nt.note_id = n.id)",
+        snippet_expr
+    )
+}
+
+fn map_result_row(row: &Row) -> Result<SearchResult> {
+    let tags: Option<String> = row.get(6)?;
+    Ok(SearchResult {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        snippet: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+        updated_at: row.get(3)?,
+        folder_id: row.get(4)?,
+        folder_name: row.get(5)?,
+        tags: tags
+            .map(|t| t.split(TAG_SEPARATOR).map(|s| s.to_string()).collect())
+            .unwrap_or_default(),
+        matched_by: MatchPath::Fulltext,
+        hit_source: HitSource::Keyword,
+    })
+}
+
+/// Quote values as a comma-separated list of SQL string literals
+///
+/// Role rules are inlined because they also appear in ORDER BY, which the
+/// count query leaves out, so placeholders would shift.
+fn sql_string_list(values: &[String]) -> String {
+    values
+        .iter()
+        .map(|v| format!("'{}'", v.replace('\'', "''")))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Run one page of a result query over `notes n` (plus whatever `from_clause` joins)
+#[allow(clippy::too_many_arguments)]
+fn select_results(
+    conn: &Connection,
+    from_clause: &str,
+    snippet_expr: &str,
+    where_clauses: &[String],
+    query_params: &[String],
+    order_by: &str,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<SearchResult>> {
+    let where_clause = if where_clauses.is_empty() {
+        "1=1".to_string()
+    } else {
+        where_clauses.join(" AND ")
+    };
+    let sql = format!(
+        "SELECT {}
+         {}
+         WHERE {}
+         ORDER BY {}, n.id
+         LIMIT {} OFFSET {}",
+        result_columns(snippet_expr),
+        from_clause,
+        where_clause,
+        order_by,
+        limit,
+        offset
+    );
+
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params_from_iter(query_params.iter()), map_result_row)?;
+    rows.collect()
 }
 
 // Filter structures for advanced search
@@ -67,18 +270,6 @@ pub fn parse_search_filters(query: &str) -> (String, Vec<SearchFilter>) {
     (cleaned_query, filters)
 }
 
-fn parse_date_filter(time_filter: &str) -> Option<String> {
-    let now = Local::now();
-    let cutoff_date = match time_filter.to_lowercase().as_str() {
-        "today" => now - Duration::hours(24),
-        "yesterday" => now - Duration::hours(48),
-        "week" => now - Duration::days(7),
-        "month" => now - Duration::days(30),
-        _ => return None,
-    };
-    cutoff_date.map(|d| d.format("%Y-%m-%d %H:%M:%S").to_string())
-}
-
 /// Search notes with optional role-based filtering
 /// 
 /// # Arguments
@@ -88,106 +279,71 @@ fn parse_date_filter(time_filter: &str) -> Option<String> {
 /// * `global_search` - If true, bypass role-based filters
 /// 
 /// # Returns
-/// * `Result<SearchResultWithMetadata>` - Search results with metadata about applied filters
+/// * `Result<SearchResultWithMetadata>` - First page of results with metadata about applied filters
 pub fn search_notes(
     conn: &Connection, 
     query: &str,
     role: Option<&str>,
     global_search: bool,
 ) -> Result<SearchResultWithMetadata> {
-    // Parse filters from query
-    let (sanitized_query, filters) = parse_search_filters(query);
-    
-    // Sanitize search terms for FTS5
-    // We keep only alphanumeric characters and spaces to prevent query injection/errors.
-    let fts_query = sanitized_query.chars()
-        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
-        .collect::<String>()
-        .trim()
-        .to_string();
-
-    if fts_query.is_empty() && filters.is_empty() {
+    search_notes_with_options(conn, query, role, global_search, &SearchOptions::default())
+}
+
+/// Search notes with pagination, sort mode and BM25 column weights
+pub fn search_notes_with_options(
+    conn: &Connection,
+    query: &str,
+    role: Option<&str>,
+    global_search: bool,
+    options: &SearchOptions,
+) -> Result<SearchResultWithMetadata> {
+    search_page(conn, query, role, global_search, options, options.page_size())
+}
+
+/// [`search_notes_with_options`] with a page size that isn't held to `MAX_PAGE_SIZE`
+fn search_page(
+    conn: &Connection,
+    query: &str,
+    role: Option<&str>,
+    global_search: bool,
+    options: &SearchOptions,
+    page_size: usize,
+) -> Result<SearchResultWithMetadata> {
+    // Parse the query language into FTS5 terms plus SQL predicates
+    let parsed = search_query::parse_query(query)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    let compiled = parsed.as_ref().map(search_query::compile).unwrap_or_default();
+
+    if compiled.is_empty() {
         return Ok(SearchResultWithMetadata {
             results: Vec::new(),
             role_filter_applied: false,
             role_filter_type: None,
             global_search_active: global_search,
+            total_count: 0,
+            next_offset: None,
+            suggestions: Vec::new(),
+            facets: SearchFacets::default(),
         });
     }
 
-    // Build dynamic SQL query with filter conditions
+    let has_match = compiled.fts_match.is_some();
     let mut where_clauses: Vec<String> = Vec::new();
-    let mut query_params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
-    let mut order_by_clause = String::from("rank");
+    let mut query_params: Vec<String> = Vec::new();
 
-    // Add FTS5 match condition if we have search terms
-    if !fts_query.is_empty() {
+    if let Some(fts_match) = &compiled.fts_match {
         where_clauses.push("notes_fts MATCH ?".to_string());
-        // We search across all columns by default. 
-        // SQLite FTS5 does not support leading wildcards (*term), only trailing (term*)
-        let fts_query = format!("{}*", fts_query);
-        query_params.push(Box::new(fts_query));
-    }
-
-    // Add tag filters
-    for filter in &filters {
-        match filter {
-            SearchFilter::Tag(tag_name) => {
-                where_clauses.push(
-                    "EXISTS (SELECT 1 FROM note_tags nt JOIN tags t ON nt.tag_id = t.id WHERE t.name LIKE ? AND nt.note_id = notes.id)".to_string()
-                );
-                query_params.push(Box::new(format!("%{}%", tag_name)));
-            }
-            SearchFilter::Created(time_filter) => {
-                if let Some(date_cutoff) = parse_date_filter(time_filter) {
-                    where_clauses.push("notes.created_at >= ?".to_string());
-                    query_params.push(Box::new(date_cutoff));
-                }
-            }
-        }
+        query_params.push(fts_match.clone());
     }
+    where_clauses.extend(compiled.predicates.iter().cloned());
+    query_params.extend(compiled.params.iter().cloned());
 
     // Apply role-based filters (unless global_search is true)
-    let mut role_filter_applied = false;
-    let mut role_filter_type = None;
-    
-    if !global_search {
-        if let Some(role_str) = role {
-            match role_str.to_lowercase().as_str() {
-                "learner" => {
-                    // Exclude notes with #work tag
-                    where_clauses.push(
-                        "NOT EXISTS (SELECT 1 FROM note_tags nt JOIN tags t ON nt.tag_id = t.id WHERE t.name = 'work' AND nt.note_id = notes.id)".to_string()
-                    );
-                    role_filter_applied = true;
-                    role_filter_type = Some("learner".to_string());
-                }
-                "manager" => {
-                    // Prioritize notes with #project tag
-                    // Use CASE in ORDER BY to put project-tagged notes first
-                    order_by_clause = String::from(
-                        "CASE WHEN EXISTS (SELECT 1 FROM note_tags nt JOIN tags t ON nt.tag_id = t.id WHERE t.name = 'project' AND nt.note_id = notes.id) THEN 0 ELSE 1 END, rank"
-                    );
-                    role_filter_applied = true;
-                    role_filter_type = Some("manager".to_string());
-                }
-                "coach" => {
-                    // Coach role: prioritize notes with #coaching or #template tags
-                    order_by_clause = String::from(
-                        "CASE WHEN EXISTS (SELECT 1 FROM note_tags nt JOIN tags t ON nt.tag_id = t.id WHERE (t.name = 'coaching' OR t.name = 'template') AND nt.note_id = notes.id) THEN 0 ELSE 1 END, rank"
-                    );
-                    role_filter_applied = true;
-                    role_filter_type = Some("coach".to_string());
-                }
-                _ => {
-                    // Unknown role, no filtering
-                }
-            }
-        }
-    } else if global_search && role.is_some() {
-        // Global search is active, but we still track what role is set
-        role_filter_type = role.map(|s| s.to_string());
-    }
+    let rules = role_rules(conn, role, global_search)?;
+    where_clauses.extend(rules.where_clauses);
+    let order_by_clause = tier_order(options, has_match, rules.boost.as_deref());
+    let role_filter_applied = rules.applied;
+    let role_filter_type = rules.role_filter_type;
 
     // Combine all WHERE clauses with AND
     let where_clause = if where_clauses.is_empty() {
@@ -197,40 +353,443 @@ pub fn search_notes(
     };
 
     // Build the full query
-    let sql = format!(
-        "SELECT n.id, n.title, snippet(notes_fts, 1, '<mark>', '</mark>', '...', 10) 
-         FROM notes n 
-         JOIN notes_fts f ON n.internal_id = f.rowid 
-         WHERE {}
-         ORDER BY {} 
-         LIMIT 20",
-        where_clause,
-        order_by_clause
-    );
+    let from_clause = if has_match {
+        "FROM notes n
+         JOIN notes_fts f ON n.internal_id = f.rowid
+         LEFT JOIN folders fo ON fo.id = n.folder_id"
+    } else {
+        "FROM notes n
+         LEFT JOIN folders fo ON fo.id = n.folder_id"
+    };
+    let snippet_expr = if has_match {
+        "snippet(notes_fts, 1, '<mark>', '</mark>', '...', 10)"
+    } else {
+        "substr(COALESCE(n.content_plaintext, n.content, ''), 1, 120)"
+    };
 
-    // Execute query with parameters
-    let mut stmt = conn.prepare(&sql)?;
-    let results = stmt.query_map(params_from_iter(query_params.iter().map(|p| p.as_str())), |row| {
-        Ok(SearchResult {
-            id: row.get(0)?,
-            title: row.get(1)?,
-            snippet: row.get(2)?,
-        })
-    })?;
+    // Full-text hits rank first, then substring hits, then hits for the spelling-corrected query
+    let mut tiers = vec![ResultTier {
+        from_clause,
+        snippet_expr,
+        where_clauses: where_clauses.clone(),
+        params: query_params.clone(),
+        order_by: order_by_clause,
+        matched_by: MatchPath::Fulltext,
+    }];
+    let mut suggestions = Vec::new();
+    if let (Some(node), Some(fts_match)) = (parsed.as_ref(), compiled.fts_match.as_ref()) {
+        if !options.exact {
+            // Everything except the leading full-text MATCH: filters and role rules
+            let mut clauses = where_clauses[1..].to_vec();
+            let mut params = query_params[1..].to_vec();
+            clauses.push("n.internal_id NOT IN (SELECT rowid FROM notes_fts WHERE notes_fts MATCH ?)".to_string());
+            params.push(fts_match.clone());
+            if let Some(excluded) = &compiled.excluded_fts {
+                clauses.push("n.internal_id NOT IN (SELECT rowid FROM notes_fts WHERE notes_fts MATCH ?)".to_string());
+                params.push(excluded.clone());
+            }
+
+            let (fallback, fallback_suggestions) =
+                fallback_tiers(conn, node, query, &clauses, &params, options, rules.boost.as_deref())?;
+            tiers.extend(fallback);
+            suggestions = fallback_suggestions;
+        }
+    }
+
+    // Every match is either encrypted or plain, so the facets carry the total
+    let (counts, facets) = if options.skip_facets {
+        (tiers.iter().map(|tier| tier.count(conn)).collect::<Result<Vec<_>>>()?, SearchFacets::default())
+    } else if tiers.len() == 1 {
+        let facets = facet_counts(conn, from_clause, &where_clause, &query_params)?;
+        (vec![facets.encryption.iter().map(|c| c.count).sum()], facets)
+    } else {
+        let membership: Vec<String> = tiers
+            .iter()
+            .map(|tier| format!("n.internal_id IN (SELECT n.internal_id {} WHERE {})", tier.from_clause, tier.where_clause()))
+            .collect();
+        let params: Vec<String> = tiers.iter().flat_map(|tier| tier.params.iter().cloned()).collect();
+        let facets = facet_counts(conn, "FROM notes n", &membership.join(" OR "), &params)?;
+        (tiers.iter().map(|tier| tier.count(conn)).collect::<Result<Vec<_>>>()?, facets)
+    };
+    let total_count: usize = counts.iter().sum();
 
+    // Page through the tiers as one list
     let mut list = Vec::new();
-    for res in results {
-        list.push(res?);
+    let mut skip = options.offset;
+    for (tier, &count) in tiers.iter().zip(&counts) {
+        if list.len() == page_size {
+            break;
+        }
+        if skip >= count {
+            skip -= count;
+            continue;
+        }
+        let mut rows = select_results(
+            conn,
+            tier.from_clause,
+            tier.snippet_expr,
+            &tier.where_clauses,
+            &tier.params,
+            &tier.order_by,
+            page_size - list.len(),
+            skip,
+        )?;
+        rows.iter_mut().for_each(|r| r.matched_by = tier.matched_by);
+        list.extend(rows);
+        skip = 0;
     }
 
+    let next = options.offset + list.len();
     Ok(SearchResultWithMetadata {
         results: list,
         role_filter_applied,
         role_filter_type,
         global_search_active: global_search,
+        total_count,
+        next_offset: if next < total_count { Some(next) } else { None },
+        suggestions,
+        facets,
     })
 }
 
+/// ORDER BY for a result tier: role-boosted notes first, then the requested sort
+///
+/// Without a full-text MATCH there is no BM25 score, so relevance falls back to recency.
+fn tier_order(options: &SearchOptions, has_match: bool, boost: Option<&str>) -> String {
+    let base_order = match options.sort {
+        SearchSort::Relevance if has_match => options.weights.bm25_expr(),
+        SearchSort::Relevance | SearchSort::Updated => "n.updated_at DESC".to_string(),
+        SearchSort::Created => "n.created_at DESC".to_string(),
+        SearchSort::Title => "n.title COLLATE NOCASE ASC".to_string(),
+    };
+    match boost {
+        // Use CASE in ORDER BY to put boosted notes first
+        Some(boost) => format!("CASE WHEN {} THEN 0 ELSE 1 END, {}", boost, base_order),
+        None => base_order,
+    }
+}
+
+/// One tier of the merged result list, ranked by its own `order_by`
+struct ResultTier {
+    from_clause: &'static str,
+    snippet_expr: &'static str,
+    where_clauses: Vec<String>,
+    params: Vec<String>,
+    order_by: String,
+    matched_by: MatchPath,
+}
+
+impl ResultTier {
+    fn where_clause(&self) -> String {
+        if self.where_clauses.is_empty() {
+            "1=1".to_string()
+        } else {
+            self.where_clauses.join(" AND ")
+        }
+    }
+
+    fn count(&self, conn: &Connection) -> Result<usize> {
+        let count: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) {} WHERE {}", self.from_clause, self.where_clause()),
+            params_from_iter(self.params.iter()),
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+}
+
+/// SQL for a role's tag rules, inlined as literals
+struct RoleRules {
+    where_clauses: Vec<String>,
+    /// Condition for notes to rank first
+    boost: Option<String>,
+    applied: bool,
+    role_filter_type: Option<String>,
+}
+
+fn role_rules(conn: &Connection, role: Option<&str>, global_search: bool) -> Result<RoleRules> {
+    let mut rules = RoleRules { where_clauses: Vec::new(), boost: None, applied: false, role_filter_type: None };
+    if global_search {
+        // Global search is active, but we still track what role is set
+        rules.role_filter_type = role.map(|s| s.to_string());
+        return Ok(rules);
+    }
+
+    if let Some(role) = role.map(|r| role_service::get_role(conn, r)).transpose()?.flatten() {
+        let has_tag = |tags: &[String]| {
+            format!(
+                "EXISTS (SELECT 1 FROM note_tags nt JOIN tags t ON nt.tag_id = t.id WHERE t.name COLLATE NOCASE IN ({}) AND nt.note_id = n.id)",
+                sql_string_list(tags)
+            )
+        };
+        if !role.include_tags.is_empty() {
+            rules.where_clauses.push(has_tag(&role.include_tags));
+        }
+        if !role.exclude_tags.is_empty() {
+            rules.where_clauses.push(format!("NOT {}", has_tag(&role.exclude_tags)));
+        }
+        if !role.boost_tags.is_empty() {
+            rules.boost = Some(has_tag(&role.boost_tags));
+        }
+        rules.applied = true;
+        rules.role_filter_type = Some(role.name);
+    }
+    Ok(rules)
+}
+
+/// Query embedding plus the model that produced it, for [`search_notes_hybrid`]
+pub struct SemanticQuery<'a> {
+    pub vector: &'a [f32],
+    pub model: &'a str,
+}
+
+/// Words of a query to embed for semantic search (filters and negations left out)
+pub fn semantic_query_text(query: &str) -> String {
+    search_query::parse_query(query)
+        .ok()
+        .flatten()
+        .map(|node| search_query::text_terms(&node).join(" "))
+        .unwrap_or_default()
+}
+
+/// Keyword search fused with embedding nearest neighbours by weighted reciprocal rank fusion
+///
+/// `options.semantic_weight` sets the blend. Semantic hits still honour the
+/// query's filters, negations and the role rules. The top `offset + limit`
+/// of each list (at least `HYBRID_CANDIDATES`, at most `HYBRID_MAX_WINDOW`)
+/// are fused. Without a query vector, a zero weight, or a filter-only
+/// query this is [`search_notes_with_options`].
+pub fn search_notes_hybrid(
+    conn: &Connection,
+    query: &str,
+    role: Option<&str>,
+    global_search: bool,
+    options: &SearchOptions,
+    semantic: Option<SemanticQuery>,
+) -> Result<SearchResultWithMetadata> {
+    let weight = options.semantic_weight.clamp(0.0, 1.0);
+    let compiled = search_query::parse_query(query)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?
+        .as_ref()
+        .map(search_query::compile)
+        .unwrap_or_default();
+    let semantic = match semantic {
+        Some(semantic) if weight > 0.0 && compiled.fts_match.is_some() => semantic,
+        _ => return search_notes_with_options(conn, query, role, global_search, options),
+    };
+
+    let page_size = options.page_size();
+    let window = (options.offset + page_size).clamp(HYBRID_CANDIDATES, HYBRID_MAX_WINDOW);
+    let lexical_options = SearchOptions { offset: 0, ..options.clone() };
+    let mut lexical = search_page(conn, query, role, global_search, &lexical_options, window)?;
+    let lexical_exhausted = lexical.next_offset.is_none();
+
+    // Nearest chunks, narrowed to notes that pass the query's filters and role rules
+    let neighbours = embedding_service::semantic_search(conn, semantic.vector, semantic.model, window)?;
+    let semantic_exhausted = neighbours.len() < window;
+    let rules = role_rules(conn, role, global_search)?;
+    let mut clauses = compiled.predicates.clone();
+    let mut params = compiled.params.clone();
+    if let Some(excluded) = &compiled.excluded_fts {
+        clauses.push("n.internal_id NOT IN (SELECT rowid FROM notes_fts WHERE notes_fts MATCH ?)".to_string());
+        params.push(excluded.clone());
+    }
+    clauses.extend(rules.where_clauses);
+    let allowed: std::collections::HashSet<String> = if neighbours.is_empty() {
+        Default::default()
+    } else {
+        clauses.push(format!("n.id IN ({})", vec!["?"; neighbours.len()].join(", ")));
+        params.extend(neighbours.iter().map(|h| h.note_id.clone()));
+        let mut stmt = conn.prepare(&format!("SELECT n.id FROM notes n WHERE {}", clauses.join(" AND ")))?;
+        let ids = stmt.query_map(params_from_iter(params.iter()), |row| row.get(0))?;
+        ids.collect::<Result<_>>()?
+    };
+    let neighbours: Vec<_> = neighbours.into_iter().filter(|h| allowed.contains(&h.note_id)).collect();
+
+    // Weighted RRF over both lists
+    let mut fused: Vec<(f64, SearchResult)> = Vec::new();
+    let semantic_rank: std::collections::HashMap<&str, usize> =
+        neighbours.iter().enumerate().map(|(rank, h)| (h.note_id.as_str(), rank)).collect();
+    let lexical_ids: std::collections::HashSet<String> = lexical.results.iter().map(|r| r.id.clone()).collect();
+    for (rank, mut result) in std::mem::take(&mut lexical.results).into_iter().enumerate() {
+        let mut score = (1.0 - weight) / (RRF_K + rank as f64);
+        if let Some(sem_rank) = semantic_rank.get(result.id.as_str()) {
+            score += weight / (RRF_K + *sem_rank as f64);
+            result.hit_source = HitSource::Both;
+        }
+        fused.push((score, result));
+    }
+
+    let semantic_only: Vec<_> = neighbours.iter().enumerate().filter(|(_, h)| !lexical_ids.contains(&h.note_id)).collect();
+    if !semantic_only.is_empty() {
+        let ids: Vec<String> = semantic_only.iter().map(|(_, h)| h.note_id.clone()).collect();
+        let rows = select_results(
+            conn,
+            "FROM notes n LEFT JOIN folders fo ON fo.id = n.folder_id",
+            "NULL",
+            &[format!("n.id IN ({})", vec!["?"; ids.len()].join(", "))],
+            &ids,
+            "n.id",
+            ids.len(),
+            0,
+        )?;
+        let mut rows: std::collections::HashMap<String, SearchResult> = rows.into_iter().map(|r| (r.id.clone(), r)).collect();
+        for (rank, hit) in semantic_only {
+            if let Some(mut result) = rows.remove(&hit.note_id) {
+                result.snippet = hit.snippet.clone();
+                result.hit_source = HitSource::Semantic;
+                fused.push((weight / (RRF_K + rank as f64), result));
+            }
+        }
+    }
+
+    fused.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.id.cmp(&b.1.id)));
+    let fused_len = fused.len();
+    let semantic_only_count = fused.iter().filter(|(_, r)| r.hit_source == HitSource::Semantic).count();
+    lexical.results = fused.into_iter().skip(options.offset).take(page_size).map(|(_, r)| r).collect();
+
+    // Past the window there may be more of either list, which a deeper window would fuse
+    let next = options.offset + lexical.results.len();
+    let exhausted = next >= fused_len && lexical_exhausted && semantic_exhausted;
+    lexical.total_count += semantic_only_count;
+    lexical.next_offset = if exhausted || next >= HYBRID_MAX_WINDOW { None } else { Some(next) };
+    Ok(lexical)
+}
+
+/// Count the matches of `from_clause`/`where_clause` by tag, folder, month and encryption
+///
+/// The matching notes are materialized once and every facet is grouped
+/// from that set, so the full-text query runs a single time.
+fn facet_counts(
+    conn: &Connection,
+    from_clause: &str,
+    where_clause: &str,
+    query_params: &[String],
+) -> Result<SearchFacets> {
+    let sql = format!(
+        "WITH hits AS MATERIALIZED (
+            SELECT n.id, n.folder_id, n.created_at, n.updated_at,
+                   n.content_encrypted IS NOT NULL AS encrypted
+            {from}
+            WHERE {filter}
+         )
+         SELECT * FROM (
+            SELECT 'tag', t.name, t.name, COUNT(*) AS c
+            FROM hits h JOIN note_tags nt ON nt.note_id = h.id JOIN tags t ON t.id = nt.tag_id
+            GROUP BY t.id ORDER BY c DESC, t.name LIMIT {limit}
+         )
+         UNION ALL
+         SELECT * FROM (
+            SELECT 'folder', h.folder_id, fo.name, COUNT(*) AS c
+            FROM hits h JOIN folders fo ON fo.id = h.folder_id
+            GROUP BY h.folder_id ORDER BY c DESC, fo.name LIMIT {limit}
+         )
+         UNION ALL
+         SELECT 'created', substr(created_at, 1, 7), substr(created_at, 1, 7), COUNT(*)
+         FROM hits WHERE created_at IS NOT NULL GROUP BY 2
+         UNION ALL
+         SELECT 'updated', substr(updated_at, 1, 7), substr(updated_at, 1, 7), COUNT(*)
+         FROM hits WHERE updated_at IS NOT NULL GROUP BY 2
+         UNION ALL
+         SELECT 'encryption', CASE WHEN encrypted THEN 'encrypted' ELSE 'plain' END,
+                CASE WHEN encrypted THEN 'Encrypted' ELSE 'Plain' END, COUNT(*)
+         FROM hits GROUP BY 2",
+        from = from_clause,
+        filter = where_clause,
+        limit = FACET_LIMIT,
+    );
+
+    let mut facets = SearchFacets::default();
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params_from_iter(query_params.iter()), |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            FacetCount {
+                value: row.get(1)?,
+                label: row.get(2)?,
+                count: row.get::<_, i64>(3)? as usize,
+            },
+        ))
+    })?;
+    for row in rows {
+        let (facet, count) = row?;
+        match facet.as_str() {
+            "tag" => facets.tags.push(count),
+            "folder" => facets.folders.push(count),
+            "created" => facets.created_months.push(count),
+            "updated" => facets.updated_months.push(count),
+            _ => facets.encryption.push(count),
+        }
+    }
+    facets.created_months.sort_by(|a, b| b.value.cmp(&a.value));
+    facets.updated_months.sort_by(|a, b| b.value.cmp(&a.value));
+    Ok(facets)
+}
+
+/// Tiers for substring matches, then matches for the spelling-corrected query
+///
+/// `clauses` and `params` exclude full-text hits and carry the query's filters;
+/// `boost` is the role boost the full-text tier ranks by.
+fn fallback_tiers(
+    conn: &Connection,
+    node: &search_query::QueryNode,
+    query: &str,
+    clauses: &[String],
+    params: &[String],
+    options: &SearchOptions,
+    boost: Option<&str>,
+) -> Result<(Vec<ResultTier>, Vec<String>)> {
+    let mut tiers = Vec::new();
+
+    let substring_expr = fuzzy_search::substring_match_expr(node);
+    if let Some(expr) = &substring_expr {
+        let mut sub_clauses = vec!["notes_trigram MATCH ?".to_string()];
+        let mut sub_params = vec![expr.clone()];
+        sub_clauses.extend(clauses.iter().cloned());
+        sub_params.extend(params.iter().cloned());
+        tiers.push(ResultTier {
+            from_clause: "FROM notes n
+             JOIN notes_trigram g ON n.internal_id = g.rowid
+             LEFT JOIN folders fo ON fo.id = n.folder_id",
+            snippet_expr: "snippet(notes_trigram, 1, '<mark>', '</mark>', '...', 32)",
+            where_clauses: sub_clauses,
+            params: sub_params,
+            order_by: tier_order(options, false, boost),
+            matched_by: MatchPath::Substring,
+        });
+    }
+
+    let corrections = fuzzy_search::corrections(conn, node)?;
+    if corrections.is_empty() {
+        return Ok((tiers, Vec::new()));
+    }
+    let suggestions = vec![fuzzy_search::corrected_query_text(query, &corrections)];
+
+    let corrected = search_query::compile(&fuzzy_search::corrected_query(node, &corrections));
+    if let Some(fts_match) = corrected.fts_match {
+        let mut fuzzy_clauses = vec!["notes_fts MATCH ?".to_string()];
+        let mut fuzzy_params = vec![fts_match];
+        fuzzy_clauses.extend(clauses.iter().cloned());
+        fuzzy_params.extend(params.iter().cloned());
+        if let Some(expr) = substring_expr {
+            fuzzy_clauses.push("n.internal_id NOT IN (SELECT rowid FROM notes_trigram WHERE notes_trigram MATCH ?)".to_string());
+            fuzzy_params.push(expr);
+        }
+        tiers.push(ResultTier {
+            from_clause: "FROM notes n
+             JOIN notes_fts f ON n.internal_id = f.rowid
+             LEFT JOIN folders fo ON fo.id = n.folder_id",
+            snippet_expr: "snippet(notes_fts, 1, '<mark>', '</mark>', '...', 10)",
+            where_clauses: fuzzy_clauses,
+            params: fuzzy_params,
+            order_by: tier_order(options, true, boost),
+            matched_by: MatchPath::Fuzzy,
+        });
+    }
+
+    Ok((tiers, suggestions))
+}
+
 /// Legacy search function for backward compatibility
 /// 
 /// # Arguments
@@ -244,81 +803,329 @@ pub fn search_notes_legacy(conn: &Connection, query: &str) -> Result<Vec<SearchR
     Ok(result.results)
 }
 
-/// Get related notes based on note content keywords
-/// For MVP: Extract top 5 keywords and search with FTS5
-pub fn get_related_notes(
+/// Card role contexts that are shown as "universal"
+const SHARED_ROLE_CONTEXTS: [&str; 3] = ["general", "all", "universal"];
+
+/// A note or card hit from [`search_unified`]
+#[derive(Debug, Clone, Serialize)]
+pub struct UnifiedSearchResult {
+    pub id: String,
+    /// "note", or the card's `type_id`
+    #[serde(rename = "type")]
+    pub result_type: String,
+    pub title: String,
+    pub description: Option<String>,
+    /// Card `role_context`, or "universal" for notes and shared cards
+    pub role: String,
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Search notes and cards together and return one ranked list
+///
+/// Notes follow the role rules of [`search_notes`]. Cards are limited to the
+/// role's `card_contexts`, with cards of the role's own context first.
+/// BM25 scores from the two indexes aren't comparable, so the lists are
+/// merged by reciprocal rank.
+pub fn search_unified(
     conn: &Connection,
-    note_content: String,
-    current_note_id: String,
+    query: &str,
+    role: Option<&str>,
+    global_search: bool,
     limit: usize,
-) -> Result<Vec<SearchResult>, String> {
-    // Don't trigger search for empty notes
-    if note_content.trim().is_empty() {
-        return Ok(Vec::new());
-    }
-
-    // Extract keywords from note content
-    // Split by whitespace, filter special chars, take top 5-10
-    let keywords: Vec<String> = note_content
-        .split_whitespace()
-        .into_iter()
-        .filter(|k| {
-            // Remove special characters and keep meaningful words
-            let cleaned = k.chars()
-                .filter(|c| c.is_alphanumeric())
-                .collect::<String>();
-            !cleaned.is_empty() && cleaned.len() > 2
-        })
-        .take(5) // Take top 5 keywords for MVP
-        .map(|k| k.to_string())
-        .collect();
+) -> Result<Vec<UnifiedSearchResult>> {
+    let limit = limit.clamp(1, MAX_PAGE_SIZE);
+    let options = SearchOptions { limit: Some(limit), ..Default::default() };
+    let notes = search_notes_with_options(conn, query, role, global_search, &options)?;
 
-    if keywords.is_empty() {
-        return Ok(Vec::new());
-    }
+    let note_hits = notes.results.into_iter().map(|note| {
+        let mut metadata = serde_json::Map::new();
+        metadata.insert("tags".into(), note.tags.into());
+        metadata.insert("folder_id".into(), note.folder_id.into());
+        metadata.insert("folder_name".into(), note.folder_name.into());
+        metadata.insert("updated_at".into(), note.updated_at.into());
+        metadata.insert("matched_by".into(), serde_json::to_value(note.matched_by).unwrap_or_default());
+        UnifiedSearchResult {
+            id: note.id,
+            result_type: "note".to_string(),
+            title: note.title,
+            description: Some(note.snippet),
+            role: "universal".to_string(),
+            metadata,
+        }
+    });
 
-    // Build FTS5 query with OR condition for multiple keywords
-    let fts_query = keywords.join(" OR ");
+    let card_role = if global_search {
+        None
+    } else {
+        role.map(|r| role_service::get_role(conn, r)).transpose()?.flatten()
+    };
+    let card_hits = match search_query::parse_query(query)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?
+        .as_ref()
+        .and_then(search_query::unscoped_fts_match)
+    {
+        Some(fts_match) => search_cards_ranked(conn, &fts_match, card_role.as_ref(), limit)?,
+        None => Vec::new(),
+    };
 
-    // Exclude current note from results
-    let mut where_clauses = vec![
-        "notes_fts MATCH ?".to_string(),
-        format!("n.id != '{}'", current_note_id), // Safely exclude current note
-    ];
+    // Reciprocal rank fusion; on ties the note comes first
+    let mut merged: Vec<(f64, usize, UnifiedSearchResult)> = note_hits
+        .enumerate()
+        .map(|(rank, hit)| (1.0 / (RRF_K + rank as f64), 0, hit))
+        .chain(card_hits.into_iter().enumerate().map(|(rank, hit)| (1.0 / (RRF_K + rank as f64), 1, hit)))
+        .collect();
+    merged.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+    merged.truncate(limit);
 
-    let mut query_params: Vec<Box<dyn rusqlite::ToSql>> = vec![
-        Box::new(fts_query),
-    ];
+    Ok(merged.into_iter().map(|(_, _, hit)| hit).collect())
+}
 
-    // Build SQL query
+fn search_cards_ranked(
+    conn: &Connection,
+    fts_match: &str,
+    role: Option<&role_service::Role>,
+    limit: usize,
+) -> Result<Vec<UnifiedSearchResult>> {
+    let role_clause = match role {
+        Some(r) if !r.card_contexts.is_empty() => {
+            format!("AND c.role_context IN ({})", sql_string_list(&r.card_contexts))
+        }
+        _ => String::new(),
+    };
+    // With no role ?2 is NULL and the CASE is a no-op
+    let role = role.map(|r| r.name.as_str());
     let sql = format!(
-        "SELECT n.id, n.title, snippet(notes_fts, 1, '<mark>', '</mark>', '...', 10) as snippet
-         FROM notes n
-         JOIN notes_fts f ON n.internal_id = f.rowid
-         WHERE {}
-         ORDER BY rank
-         LIMIT {}",
-        where_clauses.join(" AND "),
-        limit,
+        "SELECT c.id, c.type_id, c.content, c.metadata, c.role_context, c.created_at,
+                snippet(cards_fts, 0, '<mark>', '</mark>', '...', 10)
+         FROM cards c
+         JOIN cards_fts f ON c.id = f.rowid
+         WHERE cards_fts MATCH ?1 {}
+         ORDER BY CASE WHEN c.role_context = ?2 THEN 0 ELSE 1 END, rank
+         LIMIT ?3",
+        role_clause
     );
 
-    // Execute query
     let mut stmt = conn.prepare(&sql)?;
-    let results = stmt.query_map(params_from_iter(query_params.iter().map(|p| p.as_str())), |row| {
-        Ok(SearchResult {
-            id: row.get(0)?,
-            title: row.get(1)?,
-            snippet: row.get(2)?,
+    let rows = stmt.query_map(params![fts_match, role, limit as i64], |row| {
+        let id: i64 = row.get(0)?;
+        let content: String = row.get(2)?;
+        let metadata: Option<String> = row.get(3)?;
+        let role_context: Option<String> = row.get(4)?;
+        let created_at: Option<String> = row.get(5)?;
+
+        let mut metadata = metadata
+            .and_then(|m| serde_json::from_str::<serde_json::Value>(&m).ok())
+            .and_then(|v| match v {
+                serde_json::Value::Object(map) => Some(map),
+                _ => None,
+            })
+            .unwrap_or_default();
+        metadata.insert("created_at".into(), created_at.into());
+
+        let role_context = role_context.unwrap_or_else(|| "general".to_string());
+        Ok(UnifiedSearchResult {
+            id: id.to_string(),
+            result_type: row.get(1)?,
+            title: card_title(&content),
+            description: row.get(6)?,
+            role: if SHARED_ROLE_CONTEXTS.contains(&role_context.as_str()) {
+                "universal".to_string()
+            } else {
+                role_context
+            },
+            metadata,
         })
     })?;
+    rows.collect()
+}
 
-    let mut list = Vec::new();
-    for res in results {
-        list.push(res?);
+/// Cards have no title, so use the first non-empty line of the content
+fn card_title(content: &str) -> String {
+    let line = content.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or("");
+    let line = line.trim_start_matches('#').trim();
+    if line.chars().count() > 80 {
+        format!("{}...", line.chars().take(80).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+/// A related-note suggestion and why it was made
+#[derive(Serialize, Clone)]
+pub struct RelatedNote {
+    pub id: String,
+    pub title: String,
+    pub snippet: String,
+    pub updated_at: Option<String>,
+    pub tags: Vec<String>,
+    pub score: f64,
+    /// Short explanations, e.g. "3 shared tags" or "Links to Roadmap"
+    pub reasons: Vec<String>,
+}
+
+/// Nearest neighbours considered for related notes
+const RELATED_CANDIDATES: usize = 50;
+/// Embedding similarity below this isn't worth suggesting on its own
+const RELATED_MIN_SIMILARITY: f32 = 0.5;
+/// Most link targets of the current note checked for shared links
+const RELATED_MAX_TARGETS: usize = 20;
+
+#[derive(Default)]
+struct RelatedSignals {
+    similarity: Option<f32>,
+    shared_tags: Vec<String>,
+    links_to_current: bool,
+    linked_from_current: bool,
+    shared_targets: Vec<String>,
+}
+
+impl RelatedSignals {
+    fn score(&self) -> f64 {
+        self.similarity.unwrap_or(0.0) as f64
+            + 0.1 * self.shared_tags.len().min(3) as f64
+            + if self.links_to_current || self.linked_from_current { 0.3 } else { 0.0 }
+            + 0.1 * self.shared_targets.len().min(3) as f64
+    }
+
+    fn reasons(&self, current_title: &str) -> Vec<String> {
+        let mut reasons = Vec::new();
+        if let Some(similarity) = self.similarity {
+            reasons.push(format!("Similar content ({:.0}%)", similarity * 100.0));
+        }
+        match self.shared_tags.as_slice() {
+            [] => {}
+            [tag] => reasons.push(format!("Shared tag #{}", tag)),
+            tags => reasons.push(format!("{} shared tags", tags.len())),
+        }
+        if self.links_to_current {
+            reasons.push(format!("Links to {}", current_title));
+        }
+        if self.linked_from_current {
+            reasons.push(format!("Linked from {}", current_title));
+        }
+        match self.shared_targets.as_slice() {
+            [] => {}
+            [target] => reasons.push(format!("Both link to {}", target)),
+            targets => reasons.push(format!("{} shared links", targets.len())),
+        }
+        reasons
+    }
+}
+
+/// Ids of notes (other than `exclude_id`) with a resolved wiki-link to `target_id`
+fn notes_linking_to(conn: &Connection, target_id: &str, exclude_id: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT source_id FROM note_links WHERE target_id = ?1 AND source_id != ?2")?;
+    let ids = stmt.query_map(params![target_id, exclude_id], |row| row.get(0))?;
+    ids.collect()
+}
+
+/// Notes related to `current_note_id` by embedding similarity, shared tags and links
+///
+/// Each suggestion carries its reasons. Similarity uses the stored chunk
+/// embeddings, so without embeddings only tags and links count.
+pub fn get_related_notes(
+    conn: &Connection,
+    current_note_id: &str,
+    limit: usize,
+) -> Result<Vec<RelatedNote>, String> {
+    related_notes(conn, current_note_id, limit).map_err(|e| e.to_string())
+}
+
+fn related_notes(conn: &Connection, current_note_id: &str, limit: usize) -> Result<Vec<RelatedNote>> {
+    let current: Option<String> = conn
+        .query_row("SELECT title FROM notes WHERE id = ?", params![current_note_id], |row| row.get(0))
+        .optional()?;
+    let Some(current_title) = current else {
+        return Ok(Vec::new());
+    };
+
+    let mut signals: std::collections::HashMap<String, RelatedSignals> = std::collections::HashMap::new();
+
+    if let Some((vector, model)) = embedding_service::note_vector(conn, current_note_id)? {
+        for hit in embedding_service::semantic_search(conn, &vector, &model, RELATED_CANDIDATES + 1)? {
+            if hit.note_id != current_note_id && hit.score >= RELATED_MIN_SIMILARITY {
+                signals.entry(hit.note_id).or_default().similarity = Some(hit.score);
+            }
+        }
+    }
+
+    {
+        let mut stmt = conn.prepare(
+            "SELECT nt2.note_id, t.name
+             FROM note_tags nt1
+             JOIN note_tags nt2 ON nt2.tag_id = nt1.tag_id AND nt2.note_id != nt1.note_id
+             JOIN tags t ON t.id = nt1.tag_id
+             WHERE nt1.note_id = ?
+             ORDER BY t.name",
+        )?;
+        let rows = stmt.query_map(params![current_note_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (note_id, tag) = row?;
+            signals.entry(note_id).or_default().shared_tags.push(tag);
+        }
+    }
+
+    // Links come from `note_links`, so they count once link resolution has seen the notes
+    for note_id in notes_linking_to(conn, current_note_id, current_note_id)? {
+        signals.entry(note_id).or_default().links_to_current = true;
+    }
+
+    let targets: Vec<(String, String)> = {
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT l.target_id, n.title FROM note_links l JOIN notes n ON n.id = l.target_id
+             WHERE l.source_id = ?1 AND l.target_id != ?1
+             ORDER BY n.title LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![current_note_id, RELATED_MAX_TARGETS as i64], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        rows.collect::<Result<_>>()?
+    };
+    for (target_id, target_title) in &targets {
+        signals.entry(target_id.clone()).or_default().linked_from_current = true;
+        for note_id in notes_linking_to(conn, target_id, current_note_id)? {
+            signals.entry(note_id).or_default().shared_targets.push(target_title.clone());
+        }
+    }
+
+    let mut ranked: Vec<(String, RelatedSignals)> = signals.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.score().total_cmp(&a.1.score()).then_with(|| a.0.cmp(&b.0)));
+    ranked.truncate(limit.clamp(1, MAX_PAGE_SIZE));
+    if ranked.is_empty() {
+        return Ok(Vec::new());
     }
 
-    Ok(list)
+    let ids: Vec<String> = ranked.iter().map(|(id, _)| id.clone()).collect();
+    let rows = select_results(
+        conn,
+        "FROM notes n LEFT JOIN folders fo ON fo.id = n.folder_id",
+        "substr(COALESCE(n.content_plaintext, n.content, ''), 1, 120)",
+        &[format!("n.id IN ({})", vec!["?"; ids.len()].join(", "))],
+        &ids,
+        "n.id",
+        ids.len(),
+        0,
+    )?;
+    let mut rows: std::collections::HashMap<String, SearchResult> = rows.into_iter().map(|r| (r.id.clone(), r)).collect();
+
+    Ok(ranked
+        .into_iter()
+        .filter_map(|(id, signals)| {
+            rows.remove(&id).map(|row| RelatedNote {
+                id: row.id,
+                title: row.title,
+                snippet: row.snippet,
+                updated_at: row.updated_at,
+                tags: row.tags,
+                score: signals.score(),
+                reasons: signals.reasons(&current_title),
+            })
+        })
+        .collect())
 }
 
 #[cfg(test)]
+#[path = "search_service_tests.rs"]
 mod tests;
@@ -1,350 +1,389 @@
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rusqlite::Connection;
-    use tempfile::tempdir;
-    use std::path::PathBuf;
-
-    fn setup_test_db() -> Connection {
-        let temp_dir = tempdir().unwrap();
-        let db_path = temp_dir.path().join("test.db");
-        let conn = Connection::open(db_path.to_str().unwrap()).unwrap();
-        
-        // Create tables
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS notes (
-                internal_id INTEGER PRIMARY KEY AUTOINCREMENT,
-                id TEXT UNIQUE NOT NULL,
-                title TEXT NOT NULL,
-                content TEXT NOT NULL,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                folder_id TEXT,
-                is_daily_note BOOLEAN DEFAULT FALSE,
-                properties TEXT,
-                word_count INTEGER DEFAULT 0,
-                reading_time INTEGER DEFAULT 0
-            )",
-            [],
-        ).unwrap();
-        
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS tags (
-                id TEXT PRIMARY KEY,
-                name TEXT NOT NULL UNIQUE,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            )",
-            [],
-        ).unwrap();
-        
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS note_tags (
-                note_id TEXT NOT NULL,
-                tag_id TEXT NOT NULL,
-                PRIMARY KEY (note_id, tag_id),
-                FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE,
-                FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
-            )",
-            [],
-        ).unwrap();
-        
-        conn.execute(
-            "CREATE VIRTUAL TABLE IF NOT EXISTS notes_fts USING fts5(
-                    title,
-                    content,
-                    tags,
-                    properties,
-                    content='notes',
-                    content_rowid='internal_id'
-                )",
-            [],
-        ).unwrap();
-        
-        // Create triggers
-        conn.execute_batch("
-            CREATE TRIGGER IF NOT EXISTS notes_ai AFTER INSERT ON notes BEGIN
-              INSERT INTO notes_fts(rowid, title, content, properties) 
-              VALUES (new.internal_id, new.title, new.content, new.properties);
-            END;
-
-            CREATE TRIGGER IF NOT EXISTS notes_ad AFTER DELETE ON notes BEGIN
-              INSERT INTO notes_fts(notes_fts, rowid, title, content, properties) 
-              VALUES('delete', old.internal_id, old.title, old.content, old.properties);
-            END;
-
-            CREATE TRIGGER IF NOT EXISTS notes_au AFTER UPDATE ON notes BEGIN
-              INSERT INTO notes_fts(notes_fts, rowid, title, content, properties) 
-              VALUES('delete', old.internal_id, old.title, old.content, old.properties);
-              INSERT INTO notes_fts(rowid, title, content, properties) 
-              VALUES (new.internal_id, new.title, new.content, new.properties);
-            END;
-        ")?;
-        
-        conn
-    }
+use super::*;
+use crate::services::{link_service, role_service, search_index};
+use rusqlite::{params, Connection};
 
-    #[test]
-    fn test_parse_search_filters_tag_only() {
-        let query = "tag:work";
-        let (search_query, filters) = parse_search_filters(query);
-        
-        assert!(search_query.is_empty(), "Search query should be empty with tag filter only");
-        assert_eq!(filters.len(), 1, "Should have 1 filter");
-        assert_eq!(filters[0], SearchFilter::Tag("work".to_string()));
-    }
+fn setup_test_db() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(
+        "CREATE TABLE notes (
+            internal_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            id TEXT UNIQUE NOT NULL,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            content_plaintext TEXT,
+            content_encrypted BLOB,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            folder_id TEXT,
+            is_daily_note BOOLEAN DEFAULT FALSE,
+            properties TEXT
+         );
+         CREATE TABLE folders (id TEXT PRIMARY KEY, name TEXT NOT NULL, parent_id TEXT);
+         CREATE TABLE tags (id TEXT PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+         CREATE TABLE note_tags (note_id TEXT NOT NULL, tag_id TEXT NOT NULL, PRIMARY KEY (note_id, tag_id));",
+    )
+    .unwrap();
+    search_index::create_tables(&conn).unwrap();
+    role_service::create_tables(&conn).unwrap();
+    conn
+}
 
-    #[test]
-    fn test_parse_search_filters_created_only() {
-        let query = "created:today";
-        let (search_query, filters) = parse_search_filters(query);
-        
-        assert!(search_query.is_empty(), "Search query should be empty with created filter only");
-        assert_eq!(filters.len(), 1, "Should have 1 filter");
-        assert_eq!(filters[0], SearchFilter::Created("today".to_string()));
-    }
+fn add_note(conn: &Connection, id: &str, title: &str, content: &str) {
+    conn.execute(
+        "INSERT INTO notes (id, title, content) VALUES (?1, ?2, ?3)",
+        params![id, title, content],
+    )
+    .unwrap();
+}
 
-    #[test]
-    fn test_parse_search_filters_multiple_filters() {
-        let query = "tag:work created:week";
-        let (search_query, filters) = parse_search_filters(query);
-        
-        assert!(search_query.is_empty(), "Search query should be empty with filters only");
-        assert_eq!(filters.len(), 2, "Should have 2 filters");
-        assert_eq!(filters[0], SearchFilter::Tag("work".to_string()));
-        assert_eq!(filters[1], SearchFilter::Created("week".to_string()));
-    }
+fn tag_note(conn: &Connection, note_id: &str, tag: &str) {
+    conn.execute("INSERT OR IGNORE INTO tags (id, name) VALUES (?1, ?1)", params![tag]).unwrap();
+    conn.execute("INSERT INTO note_tags (note_id, tag_id) VALUES (?1, ?2)", params![note_id, tag]).unwrap();
+}
 
-    #[test]
-    fn test_parse_search_filters_mixed_query_and_filters() {
-        let query = "important tag:work";
-        let (search_query, filters) = parse_search_filters(query);
-        
-        assert_eq!(search_query, "important", "Search query should contain search term");
-        assert_eq!(filters.len(), 1, "Should have 1 filter");
-        assert_eq!(filters[0], SearchFilter::Tag("work".to_string()));
-    }
+fn ids(result: &SearchResultWithMetadata) -> Vec<&str> {
+    result.results.iter().map(|r| r.id.as_str()).collect()
+}
 
-    #[test]
-    fn test_parse_search_filters_no_filters() {
-        let query = "test query without filters";
-        let (search_query, filters) = parse_search_filters(query);
-        
-        assert_eq!(search_query, "test query without filters", "Search query should be preserved");
-        assert_eq!(filters.len(), 0, "Should have 0 filters");
-    }
+#[test]
+fn test_parse_search_filters_tag_only() {
+    let query = "tag:work";
+    let (search_query, filters) = parse_search_filters(query);
 
-    #[test]
-    fn test_parse_date_filter_today() {
-        let date_str = "today";
-        let result = parse_date_filter(date_str);
-        
-        assert!(result.is_some(), "Should parse 'today' date");
-        let cutoff = result.unwrap();
-        assert!(cutoff.contains("-"), "Date should be formatted");
-    }
+    assert!(search_query.is_empty(), "Search query should be empty with tag filter only");
+    assert_eq!(filters.len(), 1, "Should have 1 filter");
+    assert_eq!(filters[0], SearchFilter::Tag("work".to_string()));
+}
 
-    #[test]
-    fn test_parse_date_filter_yesterday() {
-        let date_str = "yesterday";
-        let result = parse_date_filter(date_str);
-        
-        assert!(result.is_some(), "Should parse 'yesterday' date");
-    }
+#[test]
+fn test_parse_search_filters_created_only() {
+    let query = "created:today";
+    let (search_query, filters) = parse_search_filters(query);
 
-    #[test]
-    fn test_parse_date_filter_invalid() {
-        let date_str = "invalid";
-        let result = parse_date_filter(date_str);
-        
-        assert!(result.is_none(), "Should return None for invalid date");
-    }
+    assert!(search_query.is_empty(), "Search query should be empty with created filter only");
+    assert_eq!(filters.len(), 1, "Should have 1 filter");
+    assert_eq!(filters[0], SearchFilter::Created("today".to_string()));
+}
 
-    #[test]
-    fn test_search_notes_with_tag_filter() {
-        let conn = setup_test_db();
-        
-        // Create test notes with tags
-        conn.execute(
-            "INSERT INTO notes (id, title, content) VALUES (?1, ?2, ?3)",
-            ["note1", "Work Note", "Content 1"]
-        ).unwrap();
-        conn.execute(
-            "INSERT INTO tags (id, name) VALUES (?1, ?2)",
-            ["tag1", "work"]
-        ).unwrap();
-        conn.execute(
-            "INSERT INTO tags (id, name) VALUES (?1, ?2)",
-            ["tag2", "personal"]
-        ).unwrap();
-        conn.execute(
-            "INSERT INTO note_tags (note_id, tag_id) VALUES (?1, ?2)",
-            ["note1", "tag1"]
-        ).unwrap();
-        
-        // Note with different tag
-        conn.execute(
-            "INSERT INTO notes (id, title, content) VALUES (?1, ?2, ?3)",
-            ["note2", "Personal Note", "Content 2"]
-        ).unwrap();
-        conn.execute(
-            "INSERT INTO note_tags (note_id, tag_id) VALUES (?1, ?2)",
-            ["note2", "tag2"]
-        ).unwrap();
-        
-        // Wait for FTS trigger
-        std::thread::sleep(std::time::Duration::from_millis(100));
-        
-        // Search with tag filter (no role, global search)
-        let result = search_notes(&conn, "tag:work", None, true).unwrap();
-        assert_eq!(result.results.len(), 1, "Should find 1 note with 'work' tag");
-        assert_eq!(result.results[0].id, "note1");
-        assert!(!result.role_filter_applied, "No role filter should be applied");
-        assert!(!result.global_search_active, "Global search should be active");
-    }
+#[test]
+fn test_parse_search_filters_multiple_filters() {
+    let query = "tag:work created:week";
+    let (search_query, filters) = parse_search_filters(query);
+
+    assert!(search_query.is_empty(), "Search query should be empty with filters only");
+    assert_eq!(filters.len(), 2, "Should have 2 filters");
+    assert_eq!(filters[0], SearchFilter::Tag("work".to_string()));
+    assert_eq!(filters[1], SearchFilter::Created("week".to_string()));
+}
+
+#[test]
+fn test_parse_search_filters_mixed_query_and_filters() {
+    let query = "important tag:work";
+    let (search_query, filters) = parse_search_filters(query);
+
+    assert_eq!(search_query, "important", "Search query should contain search term");
+    assert_eq!(filters.len(), 1, "Should have 1 filter");
+    assert_eq!(filters[0], SearchFilter::Tag("work".to_string()));
+}
+
+#[test]
+fn test_parse_search_filters_no_filters() {
+    let query = "test query without filters";
+    let (search_query, filters) = parse_search_filters(query);
+
+    assert_eq!(search_query, "test query without filters", "Search query should be preserved");
+    assert_eq!(filters.len(), 0, "Should have 0 filters");
+}
+
+#[test]
+fn test_search_notes_with_tag_filter() {
+    let conn = setup_test_db();
+    add_note(&conn, "note1", "Work Note", "Content 1");
+    tag_note(&conn, "note1", "work");
+    add_note(&conn, "note2", "Personal Note", "Content 2");
+    tag_note(&conn, "note2", "personal");
+
+    let result = search_notes(&conn, "tag:work", None, true).unwrap();
+    assert_eq!(ids(&result), vec!["note1"]);
+    assert!(!result.role_filter_applied, "No role filter should be applied");
+    assert!(result.global_search_active, "Global search should be active");
+}
+
+#[test]
+fn test_search_notes_with_multiple_filters() {
+    let conn = setup_test_db();
+    add_note(&conn, "note1", "Recent Work Note", "Content 1");
+    add_note(&conn, "note2", "Old Work Note", "Content 2");
+    conn.execute("UPDATE notes SET created_at = datetime('now', '-2 days') WHERE id = 'note1'", []).unwrap();
+    conn.execute("UPDATE notes SET created_at = datetime('now', '-10 days') WHERE id = 'note2'", []).unwrap();
+    tag_note(&conn, "note1", "work");
+    tag_note(&conn, "note2", "work");
+
+    let result = search_notes(&conn, "tag:work created:week", None, true).unwrap();
+    assert_eq!(ids(&result), vec!["note1"], "Should find only the recent note with 'work' tag");
+}
+
+#[test]
+fn test_search_notes_learner_role_excludes_work() {
+    let conn = setup_test_db();
+    add_note(&conn, "note1", "Work Note", "Content 1");
+    tag_note(&conn, "note1", "work");
+    add_note(&conn, "note2", "Personal Note", "Content 2");
+
+    // Empty query with no filters returns empty
+    let result = search_notes(&conn, "", Some("learner"), false).unwrap();
+    assert!(result.results.is_empty());
+
+    let result = search_notes(&conn, "Content", Some("learner"), false).unwrap();
+    assert_eq!(ids(&result), vec!["note2"], "Should find only the note without 'work'");
+    assert!(result.role_filter_applied, "Learner role filter should be applied");
+    assert_eq!(result.role_filter_type, Some("learner".to_string()));
+}
 
-    #[test]
-    fn test_search_notes_with_multiple_filters() {
-        let conn = setup_test_db();
-        
-        // Create note with 'work' tag
-        conn.execute(
-            "INSERT INTO notes (id, title, content, created_at) VALUES (?1, ?2, ?3, datetime('now', '-2 days'))",
-            ["note1", "Recent Work Note", "Content 1"]
-        ).unwrap();
-        conn.execute(
-            "INSERT INTO tags (id, name) VALUES (?1, ?2)",
-            ["tag1", "work"]
-        ).unwrap();
-        conn.execute(
-            "INSERT INTO note_tags (note_id, tag_id) VALUES (?1, ?2)",
-            ["note1", "tag1"]
-        ).unwrap();
-        
-        // Create old note with 'work' tag
-        conn.execute(
-            "INSERT INTO notes (id, title, content, created_at) VALUES (?1, ?2, ?3, datetime('now', '-10 days'))",
-            ["note2", "Old Work Note", "Content 2"]
-        ).unwrap();
-        conn.execute(
-            "INSERT INTO tags (id, name) VALUES (?1, ?2)",
-            ["tag2", "work"]
-        ).unwrap();
-        conn.execute(
-            "INSERT INTO note_tags (note_id, tag_id) VALUES (?1, ?2)",
-            ["note2", "tag2"]
-        ).unwrap();
-        
-        // Wait for FTS trigger
-        std::thread::sleep(std::time::Duration::from_millis(100));
-        
-        // Search with tag + created filters (no role, global search)
-        let result = search_notes(&conn, "tag:work created:week", None, true).unwrap();
-        assert_eq!(result.results.len(), 1, "Should find 1 recent note with 'work' tag");
-        assert_eq!(result.results[0].id, "note1");
+#[test]
+fn test_search_notes_manager_prioritizes_project() {
+    let conn = setup_test_db();
+    add_note(&conn, "note1", "Project Note", "Important content");
+    tag_note(&conn, "note1", "project");
+    add_note(&conn, "note2", "Regular Note", "Important content");
+
+    let result = search_notes(&conn, "Important", Some("manager"), false).unwrap();
+    assert_eq!(result.results.len(), 2, "Should find both notes");
+    assert_eq!(result.results[0].id, "note1", "Project-tagged note should be first");
+    assert!(result.role_filter_applied, "Manager role filter should be applied");
+    assert_eq!(result.role_filter_type, Some("manager".to_string()));
+}
+
+#[test]
+fn test_search_notes_global_search_bypasses_role() {
+    let conn = setup_test_db();
+    add_note(&conn, "note1", "Work Note", "Content 1");
+    tag_note(&conn, "note1", "work");
+
+    let result = search_notes(&conn, "Work", Some("learner"), true).unwrap();
+    assert_eq!(result.results.len(), 1, "Should find work note with global search");
+    assert!(!result.role_filter_applied, "Role filter should NOT be applied");
+    assert!(result.global_search_active, "Global search should be active");
+    assert_eq!(result.role_filter_type, Some("learner".to_string()), "Role type should still be tracked");
+}
+
+#[test]
+fn test_fallback_hits_page_after_fulltext_hits() {
+    let conn = setup_test_db();
+    for i in 0..3 {
+        add_note(&conn, &format!("word{}", i), "Notes", "a graph of things");
+    }
+    for i in 0..2 {
+        add_note(&conn, &format!("inner{}", i), "Notes", "a long paragraph");
     }
+    tag_note(&conn, "inner0", "writing");
 
-    #[test]
-    fn test_search_notes_learner_role_excludes_work() {
-        let conn = setup_test_db();
-        
-        // Create note with 'work' tag
-        conn.execute(
-            "INSERT INTO notes (id, title, content) VALUES (?1, ?2, ?3)",
-            ["note1", "Work Note", "Content 1"]
-        ).unwrap();
-        conn.execute(
-            "INSERT INTO tags (id, name) VALUES (?1, ?2)",
-            ["tag1", "work"]
-        ).unwrap();
-        conn.execute(
-            "INSERT INTO note_tags (note_id, tag_id) VALUES (?1, ?2)",
-            ["note1", "tag1"]
-        ).unwrap();
-        
-        // Create note without 'work' tag
-        conn.execute(
-            "INSERT INTO notes (id, title, content) VALUES (?1, ?2, ?3)",
-            ["note2", "Personal Note", "Content 2"]
-        ).unwrap();
-        
-        // Wait for FTS trigger
-        std::thread::sleep(std::time::Duration::from_millis(100));
-        
-        // Search with learner role
-        let result = search_notes(&conn, "", Some("learner"), false).unwrap();
-        // Empty query with no filters returns empty
-        assert_eq!(result.results.len(), 0);
-        
-        // Now search with content
-        let result = search_notes(&conn, "Content", Some("learner"), false).unwrap();
-        assert_eq!(result.results.len(), 1, "Should find only 1 note (excluding work)");
-        assert_eq!(result.results[0].id, "note2");
-        assert!(result.role_filter_applied, "Learner role filter should be applied");
-        assert_eq!(result.role_filter_type, Some("learner".to_string()));
+    let mut seen = Vec::new();
+    let mut offset = 0;
+    loop {
+        let options = SearchOptions { limit: Some(2), offset, ..SearchOptions::default() };
+        let page = search_notes_with_options(&conn, "graph", None, true, &options).unwrap();
+        assert_eq!(page.total_count, 5, "total must not change between pages");
+        assert_eq!(page.facets.tags.iter().map(|t| t.count).sum::<usize>(), 1);
+        assert_eq!(page.facets.encryption.iter().map(|c| c.count).sum::<usize>(), 5);
+        seen.extend(page.results.iter().map(|r| (r.id.clone(), r.matched_by)));
+        match page.next_offset {
+            Some(next) => {
+                assert_eq!(next, offset + page.results.len());
+                offset = next;
+            }
+            None => break,
+        }
     }
 
-    #[test]
-    fn test_search_notes_manager_prioritizes_project() {
-        let conn = setup_test_db();
-        
-        // Create note with 'project' tag
-        conn.execute(
-            "INSERT INTO notes (id, title, content) VALUES (?1, ?2, ?3)",
-            ["note1", "Project Note", "Important content"]
-        ).unwrap();
-        conn.execute(
-            "INSERT INTO tags (id, name) VALUES (?1, ?2)",
-            ["tag1", "project"]
-        ).unwrap();
-        conn.execute(
-            "INSERT INTO note_tags (note_id, tag_id) VALUES (?1, ?2)",
-            ["note1", "tag1"]
-        ).unwrap();
-        
-        // Create note without 'project' tag
-        conn.execute(
-            "INSERT INTO notes (id, title, content) VALUES (?1, ?2, ?3)",
-            ["note2", "Regular Note", "Important content"]
-        ).unwrap();
-        
-        // Wait for FTS trigger
-        std::thread::sleep(std::time::Duration::from_millis(100));
-        
-        // Search with manager role
-        let result = search_notes(&conn, "Important", Some("manager"), false).unwrap();
-        assert_eq!(result.results.len(), 2, "Should find both notes");
-        // Project-tagged note should come first due to ordering
-        assert_eq!(result.results[0].id, "note1", "Project-tagged note should be first");
-        assert!(result.role_filter_applied, "Manager role filter should be applied");
-        assert_eq!(result.role_filter_type, Some("manager".to_string()));
+    assert_eq!(seen.len(), 5, "every hit appears exactly once: {:?}", seen);
+    let paths: Vec<MatchPath> = seen.iter().map(|(_, path)| *path).collect();
+    assert_eq!(&paths[..3], &[MatchPath::Fulltext; 3]);
+    assert_eq!(&paths[3..], &[MatchPath::Substring; 2]);
+    let mut unique: Vec<&str> = seen.iter().map(|(id, _)| id.as_str()).collect();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(unique.len(), 5);
+}
+
+#[test]
+fn test_substring_hits_follow_requested_sort() {
+    let conn = setup_test_db();
+    add_note(&conn, "oldest", "Notes", "a long paragraph");
+    add_note(&conn, "middle", "Notes", "telegraphy history");
+    add_note(&conn, "newest", "Notes", "paragraphs everywhere");
+    // Updated order is the reverse of created order
+    conn.execute_batch(
+        "UPDATE notes SET created_at = '2026-01-01', updated_at = '2026-03-01' WHERE id = 'oldest';
+         UPDATE notes SET created_at = '2026-02-01', updated_at = '2026-02-01' WHERE id = 'middle';
+         UPDATE notes SET created_at = '2026-03-01', updated_at = '2026-01-01' WHERE id = 'newest';",
+    )
+    .unwrap();
+
+    let options = SearchOptions { sort: SearchSort::Created, ..SearchOptions::default() };
+    let result = search_notes_with_options(&conn, "graph", None, true, &options).unwrap();
+    assert!(result.results.iter().all(|r| r.matched_by == MatchPath::Substring));
+    assert_eq!(ids(&result), vec!["newest", "middle", "oldest"]);
+
+    let result = search_notes_with_options(&conn, "graph", None, true, &SearchOptions::default()).unwrap();
+    assert_eq!(ids(&result), vec!["oldest", "middle", "newest"]);
+}
+
+#[test]
+fn test_fuzzy_fallback_follows_substring_hits() {
+    let conn = setup_test_db();
+    add_note(&conn, "exact", "Cluster", "running kubernetes at home");
+    add_note(&conn, "other", "Garden", "tomatoes and basil");
+
+    let result = search_notes(&conn, "kubernets", None, true).unwrap();
+    assert_eq!(ids(&result), vec!["exact"]);
+    assert_eq!(result.results[0].matched_by, MatchPath::Fuzzy);
+    assert_eq!(result.total_count, 1);
+    assert_eq!(result.next_offset, None);
+    assert_eq!(result.suggestions, vec!["kubernetes".to_string()]);
+
+    let exact = SearchOptions { exact: true, ..SearchOptions::default() };
+    let result = search_notes_with_options(&conn, "kubernets", None, true, &exact).unwrap();
+    assert!(result.results.is_empty());
+    assert_eq!(result.total_count, 0);
+}
+
+#[test]
+fn test_facets_follow_query_and_role() {
+    let conn = setup_test_db();
+    conn.execute("INSERT INTO folders (id, name) VALUES ('f1', 'Research')", []).unwrap();
+    add_note(&conn, "plain", "Rust notes", "ownership rules");
+    add_note(&conn, "secret", "Rust secrets", "");
+    add_note(&conn, "job", "Rust at work", "ownership at work");
+    add_note(&conn, "other", "Gardening", "tomatoes");
+    conn.execute_batch(
+        "UPDATE notes SET folder_id = 'f1', created_at = '2026-03-04 10:00:00' WHERE id IN ('plain', 'secret');
+         UPDATE notes SET content_encrypted = x'00', content_plaintext = 'ownership kept private' WHERE id = 'secret';",
+    )
+    .unwrap();
+    tag_note(&conn, "plain", "rust");
+    tag_note(&conn, "job", "rust");
+    tag_note(&conn, "job", "work");
+
+    let result = search_notes(&conn, "rust", None, true).unwrap();
+    let count = |facet: &[FacetCount], value: &str| facet.iter().find(|c| c.value == value).map(|c| c.count);
+    assert_eq!(result.total_count, 3);
+    assert_eq!(count(&result.facets.tags, "rust"), Some(2));
+    assert_eq!(count(&result.facets.folders, "f1"), Some(2));
+    assert_eq!(count(&result.facets.created_months, "2026-03"), Some(2));
+    assert_eq!(count(&result.facets.encryption, "encrypted"), Some(1));
+    assert_eq!(count(&result.facets.encryption, "plain"), Some(2));
+
+    // The learner role hides notes tagged "work", and their tags leave the facets too
+    let result = search_notes(&conn, "rust", Some("learner"), false).unwrap();
+    assert_eq!(result.total_count, 2);
+    assert_eq!(count(&result.facets.tags, "work"), None);
+    assert_eq!(count(&result.facets.tags, "rust"), Some(1));
+
+    let skipped = SearchOptions { skip_facets: true, ..SearchOptions::default() };
+    let result = search_notes_with_options(&conn, "rust", None, true, &skipped).unwrap();
+    assert_eq!(result.total_count, 3);
+    assert!(result.facets.tags.is_empty());
+}
+
+#[test]
+fn test_query_language_compiles_to_filters() {
+    let conn = setup_test_db();
+    conn.execute("INSERT INTO folders (id, name) VALUES ('f1', 'Work')", []).unwrap();
+    add_note(&conn, "plan", "Release plan", "ship the rust release");
+    add_note(&conn, "draft", "Release draft", "rust release draft");
+    add_note(&conn, "tokio", "Tokio notes", "async runtime");
+    add_note(&conn, "elsewhere", "Release plan", "ship the rust release");
+    conn.execute("UPDATE notes SET folder_id = 'f1' WHERE id != 'elsewhere'", []).unwrap();
+    tag_note(&conn, "tokio", "rust");
+
+    let search = |query: &str| {
+        let mut found: Vec<String> = search_notes(&conn, query, None, true)
+            .unwrap()
+            .results
+            .into_iter()
+            .map(|r| r.id)
+            .collect();
+        found.sort();
+        found
+    };
+
+    assert_eq!(search("release -draft folder:Work"), vec!["plan"]);
+    assert_eq!(search("title:tokio OR \"rust release\" folder:work"), vec!["draft", "plan", "tokio"]);
+    assert_eq!(search("tag:rust"), vec!["tokio"]);
+    assert_eq!(search("ship NEAR/3 release -folder:Work"), vec!["elsewhere"]);
+    assert!(search_notes(&conn, "rust AND (", None, true).is_err());
+}
+
+fn embed_note(conn: &Connection, id: &str, content: &str, vector: Vec<f32>) {
+    let note = embedding_service::PendingNote {
+        id: id.to_string(),
+        title: String::new(),
+        content: content.to_string(),
+        content_hash: String::new(),
+        updated_at: None,
+    };
+    let chunk = embedding_service::TextChunk { index: 0, start: 0, end: content.len(), text: content.to_string() };
+    embedding_service::store_embeddings(conn, &note, "test-model", &[(chunk, vector)]).unwrap();
+}
+
+#[test]
+fn test_hybrid_pages_past_the_page_size_cap() {
+    let conn = setup_test_db();
+    embedding_service::create_tables(&conn).unwrap();
+    for i in 0..230 {
+        let id = format!("alpha{:03}", i);
+        add_note(&conn, &id, "Notes", "alpha");
+        embed_note(&conn, &id, "alpha", vec![1.0, i as f32 / 100.0]);
     }
+    add_note(&conn, "meaning", "Notes", "nothing in common");
+    embed_note(&conn, "meaning", "nothing in common", vec![1.0, 0.0]);
 
-    #[test]
-    fn test_search_notes_global_search_bypasses_role() {
-        let conn = setup_test_db();
-        
-        // Create note with 'work' tag
-        conn.execute(
-            "INSERT INTO notes (id, title, content) VALUES (?1, ?2, ?3)",
-            ["note1", "Work Note", "Content 1"]
-        ).unwrap();
-        conn.execute(
-            "INSERT INTO tags (id, name) VALUES (?1, ?2)",
-            ["tag1", "work"]
-        ).unwrap();
-        conn.execute(
-            "INSERT INTO note_tags (note_id, tag_id) VALUES (?1, ?2)",
-            ["note1", "tag1"]
-        ).unwrap();
-        
-        // Wait for FTS trigger
-        std::thread::sleep(std::time::Duration::from_millis(100));
-        
-        // Search with learner role but global search enabled
-        let result = search_notes(&conn, "Work", Some("learner"), true).unwrap();
-        assert_eq!(result.results.len(), 1, "Should find work note with global search");
-        assert!(!result.role_filter_applied, "Role filter should NOT be applied");
-        assert!(result.global_search_active, "Global search should be active");
-        assert_eq!(result.role_filter_type, Some("learner".to_string()), "Role type should still be tracked");
+    let vector = [1.0, 0.0];
+    let mut seen = Vec::new();
+    let mut offset = 0;
+    loop {
+        let options = SearchOptions { limit: Some(100), offset, semantic_weight: 0.5, ..SearchOptions::default() };
+        let semantic = SemanticQuery { vector: &vector, model: "test-model" };
+        let page = search_notes_hybrid(&conn, "alpha", None, true, &options, Some(semantic)).unwrap();
+        assert!(!page.results.is_empty(), "page at offset {} is empty", offset);
+        seen.extend(page.results.iter().map(|r| r.id.clone()));
+        match page.next_offset {
+            Some(next) => offset = next,
+            None => break,
+        }
     }
+
+    assert_eq!(seen.len(), 231);
+    assert!(seen.contains(&"meaning".to_string()));
+    seen.sort();
+    seen.dedup();
+    assert_eq!(seen.len(), 231, "no hit is repeated across pages");
+}
+
+#[test]
+fn test_related_notes_follow_resolved_links() {
+    let conn = setup_test_db();
+    embedding_service::create_tables(&conn).unwrap();
+    link_service::create_tables(&conn).unwrap();
+    add_note(&conn, "rust", "Rust", "See [[Cargo]]");
+    add_note(&conn, "cargo", "Cargo", "Build tool");
+    add_note(&conn, "backlink", "Intro", "Start with [[rust]]");
+    add_note(&conn, "sibling", "Crates", "Published with [[Cargo]]");
+    add_note(&conn, "book", "Rust Book", "Nothing here");
+    add_note(&conn, "prefix", "Reading", "Next up: [[Rust Book]]");
+    add_note(&conn, "tagged", "Ownership", "Borrowing");
+    tag_note(&conn, "rust", "lang");
+    tag_note(&conn, "tagged", "lang");
+    link_service::resolve_links(&conn, None).unwrap();
+
+    let related = get_related_notes(&conn, "rust", 10).unwrap();
+    let reasons = |id: &str| related.iter().find(|r| r.id == id).map(|r| r.reasons.clone());
+
+    assert_eq!(reasons("backlink").unwrap(), ["Links to Rust"]);
+    assert_eq!(reasons("cargo").unwrap(), ["Linked from Rust"]);
+    assert_eq!(reasons("sibling").unwrap(), ["Both link to Cargo"]);
+    assert_eq!(reasons("tagged").unwrap(), ["Shared tag #lang"]);
+    assert!(reasons("prefix").is_none(), "a link to \"Rust Book\" is not a link to \"Rust\"");
+    assert!(reasons("book").is_none());
 }
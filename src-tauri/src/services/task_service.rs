@@ -0,0 +1,134 @@
+//! Tasks: action items, usually extracted from a note
+//!
+//! A task optionally belongs to the note it came from; deleting the note
+//! leaves its tasks in place with `note_id` still set, like other
+//! references to trashed notes.
+
+use chrono::Local;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::Serialize;
+use uuid::Uuid;
+
+pub fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tasks (
+            id TEXT PRIMARY KEY,
+            note_id TEXT,
+            text TEXT NOT NULL,
+            done BOOLEAN NOT NULL DEFAULT FALSE,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            completed_at DATETIME
+        )",
+        [],
+    )?;
+
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_tasks_note ON tasks(note_id)", [])?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Task {
+    pub id: String,
+    pub note_id: Option<String>,
+    pub text: String,
+    pub done: bool,
+    pub created_at: Option<String>,
+    pub completed_at: Option<String>,
+}
+
+fn now() -> String {
+    Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+const TASK_COLUMNS: &str = "id, note_id, text, done, created_at, completed_at";
+
+fn map_task(row: &rusqlite::Row) -> Result<Task> {
+    Ok(Task {
+        id: row.get(0)?,
+        note_id: row.get(1)?,
+        text: row.get(2)?,
+        done: row.get(3)?,
+        created_at: row.get(4)?,
+        completed_at: row.get(5)?,
+    })
+}
+
+pub fn get_task(conn: &Connection, id: &str) -> Result<Option<Task>> {
+    conn.query_row(&format!("SELECT {} FROM tasks WHERE id = ?", TASK_COLUMNS), params![id], map_task)
+        .optional()
+}
+
+/// Add a task for each non-empty line of `texts`, skipping ones the note already has open
+pub fn add_tasks(conn: &Connection, note_id: Option<&str>, texts: &[String]) -> Result<Vec<Task>> {
+    let mut added = Vec::new();
+    for text in texts.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM tasks WHERE note_id IS ? AND text = ? AND done = 0)",
+            params![note_id, text],
+            |row| row.get(0),
+        )?;
+        if exists {
+            continue;
+        }
+        let id = Uuid::new_v4().to_string();
+        conn.execute(
+            "INSERT INTO tasks (id, note_id, text, created_at) VALUES (?, ?, ?, ?)",
+            params![id, note_id, text, now()],
+        )?;
+        added.extend(get_task(conn, &id)?);
+    }
+    Ok(added)
+}
+
+/// Tasks of one note, or all tasks; open ones first, oldest first
+pub fn list_tasks(conn: &Connection, note_id: Option<&str>) -> Result<Vec<Task>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM tasks WHERE ?1 IS NULL OR note_id = ?1 ORDER BY done, created_at, rowid",
+        TASK_COLUMNS
+    ))?;
+    let tasks = stmt.query_map(params![note_id], map_task)?;
+    tasks.collect()
+}
+
+/// Mark a task done or open again; `None` if it doesn't exist
+pub fn set_done(conn: &Connection, id: &str, done: bool) -> Result<Option<Task>> {
+    let completed_at = if done { Some(now()) } else { None };
+    conn.execute(
+        "UPDATE tasks SET done = ?, completed_at = ? WHERE id = ?",
+        params![done, completed_at, id],
+    )?;
+    get_task(conn, id)
+}
+
+pub fn delete_task(conn: &Connection, id: &str) -> Result<bool> {
+    Ok(conn.execute("DELETE FROM tasks WHERE id = ?", params![id])? > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_complete_and_list_tasks() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_tables(&conn).unwrap();
+
+        let texts = vec!["Email Sam".to_string(), "  ".to_string(), "Book venue".to_string()];
+        let added = add_tasks(&conn, Some("n1"), &texts).unwrap();
+        assert_eq!(added.len(), 2);
+        // Open duplicates are skipped
+        assert!(add_tasks(&conn, Some("n1"), &["Email Sam".to_string()]).unwrap().is_empty());
+        add_tasks(&conn, None, &["Water plants".to_string()]).unwrap();
+
+        let done = set_done(&conn, &added[0].id, true).unwrap().unwrap();
+        assert!(done.done && done.completed_at.is_some());
+
+        let tasks: Vec<String> = list_tasks(&conn, Some("n1")).unwrap().into_iter().map(|t| t.text).collect();
+        assert_eq!(tasks, vec!["Book venue", "Email Sam"]);
+        assert_eq!(list_tasks(&conn, None).unwrap().len(), 3);
+
+        assert!(delete_task(&conn, &added[1].id).unwrap());
+        assert_eq!(list_tasks(&conn, Some("n1")).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,169 @@
+//! Deleted notes kept for restoring
+//!
+//! Trashing moves a note's row and its tag links out of `notes` into
+//! `trashed_notes`, so search, embeddings and links stop seeing it without
+//! every query having to filter on a deleted flag.
+
+use chrono::Local;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::Serialize;
+
+/// Columns copied between `notes` and `trashed_notes`
+const NOTE_COLUMNS: &str = "id, title, content, created_at, updated_at, folder_id, is_daily_note, properties,
+     word_count, reading_time, content_encrypted, nonce, content_plaintext";
+
+pub fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS trashed_notes (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            created_at DATETIME,
+            updated_at DATETIME,
+            folder_id TEXT,
+            is_daily_note BOOLEAN DEFAULT FALSE,
+            properties TEXT,
+            word_count INTEGER DEFAULT 0,
+            reading_time INTEGER DEFAULT 0,
+            content_encrypted BLOB,
+            nonce BLOB,
+            content_plaintext TEXT,
+            -- why the note was trashed, e.g. Merged into Roadmap
+            reason TEXT,
+            trashed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS trashed_note_tags (
+            note_id TEXT NOT NULL,
+            tag_id TEXT NOT NULL,
+            PRIMARY KEY (note_id, tag_id),
+            FOREIGN KEY (note_id) REFERENCES trashed_notes(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrashedNote {
+    pub id: String,
+    pub title: String,
+    pub reason: Option<String>,
+    pub trashed_at: Option<String>,
+}
+
+/// Move a note and its tag links to the trash; `Ok(false)` if there is no such note
+pub fn trash_note(conn: &Connection, note_id: &str, reason: Option<&str>) -> Result<bool> {
+    let tx = conn.unchecked_transaction()?;
+    let moved = move_to_trash(&tx, note_id, reason)?;
+    tx.commit()?;
+    Ok(moved)
+}
+
+/// [`trash_note`] without its own transaction, for callers already inside one
+pub fn move_to_trash(conn: &Connection, note_id: &str, reason: Option<&str>) -> Result<bool> {
+    // A note trashed, restored and trashed again replaces its old trash entry
+    conn.execute("DELETE FROM trashed_note_tags WHERE note_id = ?", params![note_id])?;
+    conn.execute("DELETE FROM trashed_notes WHERE id = ?", params![note_id])?;
+    let moved = conn.execute(
+        &format!(
+            "INSERT INTO trashed_notes ({cols}, reason, trashed_at)
+             SELECT {cols}, ?2, ?3 FROM notes WHERE id = ?1",
+            cols = NOTE_COLUMNS
+        ),
+        params![note_id, reason, Local::now().format("%Y-%m-%d %H:%M:%S").to_string()],
+    )?;
+    if moved == 0 {
+        return Ok(false);
+    }
+
+    conn.execute(
+        "INSERT INTO trashed_note_tags (note_id, tag_id) SELECT note_id, tag_id FROM note_tags WHERE note_id = ?",
+        params![note_id],
+    )?;
+    conn.execute("DELETE FROM note_tags WHERE note_id = ?", params![note_id])?;
+    // notes_ad drops the note from the search indexes, note_embeddings_ad from embeddings
+    conn.execute("DELETE FROM notes WHERE id = ?", params![note_id])?;
+    Ok(true)
+}
+
+/// Most recently trashed first
+pub fn list_trash(conn: &Connection) -> Result<Vec<TrashedNote>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, reason, trashed_at FROM trashed_notes ORDER BY trashed_at DESC, rowid DESC",
+    )?;
+    let notes = stmt.query_map([], |row| {
+        Ok(TrashedNote {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            reason: row.get(2)?,
+            trashed_at: row.get(3)?,
+        })
+    })?;
+    notes.collect()
+}
+
+/// Put a trashed note back, with the tags that still exist
+pub fn restore_note(conn: &Connection, note_id: &str) -> Result<(), String> {
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    restore_from_trash(&tx, note_id)?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+/// [`restore_note`] without its own transaction, for callers already inside one
+pub fn restore_from_trash(conn: &Connection, note_id: &str) -> Result<(), String> {
+    let trashed: Option<String> = conn
+        .query_row("SELECT id FROM trashed_notes WHERE id = ?", params![note_id], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if trashed.is_none() {
+        return Err(format!("Note {} is not in the trash", note_id));
+    }
+    let exists: bool = conn
+        .query_row("SELECT EXISTS(SELECT 1 FROM notes WHERE id = ?)", params![note_id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if exists {
+        return Err(format!("A note with id {} already exists", note_id));
+    }
+
+    conn.execute(
+        &format!(
+            "INSERT INTO notes ({cols}) SELECT {cols} FROM trashed_notes WHERE id = ?",
+            cols = NOTE_COLUMNS
+        ),
+        params![note_id],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO note_tags (note_id, tag_id)
+         SELECT note_id, tag_id FROM trashed_note_tags
+         WHERE note_id = ? AND tag_id IN (SELECT id FROM tags)",
+        params![note_id],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM trashed_note_tags WHERE note_id = ?", params![note_id])
+        .map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM trashed_notes WHERE id = ?", params![note_id])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Permanently delete everything in the trash; returns how many notes were removed
+pub fn empty_trash(conn: &Connection) -> Result<usize> {
+    conn.execute("DELETE FROM trashed_note_tags", [])?;
+    conn.execute("DELETE FROM trashed_notes", [])
+}
+
+/// Permanently delete notes trashed more than `days` days ago
+pub fn purge_older_than(conn: &Connection, days: i64) -> Result<usize> {
+    let cutoff = (Local::now() - chrono::Duration::days(days)).format("%Y-%m-%d %H:%M:%S").to_string();
+    conn.execute(
+        "DELETE FROM trashed_note_tags WHERE note_id IN (SELECT id FROM trashed_notes WHERE trashed_at < ?)",
+        params![cutoff],
+    )?;
+    conn.execute("DELETE FROM trashed_notes WHERE trashed_at < ?", params![cutoff])
+}
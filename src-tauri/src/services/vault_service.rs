@@ -0,0 +1,724 @@
+//! Full vault dump and load
+//!
+//! A vault dump is a single JSON document that captures everything a user
+//! owns, independent of the SQLite layout in `db_service::init_db`. Scripts
+//! can rely on this format; bump [`VAULT_SCHEMA_VERSION`] on any breaking change.
+//!
+//! ```json
+//! {
+//!   "schema_version": 1,
+//!   "exported_at": "2026-01-01T12:00:00Z",
+//!   "notes": [{ "id", "title", "content", "created_at", "updated_at", "folder_id",
+//!               "is_daily_note", "properties", "metadata", "word_count", "reading_time",
+//!               "content_encrypted", "nonce", "content_plaintext" }],
+//!   "folders": [{ "id", "name", "parent_id", "created_at", "updated_at" }],
+//!   "tags": [{ "id", "name", "created_at" }],
+//!   "note_tags": [{ "note_id", "tag_id" }],
+//!   "cards": [{ "id", "type_id", "content", "metadata", "role_context", "created_at" }],
+//!   "card_schedule": [{ "card_id", "state", "due_at", "interval_days", "ease_factor", "reps", "lapses" }],
+//!   "card_reviews": [{ "card_id", "reviewed_at", "rating", "interval_days", "last_interval_days",
+//!                      "ease_factor", "time_ms", "review_type" }],
+//!   "dashboard_layouts": [{ "role", "widget_order", "updated_at" }],
+//!   "roles": [{ "name", "display_name", "include_tags", "exclude_tags", "boost_tags",
+//!               "card_contexts", "default_widgets", "updated_at" }],
+//!   "saved_searches": [{ "id", "name", "query", "role", "sort", "pinned", "parent_folder_id",
+//!                        "position", "notify", "last_checked_at", "last_count", "created_at", "updated_at" }],
+//!   "settings": { "<column>": <value>, ... }
+//! }
+//! ```
+//!
+//! Encrypted notes are dumped as stored (`content_encrypted` and `nonce` are
+//! base64), so the dump is only as private as the database it came from.
+//! Loading requires an empty vault and restores every record with its original ids;
+//! the dump's roles replace the built-in ones.
+//! Saved searches come back without their notification history, so their
+//! next check records current matches instead of reporting them all.
+
+use chrono::Utc;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+
+use crate::services::{db_service, role_service, saved_search_service};
+use crate::services::role_service::Role;
+use crate::services::saved_search_service::SavedSearch;
+
+/// Version of the dump format written by this build
+pub const VAULT_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VaultDump {
+    pub schema_version: u32,
+    pub exported_at: String,
+    pub notes: Vec<VaultNote>,
+    pub folders: Vec<VaultFolder>,
+    pub tags: Vec<VaultTag>,
+    pub note_tags: Vec<VaultNoteTag>,
+    pub cards: Vec<VaultCard>,
+    #[serde(default)]
+    pub card_schedule: Vec<VaultCardSchedule>,
+    #[serde(default)]
+    pub card_reviews: Vec<VaultCardReview>,
+    #[serde(default)]
+    pub dashboard_layouts: Vec<VaultDashboardLayout>,
+    #[serde(default)]
+    pub roles: Vec<Role>,
+    #[serde(default)]
+    pub saved_searches: Vec<SavedSearch>,
+    #[serde(default)]
+    pub settings: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VaultNote {
+    pub id: String,
+    pub title: String,
+    pub content: String,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub folder_id: Option<String>,
+    #[serde(default)]
+    pub is_daily_note: bool,
+    pub properties: Option<String>,
+    pub metadata: Option<String>,
+    #[serde(default)]
+    pub word_count: i64,
+    #[serde(default)]
+    pub reading_time: i64,
+    pub content_encrypted: Option<String>,
+    pub nonce: Option<String>,
+    pub content_plaintext: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VaultFolder {
+    pub id: String,
+    pub name: String,
+    pub parent_id: Option<String>,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VaultTag {
+    pub id: String,
+    pub name: String,
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VaultNoteTag {
+    pub note_id: String,
+    pub tag_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VaultCard {
+    pub id: i64,
+    pub type_id: String,
+    pub content: String,
+    pub metadata: Option<String>,
+    pub role_context: Option<String>,
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VaultCardSchedule {
+    pub card_id: i64,
+    pub state: String,
+    pub due_at: Option<String>,
+    pub interval_days: i64,
+    pub ease_factor: f64,
+    pub reps: i64,
+    pub lapses: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VaultCardReview {
+    pub card_id: i64,
+    pub reviewed_at: String,
+    pub rating: i64,
+    pub interval_days: i64,
+    pub last_interval_days: i64,
+    pub ease_factor: f64,
+    pub time_ms: i64,
+    pub review_type: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VaultDashboardLayout {
+    pub role: String,
+    pub widget_order: Vec<String>,
+    pub updated_at: Option<String>,
+}
+
+/// Counts of restored records
+#[derive(Debug, Serialize)]
+pub struct VaultLoadReport {
+    pub notes: usize,
+    pub folders: usize,
+    pub tags: usize,
+    pub cards: usize,
+}
+
+fn table_exists(conn: &Connection, table: &str) -> bool {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+        [table],
+        |row| row.get(0),
+    )
+    .unwrap_or(false)
+}
+
+/// Read the whole vault into a [`VaultDump`]
+pub fn dump_vault(conn: &Connection) -> Result<VaultDump, String> {
+    let has_metadata = db_service::table_has_column(conn, "notes", "metadata");
+    let notes_sql = format!(
+        "SELECT id, title, content, created_at, updated_at, folder_id, is_daily_note, properties, {},
+                word_count, reading_time, content_encrypted, nonce, content_plaintext
+         FROM notes ORDER BY internal_id",
+        if has_metadata { "metadata" } else { "NULL" }
+    );
+    let notes = query_all(conn, &notes_sql, |row| {
+        Ok(VaultNote {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            content: row.get(2)?,
+            created_at: row.get(3)?,
+            updated_at: row.get(4)?,
+            folder_id: row.get(5)?,
+            is_daily_note: row.get::<_, Option<bool>>(6)?.unwrap_or(false),
+            properties: row.get(7)?,
+            metadata: row.get(8)?,
+            word_count: row.get::<_, Option<i64>>(9)?.unwrap_or(0),
+            reading_time: row.get::<_, Option<i64>>(10)?.unwrap_or(0),
+            content_encrypted: row.get(11)?,
+            nonce: row.get(12)?,
+            content_plaintext: row.get(13)?,
+        })
+    })?;
+
+    let folders = query_all(
+        conn,
+        "SELECT id, name, parent_id, created_at, updated_at FROM folders ORDER BY rowid",
+        |row| {
+            Ok(VaultFolder {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                parent_id: row.get(2)?,
+                created_at: row.get(3)?,
+                updated_at: row.get(4)?,
+            })
+        },
+    )?;
+
+    let tags = query_all(conn, "SELECT id, name, created_at FROM tags ORDER BY rowid", |row| {
+        Ok(VaultTag {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            created_at: row.get(2)?,
+        })
+    })?;
+
+    let note_tags = query_all(conn, "SELECT note_id, tag_id FROM note_tags ORDER BY note_id, tag_id", |row| {
+        Ok(VaultNoteTag {
+            note_id: row.get(0)?,
+            tag_id: row.get(1)?,
+        })
+    })?;
+
+    let cards = query_all(
+        conn,
+        "SELECT id, type_id, content, metadata, role_context, created_at FROM cards ORDER BY id",
+        |row| {
+            Ok(VaultCard {
+                id: row.get(0)?,
+                type_id: row.get(1)?,
+                content: row.get(2)?,
+                metadata: row.get(3)?,
+                role_context: row.get(4)?,
+                created_at: row.get(5)?,
+            })
+        },
+    )?;
+
+    let card_schedule = query_all(
+        conn,
+        "SELECT card_id, state, due_at, interval_days, ease_factor, reps, lapses FROM card_schedule ORDER BY card_id",
+        |row| {
+            Ok(VaultCardSchedule {
+                card_id: row.get(0)?,
+                state: row.get(1)?,
+                due_at: row.get(2)?,
+                interval_days: row.get(3)?,
+                ease_factor: row.get(4)?,
+                reps: row.get(5)?,
+                lapses: row.get(6)?,
+            })
+        },
+    )?;
+
+    let card_reviews = query_all(
+        conn,
+        "SELECT card_id, reviewed_at, rating, interval_days, last_interval_days, ease_factor, time_ms, review_type
+         FROM card_reviews ORDER BY id",
+        |row| {
+            Ok(VaultCardReview {
+                card_id: row.get(0)?,
+                reviewed_at: row.get(1)?,
+                rating: row.get(2)?,
+                interval_days: row.get(3)?,
+                last_interval_days: row.get(4)?,
+                ease_factor: row.get(5)?,
+                time_ms: row.get(6)?,
+                review_type: row.get(7)?,
+            })
+        },
+    )?;
+
+    let dashboard_layouts = if table_exists(conn, "role_dashboard_layouts") {
+        query_all(
+            conn,
+            "SELECT role, widget_order, updated_at FROM role_dashboard_layouts ORDER BY role",
+            |row| {
+                let widget_order: String = row.get(1)?;
+                Ok(VaultDashboardLayout {
+                    role: row.get(0)?,
+                    widget_order: serde_json::from_str(&widget_order).unwrap_or_default(),
+                    updated_at: row.get(2)?,
+                })
+            },
+        )?
+    } else {
+        Vec::new()
+    };
+
+    let roles = if table_exists(conn, "roles") {
+        role_service::list_roles(conn).map_err(|e| e.to_string())?
+    } else {
+        Vec::new()
+    };
+
+    let saved_searches = if table_exists(conn, "saved_searches") {
+        saved_search_service::list_saved_searches(conn).map_err(|e| e.to_string())?
+    } else {
+        Vec::new()
+    };
+
+    Ok(VaultDump {
+        schema_version: VAULT_SCHEMA_VERSION,
+        exported_at: Utc::now().to_rfc3339(),
+        notes,
+        folders,
+        tags,
+        note_tags,
+        cards,
+        card_schedule,
+        card_reviews,
+        dashboard_layouts,
+        roles,
+        saved_searches,
+        settings: dump_settings(conn)?,
+    })
+}
+
+/// Restore a [`VaultDump`] into an empty vault
+pub fn load_vault(conn: &Connection, dump: &VaultDump) -> Result<VaultLoadReport, String> {
+    if dump.schema_version > VAULT_SCHEMA_VERSION {
+        return Err(format!(
+            "Vault dump uses schema version {}, but this version only understands up to {}",
+            dump.schema_version, VAULT_SCHEMA_VERSION
+        ));
+    }
+
+    if !vault_is_empty(conn)? {
+        return Err("Vault is not empty; load a dump only into a new vault".to_string());
+    }
+
+    let has_metadata = db_service::table_has_column(conn, "notes", "metadata");
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+
+    for folder in &dump.folders {
+        tx.execute(
+            "INSERT INTO folders (id, name, parent_id, created_at, updated_at)
+             VALUES (?1, ?2, ?3, COALESCE(?4, CURRENT_TIMESTAMP), COALESCE(?5, CURRENT_TIMESTAMP))",
+            params![folder.id, folder.name, folder.parent_id, folder.created_at, folder.updated_at],
+        )
+        .map_err(|e| format!("Failed to restore folder {}: {}", folder.id, e))?;
+    }
+
+    for note in &dump.notes {
+        tx.execute(
+            "INSERT INTO notes (id, title, content, created_at, updated_at, folder_id, is_daily_note, properties,
+                                word_count, reading_time, content_encrypted, nonce, content_plaintext)
+             VALUES (?1, ?2, ?3, COALESCE(?4, CURRENT_TIMESTAMP), COALESCE(?5, CURRENT_TIMESTAMP),
+                     ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                note.id,
+                note.title,
+                note.content,
+                note.created_at,
+                note.updated_at,
+                note.folder_id,
+                note.is_daily_note,
+                note.properties,
+                note.word_count,
+                note.reading_time,
+                note.content_encrypted,
+                note.nonce,
+                note.content_plaintext,
+            ],
+        )
+        .map_err(|e| format!("Failed to restore note {}: {}", note.id, e))?;
+
+        if has_metadata {
+            tx.execute(
+                "UPDATE notes SET metadata = ?1 WHERE id = ?2",
+                params![note.metadata.as_deref().unwrap_or("{}"), note.id],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+
+    for tag in &dump.tags {
+        tx.execute(
+            "INSERT INTO tags (id, name, created_at) VALUES (?1, ?2, COALESCE(?3, CURRENT_TIMESTAMP))",
+            params![tag.id, tag.name, tag.created_at],
+        )
+        .map_err(|e| format!("Failed to restore tag {}: {}", tag.name, e))?;
+    }
+
+    for link in &dump.note_tags {
+        tx.execute(
+            "INSERT OR IGNORE INTO note_tags (note_id, tag_id) VALUES (?1, ?2)",
+            params![link.note_id, link.tag_id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    for card in &dump.cards {
+        tx.execute(
+            "INSERT INTO cards (id, type_id, content, metadata, role_context, created_at)
+             VALUES (?1, ?2, ?3, COALESCE(?4, '{}'), COALESCE(?5, 'general'), COALESCE(?6, datetime('now')))",
+            params![card.id, card.type_id, card.content, card.metadata, card.role_context, card.created_at],
+        )
+        .map_err(|e| format!("Failed to restore card {}: {}", card.id, e))?;
+    }
+
+    for s in &dump.card_schedule {
+        tx.execute(
+            "INSERT INTO card_schedule (card_id, state, due_at, interval_days, ease_factor, reps, lapses)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![s.card_id, s.state, s.due_at, s.interval_days, s.ease_factor, s.reps, s.lapses],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    for r in &dump.card_reviews {
+        tx.execute(
+            "INSERT INTO card_reviews (card_id, reviewed_at, rating, interval_days, last_interval_days, ease_factor, time_ms, review_type)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                r.card_id,
+                r.reviewed_at,
+                r.rating,
+                r.interval_days,
+                r.last_interval_days,
+                r.ease_factor,
+                r.time_ms,
+                r.review_type,
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    if !dump.dashboard_layouts.is_empty() && table_exists(&tx, "role_dashboard_layouts") {
+        for layout in &dump.dashboard_layouts {
+            let widget_order = serde_json::to_string(&layout.widget_order).map_err(|e| e.to_string())?;
+            tx.execute(
+                "INSERT OR REPLACE INTO role_dashboard_layouts (role, widget_order, updated_at)
+                 VALUES (?1, ?2, COALESCE(?3, CURRENT_TIMESTAMP))",
+                params![layout.role, widget_order, layout.updated_at],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+
+    // An exact restore: built-in roles the source vault deleted stay deleted. A dump
+    // without roles keeps the built-in ones so the vault still has some.
+    if table_exists(&tx, "roles") && !dump.roles.is_empty() {
+        tx.execute("DELETE FROM roles", []).map_err(|e| e.to_string())?;
+        for role in &dump.roles {
+            let saved = role_service::save_role(&tx, role)?;
+            tx.execute(
+                "UPDATE roles SET updated_at = COALESCE(?1, updated_at) WHERE name = ?2",
+                params![role.updated_at, saved.name],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+
+    if table_exists(&tx, "saved_searches") {
+        for search in &dump.saved_searches {
+            tx.execute(
+                "INSERT INTO saved_searches (id, name, query, role, sort, pinned, parent_folder_id, position,
+                                             notify, last_count, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10,
+                         COALESCE(?11, CURRENT_TIMESTAMP), COALESCE(?12, CURRENT_TIMESTAMP))",
+                params![
+                    search.id,
+                    search.name,
+                    search.query,
+                    search.role,
+                    saved_search_service::sort_to_str(search.sort),
+                    search.pinned,
+                    search.parent_folder_id,
+                    search.position,
+                    search.notify,
+                    search.last_count,
+                    search.created_at,
+                    search.updated_at,
+                ],
+            )
+            .map_err(|e| format!("Failed to restore saved search {}: {}", search.name, e))?;
+        }
+    }
+
+    load_settings(&tx, &dump.settings)?;
+
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(VaultLoadReport {
+        notes: dump.notes.len(),
+        folders: dump.folders.len(),
+        tags: dump.tags.len(),
+        cards: dump.cards.len(),
+    })
+}
+
+/// Write a vault dump to `path` as pretty-printed JSON
+pub fn export_vault_json(conn: &Connection, path: &Path) -> Result<(), String> {
+    let dump = dump_vault(conn)?;
+    let json = serde_json::to_string_pretty(&dump).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+/// Read a vault dump from `path` and restore it into an empty vault
+pub fn import_vault_json(conn: &Connection, path: &Path) -> Result<VaultLoadReport, String> {
+    let json = fs::read_to_string(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    let dump: VaultDump = serde_json::from_str(&json).map_err(|e| format!("Invalid vault dump: {}", e))?;
+    load_vault(conn, &dump)
+}
+
+fn query_all<T, F>(conn: &Connection, sql: &str, map: F) -> Result<Vec<T>, String>
+where
+    F: FnMut(&rusqlite::Row<'_>) -> rusqlite::Result<T>,
+{
+    let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], map).map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+}
+
+/// The settings row is dumped column by column, so new settings columns travel automatically
+/// Whether the vault holds nothing a load could collide with or overwrite: no records,
+/// and settings as a new vault has them
+fn vault_is_empty(conn: &Connection) -> Result<bool, String> {
+    let mut counts = vec![
+        "SELECT COUNT(*) FROM notes",
+        "SELECT COUNT(*) FROM folders",
+        "SELECT COUNT(*) FROM tags",
+        "SELECT COUNT(*) FROM cards",
+        "SELECT COUNT(*) FROM settings WHERE id <> 1 OR encryption_enabled OR encryption_passphrase_hash IS NOT NULL",
+    ];
+    if table_exists(conn, "saved_searches") {
+        counts.push("SELECT COUNT(*) FROM saved_searches");
+    }
+    if table_exists(conn, "role_dashboard_layouts") {
+        counts.push("SELECT COUNT(*) FROM role_dashboard_layouts");
+    }
+    for sql in counts {
+        let count: i64 = conn.query_row(sql, [], |row| row.get(0)).map_err(|e| e.to_string())?;
+        if count > 0 {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn dump_settings(conn: &Connection) -> Result<Map<String, Value>, String> {
+    let mut stmt = conn.prepare("SELECT * FROM settings WHERE id = 1").map_err(|e| e.to_string())?;
+    let columns: Vec<String> = stmt.column_names().into_iter().map(str::to_string).collect();
+    let mut rows = stmt.query([]).map_err(|e| e.to_string())?;
+
+    let mut settings = Map::new();
+    if let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        for (i, column) in columns.iter().enumerate() {
+            if column == "id" {
+                continue;
+            }
+            let value = match row.get::<_, SqlValue>(i).map_err(|e| e.to_string())? {
+                SqlValue::Null => Value::Null,
+                SqlValue::Integer(n) => Value::from(n),
+                SqlValue::Real(f) => Value::from(f),
+                SqlValue::Text(s) => Value::String(s),
+                SqlValue::Blob(_) => continue,
+            };
+            settings.insert(column.clone(), value);
+        }
+    }
+    Ok(settings)
+}
+
+fn load_settings(conn: &Connection, settings: &Map<String, Value>) -> Result<(), String> {
+    conn.execute("INSERT OR IGNORE INTO settings (id) VALUES (1)", [])
+        .map_err(|e| e.to_string())?;
+
+    for (column, value) in settings {
+        // Column names come from the dump, so only accept ones the table really has
+        if column == "id" || !db_service::table_has_column(conn, "settings", column) {
+            continue;
+        }
+        let value = match value {
+            Value::Null => SqlValue::Null,
+            Value::Bool(b) => SqlValue::Integer(*b as i64),
+            Value::Number(n) => n
+                .as_i64()
+                .map(SqlValue::Integer)
+                .unwrap_or_else(|| SqlValue::Real(n.as_f64().unwrap_or(0.0))),
+            Value::String(s) => SqlValue::Text(s.clone()),
+            other => SqlValue::Text(other.to_string()),
+        };
+        conn.execute(&format!("UPDATE settings SET \"{}\" = ?1 WHERE id = 1", column), params![value])
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::cards;
+
+    fn setup_test_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (
+                internal_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                id TEXT UNIQUE NOT NULL,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                folder_id TEXT,
+                is_daily_note BOOLEAN DEFAULT FALSE,
+                properties TEXT,
+                word_count INTEGER DEFAULT 0,
+                reading_time INTEGER DEFAULT 0,
+                content_encrypted BLOB,
+                nonce BLOB,
+                content_plaintext TEXT
+            );
+            CREATE TABLE settings (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                encryption_enabled BOOLEAN DEFAULT FALSE,
+                encryption_passphrase_hash TEXT,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            );
+            INSERT INTO settings (id, encryption_enabled) VALUES (1, FALSE);
+            CREATE TABLE folders (id TEXT PRIMARY KEY, name TEXT NOT NULL, parent_id TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP, updated_at DATETIME DEFAULT CURRENT_TIMESTAMP);
+            CREATE TABLE tags (id TEXT PRIMARY KEY, name TEXT NOT NULL UNIQUE, created_at DATETIME DEFAULT CURRENT_TIMESTAMP);
+            CREATE TABLE note_tags (note_id TEXT NOT NULL, tag_id TEXT NOT NULL, PRIMARY KEY (note_id, tag_id));
+            CREATE TABLE role_dashboard_layouts (role TEXT PRIMARY KEY, widget_order TEXT NOT NULL,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP);",
+        )
+        .unwrap();
+        cards::create_tables(&conn).unwrap();
+        role_service::create_tables(&conn).unwrap();
+        saved_search_service::create_tables(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_dump_and_load_round_trip() {
+        let source = setup_test_db();
+        source
+            .execute_batch(
+                "INSERT INTO folders (id, name) VALUES ('f1', 'Work');
+                 INSERT INTO notes (id, title, content, folder_id, properties) VALUES ('n1', 'Hello', 'World', 'f1', '{\"a\":1}');
+                 INSERT INTO notes (id, title, content, content_encrypted, nonce, content_plaintext)
+                     VALUES ('n2', 'Secret', '', 'Y2lwaGVy', 'bm9uY2U=', 'plain');
+                 INSERT INTO tags (id, name) VALUES ('t1', 'rust');
+                 INSERT INTO note_tags (note_id, tag_id) VALUES ('n1', 't1');
+                 INSERT INTO role_dashboard_layouts (role, widget_order) VALUES ('learner', '[\"reading-list\"]');
+                 UPDATE settings SET encryption_enabled = TRUE WHERE id = 1;",
+            )
+            .unwrap();
+        cards::create_card(&source, "flashcard", "Q", "{\"back\":\"A\"}", "learner").unwrap();
+        source
+            .execute(
+                "INSERT INTO roles (name, display_name, include_tags, updated_at)
+                 VALUES ('on-call', 'On-call', '[\"incident\"]', '2026-01-01 00:00:00')",
+                [],
+            )
+            .unwrap();
+        source
+            .execute(
+                "INSERT INTO saved_searches (id, name, query, role, sort, pinned, parent_folder_id, notify)
+                 VALUES ('s1', 'Incidents', 'tag:incident', 'on-call', 'updated', TRUE, 'f1', TRUE)",
+                [],
+            )
+            .unwrap();
+
+        let dump = dump_vault(&source).unwrap();
+        assert_eq!(dump.schema_version, VAULT_SCHEMA_VERSION);
+
+        let json = serde_json::to_string(&dump).unwrap();
+        let parsed: VaultDump = serde_json::from_str(&json).unwrap();
+
+        let target = setup_test_db();
+        let report = load_vault(&target, &parsed).unwrap();
+        assert_eq!(report.notes, 2);
+        assert_eq!(dump.saved_searches.len(), 1);
+
+        let mut restored = dump_vault(&target).unwrap();
+        restored.exported_at = dump.exported_at.clone();
+        assert_eq!(restored, dump);
+    }
+
+    #[test]
+    fn test_load_refuses_non_empty_vault() {
+        let conn = setup_test_db();
+        conn.execute("INSERT INTO notes (id, title, content) VALUES ('n1', 'x', 'y')", []).unwrap();
+        let dump = dump_vault(&conn).unwrap();
+
+        assert!(load_vault(&conn, &dump).is_err());
+
+        // Records other than notes count too
+        for sql in [
+            "INSERT INTO saved_searches (id, name, query) VALUES ('s1', 'Mine', 'x')",
+            "INSERT INTO role_dashboard_layouts (role, widget_order) VALUES ('learner', '[]')",
+            "UPDATE settings SET encryption_enabled = TRUE WHERE id = 1",
+        ] {
+            let target = setup_test_db();
+            target.execute(sql, []).unwrap();
+            assert!(load_vault(&target, &dump).is_err(), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn test_load_replaces_built_in_roles() {
+        let source = setup_test_db();
+        role_service::delete_role(&source, "coach").unwrap();
+        let dump = dump_vault(&source).unwrap();
+
+        let target = setup_test_db();
+        assert!(role_service::get_role(&target, "coach").unwrap().is_some());
+        load_vault(&target, &dump).unwrap();
+        assert!(role_service::get_role(&target, "coach").unwrap().is_none());
+        assert!(role_service::get_role(&target, "learner").unwrap().is_some());
+    }
+}
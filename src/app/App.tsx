@@ -5,10 +5,12 @@ import { NotesPage } from './pages/NotesPage'
 import DashboardPage from './pages/Dashboard'
 import SettingsPage from './pages/Settings'
 import { GraphPage } from './pages/GraphPage'
+import { ChatPage } from './pages/ChatPage'
 import { NeuralBar } from '../features/capture/NeuralBar'
 import { AskModal } from '../features/retrieval/AskModal'
 import { CommandPalette } from '../features/retrieval/components/CommandPalette'
 import { useState, useEffect } from 'react'
+import { invoke } from '@tauri-apps/api/tauri'
 
 import { useRoleStore } from '../shared/stores/role-store'
 // import { Sparkles } from 'lucide-react' - temporarily using emoji due to import issues
@@ -17,19 +19,34 @@ import { CaptureModal } from './components/CaptureModal'
 import { useCaptureModal } from '../shared/hooks/useCaptureModal'
 import { RoleSearchModal } from '../features/search/components/RoleSearchModal'
 import { useKeyboardShortcut } from '../shared/hooks/useKeyboardShortcut'
+import type { Insight } from '../features/ai/components/InsightsPanel'
 
 function SubconsciousToast() {
-  const [insight, setInsight] = useState<string | null>(null);
+  const [insight, setInsight] = useState<Insight | null>(null);
+
+  const answer = async (command: 'accept_insight' | 'dismiss_insight' | 'snooze_insight') => {
+    if (!insight) return;
+    try {
+      await invoke(command, { id: insight.id });
+    } catch (error) {
+      console.error(`Failed to ${command}:`, error);
+    }
+    setInsight(null);
+  };
 
   useEffect(() => {
-    const handleInsight = (e: any) => {
-      setInsight(e.detail);
-      setTimeout(() => setInsight(null), 5000);
-    };
+    const handleInsight = (e: any) => setInsight(e.detail);
     window.addEventListener('subconscious-toast', handleInsight);
     return () => window.removeEventListener('subconscious-toast', handleInsight);
   }, []);
 
+  // Each insight gets its own 15s, and the timer goes away with the component
+  useEffect(() => {
+    if (!insight) return;
+    const timer = setTimeout(() => setInsight(null), 15000);
+    return () => clearTimeout(timer);
+  }, [insight]);
+
   return (
     <AnimatePresence>
       {insight && (
@@ -44,7 +61,15 @@ function SubconsciousToast() {
           </div>
           <div>
             <h4 className="font-sans text-sm font-bold text-white">Subconscious Connection</h4>
-            <p className="font-mono text-xs text-neutral-300 mt-1">{insight}</p>
+            <p className="font-mono text-xs text-neutral-300 mt-1">{insight.description}</p>
+            {insight.reasons.length > 0 && (
+              <p className="font-mono text-[10px] text-neutral-500 mt-1">{insight.reasons.join(' · ')}</p>
+            )}
+            <div className="flex gap-2 mt-2 font-mono text-xs">
+              <button className="text-primary hover:underline" onClick={() => answer('accept_insight')}>Link</button>
+              <button className="text-neutral-400 hover:underline" onClick={() => answer('snooze_insight')}>Later</button>
+              <button className="text-neutral-400 hover:underline" onClick={() => answer('dismiss_insight')}>Dismiss</button>
+            </div>
           </div>
         </motion.div>
       )}
@@ -84,7 +109,7 @@ export default function App() {
       const { listen } = await import('@tauri-apps/api/event');
       const { toast } = await import('sonner'); // Assuming sonner is installed or Use simple alert for MVP if not
 
-      unlisten = await listen<string>('insight-found', (event) => {
+      unlisten = await listen<Insight>('insight-found', (event) => {
         // Simple custom toast or console for MVP if UI lib missing
         console.log("Insight:", event.payload);
         // Dispatch custom event for a Toast component to pick up, or use a library
@@ -130,6 +155,7 @@ export default function App() {
         <Route path="/notes/new" element={<NotesPage />} />
         <Route path="/capture" element={<NeuralBar />} />
         <Route path="/graph" element={<GraphPage />} />
+        <Route path="/chat" element={<ChatPage />} />
       </Routes>
     </BrowserRouter>
   )
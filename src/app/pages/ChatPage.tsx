@@ -0,0 +1,38 @@
+import { ChatPanel } from '../../features/ai/components/ChatPanel';
+import { useNavigate } from 'react-router-dom';
+
+/**
+ * Chat Page - Conversations with the local model over your notes
+ *
+ * - Sessions keep their history and a scope (vault, folder, tag or selected notes)
+ * - Answers cite the note passages they used; click one to open the note
+ * - Any answer can be saved as a note that links back to its sources
+ */
+export function ChatPage() {
+  const navigate = useNavigate();
+
+  return (
+    <div className="flex flex-col h-screen bg-neutral-50">
+      {/* Header */}
+      <div className="border-b border-neutral-200 bg-white px-6 py-4 flex items-center gap-4">
+        <button
+          onClick={() => navigate('/dashboard')}
+          className="px-3 py-2 bg-neutral-100 hover:bg-neutral-200 rounded-none transition-colors text-sm font-medium"
+          title="Back to Dashboard"
+        >
+          ← Back
+        </button>
+        <div>
+          <h1 className="text-lg font-semibold text-neutral-900">AI Chat</h1>
+          <p className="text-xs text-neutral-500">Ask questions answered from your notes</p>
+        </div>
+      </div>
+
+      <div className="flex-1 overflow-hidden">
+        <ChatPanel />
+      </div>
+    </div>
+  );
+}
+
+export default ChatPage;
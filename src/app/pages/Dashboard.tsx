@@ -3,9 +3,12 @@ import { motion } from 'framer-motion';
 import { TopBar } from '../../shared/components/layout/TopBar';
 import { Card, StatCard, QuickActionCard, SectionHeader } from '../../shared/components/dashboard/Card';
 import { useNavigate } from 'react-router-dom';
+import { InsightsPanel } from '../../features/ai/components/InsightsPanel';
+import { useNotesStore } from '../../shared/hooks/useNotesStore';
 
 export default function DashboardPage() {
   const navigate = useNavigate();
+  const setSelectedNoteId = useNotesStore((state) => state.setSelectedNoteId);
 
   // Keyboard shortcut for Graph View
   useEffect(() => {
@@ -101,6 +104,15 @@ export default function DashboardPage() {
           />
         </div>
 
+        {/* Connections found between notes */}
+        <SectionHeader title="Insights" />
+        <InsightsPanel
+          onOpenNote={(noteId) => {
+            setSelectedNoteId(noteId);
+            navigate('/notes');
+          }}
+        />
+
         {/* Recent Notes */}
         <SectionHeader title="Recent Notes" />
         <div className="grid grid-cols-1 md:grid-cols-2 lg:grid-cols-3 gap-6">
@@ -1,5 +1,7 @@
-import React, { useState, useEffect, useCallback } from 'react';
+import React, { useState, useEffect, useCallback, useRef } from 'react';
 import { invoke } from '@tauri-apps/api/tauri';
+import { useNotesStore, NotePassage } from '../../shared/hooks/useNotesStore';
+import { NoteAIPanel } from '../../features/ai/components/NoteAIPanel';
 
 interface Note {
   id: string;
@@ -11,8 +13,31 @@ interface Note {
 interface RelatedNote {
   id: string;
   title: string;
+  snippet: string;
   updated_at: string;
   tags: string[];
+  score: number;
+  reasons: string[];
+}
+
+/** UTF-16 index of character `chars` of `text`; passage offsets count characters */
+function utf16Index(text: string, chars: number): number {
+  let index = 0;
+  for (const c of text) {
+    if (chars-- <= 0) break;
+    index += c.length;
+  }
+  return index;
+}
+
+/** Scroll `textarea` so the text at `index` sits mid-view, and the textarea into view */
+function scrollToIndex(textarea: HTMLTextAreaElement, index: number) {
+  const value = textarea.value;
+  textarea.value = value.slice(0, index);
+  const top = textarea.scrollHeight;
+  textarea.value = value;
+  textarea.scrollTop = Math.max(0, top - textarea.clientHeight / 2);
+  textarea.scrollIntoView({ block: 'nearest' });
 }
 
 export function NotesPage() {
@@ -24,10 +49,39 @@ export function NotesPage() {
   const [editContent, setEditContent] = useState('');
   const [isEditing, setIsEditing] = useState(false);
 
+  const contentRef = useRef<HTMLTextAreaElement>(null);
+  // Passage to highlight once the requested note is in the editor
+  const [pendingPassage, setPendingPassage] = useState<NotePassage | null>(null);
+
+  // Notes opened from elsewhere, e.g. an AI answer's sources. The request is taken
+  // once and cleared, so coming back to this page doesn't reopen it.
+  const requestedNoteId = useNotesStore((state) => state.selectedNoteId);
+  const requestedPassage = useNotesStore((state) => state.selectedPassage);
+  const setRequestedNoteId = useNotesStore((state) => state.setSelectedNoteId);
+
   useEffect(() => {
     loadNotes();
   }, []);
 
+  useEffect(() => {
+    if (!requestedNoteId) return;
+    setSelectedNoteId(requestedNoteId);
+    setPendingPassage(requestedPassage);
+    setRequestedNoteId(null);
+  }, [requestedNoteId, requestedPassage, setRequestedNoteId]);
+
+  useEffect(() => {
+    const textarea = contentRef.current;
+    const note = notes.find(n => n.id === selectedNoteId);
+    if (!pendingPassage || !textarea || !note || editContent !== note.content) return;
+    const start = utf16Index(editContent, pendingPassage.start);
+    const end = utf16Index(editContent, pendingPassage.end);
+    scrollToIndex(textarea, start);
+    textarea.focus({ preventScroll: true });
+    textarea.setSelectionRange(start, end);
+    setPendingPassage(null);
+  }, [pendingPassage, selectedNoteId, notes, editContent]);
+
   useEffect(() => {
     if (selectedNoteId) {
       const note = notes.find(n => n.id === selectedNoteId);
@@ -35,7 +89,7 @@ export function NotesPage() {
         setEditTitle(note.title);
         setEditContent(note.content);
         setIsEditing(true);
-        loadRelatedNotes(note.id, note.content);
+        loadRelatedNotes(note.id);
       }
     } else {
       setEditTitle('');
@@ -57,10 +111,9 @@ export function NotesPage() {
     }
   };
 
-  const loadRelatedNotes = async (noteId: string, content: string) => {
+  const loadRelatedNotes = async (noteId: string) => {
     try {
       const result = await invoke('get_related_notes', {
-        noteContent: content,
         limit: 10,
         currentNoteId: noteId,
       }) as any[];
@@ -187,6 +240,7 @@ export function NotesPage() {
 
               {/* Content Textarea */}
               <textarea
+                ref={contentRef}
                 value={editContent}
                 onChange={(e) => setEditContent(e.target.value)}
                 placeholder="Start writing..."
@@ -322,13 +376,17 @@ export function NotesPage() {
           )}
         </div>
 
-        {/* Right: Related Notes Pane */}
+        {/* Right: AI Actions and Related Notes Pane */}
         <div style={{
           width: 720,
           backgroundColor: '#f5f5f5',
           padding: '20px',
           overflow: 'auto',
         }}>
+          {selectedNoteId && notes.some(n => n.id === selectedNoteId) && (
+            <NoteAIPanel noteId={selectedNoteId} onApplied={loadNotes} />
+          )}
+
           <div style={{
             fontSize: 12,
             fontWeight: 600,
@@ -380,6 +438,15 @@ export function NotesPage() {
                     })}
                     {note.tags && note.tags.length > 0 && ` | ${note.tags.map((t: string) => `#${t}`).join(' ')}`}
                   </div>
+                  {note.reasons.length > 0 && (
+                    <div style={{
+                      fontSize: 12,
+                      color: '#666',
+                      marginTop: 4,
+                    }}>
+                      {note.reasons.join(' · ')}
+                    </div>
+                  )}
                 </div>
               ))}
             </div>
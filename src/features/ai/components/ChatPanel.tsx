@@ -0,0 +1,244 @@
+import { useEffect, useRef, useState } from 'react';
+import { useNavigate } from 'react-router-dom';
+import ReactMarkdown from 'react-markdown';
+import { FilePlus, Loader2, MessageSquarePlus, Send, Square, Trash2 } from 'lucide-react';
+import { aiService, ChatMessageRecord, ChatScope, ChatSession, Source } from '../../../shared/services/aiService';
+import { organizationService, Folder, Tag } from '../../../shared/services/organizationService';
+import { useNotesStore } from '../../../shared/hooks/useNotesStore';
+
+const scopeLabel = (scope: ChatScope, folders: Folder[]) => {
+    switch (scope.type) {
+        case 'all': return 'Whole vault';
+        case 'folder': return `Folder: ${folders.find((f) => f.id === scope.id)?.name ?? scope.id}`;
+        case 'tag': return `Tag: #${scope.name}`;
+        case 'notes': return `${scope.ids.length} selected notes`;
+    }
+};
+
+export function ChatPanel() {
+    const [sessions, setSessions] = useState<ChatSession[]>([]);
+    const [active, setActive] = useState<ChatSession | null>(null);
+    const [messages, setMessages] = useState<ChatMessageRecord[]>([]);
+    const [input, setInput] = useState('');
+    const [streaming, setStreaming] = useState<string | null>(null);
+    const [folders, setFolders] = useState<Folder[]>([]);
+    const [tags, setTags] = useState<Tag[]>([]);
+    const requestId = useRef<string | null>(null);
+    const { notes, setSelectedNoteId } = useNotesStore();
+    const navigate = useNavigate();
+
+    const loadSessions = async () => {
+        const list = await aiService.listChatSessions();
+        setSessions(list);
+        return list;
+    };
+
+    useEffect(() => {
+        loadSessions().then((list) => list[0] && openSession(list[0]));
+        organizationService.getFolders().then(setFolders).catch(() => setFolders([]));
+        organizationService.getTags().then(setTags).catch(() => setTags([]));
+    }, []);
+
+    const openSession = async (session: ChatSession) => {
+        setActive(session);
+        setMessages(await aiService.getChatMessages(session.id));
+    };
+
+    const handleNewSession = async () => {
+        const session = await aiService.createChatSession(active?.scope);
+        await loadSessions();
+        await openSession(session);
+    };
+
+    const handleDeleteSession = async (session: ChatSession) => {
+        if (!confirm(`Delete "${session.title}"?`)) return;
+        await aiService.deleteChatSession(session.id);
+        const list = await loadSessions();
+        if (active?.id === session.id) {
+            setActive(null);
+            setMessages([]);
+            if (list[0]) await openSession(list[0]);
+        }
+    };
+
+    const handleScope = async (scope: ChatScope) => {
+        if (!active) return;
+        setActive(await aiService.updateChatSession(active.id, { scope }));
+    };
+
+    const handleSend = async () => {
+        const content = input.trim();
+        if (!content || requestId.current) return;
+        const session = active ?? await aiService.createChatSession();
+        if (!active) setActive(session);
+
+        const id = crypto.randomUUID();
+        requestId.current = id;
+        setInput('');
+        setStreaming('');
+        setMessages((prev) => [...prev, { id: -1, session_id: session.id, role: 'user', content, sources: [], created_at: null }]);
+        try {
+            await aiService.sendChatMessage(session.id, content, id, (token) => {
+                setStreaming((prev) => (prev ?? '') + token);
+            });
+        } catch (error) {
+            // Nothing was stored; give the question back to retry
+            setInput(content);
+            alert(`Chat failed: ${error}`);
+        } finally {
+            requestId.current = null;
+            setStreaming(null);
+            setMessages(await aiService.getChatMessages(session.id));
+            await loadSessions();
+        }
+    };
+
+    const handleSaveAsNote = async (message: ChatMessageRecord) => {
+        try {
+            const noteId = await aiService.saveChatAnswerAsNote(message.id);
+            setSelectedNoteId(noteId);
+            navigate('/notes');
+        } catch (error) {
+            alert(`Could not save note: ${error}`);
+        }
+    };
+
+    const handleOpenSource = (source: Source) => {
+        setSelectedNoteId(source.note_id, { start: source.start, end: source.end });
+        navigate('/notes');
+    };
+
+    const scopeValue = (scope: ChatScope) => {
+        switch (scope.type) {
+            case 'folder': return `folder:${scope.id}`;
+            case 'tag': return `tag:${scope.name}`;
+            default: return scope.type;
+        }
+    };
+
+    return (
+        <div className="flex h-full">
+            <aside className="w-64 border-r border-neutral-200 bg-white flex flex-col">
+                <button onClick={handleNewSession} className="rg-btn rg-btn-secondary m-3 flex items-center gap-2">
+                    <MessageSquarePlus className="w-4 h-4" /> New Chat
+                </button>
+                <div className="flex-1 overflow-y-auto">
+                    {sessions.map((session) => (
+                        <div
+                            key={session.id}
+                            onClick={() => openSession(session)}
+                            className={`group flex items-center justify-between px-3 py-2 text-sm cursor-pointer border-b border-neutral-100 ${active?.id === session.id ? 'bg-primary/10 text-primary' : 'text-neutral-700 hover:bg-neutral-50'}`}
+                        >
+                            <span className="truncate">{session.title}</span>
+                            <button
+                                onClick={(e) => { e.stopPropagation(); handleDeleteSession(session); }}
+                                className="opacity-0 group-hover:opacity-100 text-neutral-400 hover:text-red-600"
+                                title="Delete chat"
+                            >
+                                <Trash2 className="w-3 h-3" />
+                            </button>
+                        </div>
+                    ))}
+                </div>
+            </aside>
+
+            <section className="flex-1 flex flex-col">
+                {active && (
+                    <div className="border-b border-neutral-200 bg-white px-4 py-2 flex items-center gap-3">
+                        <span className="font-mono text-xs font-bold text-neutral-600 uppercase tracking-wider">Scope</span>
+                        <select
+                            value={scopeValue(active.scope)}
+                            onChange={(e) => {
+                                const [kind, ...rest] = e.target.value.split(':');
+                                const value = rest.join(':');
+                                if (kind === 'folder') handleScope({ type: 'folder', id: value });
+                                else if (kind === 'tag') handleScope({ type: 'tag', name: value });
+                                else if (kind === 'notes') handleScope({ type: 'notes', ids: active.scope.type === 'notes' ? active.scope.ids : [] });
+                                else handleScope({ type: 'all' });
+                            }}
+                            className="border border-neutral-200 px-2 py-1 text-sm"
+                        >
+                            <option value="all">Whole vault</option>
+                            {folders.map((f) => <option key={f.id} value={`folder:${f.id}`}>Folder: {f.name}</option>)}
+                            {tags.map((t) => <option key={t.id} value={`tag:${t.name}`}>Tag: #{t.name}</option>)}
+                            <option value="notes">Selected notes…</option>
+                        </select>
+                        {active.scope.type === 'notes' && (
+                            <select
+                                multiple
+                                value={active.scope.ids}
+                                onChange={(e) => handleScope({ type: 'notes', ids: Array.from(e.target.selectedOptions, (o) => o.value) })}
+                                className="border border-neutral-200 px-2 py-1 text-sm h-20 flex-1"
+                            >
+                                {notes.map((n) => <option key={n.id} value={n.id}>{n.title}</option>)}
+                            </select>
+                        )}
+                        <span className="ml-auto font-mono text-xs text-neutral-500">{scopeLabel(active.scope, folders)}</span>
+                    </div>
+                )}
+
+                <div className="flex-1 overflow-y-auto p-4 space-y-4">
+                    {messages.map((message) => (
+                        <div key={message.id} className={message.role === 'user' ? 'flex justify-end' : ''}>
+                            <div className={`max-w-2xl p-3 border ${message.role === 'user' ? 'bg-primary/10 border-primary/20' : 'bg-white border-neutral-200'}`}>
+                                <div className="prose prose-sm max-w-none">
+                                    <ReactMarkdown>{message.content}</ReactMarkdown>
+                                </div>
+                                {message.sources.length > 0 && (
+                                    <div className="mt-2 space-y-1">
+                                        {message.sources.map((source) => (
+                                            <button
+                                                key={source.marker}
+                                                onClick={() => handleOpenSource(source)}
+                                                className={`block text-left text-xs truncate w-full hover:underline ${source.cited ? 'text-neutral-900' : 'text-neutral-400'}`}
+                                            >
+                                                <span className={`font-mono ${source.cited ? 'text-primary font-bold' : ''}`}>[{source.marker}]</span>{' '}
+                                                {source.title}{source.heading ? ` › ${source.heading}` : ''}
+                                            </button>
+                                        ))}
+                                    </div>
+                                )}
+                                {message.role === 'assistant' && message.sources.length > 0 && (
+                                    <button
+                                        onClick={() => handleSaveAsNote(message)}
+                                        className="mt-2 flex items-center gap-1 text-xs font-mono text-neutral-500 hover:text-neutral-900"
+                                    >
+                                        <FilePlus className="w-3 h-3" /> Save as note
+                                    </button>
+                                )}
+                            </div>
+                        </div>
+                    ))}
+                    {streaming !== null && (
+                        <div className="max-w-2xl p-3 border bg-white border-neutral-200">
+                            {streaming ? (
+                                <div className="prose prose-sm max-w-none"><ReactMarkdown>{streaming}</ReactMarkdown></div>
+                            ) : (
+                                <Loader2 className="w-4 h-4 animate-spin text-primary" />
+                            )}
+                        </div>
+                    )}
+                </div>
+
+                <div className="border-t border-neutral-200 bg-white p-3 flex items-center gap-2">
+                    <input
+                        value={input}
+                        onChange={(e) => setInput(e.target.value)}
+                        onKeyDown={(e) => { if (e.key === 'Enter') handleSend(); }}
+                        placeholder="Ask about your notes..."
+                        className="flex-1 border border-neutral-200 px-3 py-2 text-sm"
+                    />
+                    {streaming !== null ? (
+                        <button onClick={() => requestId.current && aiService.cancelGeneration(requestId.current)} className="rg-btn rg-btn-secondary flex items-center gap-1">
+                            <Square className="w-4 h-4" /> Stop
+                        </button>
+                    ) : (
+                        <button onClick={handleSend} disabled={!input.trim()} className="rg-btn rg-btn-secondary flex items-center gap-1">
+                            <Send className="w-4 h-4" /> Send
+                        </button>
+                    )}
+                </div>
+            </section>
+        </div>
+    );
+}
@@ -0,0 +1,138 @@
+import React, { useCallback, useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/tauri';
+import { Sparkles, Link2, Clock, X } from 'lucide-react';
+import { cn } from '../../../shared/utils';
+
+export interface Insight {
+  id: string;
+  title: string;
+  description: string;
+  note_a_id: string;
+  note_a_title: string;
+  note_b_id: string;
+  note_b_title: string;
+  score: number;
+  reasons: string[];
+  status: string;
+  snoozed_until: string | null;
+  created_at: string | null;
+}
+
+type InsightAnswer = 'accept_insight' | 'dismiss_insight' | 'snooze_insight';
+
+interface InsightsPanelProps {
+  onOpenNote?: (noteId: string) => void;
+}
+
+/** Stored connection insights, open ones first; answered ones on request */
+export function InsightsPanel({ onOpenNote }: InsightsPanelProps) {
+  const [insights, setInsights] = useState<Insight[]>([]);
+  const [includeAnswered, setIncludeAnswered] = useState(false);
+  const [isLoading, setIsLoading] = useState(true);
+
+  const load = useCallback(async () => {
+    try {
+      setInsights(await invoke<Insight[]>('list_insights', { includeAnswered, limit: 50 }));
+    } catch (error) {
+      console.error('Failed to load insights:', error);
+    } finally {
+      setIsLoading(false);
+    }
+  }, [includeAnswered]);
+
+  useEffect(() => {
+    load();
+    // A new insight was just announced by the toast
+    window.addEventListener('subconscious-toast', load);
+    return () => window.removeEventListener('subconscious-toast', load);
+  }, [load]);
+
+  const answer = async (command: InsightAnswer, id: string) => {
+    try {
+      await invoke(command, { id });
+    } catch (error) {
+      console.error(`Failed to ${command}:`, error);
+    }
+    load();
+  };
+
+  return (
+    <div className="bg-white border border-neutral-200 p-6">
+      <div className="flex items-center justify-between mb-4">
+        <div className="flex items-center gap-3">
+          <Sparkles className="w-5 h-5 text-primary" />
+          <h3 className="font-sans font-bold text-neutral-900">Connections</h3>
+        </div>
+        <label className="flex items-center gap-2 font-mono text-xs text-neutral-500">
+          <input
+            type="checkbox"
+            checked={includeAnswered}
+            onChange={(e) => setIncludeAnswered(e.target.checked)}
+          />
+          Show answered
+        </label>
+      </div>
+
+      {isLoading ? (
+        <p className="font-mono text-xs text-neutral-400">Loading…</p>
+      ) : insights.length === 0 ? (
+        <p className="font-mono text-xs text-neutral-400">No connections found yet</p>
+      ) : (
+        <ul className="divide-y divide-neutral-100">
+          {insights.map((insight) => (
+            <li key={insight.id} className="py-3 flex items-start justify-between gap-4">
+              <div className="min-w-0">
+                <p className="font-sans text-sm text-neutral-900">
+                  <button className="font-semibold hover:underline" onClick={() => onOpenNote?.(insight.note_a_id)}>
+                    {insight.note_a_title}
+                  </button>
+                  {' ↔ '}
+                  <button className="font-semibold hover:underline" onClick={() => onOpenNote?.(insight.note_b_id)}>
+                    {insight.note_b_title}
+                  </button>
+                </p>
+                {insight.reasons.length > 0 && (
+                  <p className="font-mono text-[10px] text-neutral-500 mt-1">{insight.reasons.join(' · ')}</p>
+                )}
+              </div>
+              {insight.status !== 'accepted' && insight.status !== 'dismissed' ? (
+                <div className="flex gap-1 shrink-0">
+                  <button
+                    title="Link the notes"
+                    className="p-1 text-primary hover:bg-primary/10"
+                    onClick={() => answer('accept_insight', insight.id)}
+                  >
+                    <Link2 className="w-4 h-4" />
+                  </button>
+                  <button
+                    title="Remind me later"
+                    className="p-1 text-neutral-400 hover:bg-neutral-100"
+                    onClick={() => answer('snooze_insight', insight.id)}
+                  >
+                    <Clock className="w-4 h-4" />
+                  </button>
+                  <button
+                    title="Dismiss"
+                    className="p-1 text-neutral-400 hover:bg-neutral-100"
+                    onClick={() => answer('dismiss_insight', insight.id)}
+                  >
+                    <X className="w-4 h-4" />
+                  </button>
+                </div>
+              ) : (
+                <span
+                  className={cn(
+                    'font-mono text-[10px] uppercase shrink-0',
+                    insight.status === 'accepted' ? 'text-primary' : 'text-neutral-400'
+                  )}
+                >
+                  {insight.status}
+                </span>
+              )}
+            </li>
+          ))}
+        </ul>
+      )}
+    </div>
+  );
+}
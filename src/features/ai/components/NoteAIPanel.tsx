@@ -0,0 +1,173 @@
+import { useEffect, useState } from 'react';
+import { Check, ListChecks, Loader2, Sparkles, Tags, Trash2, Type, X } from 'lucide-react';
+import { aiService, NoteAction, NoteSuggestion, Task } from '../../../shared/services/aiService';
+
+const ACTIONS: { action: NoteAction; label: string; icon: typeof Sparkles }[] = [
+    { action: 'summary', label: 'Summarize', icon: Sparkles },
+    { action: 'title', label: 'Suggest title', icon: Type },
+    { action: 'tags', label: 'Suggest tags', icon: Tags },
+    { action: 'action_items', label: 'Extract action items', icon: ListChecks },
+];
+
+const actionLabel = (action: NoteAction) => ACTIONS.find((a) => a.action === action)?.label ?? action;
+
+interface NoteAIPanelProps {
+    noteId: string;
+    /** Called after a suggestion changed the note, so its title or content can be reloaded */
+    onApplied?: () => void;
+}
+
+/** AI actions on a note: suggestions are generated in the background and applied after review */
+export function NoteAIPanel({ noteId, onApplied }: NoteAIPanelProps) {
+    const [suggestions, setSuggestions] = useState<NoteSuggestion[]>([]);
+    const [tasks, setTasks] = useState<Task[]>([]);
+    // Edited output per suggestion: text for summaries and titles, the checked items for lists
+    const [edits, setEdits] = useState<Record<string, string[]>>({});
+
+    const loadSuggestions = () => aiService.listNoteSuggestions(noteId).then(setSuggestions).catch(() => setSuggestions([]));
+    const loadTasks = () => aiService.listTasks(noteId).then(setTasks).catch(() => setTasks([]));
+
+    useEffect(() => {
+        setEdits({});
+        loadSuggestions();
+        loadTasks();
+        const unlisten = aiService.onNoteSuggestionReady((suggestion) => {
+            if (suggestion.note_id === noteId) loadSuggestions();
+        });
+        return () => { unlisten.then((stop) => stop()); };
+    }, [noteId]);
+
+    const handleRequest = async (action: NoteAction) => {
+        try {
+            await aiService.requestNoteActions(noteId, [action]);
+            await loadSuggestions();
+        } catch (error) {
+            alert(`Could not queue ${actionLabel(action).toLowerCase()}: ${error}`);
+        }
+    };
+
+    const handleApply = async (suggestion: NoteSuggestion) => {
+        try {
+            await aiService.applyNoteSuggestion(suggestion.id, edits[suggestion.id]);
+            await loadSuggestions();
+            if (suggestion.action === 'action_items') await loadTasks();
+            onApplied?.();
+        } catch (error) {
+            alert(`Could not apply suggestion: ${error}`);
+        }
+    };
+
+    const handleDismiss = async (suggestion: NoteSuggestion) => {
+        await aiService.dismissNoteSuggestion(suggestion.id);
+        await loadSuggestions();
+    };
+
+    const handleToggleTask = async (task: Task) => {
+        const updated = await aiService.setTaskDone(task.id, !task.done);
+        setTasks((prev) => prev.map((t) => (t.id === task.id ? updated : t)));
+    };
+
+    const handleDeleteTask = async (task: Task) => {
+        await aiService.deleteTask(task.id);
+        setTasks((prev) => prev.filter((t) => t.id !== task.id));
+    };
+
+    const isList = (action: NoteAction) => action === 'tags' || action === 'action_items';
+    const valuesOf = (suggestion: NoteSuggestion) => edits[suggestion.id] ?? suggestion.output;
+    const setValues = (id: string, values: string[]) => setEdits((prev) => ({ ...prev, [id]: values }));
+
+    const renderOutput = (suggestion: NoteSuggestion) => {
+        const values = valuesOf(suggestion);
+        if (!isList(suggestion.action)) {
+            return (
+                <textarea
+                    value={values.join('\n')}
+                    onChange={(e) => setValues(suggestion.id, [e.target.value])}
+                    rows={suggestion.action === 'summary' ? 4 : 1}
+                    className="w-full border border-neutral-200 px-2 py-1 text-sm"
+                />
+            );
+        }
+        if (suggestion.output.length === 0) {
+            return <div className="text-xs text-neutral-500">Nothing found.</div>;
+        }
+        return suggestion.output.map((item) => (
+            <label key={item} className="flex items-center gap-2 text-sm">
+                <input
+                    type="checkbox"
+                    checked={values.includes(item)}
+                    onChange={(e) => setValues(
+                        suggestion.id,
+                        e.target.checked ? [...values, item] : values.filter((v) => v !== item),
+                    )}
+                />
+                {suggestion.action === 'tags' ? `#${item}` : item}
+            </label>
+        ));
+    };
+
+    return (
+        <div className="mb-6 space-y-3">
+            <div className="font-mono text-xs font-bold text-neutral-600 uppercase tracking-wider">AI Actions</div>
+            <div className="flex flex-wrap gap-2">
+                {ACTIONS.map(({ action, label, icon: Icon }) => (
+                    <button key={action} onClick={() => handleRequest(action)} className="rg-btn rg-btn-secondary flex items-center gap-1 text-xs">
+                        <Icon className="w-3 h-3" /> {label}
+                    </button>
+                ))}
+            </div>
+
+            {suggestions.map((suggestion) => (
+                <div key={suggestion.id} className="bg-white border border-neutral-200 p-3 space-y-2">
+                    <div className="flex items-center justify-between">
+                        <span className="font-mono text-xs font-bold text-neutral-700">{actionLabel(suggestion.action)}</span>
+                        <button onClick={() => handleDismiss(suggestion)} className="text-neutral-400 hover:text-red-600" title="Dismiss">
+                            <X className="w-3 h-3" />
+                        </button>
+                    </div>
+                    {(suggestion.status === 'queued' || suggestion.status === 'running') && (
+                        <div className="flex items-center gap-2 text-xs text-neutral-500">
+                            <Loader2 className="w-3 h-3 animate-spin text-primary" />
+                            {suggestion.status === 'queued' ? 'Waiting for the model…' : 'Generating…'}
+                        </div>
+                    )}
+                    {suggestion.status === 'failed' && (
+                        <div className="text-xs text-red-600">{suggestion.error}</div>
+                    )}
+                    {suggestion.status === 'ready' && (
+                        <>
+                            {renderOutput(suggestion)}
+                            <button
+                                onClick={() => handleApply(suggestion)}
+                                disabled={valuesOf(suggestion).every((v) => !v.trim())}
+                                className="rg-btn rg-btn-secondary flex items-center gap-1 text-xs"
+                            >
+                                <Check className="w-3 h-3" /> Apply
+                            </button>
+                            {suggestion.model && <div className="font-mono text-[10px] text-neutral-400">{suggestion.model}</div>}
+                        </>
+                    )}
+                </div>
+            ))}
+
+            {tasks.length > 0 && (
+                <div className="bg-white border border-neutral-200 p-3 space-y-1">
+                    <div className="font-mono text-xs font-bold text-neutral-700 mb-1">Tasks</div>
+                    {tasks.map((task) => (
+                        <div key={task.id} className="group flex items-center gap-2 text-sm">
+                            <input type="checkbox" checked={task.done} onChange={() => handleToggleTask(task)} />
+                            <span className={`flex-1 ${task.done ? 'line-through text-neutral-400' : ''}`}>{task.text}</span>
+                            <button
+                                onClick={() => handleDeleteTask(task)}
+                                className="opacity-0 group-hover:opacity-100 text-neutral-400 hover:text-red-600"
+                                title="Delete task"
+                            >
+                                <Trash2 className="w-3 h-3" />
+                            </button>
+                        </div>
+                    ))}
+                </div>
+            )}
+        </div>
+    );
+}
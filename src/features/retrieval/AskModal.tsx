@@ -1,31 +1,57 @@
 import { Command } from 'cmdk';
-import { useState, useEffect } from 'react';
-import { Search, Brain, FileText, Sparkles, Loader2 } from 'lucide-react';
-import { invoke } from '@tauri-apps/api/tauri';
+import { useState, useEffect, useRef } from 'react';
+import { Search, Brain, FileText, Sparkles, Loader2, Square } from 'lucide-react';
+import { aiService, GenerationStats, Source } from '../../shared/services/aiService';
+import { useNotesStore } from '../../shared/hooks/useNotesStore';
+import { useNavigate } from 'react-router-dom';
 import ReactMarkdown from 'react-markdown';
 
 export function AskModal({ isOpen, setIsOpen }: { isOpen: boolean; setIsOpen: (v: boolean) => void }) {
     const [query, setQuery] = useState('');
     const [response, setResponse] = useState<string | null>(null);
     const [isLoading, setIsLoading] = useState(false);
+    const [stats, setStats] = useState<GenerationStats | null>(null);
+    const [sources, setSources] = useState<Source[]>([]);
+    const requestId = useRef<string | null>(null);
+    const { setSelectedNoteId } = useNotesStore();
+    const navigate = useNavigate();
 
     const handleAskAI = async () => {
         if (!query.trim()) return;
 
+        const id = crypto.randomUUID();
+        requestId.current = id;
         setIsLoading(true);
         setResponse(null);
+        setStats(null);
+        setSources([]);
 
         try {
-            const result = await invoke<string>('synthesize_query', { query });
-            setResponse(result);
+            const result = await aiService.synthesizeQuery(query, id, (token) => {
+                setResponse((prev) => (prev ?? '') + token);
+            });
+            setResponse(result.text);
+            setStats(result.stats);
+            setSources(result.sources);
         } catch (error) {
             console.error('Failed to ask brain:', error);
-            setResponse('**Error:** Failed to connect to the Neural Core. Please ensure Ollama is running.');
+            setResponse(`**Error:** ${error}`);
         } finally {
+            requestId.current = null;
             setIsLoading(false);
         }
     };
 
+    const handleOpenSource = (source: Source) => {
+        setSelectedNoteId(source.note_id, { start: source.start, end: source.end });
+        navigate('/notes');
+        setIsOpen(false);
+    };
+
+    const handleStop = () => {
+        if (requestId.current) aiService.cancelGeneration(requestId.current);
+    };
+
     // Toggle with Cmd+K handler should be in parent or global hook, 
     // but if this is mounted conditionally, it handles its own internal logic.
 
@@ -84,7 +110,7 @@ export function AskModal({ isOpen, setIsOpen }: { isOpen: boolean; setIsOpen: (v
                             </Command.Group>
                         )}
 
-                        {isLoading && (
+                        {isLoading && !response && (
                             <div className="p-8 flex flex-col items-center justify-center text-neutral-600 space-y-3">
                                 <Loader2 className="w-8 h-8 animate-spin text-primary" />
                                 <p className="text-sm font-medium animate-pulse">Synthesizing neural connections...</p>
@@ -96,9 +122,43 @@ export function AskModal({ isOpen, setIsOpen }: { isOpen: boolean; setIsOpen: (v
                                 <div className="prose prose-sm max-w-none">
                                     <ReactMarkdown>{response}</ReactMarkdown>
                                 </div>
+                                <div className="mt-3 flex items-center justify-between text-xs font-mono text-neutral-500">
+                                    {isLoading ? (
+                                        <button onClick={handleStop} className="flex items-center gap-1 hover:text-neutral-900">
+                                            <Square className="w-3 h-3" /> Stop
+                                        </button>
+                                    ) : (
+                                        <span>{stats?.cancelled ? 'Stopped' : ''}</span>
+                                    )}
+                                    {stats && (
+                                        <span>{stats.tokens} tokens · {stats.tokens_per_second.toFixed(1)} tok/s</span>
+                                    )}
+                                </div>
                             </div>
                         )}
 
+                        {sources.length > 0 && (
+                            <Command.Group heading="Sources" className="mt-2 text-xs font-mono font-semibold text-neutral-500 uppercase tracking-wider px-2">
+                                {sources.map((source) => (
+                                    <Command.Item
+                                        key={source.marker}
+                                        value={`source-${source.marker}`}
+                                        onSelect={() => handleOpenSource(source)}
+                                        className={`flex items-start gap-2 px-3 py-2 border rounded-none hover:bg-neutral-50 cursor-pointer aria-selected:bg-neutral-100 transition-colors text-sm font-sans font-normal normal-case border-neutral-200 ${source.cited ? 'text-neutral-900' : 'text-neutral-500'}`}
+                                    >
+                                        <span className={`font-mono text-xs ${source.cited ? 'text-primary font-bold' : ''}`}>[{source.marker}]</span>
+                                        <div className="min-w-0">
+                                            <p className="truncate">
+                                                {source.title}
+                                                {source.heading ? ` › ${source.heading}` : ''}
+                                            </p>
+                                            <p className="truncate text-xs text-neutral-500">{source.text}</p>
+                                        </div>
+                                    </Command.Item>
+                                ))}
+                            </Command.Group>
+                        )}
+
                         <Command.Group heading="Recent Cards" className="mt-2 text-xs font-mono font-semibold text-neutral-500 uppercase tracking-wider px-2">
                             <Command.Item className="flex items-center gap-2 px-3 py-2 border rounded-none text-neutral-700 hover:bg-neutral-50 cursor-pointer aria-selected:bg-neutral-100 transition-colors text-sm font-sans font-normal normal-case border-neutral-200">
                                 <FileText className="w-4 h-4 text-neutral-500" />
@@ -10,6 +10,12 @@ interface SearchResult {
     id: string;
     title: string;
     snippet: string;
+    updated_at: string | null;
+    folder_id: string | null;
+    folder_name: string | null;
+    tags: string[];
+    matched_by: 'fulltext' | 'substring' | 'fuzzy';
+    hit_source: 'keyword' | 'semantic' | 'both';
 }
 
 interface SearchResultWithMetadata {
@@ -17,6 +23,24 @@ interface SearchResultWithMetadata {
     role_filter_applied: boolean;
     role_filter_type: string | null;
     global_search_active: boolean;
+    total_count: number;
+    next_offset: number | null;
+    suggestions: string[];
+    facets: SearchFacets;
+}
+
+interface FacetCount {
+    value: string;
+    label: string;
+    count: number;
+}
+
+interface SearchFacets {
+    tags: FacetCount[];
+    folders: FacetCount[];
+    created_months: FacetCount[];
+    updated_months: FacetCount[];
+    encryption: FacetCount[];
 }
 
 interface ActiveFilter {
@@ -13,8 +13,9 @@ export const SearchResultItem: React.FC<SearchResultItemProps> = ({
   isSelected,
   onClick
 }) => {
-  const icons: Record<string, string> = {
+  const icons: Record<SearchResult['type'], string> = {
     note: '📝',
+    card: '🗂️',
     task: '✅',
     project: '📁',
     learning: '📚',
@@ -22,7 +23,7 @@ export const SearchResultItem: React.FC<SearchResultItemProps> = ({
     template: '📋'
   };
 
-  const roleBadges: Record<string, string> = {
+  const roleBadges: Partial<Record<SearchResult['role'], string>> = {
     manager: 'bg-blue-100 text-blue-800',
     learner: 'bg-green-100 text-green-800',
     coach: 'bg-purple-100 text-purple-800'
@@ -39,7 +40,7 @@ export const SearchResultItem: React.FC<SearchResultItemProps> = ({
       `}
       onClick={onClick}
     >
-      <span className="text-xl">{icons[result.type] || '📄'}</span>
+      <span className="text-xl">{icons[result.type]}</span>
       <div className="flex-1 min-w-0">
         <div className="font-medium text-neutral-900 truncate">
           {result.title}
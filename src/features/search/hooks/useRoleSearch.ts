@@ -3,15 +3,27 @@ import { invoke } from '@tauri-apps/api/tauri';
 import { useRoleStore } from '../../../shared/stores/role-store';
 import { useDebounce } from '@/shared/hooks/useDebounce';
 
+const RESULT_TYPES = ['note', 'card', 'task', 'project', 'learning', 'team', 'template'] as const;
+const RESULT_ROLES = ['manager', 'learner', 'coach', 'universal'] as const;
+
+export type SearchResultType = typeof RESULT_TYPES[number];
+export type SearchResultRole = typeof RESULT_ROLES[number];
+
 export interface SearchResult {
   id: string;
-  type: 'note' | 'task' | 'project' | 'learning' | 'team' | 'template';
+  type: SearchResultType;
   title: string;
   description?: string;
-  role: 'manager' | 'learner' | 'coach' | 'universal';
+  role: SearchResultRole;
   metadata: Record<string, any>;
 }
 
+// Cards carry their own type ids and role contexts; anything unknown is shown as a generic card
+const parseType = (value: unknown): SearchResultType =>
+  RESULT_TYPES.find((t) => t === value) ?? 'card';
+const parseRole = (value: unknown): SearchResultRole =>
+  RESULT_ROLES.find((r) => r === value) ?? 'universal';
+
 export function useRoleSearch() {
   const activeRole = useRoleStore((state) => state.activeRole);
   const [query, setQuery] = useState('');
@@ -47,10 +59,10 @@ export function useRoleSearch() {
       // Validate and transform results
       const validatedResults = (response as any[]).map((item: any) => ({
         id: item.id,
-        type: item.type,
+        type: parseType(item.type),
         title: item.title,
         description: item.description,
-        role: item.role,
+        role: parseRole(item.role),
         metadata: item.metadata || {}
       }));
 
@@ -88,6 +88,22 @@ describe('useRoleSearch', () => {
     expect(result.current.error).toBe(null);
   });
 
+  it('should map unknown card types and roles onto known values', async () => {
+    mockInvoke.mockResolvedValue([
+      { id: '7', type: 'flashcard', title: 'Card', role: 'research', metadata: {} }
+    ]);
+
+    const { result } = renderHook(() => useRoleSearch());
+
+    await act(async () => {
+      result.current.setQuery('card');
+      await new Promise(resolve => setTimeout(resolve, 400));
+    });
+
+    expect(result.current.results[0].type).toBe('card');
+    expect(result.current.results[0].role).toBe('universal');
+  });
+
   it('should handle errors', async () => {
     mockInvoke.mockRejectedValue(new Error('Search failed'));
 
@@ -1,20 +1,93 @@
 import React, { useEffect, useState } from 'react';
-import { aiService, ModelStatus } from '../../../shared/services/aiService';
-import { Sparkles, Trash2, RefreshCw, HardDrive, CheckCircle2 } from 'lucide-react';
+import { open } from '@tauri-apps/api/dialog';
+import { aiService, DownloadProgress, ModelEntry, ModelStatus, ModelTask, ProviderKind, ProviderSettings } from '../../../shared/services/aiService';
+import { Sparkles, Trash2, RefreshCw, HardDrive, CheckCircle2, Plus, Download } from 'lucide-react';
+
+const PROVIDERS: { value: ProviderKind; label: string; placeholder: string }[] = [
+    { value: 'embedded', label: 'Built-in (Candle)', placeholder: '' },
+    { value: 'ollama', label: 'Ollama', placeholder: 'http://localhost:11434' },
+    { value: 'openai', label: 'OpenAI-compatible (llama.cpp, LM Studio)', placeholder: 'http://localhost:8080/v1' },
+];
+
+const TASKS: { task: ModelTask; label: string }[] = [
+    { task: 'chat', label: 'Chat' },
+    { task: 'summarize', label: 'Summarize' },
+    { task: 'tag', label: 'Tag' },
+];
 
 export const AISettingsPanel = () => {
     const [status, setStatus] = useState<ModelStatus | null>(null);
     const [isLoading, setIsLoading] = useState(false);
+    const [models, setModels] = useState<ModelEntry[]>([]);
+    const [taskModels, setTaskModels] = useState<Record<ModelTask, string> | null>(null);
+    const [download, setDownload] = useState<DownloadProgress | null>(null);
+    const [provider, setProvider] = useState<ProviderSettings | null>(null);
 
     const fetchStatus = async () => {
         try {
-            const s = await aiService.getModelStatus();
+            const [s, m, t, p] = await Promise.all([
+                aiService.getModelStatus(),
+                aiService.listModels(),
+                aiService.getTaskModels(),
+                aiService.getLlmSettings(),
+            ]);
             setStatus(s);
+            setModels(m);
+            setTaskModels(t);
+            setProvider(p);
         } catch (e) {
             console.error("Failed to fetch model status", e);
         }
     };
 
+    const handleAddModel = async () => {
+        const modelPath = await open({ title: 'Choose a GGUF model', filters: [{ name: 'GGUF', extensions: ['gguf'] }] });
+        if (typeof modelPath !== 'string') return;
+        const tokenizerPath = await open({ title: 'Choose its tokenizer.json', filters: [{ name: 'Tokenizer', extensions: ['json'] }] });
+        if (typeof tokenizerPath !== 'string') return;
+        try {
+            await aiService.addLocalModel(modelPath, tokenizerPath);
+            await fetchStatus();
+        } catch (e) {
+            alert(`Could not add model: ${e}`);
+        }
+    };
+
+    const handleDownload = async () => {
+        setIsLoading(true);
+        try {
+            await aiService.downloadModel(undefined, setDownload);
+            await fetchStatus();
+        } catch (e) {
+            alert(`Download failed: ${e}`);
+        } finally {
+            setDownload(null);
+            setIsLoading(false);
+        }
+    };
+
+    const handleSaveProvider = async () => {
+        if (!provider) return;
+        try {
+            await aiService.saveLlmSettings(provider);
+            await fetchStatus();
+        } catch (e) {
+            alert(`Could not save provider: ${e}`);
+        }
+    };
+
+    const handleRemoveModel = async (id: string) => {
+        await aiService.removeModel(id);
+        await fetchStatus();
+    };
+
+    const handleTaskModel = async (task: ModelTask, modelId: string) => {
+        await aiService.setTaskModel(task, modelId);
+        await fetchStatus();
+    };
+
+    const chatModel = models.find((m) => m.id === taskModels?.chat);
+
     useEffect(() => {
         fetchStatus();
     }, []);
@@ -43,7 +116,7 @@ export const AISettingsPanel = () => {
                     </div>
                     <div>
                         <h2 className="font-sans font-bold text-neutral-900 text-lg">Local Intelligence Engine</h2>
-                        <p className="font-mono text-xs font-medium text-neutral-600 uppercase tracking-wider">Qwen 2.5 0.5B Instruct (Quantized)</p>
+                        <p className="font-mono text-xs font-medium text-neutral-600 uppercase tracking-wider">{chatModel?.name ?? 'No model selected'}</p>
                     </div>
                 </div>
                 <div className={`
@@ -71,7 +144,108 @@ export const AISettingsPanel = () => {
                 </div>
             </div>
 
-            <div className="pt-4 border-t border-neutral-200 flex justify-end gap-3">
+            {provider && (
+                <div className="space-y-3">
+                    <p className="font-mono text-xs font-bold text-neutral-600 uppercase tracking-wider">Provider</p>
+                    <select
+                        value={provider.provider}
+                        onChange={(e) => setProvider({ ...provider, provider: e.target.value as ProviderKind })}
+                        className="w-full border border-neutral-200 px-2 py-1 text-sm"
+                    >
+                        {PROVIDERS.map((p) => (
+                            <option key={p.value} value={p.value}>{p.label}</option>
+                        ))}
+                    </select>
+                    {provider.provider !== 'embedded' && (
+                        <div className="grid grid-cols-3 gap-3">
+                            <input
+                                value={provider.base_url ?? ''}
+                                onChange={(e) => setProvider({ ...provider, base_url: e.target.value })}
+                                placeholder={PROVIDERS.find((p) => p.value === provider.provider)?.placeholder}
+                                className="border border-neutral-200 px-2 py-1 text-sm font-mono"
+                            />
+                            <input
+                                value={provider.model ?? ''}
+                                onChange={(e) => setProvider({ ...provider, model: e.target.value })}
+                                placeholder={provider.provider === 'ollama' ? 'llama3.1:8b' : 'Model (optional)'}
+                                className="border border-neutral-200 px-2 py-1 text-sm font-mono"
+                            />
+                            <input
+                                type="password"
+                                value={provider.api_key ?? ''}
+                                onChange={(e) => setProvider({ ...provider, api_key: e.target.value })}
+                                placeholder={provider.api_key_locked ? 'Stored — unlock the vault to change' : 'API key (optional)'}
+                                className="border border-neutral-200 px-2 py-1 text-sm font-mono"
+                            />
+                        </div>
+                    )}
+                    <div className="flex justify-end">
+                        <button onClick={handleSaveProvider} className="rg-btn rg-btn-secondary">Save Provider</button>
+                    </div>
+                </div>
+            )}
+
+            <div className="space-y-3">
+                <div className="flex items-center justify-between">
+                    <p className="font-mono text-xs font-bold text-neutral-600 uppercase tracking-wider">Models</p>
+                    <button onClick={handleAddModel} className="rg-btn rg-btn-secondary flex items-center gap-2">
+                        <Plus className="w-4 h-4" />
+                        Add Local Model
+                    </button>
+                </div>
+                {models.map((model) => (
+                    <div key={model.id} className="flex items-center justify-between border border-neutral-200 p-3">
+                        <div>
+                            <p className="font-sans text-sm font-medium text-neutral-900">{model.name}</p>
+                            <p className="font-mono text-xs text-neutral-500">
+                                {model.architecture ?? 'unknown'} · {model.prompt_format}
+                                {model.context_length ? ` · ${model.context_length} ctx` : ''}
+                                {model.downloaded ? '' : ' · not downloaded'}
+                            </p>
+                        </div>
+                        {model.source === 'local' && (
+                            <button onClick={() => handleRemoveModel(model.id)} className="text-neutral-400 hover:text-red-600" title="Remove from list">
+                                <Trash2 className="w-4 h-4" />
+                            </button>
+                        )}
+                    </div>
+                ))}
+                <div className="grid grid-cols-3 gap-4">
+                    {TASKS.map(({ task, label }) => (
+                        <label key={task} className="flex flex-col gap-1">
+                            <span className="font-mono text-xs font-bold text-neutral-600 uppercase tracking-wider">{label}</span>
+                            <select
+                                value={taskModels?.[task] ?? ''}
+                                onChange={(e) => handleTaskModel(task, e.target.value)}
+                                className="border border-neutral-200 px-2 py-1 text-sm"
+                            >
+                                {models.map((model) => (
+                                    <option key={model.id} value={model.id}>{model.name}</option>
+                                ))}
+                            </select>
+                        </label>
+                    ))}
+                </div>
+            </div>
+
+            <div className="pt-4 border-t border-neutral-200 flex justify-end items-center gap-3">
+                 {download && (
+                    <p className="font-mono text-xs text-neutral-600 mr-auto">
+                        Downloading {download.file}: {(download.downloaded / 1024 / 1024).toFixed(1)}
+                        {download.total ? ` / ${(download.total / 1024 / 1024).toFixed(1)}` : ''} MB
+                        {download.eta_seconds != null ? ` · ${download.eta_seconds}s left` : ''}
+                    </p>
+                 )}
+                 {!status.downloaded && chatModel?.source === 'catalog' && (
+                    <button
+                        onClick={handleDownload}
+                        disabled={isLoading}
+                        className="rg-btn rg-btn-secondary flex items-center gap-2"
+                    >
+                        <Download className="w-4 h-4" />
+                        Download Model
+                    </button>
+                 )}
                  {status.downloaded && (
                     <button 
                         onClick={handleDelete}
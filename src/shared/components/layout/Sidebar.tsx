@@ -7,6 +7,7 @@ import {
   Users,
   Info,
   Settings,
+  MessageSquare,
   LogOut,
   ChevronRight
 } from 'lucide-react';
@@ -18,6 +19,7 @@ import { FolderTree } from '../organization/FolderTree';
 const mainNavItems = [
   { icon: LayoutDashboard, label: 'Dashboard', path: '/dashboard' },
   { icon: FileText, label: 'My Notes', path: '/notes' },
+  { icon: MessageSquare, label: 'AI Chat', path: '/chat' },
   { icon: Star, label: 'Favorites', path: '/favorites' },
   { icon: Users, label: 'Collaborators', path: '/collaborators' },
   { icon: Info, label: 'Details', path: '/details' },
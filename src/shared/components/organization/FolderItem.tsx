@@ -6,13 +6,15 @@ import {
     Plus,
     FileText
 } from 'lucide-react';
-import { Folder } from '../../services/organizationService';
+import { Folder, SmartFolder } from '../../services/organizationService';
+import { SmartFolderItem } from './SmartFolderItem';
 import { cn } from '../../utils';
 import { useNotesStore } from '../../hooks/useNotesStore';
 
 interface FolderItemProps {
     folder: Folder;
     allFolders: Folder[];
+    smartFolders?: SmartFolder[];
     depth?: number;
     onAddSubfolder: (parentId: string) => void;
     onSelect: (folderId: string) => void;
@@ -22,6 +24,7 @@ interface FolderItemProps {
 export const FolderItem: React.FC<FolderItemProps> = ({
     folder,
     allFolders,
+    smartFolders = [],
     depth = 0,
     onAddSubfolder,
     onSelect,
@@ -31,10 +34,11 @@ export const FolderItem: React.FC<FolderItemProps> = ({
     const { notes, setSelectedNoteId, selectedNoteId } = useNotesStore();
 
     const children = allFolders.filter(f => f.parent_id === folder.id);
+    const childSmartFolders = smartFolders.filter(f => f.parent_folder_id === folder.id);
     const folderNotes = notes.filter(n => n.folderId === folder.id);
     const isSelected = selectedId === folder.id;
 
-    const hasContent = children.length > 0 || folderNotes.length > 0;
+    const hasContent = children.length > 0 || childSmartFolders.length > 0 || folderNotes.length > 0;
 
     return (
         <div className="select-none">
@@ -78,6 +82,7 @@ export const FolderItem: React.FC<FolderItemProps> = ({
                             key={child.id}
                             folder={child}
                             allFolders={allFolders}
+                            smartFolders={smartFolders}
                             depth={depth + 1}
                             onAddSubfolder={onAddSubfolder}
                             onSelect={onSelect}
@@ -85,6 +90,11 @@ export const FolderItem: React.FC<FolderItemProps> = ({
                         />
                     ))}
 
+                    {/* Smart folders */}
+                    {childSmartFolders.map(smartFolder => (
+                        <SmartFolderItem key={smartFolder.id} folder={smartFolder} depth={depth + 1} />
+                    ))}
+
                     {/* Notes */}
                     {folderNotes.map(note => (
                         <div
@@ -2,6 +2,7 @@ import React, { useState } from 'react';
 import { useQuery, useMutation, useQueryClient } from '@tanstack/react-query';
 import { organizationService, Folder } from '../../services/organizationService';
 import { FolderItem } from './FolderItem';
+import { SmartFolderItem } from './SmartFolderItem';
 import { FolderPlus, Plus } from 'lucide-react';
 
 export const FolderTree: React.FC = () => {
@@ -13,6 +14,11 @@ export const FolderTree: React.FC = () => {
         queryFn: organizationService.getFolders,
     });
 
+    const { data: smartFolders = [] } = useQuery({
+        queryKey: ['smart-folders'],
+        queryFn: organizationService.getSmartFolders,
+    });
+
     const createFolderMutation = useMutation({
         mutationFn: ({ name, parentId }: { name: string; parentId: string | null }) =>
             organizationService.createFolder(name, parentId),
@@ -38,6 +44,10 @@ export const FolderTree: React.FC = () => {
     if (isLoading) return <div className="px-6 py-2 font-mono text-[10px] text-neutral-500 uppercase">Loading folders...</div>;
 
     const rootFolders = folders.filter(f => !f.parent_id);
+    // Smart folders whose parent folder was deleted are shown at the root
+    const rootSmartFolders = smartFolders.filter(
+        f => !f.parent_folder_id || !folders.some(folder => folder.id === f.parent_folder_id)
+    );
 
     return (
         <div className="space-y-1">
@@ -57,13 +67,18 @@ export const FolderTree: React.FC = () => {
                         key={folder.id}
                         folder={folder}
                         allFolders={folders}
+                        smartFolders={smartFolders}
                         onAddSubfolder={handleAddSubfolder}
                         onSelect={setSelectedFolderId}
                         selectedId={selectedFolderId}
                     />
                 ))}
 
-                {rootFolders.length === 0 && (
+                {rootSmartFolders.map(smartFolder => (
+                    <SmartFolderItem key={smartFolder.id} folder={smartFolder} />
+                ))}
+
+                {rootFolders.length === 0 && rootSmartFolders.length === 0 && (
                     <div className="px-6 py-4 border border-dashed border-neutral-200 text-center">
                         <p className="font-mono text-[10px] text-neutral-500 font-bold uppercase tracking-wider mb-2">No folders</p>
                         <button
@@ -0,0 +1,67 @@
+import React, { useState } from 'react';
+import { useQuery } from '@tanstack/react-query';
+import { ChevronDown, ChevronRight, FileText, FolderSearch } from 'lucide-react';
+import { organizationService, SmartFolder } from '../../services/organizationService';
+import { cn } from '../../utils';
+import { useNotesStore } from '../../hooks/useNotesStore';
+
+interface SmartFolderItemProps {
+    folder: SmartFolder;
+    depth?: number;
+}
+
+/** A saved search in the folder tree; its matches are loaded when it is expanded */
+export const SmartFolderItem: React.FC<SmartFolderItemProps> = ({ folder, depth = 0 }) => {
+    const [isExpanded, setIsExpanded] = useState(false);
+    const { setSelectedNoteId, selectedNoteId } = useNotesStore();
+
+    const { data: notes = [], isLoading } = useQuery({
+        queryKey: ['smart-folder-notes', folder.id],
+        queryFn: () => organizationService.getSmartFolderNotes(folder.id),
+        enabled: isExpanded,
+    });
+
+    return (
+        <div className="select-none">
+            <div
+                className="group flex items-center gap-2 px-3 py-1.5 border rounded-none cursor-pointer transition-colors text-neutral-600 hover:bg-neutral-50 hover:text-neutral-900 border-neutral-200"
+                style={{ paddingLeft: `${(depth * 12) + 12}px` }}
+                onClick={() => setIsExpanded(!isExpanded)}
+                title={folder.query}
+            >
+                <span className="w-4 h-4 flex items-center justify-center">
+                    {folder.count > 0 ? (
+                        isExpanded ? <ChevronDown className="w-3 h-3" /> : <ChevronRight className="w-3 h-3" />
+                    ) : null}
+                </span>
+                <FolderSearch className="w-4 h-4 text-neutral-400" />
+                <span className="font-sans text-xs font-medium truncate">{folder.name}</span>
+                <span className="ml-auto font-mono text-[10px] text-neutral-400">{folder.count}</span>
+            </div>
+
+            {isExpanded && (
+                <div className="mt-0.5">
+                    {isLoading && (
+                        <div className="px-6 py-1 font-mono text-[10px] text-neutral-500 uppercase">Loading...</div>
+                    )}
+                    {notes.map(note => (
+                        <div
+                            key={note.id}
+                            className={cn(
+                                "flex items-center gap-2 px-3 py-1.5 border rounded-none cursor-pointer transition-colors",
+                                selectedNoteId === note.id
+                                    ? "bg-neutral-50 text-neutral-900 border-neutral-300"
+                                    : "text-neutral-500 hover:text-neutral-900 hover:bg-neutral-50 border-neutral-200"
+                            )}
+                            style={{ paddingLeft: `${((depth + 1) * 12) + 28}px` }}
+                            onClick={() => setSelectedNoteId(note.id)}
+                        >
+                            <FileText className="w-3 h-3 text-neutral-400" />
+                            <span className="font-mono text-[11px] truncate">{note.title}</span>
+                        </div>
+                    ))}
+                </div>
+            )}
+        </div>
+    );
+};
@@ -1,22 +1,31 @@
 import { create } from 'zustand';
 import type { Note } from '../types';
 
+/** Character offsets of a passage in a note's content, as in an AI answer's sources */
+export interface NotePassage {
+  start: number;
+  end: number;
+}
+
 interface NotesStore {
   notes: Note[];
   selectedNoteId: string | null;
+  /** Passage of the selected note to scroll to and highlight */
+  selectedPassage: NotePassage | null;
   isLoading: boolean;
   addNote: (title: string, content: string) => Promise<Note>;
   updateNote: (id: string, updates: Partial<Note>) => Promise<Note>;
   deleteNote: (id: string) => Promise<void>;
   setNotes: (notes: Note[]) => void;
-  setSelectedNoteId: (id: string | null) => void;
+  setSelectedNoteId: (id: string | null, passage?: NotePassage | null) => void;
 }
 
 export const useNotesStore = create<NotesStore>((set, get) => ({
   notes: [],
   selectedNoteId: null,
+  selectedPassage: null,
   isLoading: false,
-  setSelectedNoteId: (id) => set({ selectedNoteId: id }),
+  setSelectedNoteId: (id, passage = null) => set({ selectedNoteId: id, selectedPassage: passage }),
 
   addNote: async (title, content) => {
     const newNote: Note = {
@@ -1,4 +1,5 @@
 import { invoke } from '@tauri-apps/api/tauri';
+import { listen } from '@tauri-apps/api/event';
 
 export interface ModelStatus {
   downloaded: boolean;
@@ -6,6 +7,135 @@ export interface ModelStatus {
   model_size: number;
 }
 
+export type ProviderKind = 'embedded' | 'ollama' | 'openai';
+
+export interface ProviderSettings {
+  provider: ProviderKind;
+  base_url: string | null;
+  model: string | null;
+  api_key: string | null;
+  /** A key is stored but the vault is locked; saving without a key keeps it */
+  api_key_locked?: boolean;
+}
+
+export type ModelTask = 'chat' | 'summarize' | 'tag';
+
+export interface ModelEntry {
+  id: string;
+  name: string;
+  source: 'catalog' | 'local';
+  model_path: string;
+  tokenizer_path: string;
+  model_url: string | null;
+  tokenizer_url: string | null;
+  architecture: string | null;
+  chat_template: string | null;
+  prompt_format: string;
+  context_length: number | null;
+  eos_token_ids: number[];
+  downloaded: boolean;
+  model_size: number;
+}
+
+export interface DownloadProgress {
+  model_id: string;
+  file: 'model' | 'tokenizer';
+  downloaded: number;
+  total: number | null;
+  bytes_per_second: number;
+  eta_seconds: number | null;
+}
+
+export interface GenerationOptions {
+  max_tokens?: number;
+  temperature?: number | null;
+  top_p?: number | null;
+  seed?: number | null;
+  repeat_penalty?: number;
+}
+
+export interface GenerationStats {
+  tokens: number;
+  elapsed_ms: number;
+  tokens_per_second: number;
+  cancelled: boolean;
+}
+
+/** A note passage the answer was given, cited in the text as `[marker]` */
+export interface Source {
+  marker: number;
+  note_id: string;
+  title: string;
+  heading: string | null;
+  /** Character offsets of the passage in the note's content */
+  start: number;
+  end: number;
+  text: string;
+  cited: boolean;
+}
+
+export interface Answer {
+  request_id: string;
+  text: string;
+  sources: Source[];
+  stats: GenerationStats | null;
+}
+
+/** Which notes a chat session answers from (the backend's NoteScope) */
+export type ChatScope =
+  | { type: 'all' }
+  | { type: 'folder'; id: string }
+  | { type: 'tag'; name: string }
+  | { type: 'notes'; ids: string[] };
+
+export interface ChatSession {
+  id: string;
+  title: string;
+  scope: ChatScope;
+  summary: string | null;
+  summarized_count: number;
+  created_at: string | null;
+  updated_at: string | null;
+}
+
+export interface ChatMessageRecord {
+  id: number;
+  session_id: string;
+  role: 'user' | 'assistant' | 'system';
+  content: string;
+  sources: Source[];
+  created_at: string | null;
+}
+
+export interface ChatReply {
+  question: ChatMessageRecord;
+  answer: ChatMessageRecord;
+  stats: GenerationStats | null;
+}
+
+export type NoteAction = 'summary' | 'title' | 'tags' | 'action_items';
+
+export interface NoteSuggestion {
+  id: string;
+  note_id: string;
+  action: NoteAction;
+  status: 'queued' | 'running' | 'ready' | 'failed' | 'applied' | 'dismissed';
+  output: string[];
+  error: string | null;
+  model: string | null;
+  created_at: string | null;
+  updated_at: string | null;
+}
+
+export interface Task {
+  id: string;
+  note_id: string | null;
+  text: string;
+  done: boolean;
+  created_at: string | null;
+  completed_at: string | null;
+}
+
 export const aiService = {
   synthesizeNotes: async (noteIds: string[], promptType: string): Promise<string> => {
     return invoke('synthesize_notes', {
@@ -13,13 +143,122 @@ export const aiService = {
       promptType,
     });
   },
-  synthesizeQuery: async (query: string): Promise<string> => {
-     return invoke('synthesize_query', { query });
+  /** Streams text to `onToken` as it is generated and resolves with the full answer */
+  synthesizeQuery: async (
+    query: string,
+    requestId: string,
+    onToken?: (token: string) => void,
+    options?: GenerationOptions,
+  ): Promise<Answer> => {
+    const unlisten = await listen<{ request_id: string; token: string }>('llm-token', (event) => {
+      if (event.payload.request_id === requestId) onToken?.(event.payload.token);
+    });
+    try {
+      return await invoke<Answer>('synthesize_query', { query, requestId, options });
+    } finally {
+      unlisten();
+    }
+  },
+  createChatSession: async (scope?: ChatScope, title?: string): Promise<ChatSession> => {
+    return invoke('create_chat_session', { title, scope });
+  },
+  listChatSessions: async (): Promise<ChatSession[]> => {
+    return invoke('list_chat_sessions');
+  },
+  getChatMessages: async (sessionId: string): Promise<ChatMessageRecord[]> => {
+    return invoke('get_chat_messages', { sessionId });
+  },
+  updateChatSession: async (id: string, changes: { title?: string; scope?: ChatScope }): Promise<ChatSession> => {
+    return invoke('update_chat_session', { id, ...changes });
+  },
+  deleteChatSession: async (id: string): Promise<boolean> => {
+    return invoke('delete_chat_session', { id });
+  },
+  /** Streams the answer to `onToken` and resolves once both messages are stored */
+  sendChatMessage: async (
+    sessionId: string,
+    content: string,
+    requestId: string,
+    onToken?: (token: string) => void,
+    options?: GenerationOptions,
+  ): Promise<ChatReply> => {
+    const unlisten = await listen<{ request_id: string; token: string }>('llm-token', (event) => {
+      if (event.payload.request_id === requestId) onToken?.(event.payload.token);
+    });
+    try {
+      return await invoke<ChatReply>('send_chat_message', { sessionId, content, requestId, options });
+    } finally {
+      unlisten();
+    }
+  },
+  /** Saves an answer as a new note linking to its sources; resolves with the note id */
+  saveChatAnswerAsNote: async (messageId: number): Promise<string> => {
+    return invoke('save_chat_answer_as_note', { messageId });
+  },
+  /** Queues actions on a note; each suggestion is generated in the background */
+  requestNoteActions: async (noteId: string, actions: NoteAction[]): Promise<NoteSuggestion[]> => {
+    return invoke('request_note_actions', { noteId, actions });
+  },
+  listNoteSuggestions: async (noteId: string): Promise<NoteSuggestion[]> => {
+    return invoke('list_note_suggestions', { noteId });
+  },
+  /** Applies a reviewed suggestion, with `values` replacing its output if edited */
+  applyNoteSuggestion: async (id: string, values?: string[]): Promise<NoteSuggestion> => {
+    return invoke('apply_note_suggestion', { id, values });
+  },
+  dismissNoteSuggestion: async (id: string): Promise<boolean> => {
+    return invoke('dismiss_note_suggestion', { id });
+  },
+  /** Calls `onReady` whenever a background suggestion finishes; resolves with an unlisten function */
+  onNoteSuggestionReady: async (onReady: (suggestion: NoteSuggestion) => void): Promise<() => void> => {
+    return listen<NoteSuggestion>('note-suggestion-ready', (event) => onReady(event.payload));
+  },
+  listTasks: async (noteId?: string): Promise<Task[]> => {
+    return invoke('list_tasks', { noteId });
+  },
+  setTaskDone: async (id: string, done: boolean): Promise<Task> => {
+    return invoke('set_task_done', { id, done });
+  },
+  deleteTask: async (id: string): Promise<boolean> => {
+    return invoke('delete_task', { id });
+  },
+  cancelGeneration: async (requestId: string): Promise<boolean> => {
+    return invoke('cancel_generation', { requestId });
+  },
+  getModelStatus: async (modelId?: string): Promise<ModelStatus> => {
+    return invoke('get_model_status', { modelId });
+  },
+  deleteModel: async (modelId?: string): Promise<void> => {
+    return invoke('delete_model', { modelId });
+  },
+  /** Downloads a catalog model, resuming an interrupted download */
+  downloadModel: async (modelId?: string, onProgress?: (progress: DownloadProgress) => void): Promise<ModelStatus> => {
+    const unlisten = await listen<DownloadProgress>('model-download-progress', (event) => onProgress?.(event.payload));
+    try {
+      return await invoke<ModelStatus>('download_model', { modelId });
+    } finally {
+      unlisten();
+    }
+  },
+  getLlmSettings: async (): Promise<ProviderSettings> => {
+    return invoke('get_llm_settings');
+  },
+  saveLlmSettings: async (settings: ProviderSettings): Promise<void> => {
+    return invoke('save_llm_settings', { settings });
+  },
+  listModels: async (): Promise<ModelEntry[]> => {
+    return invoke('list_models');
+  },
+  addLocalModel: async (modelPath: string, tokenizerPath: string, name?: string): Promise<ModelEntry> => {
+    return invoke('add_local_model', { modelPath, tokenizerPath, name });
+  },
+  removeModel: async (id: string): Promise<void> => {
+    return invoke('remove_model', { id });
   },
-  getModelStatus: async (): Promise<ModelStatus> => {
-    return invoke('get_model_status');
+  getTaskModels: async (): Promise<Record<ModelTask, string>> => {
+    return invoke('get_task_models');
   },
-  deleteModel: async (): Promise<void> => {
-    return invoke('delete_model');
+  setTaskModel: async (task: ModelTask, modelId: string): Promise<void> => {
+    return invoke('set_task_model', { task, modelId });
   }
 };
@@ -11,6 +11,27 @@ export interface Tag {
     name: string;
 }
 
+/** A pinned saved search shown in the folder tree */
+export interface SmartFolder {
+    id: string;
+    name: string;
+    query: string;
+    parent_folder_id: string | null;
+    position: number;
+    count: number;
+}
+
+export interface SmartFolderNote {
+    id: string;
+    title: string;
+}
+
+interface SavedSearchPage {
+    results: SmartFolderNote[];
+    total_count: number;
+    next_offset: number | null;
+}
+
 export const organizationService = {
     createFolder: async (name: string, parentId: string | null = null): Promise<Folder> => {
         return await invoke<Folder>('create_folder', { name, parentId });
@@ -20,6 +41,10 @@ export const organizationService = {
         return await invoke<Folder[]>('get_folders');
     },
 
+    getTags: async (): Promise<Tag[]> => {
+        return await invoke<Tag[]>('get_tags');
+    },
+
     updateNoteFolder: async (noteId: string, folderId: string | null): Promise<void> => {
         return await invoke<void>('update_note_folder', { noteId, folderId });
     },
@@ -39,4 +64,13 @@ export const organizationService = {
     getNoteTags: async (noteId: string): Promise<Tag[]> => {
         return await invoke<Tag[]>('get_note_tags', { noteId });
     },
+
+    getSmartFolders: async (): Promise<SmartFolder[]> => {
+        return await invoke<SmartFolder[]>('get_smart_folders');
+    },
+
+    getSmartFolderNotes: async (id: string, limit = 50): Promise<SmartFolderNote[]> => {
+        const page = await invoke<SavedSearchPage>('run_saved_search', { id, limit, offset: 0 });
+        return page.results;
+    },
 };